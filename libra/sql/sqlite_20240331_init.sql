@@ -18,9 +18,37 @@ CREATE TABLE IF NOT EXISTS `reference` (
     )
 );
 --  (name, kind, remote) as unique key when remote is not null
-CREATE UNIQUE INDEX idx_name_kind_remote ON `reference`(`name`, `kind`, `remote`)
+CREATE UNIQUE INDEX IF NOT EXISTS idx_name_kind_remote ON `reference`(`name`, `kind`, `remote`)
 WHERE `remote` IS NOT NULL;
 
 -- (name, kind) as unique key when remote is null
-CREATE UNIQUE INDEX idx_name_kind ON `reference`(`name`, `kind`)
-WHERE `remote` IS NULL;
\ No newline at end of file
+CREATE UNIQUE INDEX IF NOT EXISTS idx_name_kind ON `reference`(`name`, `kind`)
+WHERE `remote` IS NULL;
+
+-- stash entries (refs/stash & its reflog in Git), the latest one is `stash@{0}`
+CREATE TABLE IF NOT EXISTS `stash` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- the `WIP` commit which records the worktree
+    `commit` TEXT NOT NULL,
+    `message` TEXT NOT NULL
+);
+
+-- history of the ref movements, `ref_name` is `HEAD` or `refs/heads/<branch>`
+CREATE TABLE IF NOT EXISTS `reflog` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    `ref_name` TEXT NOT NULL,
+    -- zero hash if the ref is created
+    `old_oid` TEXT NOT NULL,
+    `new_oid` TEXT NOT NULL,
+    `committer_name` TEXT NOT NULL,
+    `committer_email` TEXT NOT NULL,
+    `timestamp` INTEGER NOT NULL,
+    `message` TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_reflog_ref_name ON `reflog`(`ref_name`);
+
+-- shallow boundary (`.git/shallow` in Git): the commits whose parents are not fetched
+CREATE TABLE IF NOT EXISTS `shallow` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    `commit` TEXT NOT NULL UNIQUE
+);
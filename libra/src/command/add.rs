@@ -3,6 +3,7 @@ use clap::Parser;
 use mercury::internal::object::blob::Blob;
 use crate::command::status;
 use mercury::internal::index::{Index, IndexEntry};
+use crate::utils::ignore::IgnoreMatcher;
 use crate::utils::object_ext::BlobExt;
 
 use crate::utils::{path, util};
@@ -27,10 +28,13 @@ pub struct AddArgs {
     /// more detailed output
     #[clap(short, long)]
     pub verbose: bool,
+
+    /// Allow adding otherwise ignored files.
+    #[clap(short, long)]
+    pub force: bool,
 }
 
 pub async fn execute(args: AddArgs) {
-    // TODO .gitignore
     if !util::check_repo_exist() {
         return;
     }
@@ -48,7 +52,7 @@ pub async fn execute(args: AddArgs) {
     }
 
     // index vs worktree
-    let mut changes = status::changes_to_be_staged(); // to workdir
+    let mut changes = status::changes_to_be_staged().await; // to workdir, except ignored files
     // filter paths to fit `pathspec` that user inputs
     changes.new = util::filter_to_fit_paths(&changes.new, &paths);
     // if `--all` & <pathspec> is given, it will update `index` as well, so no need to filter `deleted` & `modified`
@@ -66,12 +70,41 @@ pub async fn execute(args: AddArgs) {
 
     let index_file = path::index();
     let mut index = Index::load(&index_file).unwrap();
+
+    // untracked files which are ignored, but explicitly given by user
+    let ignored = explicit_ignored_files(&args.pathspec, &index).await;
+    if !ignored.is_empty() {
+        if args.force {
+            files.extend(ignored);
+        } else if !args.update {
+            println!("The following paths are ignored by one of your .gitignore files:");
+            for file in &ignored {
+                println!("{}", file.display());
+            }
+            println!("hint: Use -f if you really want to add them.");
+        }
+    }
     for file in &files {
         add_a_file(file, &mut index, args.verbose).await;
     }
     index.save(&index_file).unwrap();
 }
 
+/// Find the untracked & ignored files given in `pathspec` (files, not dirs)
+/// - output: to workdir
+async fn explicit_ignored_files(pathspec: &[String], index: &Index) -> Vec<PathBuf> {
+    let mut matcher = IgnoreMatcher::load().await;
+    let workdir = util::working_dir();
+    pathspec
+        .iter()
+        .map(PathBuf::from)
+        .filter(|file| file.is_file() && util::is_sub_path(file, &workdir))
+        .map(util::to_workdir_path)
+        .filter(|file| !index.tracked(file.to_str().unwrap(), 0))
+        .filter(|file| matcher.is_ignored(file, false))
+        .collect()
+}
+
 /// `file` path must relative to the working directory
 async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
     let workdir = util::working_dir();
@@ -90,8 +123,13 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
 
     let file_abs = util::workdir_to_absolute(file);
     let file_str = file.to_str().unwrap();
+    // adding a conflicted file marks it as resolved
+    let mut unmerged = false;
+    for stage in 1..=3 {
+        unmerged |= index.remove(file_str, stage).is_some();
+    }
     if !file_abs.exists() {
-        if index.tracked(file_str, 0) {
+        if index.tracked(file_str, 0) || unmerged {
             // file is removed
             index.remove(file_str, 0);
             if verbose {
@@ -132,10 +170,33 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::test;
 
     #[test]
     #[should_panic]
     fn test_args_parse_update_conflict_with_all() {
         let _ = AddArgs::parse_from(["test", "-A", "-u"]);
     }
+
+    #[tokio::test]
+    async fn test_add_ignored_files() {
+        test::setup_with_new_libra().await;
+        test::ensure_file(".gitignore", Some("*.log\nbuild/\n"));
+        test::ensure_file("a.txt", Some("a"));
+        test::ensure_file("debug.log", Some("log"));
+        test::ensure_file("build/out.txt", Some("out"));
+
+        execute(AddArgs::parse_from(["add", "-A"])).await;
+        let index = Index::load(path::index()).unwrap();
+        assert!(index.tracked("a.txt", 0));
+        assert!(index.tracked(".gitignore", 0));
+        assert!(!index.tracked("debug.log", 0));
+        assert!(!index.tracked("build/out.txt", 0));
+
+        execute(AddArgs::parse_from(["add", "debug.log"])).await;
+        assert!(!Index::load(path::index()).unwrap().tracked("debug.log", 0));
+
+        execute(AddArgs::parse_from(["add", "-f", "debug.log"])).await;
+        assert!(Index::load(path::index()).unwrap().tracked("debug.log", 0));
+    }
 }
@@ -0,0 +1,640 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::Signature;
+use mercury::internal::object::tree::TreeItemMode;
+
+use crate::command::{load_object, parse_commit_msg};
+use crate::internal::head::Head;
+use crate::internal::shallow::{self, Shallow};
+use crate::utils::{diff, revision, util};
+
+/// The time format of the default output, like `2024-01-02 15:04:05 +0800`
+const BLAME_DATE: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Parser, Debug)]
+pub struct BlameArgs {
+    /// `[<rev>] <file>`: blame the file as of the revision, or the file in the working tree if omitted
+    #[clap(required = true, num_args = 1..=2, value_name = "[REV] FILE")]
+    pub args: Vec<String>,
+    /// Only blame the lines in the range `<start>,<end>`, `<start>,+<count>` or `<start>,-<count>` (1-based), can be repeated
+    #[clap(short = 'L', value_name = "RANGE")]
+    pub ranges: Vec<String>,
+    /// Show the result in a format designed for machine consumption
+    #[clap(long)]
+    pub porcelain: bool,
+    /// Ignore whitespace when comparing the lines of a commit with its parents
+    #[clap(short = 'w')]
+    pub ignore_whitespace: bool,
+}
+
+/// The origin of a line: the commit that last changed it (`None` if not committed yet) & its line number there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlameLine {
+    pub commit: Option<SHA1>,
+    /// 0-based line number in the version of the commit
+    pub orig_line: usize,
+    /// 0-based line number in the final version
+    pub final_line: usize,
+}
+
+pub async fn execute(args: BlameArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let (rev, file) = match args.args.as_slice() {
+        [file] => (None, file),
+        [rev, file] => (Some(rev), file),
+        _ => unreachable!("checked by clap"),
+    };
+    let path = util::to_workdir_path(file);
+
+    let (start, content) = match rev {
+        Some(rev) => {
+            let commit = match revision::resolve_commit(rev).await {
+                Ok(commit) => commit,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            match file_content(&commit, &path) {
+                Some(content) => (commit, content),
+                None => {
+                    eprintln!("fatal: no such path {} in {}", path.display(), rev);
+                    return;
+                }
+            }
+        }
+        None => {
+            let Some(head) = Head::current_commit().await else {
+                eprintln!("fatal: no such ref: HEAD");
+                return;
+            };
+            if file_content(&head, &path).is_none() {
+                eprintln!("fatal: no such path '{}' in HEAD", path.display());
+                return;
+            }
+            match fs::read(util::workdir_to_absolute(&path)) {
+                Ok(content) => (head, content),
+                Err(e) => {
+                    eprintln!("fatal: cannot stat path '{}': {}", path.display(), e);
+                    return;
+                }
+            }
+        }
+    };
+
+    let lines = diff::split_lines(&content);
+    let selected = match parse_ranges(&args.ranges, &path, lines.len()) {
+        Ok(selected) => selected,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let result = blame(
+        start,
+        rev.is_none(),
+        &path,
+        &content,
+        &selected,
+        args.ignore_whitespace,
+    )
+    .await;
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+
+    let boundary = boundary_commits(&result, &Shallow::list().await);
+    let output = match args.porcelain {
+        true => format_porcelain(&result, &lines, &path, &boundary),
+        false => format_default(&result, &lines, &boundary),
+    };
+    print!("{}", output);
+}
+
+/// Parse the `-L` ranges into the selected 0-based line numbers (sorted, without duplicates), all lines if no range
+fn parse_ranges(ranges: &[String], path: &Path, total: usize) -> Result<Vec<usize>, String> {
+    if ranges.is_empty() {
+        return Ok((0..total).collect());
+    }
+    let mut selected = HashSet::new();
+    for range in ranges {
+        let invalid = || format!("invalid -L range '{}'", range);
+        let number = |s: &str| s.parse::<usize>().map_err(|_| invalid());
+        let (start, end) = range.split_once(',').unwrap_or((range, ""));
+        let start = match start {
+            "" => 1,
+            _ => number(start)?,
+        };
+        if start == 0 {
+            return Err(invalid());
+        }
+        if start > total {
+            return Err(format!("file {} has only {} lines", path.display(), total));
+        }
+        let (first, last) = if end.is_empty() {
+            (start, total)
+        } else if let Some(count) = end.strip_prefix('+') {
+            (start, start + number(count)?.max(1) - 1)
+        } else if let Some(count) = end.strip_prefix('-') {
+            (
+                start.saturating_sub(number(count)?.max(1) - 1).max(1),
+                start,
+            )
+        } else {
+            let end = number(end)?;
+            (start.min(end.max(1)), start.max(end))
+        };
+        selected.extend(first - 1..last.min(total));
+    }
+    let mut selected: Vec<_> = selected.into_iter().collect();
+    selected.sort();
+    Ok(selected)
+}
+
+/// Content of the file at `path` in the commit, `None` if not a file there
+fn file_content(commit: &SHA1, path: &Path) -> Option<Vec<u8>> {
+    let commit = load_object::<Commit>(commit).ok()?;
+    let blob = blob_at(&commit.tree_id, path)?;
+    load_object::<Blob>(&blob).ok().map(|blob| blob.data)
+}
+
+/// The blob of `path` in the tree, `None` if not a file there
+fn blob_at(tree: &SHA1, path: &Path) -> Option<SHA1> {
+    match revision::lookup_path(tree, path)? {
+        (TreeItemMode::Blob | TreeItemMode::BlobExecutable | TreeItemMode::Link, id) => Some(id),
+        _ => None,
+    }
+}
+
+/// Attribute the `selected` lines of `content` (the file at `path`) to the commits that last changed them.
+///
+/// The commits are visited from the newest (by committer time) like Git: the lines of a commit are passed to
+/// each parent in turn if they are unchanged there (equal in the diff), the remaining ones are blamed on it.
+/// If `uncommitted`, `content` is the working tree version on top of `start`, otherwise the version of `start`.
+pub async fn blame(
+    start: SHA1,
+    uncommitted: bool,
+    path: &Path,
+    content: &[u8],
+    selected: &[usize],
+    ignore_whitespace: bool,
+) -> Result<Vec<BlameLine>, String> {
+    let shallow = Shallow::list().await;
+    let mut walk = BlameWalk {
+        path,
+        ignore_whitespace,
+        pending: HashMap::new(),
+        queue: BinaryHeap::new(),
+        commits: HashMap::new(),
+    };
+    let mut result = Vec::new();
+    // `(final_line, line in the version of the suspect)`
+    let lines: Vec<(usize, usize)> = selected.iter().map(|&i| (i, i)).collect();
+    if uncommitted {
+        let remaining = walk.pass_to_parents(lines, None, content, &[start])?;
+        result.extend(
+            remaining
+                .into_iter()
+                .map(|(final_line, orig_line)| BlameLine {
+                    commit: None,
+                    orig_line,
+                    final_line,
+                }),
+        );
+    } else {
+        walk.suspect(start, lines)?;
+    }
+
+    while let Some((_, id)) = walk.queue.pop() {
+        let Some(lines) = walk.pending.remove(&id) else {
+            continue;
+        };
+        let commit = walk.commit(&id)?.clone();
+        let blob = walk.blob_of(&id)?;
+        let data = load_object::<Blob>(&blob).map_err(|e| e.to_string())?.data;
+        let parents = shallow::parents(&commit, &shallow);
+        let remaining = walk.pass_to_parents(lines, Some(blob), &data, &parents)?;
+        result.extend(
+            remaining
+                .into_iter()
+                .map(|(final_line, orig_line)| BlameLine {
+                    commit: Some(id),
+                    orig_line,
+                    final_line,
+                }),
+        );
+    }
+    result.sort_by_key(|line| line.final_line);
+    Ok(result)
+}
+
+/// State of the history walk of `blame`
+struct BlameWalk<'a> {
+    path: &'a Path,
+    ignore_whitespace: bool,
+    /// the lines waiting to be examined in each commit
+    pending: HashMap<SHA1, Vec<(usize, usize)>>,
+    /// the commits with pending lines, the newest first
+    queue: BinaryHeap<(usize, SHA1)>,
+    commits: HashMap<SHA1, Commit>,
+}
+
+impl BlameWalk<'_> {
+    fn commit(&mut self, id: &SHA1) -> Result<&Commit, String> {
+        if !self.commits.contains_key(id) {
+            let commit =
+                load_object::<Commit>(id).map_err(|e| format!("{}: {}", id.to_plain_str(), e))?;
+            self.commits.insert(*id, commit);
+        }
+        Ok(&self.commits[id])
+    }
+
+    fn blob_of(&mut self, id: &SHA1) -> Result<SHA1, String> {
+        let path = self.path;
+        let tree = self.commit(id)?.tree_id;
+        blob_at(&tree, path)
+            .ok_or_else(|| format!("no such path {} in {}", path.display(), id.to_plain_str()))
+    }
+
+    /// Add lines to examine in a commit
+    fn suspect(&mut self, id: SHA1, lines: Vec<(usize, usize)>) -> Result<(), String> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        if !self.pending.contains_key(&id) {
+            let time = self.commit(&id)?.committer.timestamp;
+            self.queue.push((time, id));
+        }
+        self.pending.entry(id).or_default().extend(lines);
+        Ok(())
+    }
+
+    /// Pass the lines unchanged in a parent to it, return the others
+    fn pass_to_parents(
+        &mut self,
+        mut lines: Vec<(usize, usize)>,
+        blob: Option<SHA1>,
+        content: &[u8],
+        parents: &[SHA1],
+    ) -> Result<Vec<(usize, usize)>, String> {
+        for parent in parents {
+            if lines.is_empty() {
+                break;
+            }
+            let parent_tree = self.commit(parent)?.tree_id;
+            let parent_blob = match blob_at(&parent_tree, self.path) {
+                Some(parent_blob) => parent_blob,
+                None => continue, // the file is added (renames are not followed)
+            };
+            if Some(parent_blob) == blob {
+                self.suspect(*parent, lines)?;
+                return Ok(Vec::new());
+            }
+            let parent_content = load_object::<Blob>(&parent_blob)
+                .map_err(|e| e.to_string())?
+                .data;
+            let map = self.unchanged_lines(&parent_content, content);
+            let mut passed = Vec::new();
+            lines.retain(|&(final_line, line)| match map.get(&line) {
+                Some(&parent_line) => {
+                    passed.push((final_line, parent_line));
+                    false
+                }
+                None => true,
+            });
+            self.suspect(*parent, passed)?;
+        }
+        Ok(lines)
+    }
+
+    /// Map the line numbers of `new` to those of `old` for the unchanged lines
+    fn unchanged_lines(&self, old: &[u8], new: &[u8]) -> HashMap<usize, usize> {
+        let (old, new) = (diff::split_lines(old), diff::split_lines(new));
+        let blocks = if self.ignore_whitespace {
+            let strip = |lines: &[&[u8]]| -> Vec<Vec<u8>> {
+                lines
+                    .iter()
+                    .map(|line| {
+                        line.iter()
+                            .filter(|b| !b.is_ascii_whitespace())
+                            .copied()
+                            .collect()
+                    })
+                    .collect()
+            };
+            let (old, new) = (strip(&old), strip(&new));
+            let old: Vec<&[u8]> = old.iter().map(Vec::as_slice).collect();
+            let new: Vec<&[u8]> = new.iter().map(Vec::as_slice).collect();
+            diff::matching_blocks(&old, &new)
+        } else {
+            diff::matching_blocks(&old, &new)
+        };
+        blocks
+            .into_iter()
+            .flat_map(|(old_start, new_start, len)| {
+                (0..len).map(move |i| (new_start + i, old_start + i))
+            })
+            .collect()
+    }
+}
+
+/// The local time zone like `+0800`, for the lines not committed yet
+fn local_timezone() -> String {
+    chrono::Local::now().format("%z").to_string()
+}
+
+fn line_text(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).to_string()
+}
+
+/// The commits in the result where the history stops: the root commits & the shallow ones
+fn boundary_commits(result: &[BlameLine], shallow: &HashSet<SHA1>) -> HashSet<SHA1> {
+    let commits: HashSet<_> = result.iter().filter_map(|line| line.commit).collect();
+    commits
+        .into_iter()
+        .filter(|id| {
+            shallow.contains(id)
+                || load_object::<Commit>(id).is_ok_and(|c| c.parent_commit_ids.is_empty())
+        })
+        .collect()
+}
+
+/// The default format of Git: `<hash> (<author> <time> <line>) <content>`,
+/// the boundary commits are marked with `^`
+fn format_default(result: &[BlameLine], lines: &[&[u8]], boundary: &HashSet<SHA1>) -> String {
+    let mut commits: HashMap<SHA1, Commit> = HashMap::new();
+    for line in result {
+        if let Some(id) = line.commit {
+            commits
+                .entry(id)
+                .or_insert_with(|| load_object::<Commit>(&id).unwrap());
+        }
+    }
+    let now = chrono::Local::now().timestamp() as usize;
+    let author = |line: &BlameLine| match line.commit {
+        Some(id) => {
+            let author = &commits[&id].author;
+            (
+                author.name.clone(),
+                util::format_time(author.timestamp, &author.timezone, BLAME_DATE),
+            )
+        }
+        None => (
+            "Not Committed Yet".to_string(),
+            util::format_time(now, &local_timezone(), BLAME_DATE),
+        ),
+    };
+    let name_width = result
+        .iter()
+        .map(|line| author(line).0.chars().count())
+        .max()
+        .unwrap_or(0);
+    let number_width = result
+        .last()
+        .map_or(1, |line| (line.final_line + 1).to_string().len());
+
+    let mut output = String::new();
+    for line in result {
+        let hash = match line.commit {
+            Some(id) => {
+                let hex = id.to_plain_str();
+                match boundary.contains(&id) {
+                    true => format!("^{}", &hex[..7]),
+                    false => hex[..8].to_string(),
+                }
+            }
+            None => "0".repeat(8),
+        };
+        let (name, time) = author(line);
+        output.push_str(&format!(
+            "{} ({:<name_width$} {} {:>number_width$}) {}\n",
+            hash,
+            name,
+            time,
+            line.final_line + 1,
+            line_text(lines[line.final_line]),
+        ));
+    }
+    output
+}
+
+/// The porcelain format of Git: a header `<hash> <orig_line> <final_line> [<lines in group>]` for each line,
+/// the information of the commit when it first appears, then the content prefixed with a TAB
+fn format_porcelain(
+    result: &[BlameLine],
+    lines: &[&[u8]],
+    path: &Path,
+    boundary: &HashSet<SHA1>,
+) -> String {
+    let filename = util::path_to_string(&PathBuf::from(path));
+    let mut shown = HashSet::new();
+    let mut output = String::new();
+    for (i, line) in result.iter().enumerate() {
+        let hex = line.commit.map_or("0".repeat(40), |id| id.to_plain_str());
+        let continues = |prev: &BlameLine| {
+            prev.commit == line.commit
+                && prev.orig_line + 1 == line.orig_line
+                && prev.final_line + 1 == line.final_line
+        };
+        if i > 0 && continues(&result[i - 1]) {
+            output.push_str(&format!(
+                "{} {} {}\n",
+                hex,
+                line.orig_line + 1,
+                line.final_line + 1
+            ));
+        } else {
+            let group = 1 + result[i + 1..]
+                .iter()
+                .zip(result[i..].iter())
+                .take_while(|(next, prev)| {
+                    next.commit == prev.commit
+                        && prev.orig_line + 1 == next.orig_line
+                        && prev.final_line + 1 == next.final_line
+                })
+                .count();
+            output.push_str(&format!(
+                "{} {} {} {}\n",
+                hex,
+                line.orig_line + 1,
+                line.final_line + 1,
+                group
+            ));
+        }
+        if shown.insert(line.commit) {
+            match line.commit {
+                Some(id) => {
+                    let commit = load_object::<Commit>(&id).unwrap();
+                    push_signature(&mut output, "author", &commit.author);
+                    push_signature(&mut output, "committer", &commit.committer);
+                    let (message, _) = parse_commit_msg(&commit.message);
+                    let summary = message.lines().next().unwrap_or_default();
+                    output.push_str(&format!("summary {}\n", summary));
+                    if boundary.contains(&id) {
+                        output.push_str("boundary\n");
+                    } else if let Some(parent) = commit
+                        .parent_commit_ids
+                        .iter()
+                        .find(|p| file_content(p, path).is_some())
+                    {
+                        output.push_str(&format!(
+                            "previous {} {}\n",
+                            parent.to_plain_str(),
+                            filename
+                        ));
+                    }
+                }
+                None => {
+                    let (now, timezone) = (chrono::Local::now().timestamp(), local_timezone());
+                    for role in ["author", "committer"] {
+                        output.push_str(&format!("{} Not Committed Yet\n", role));
+                        output.push_str(&format!("{}-mail <not.committed.yet>\n", role));
+                        output.push_str(&format!(
+                            "{}-time {}\n{}-tz {}\n",
+                            role, now, role, timezone
+                        ));
+                    }
+                    output.push_str(&format!(
+                        "summary Version of {} from {}\n",
+                        filename, filename
+                    ));
+                }
+            }
+            output.push_str(&format!("filename {}\n", filename));
+        }
+        output.push_str(&format!("\t{}\n", line_text(lines[line.final_line])));
+    }
+    output
+}
+
+fn push_signature(output: &mut String, role: &str, signature: &Signature) {
+    output.push_str(&format!("{} {}\n", role, signature.name));
+    output.push_str(&format!("{}-mail <{}>\n", role, signature.email));
+    output.push_str(&format!("{}-time {}\n", role, signature.timestamp));
+    output.push_str(&format!("{}-tz {}\n", role, signature.timezone));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    async fn blamed_commits(
+        start: SHA1,
+        uncommitted: bool,
+        content: &str,
+        ignore_whitespace: bool,
+    ) -> Vec<Option<SHA1>> {
+        let path = Path::new("a.txt");
+        let selected: Vec<_> = (0..diff::split_lines(content.as_bytes()).len()).collect();
+        let result = blame(
+            start,
+            uncommitted,
+            path,
+            content.as_bytes(),
+            &selected,
+            ignore_whitespace,
+        )
+        .await;
+        result
+            .unwrap()
+            .into_iter()
+            .map(|line| line.commit)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let path = Path::new("a.txt");
+        let ranges = |ranges: &[&str]| {
+            let ranges: Vec<String> = ranges.iter().map(|r| r.to_string()).collect();
+            parse_ranges(&ranges, path, 10)
+        };
+        assert_eq!(ranges(&[]).unwrap(), (0..10).collect::<Vec<_>>());
+        assert_eq!(ranges(&["2,4"]).unwrap(), vec![1, 2, 3]);
+        assert_eq!(ranges(&["4,2"]).unwrap(), vec![1, 2, 3]);
+        assert_eq!(ranges(&["3,+2", "9"]).unwrap(), vec![2, 3, 8, 9]);
+        assert_eq!(
+            ranges(&["3,-2", "2,20"]).unwrap(),
+            (1..10).collect::<Vec<_>>()
+        );
+        assert_eq!(ranges(&["11"]).unwrap_err(), "file a.txt has only 10 lines");
+        assert!(ranges(&["0,1"]).is_err());
+        assert!(ranges(&["a,b"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_blame() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "a\nb\nc\n", "update a.txt").await;
+        let second = test::commit_file("a.txt", "a\nB\nc\n", "update a.txt").await;
+        let third = test::commit_file("a.txt", "a\nB\nc\nd\n", "update a.txt").await;
+        let fourth = test::commit_file("a.txt", "  a\nB\nc\nd\n", "update a.txt").await;
+
+        let content = "  a\nB\nc\nd\n";
+        let expected = vec![Some(fourth), Some(second), Some(first), Some(third)];
+        assert_eq!(
+            blamed_commits(fourth, false, content, false).await,
+            expected
+        );
+        let expected = vec![Some(first), Some(second), Some(first), Some(third)];
+        assert_eq!(blamed_commits(fourth, false, content, true).await, expected);
+
+        // the changes in the working tree are not committed yet
+        let content = "x\n  a\nB\nc\nd\n";
+        let expected = vec![None, Some(fourth), Some(second), Some(first), Some(third)];
+        assert_eq!(blamed_commits(fourth, true, content, false).await, expected);
+
+        // only the selected lines, with their line numbers in the commits
+        let content = "a\nB\nc\nd\n";
+        let result = blame(
+            third,
+            false,
+            Path::new("a.txt"),
+            content.as_bytes(),
+            &[2, 3],
+            false,
+        )
+        .await
+        .unwrap();
+        let expected = vec![
+            BlameLine {
+                commit: Some(first),
+                orig_line: 2,
+                final_line: 2,
+            },
+            BlameLine {
+                commit: Some(third),
+                orig_line: 3,
+                final_line: 3,
+            },
+        ];
+        assert_eq!(result, expected);
+
+        let boundary = boundary_commits(&result, &HashSet::new());
+        let lines = diff::split_lines(content.as_bytes());
+        let porcelain = format_porcelain(&result, &lines, Path::new("a.txt"), &boundary);
+        assert!(porcelain.starts_with(&format!("{} 3 3 1\nauthor ", first.to_plain_str())));
+        assert!(porcelain.contains("boundary\nfilename a.txt\n\tc\n"));
+        assert!(porcelain.contains(&format!(
+            "previous {} a.txt\nfilename a.txt\n\td\n",
+            second.to_plain_str()
+        )));
+        let default = format_default(&result, &lines, &boundary);
+        assert!(default.starts_with(&format!("^{} (", &first.to_plain_str()[..7])));
+        assert!(default.ends_with(" 4) d\n"));
+    }
+}
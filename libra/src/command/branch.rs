@@ -1,12 +1,13 @@
 use crate::{
     internal::{branch::Branch, config::Config, head::Head},
-    utils::{self, client_storage::ClientStorage},
+    utils,
 };
 use clap::Parser;
 use colored::Colorize;
 use mercury::{hash::SHA1, internal::object::commit::Commit};
 
 use crate::command::load_object;
+use crate::internal::reflog::{self, Reflog};
 
 #[derive(Parser, Debug)]
 pub struct BranchArgs {
@@ -93,6 +94,7 @@ pub async fn create_branch(new_branch: String, branch_or_commit: Option<String>)
         panic!("fatal: A branch named '{}' already exists.", new_branch);
     }
 
+    let base = branch_or_commit.clone().unwrap_or_else(|| "HEAD".to_string());
     let commit_id = match branch_or_commit {
         Some(branch_or_commit) => {
             let commit = get_target_commit(&branch_or_commit).await;
@@ -114,6 +116,7 @@ pub async fn create_branch(new_branch: String, branch_or_commit: Option<String>)
 
     // create branch
     Branch::update_branch(&new_branch, &commit_id.to_plain_str(), None).await;
+    Reflog::insert(&reflog::branch_ref(&new_branch), None, commit_id, &format!("branch: Created from {}", base)).await;
 }
 
 async fn delete_branch(branch_name: String) {
@@ -132,6 +135,9 @@ async fn delete_branch(branch_name: String) {
     }
 
     Branch::delete_branch(&branch_name, None).await;
+    if let Err(e) = Reflog::delete(&reflog::branch_ref(&branch_name)).await {
+        eprintln!("warning: unable to delete the reflog of '{}': {}", branch_name, e);
+    }
 }
 
 async fn show_current_branch() {
@@ -186,25 +192,9 @@ async fn list_branches(remotes: bool) {
     }
 }
 
+/// Resolve the branch, tag, hash or any revision like `HEAD~2` to a commit, see [`revision`](utils::revision)
 pub async fn get_target_commit(branch_or_commit: &str) -> Result<SHA1, Box<dyn std::error::Error>> {
-    let possible_branches = Branch::search_branch(branch_or_commit).await;
-    if possible_branches.len() > 1 {
-        return Err("fatal: Ambiguous branch name".into());
-        // TODO: git have a priority list of branches to use, continue with ambiguity, we didn't implement it yet
-    }
-
-    if possible_branches.is_empty() {
-        let storage = ClientStorage::init(utils::path::objects());
-        let possible_commits = storage.search(branch_or_commit);
-        if possible_commits.len() > 1 || possible_commits.is_empty() {
-            return Err(
-                format!("fatal: {} is not something we can merge", branch_or_commit).into(),
-            );
-        }
-        Ok(possible_commits[0])
-    } else {
-        Ok(possible_branches[0].commit)
-    }
+    utils::revision::resolve_commit(branch_or_commit).await.map_err(Into::into)
 }
 
 fn is_valid_git_branch_name(name: &str) -> bool {
@@ -255,6 +245,8 @@ mod tests {
         let commit_args = CommitArgs {
             message: "first".to_string(),
             allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
         };
         commit::execute(commit_args).await;
         let first_commit_id = Branch::find_branch("master", None).await.unwrap().commit;
@@ -262,6 +254,8 @@ mod tests {
         let commit_args = CommitArgs {
             message: "second".to_string(),
             allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
         };
         commit::execute(commit_args).await;
         let second_commit_id = Branch::find_branch("master", None).await.unwrap().commit;
@@ -339,6 +333,8 @@ mod tests {
         let args = CommitArgs {
             message: "first".to_string(),
             allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
         };
         commit::execute(args).await;
         let hash = Head::current_commit().await.unwrap();
@@ -370,6 +366,8 @@ mod tests {
         let args = CommitArgs {
             message: "first".to_string(),
             allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
         };
         commit::execute(args).await;
 
@@ -0,0 +1,170 @@
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use clap::{ArgGroup, Parser};
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::utils::{revision, util};
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("mode").required(true).args(["show_type", "size", "pretty", "exists", "batch", "batch_check"])))]
+pub struct CatFileArgs {
+    /// Show the type of the object
+    #[clap(short = 't')]
+    pub show_type: bool,
+    /// Show the size of the object
+    #[clap(short = 's')]
+    pub size: bool,
+    /// Pretty-print the content of the object
+    #[clap(short = 'p')]
+    pub pretty: bool,
+    /// Exit with zero status if the object exists and is valid, with non-zero status otherwise
+    #[clap(short = 'e')]
+    pub exists: bool,
+    /// Print `<hash> <type> <size>` and the content of each object named on stdin
+    #[clap(long)]
+    pub batch: bool,
+    /// Print `<hash> <type> <size>` of each object named on stdin
+    #[clap(long)]
+    pub batch_check: bool,
+    /// The object to show, any revision like `HEAD~1:src/main.rs`
+    #[clap(required_unless_present_any = ["batch", "batch_check"], conflicts_with_all = ["batch", "batch_check"])]
+    pub object: Option<String>,
+}
+
+pub async fn execute(args: CatFileArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    if args.batch || args.batch_check {
+        if let Err(e) = batch(args.batch).await {
+            eprintln!("fatal: {}", e);
+        }
+        return;
+    }
+
+    let name = args.object.unwrap();
+    let object = match revision::resolve(&name).await {
+        Ok(id) => read_object(&id),
+        Err(e) => Err(e),
+    };
+    let (obj_type, data) = match object {
+        Ok(object) => object,
+        // `-e` only reports by the exit status
+        Err(_) if args.exists => process::exit(1),
+        Err(_) => {
+            eprintln!("fatal: Not a valid object name {}", name);
+            return;
+        }
+    };
+    if args.show_type {
+        println!("{}", obj_type);
+    } else if args.size {
+        println!("{}", data.len());
+    } else if args.pretty {
+        let _ = io::stdout().write_all(&pretty(obj_type, &data));
+    }
+}
+
+/// Read the type & the content of an object
+pub fn read_object(id: &SHA1) -> Result<(ObjectType, Vec<u8>), String> {
+    let storage = util::objects_storage();
+    let obj_type = storage.get_object_type(id).map_err(|e| e.to_string())?;
+    let data = storage.get(id).map_err(|e| e.to_string())?;
+    Ok((obj_type, data))
+}
+
+/// The content for humans: the entries of a tree, the raw content of the others
+pub fn pretty(obj_type: ObjectType, data: &[u8]) -> Vec<u8> {
+    if obj_type != ObjectType::Tree {
+        return data.to_vec();
+    }
+    let tree = Tree::from_bytes(data, SHA1::default()).unwrap();
+    let mut output = String::new();
+    for item in tree.tree_items {
+        let item_type = match item.mode {
+            TreeItemMode::Tree => ObjectType::Tree,
+            TreeItemMode::Commit => ObjectType::Commit,
+            _ => ObjectType::Blob,
+        };
+        let mode = String::from_utf8_lossy(item.mode.to_bytes());
+        output.push_str(&format!(
+            "{:0>6} {} {}\t{}\n",
+            mode,
+            item_type,
+            item.id.to_plain_str(),
+            item.name
+        ));
+    }
+    output.into_bytes()
+}
+
+/// Answer the objects named on stdin, the missing ones with `<name> missing`
+async fn batch(with_content: bool) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let name = line.trim();
+        let object = match revision::resolve(name).await {
+            Ok(id) => read_object(&id).map(|object| (id, object)),
+            Err(e) => Err(e),
+        };
+        match object {
+            Ok((id, (obj_type, data))) => {
+                writeln!(stdout, "{} {} {}", id.to_plain_str(), obj_type, data.len())?;
+                if with_content {
+                    stdout.write_all(&data)?;
+                    writeln!(stdout)?;
+                }
+            }
+            Err(_) => writeln!(stdout, "{} missing", name)?,
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::internal::object::blob::Blob;
+
+    use super::*;
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_read_and_pretty() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "add a").await;
+        let head = test::commit_file("dir/b.txt", "b\n", "add b").await;
+        let (obj_type, data) = read_object(&head).unwrap();
+        assert_eq!(obj_type, ObjectType::Commit);
+        assert_eq!(pretty(obj_type, &data), data);
+
+        let tree = revision::resolve("HEAD^{tree}").await.unwrap();
+        let (obj_type, data) = read_object(&tree).unwrap();
+        let dir = revision::resolve("HEAD:dir").await.unwrap();
+        let expected = format!(
+            "100644 blob {}\ta.txt\n040000 tree {}\tdir\n",
+            Blob::from_content("a\n").id.to_plain_str(),
+            dir.to_plain_str()
+        );
+        assert_eq!(
+            String::from_utf8(pretty(obj_type, &data)).unwrap(),
+            expected
+        );
+        assert!(read_object(&SHA1::default()).is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        assert!(CatFileArgs::try_parse_from(["cat-file", "HEAD"]).is_err());
+        assert!(CatFileArgs::try_parse_from(["cat-file", "-t", "-p", "HEAD"]).is_err());
+        assert!(CatFileArgs::try_parse_from(["cat-file", "-p"]).is_err());
+        assert!(CatFileArgs::try_parse_from(["cat-file", "--batch"]).is_ok());
+        assert!(CatFileArgs::try_parse_from(["cat-file", "--batch-check", "HEAD"]).is_err());
+    }
+}
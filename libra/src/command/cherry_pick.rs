@@ -0,0 +1,447 @@
+//! `cherry-pick` & `revert`, they share the same sequencer:
+//! the commits are applied one by one, the remaining ones are saved in `.libra/sequencer`,
+//! so that the sequence can be continued after the conflicts are resolved.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::utils::merge::MergeLabels;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::branch::get_target_commit;
+use super::{commit, format_commit_msg, merge, parse_commit_msg, rebase, reset, save_object, status};
+
+/// files in `.libra/sequencer`
+const HEAD: &str = "head";
+const TODO: &str = "todo";
+const OPTS: &str = "opts";
+/// option of `-x`, saved in `opts`
+const RECORD_ORIGIN: &str = "record-origin";
+
+#[derive(Parser, Debug)]
+pub struct CherryPickArgs {
+    /// Commits to cherry-pick, applied in order
+    #[clap(required_unless_present_any(["continue_pick", "skip", "abort"]))]
+    pub commits: Vec<String>,
+
+    /// Append "(cherry picked from commit ...)" to the commit message
+    #[clap(short = 'x')]
+    pub record_origin: bool,
+
+    /// Apply the changes to the index & worktree, but don't commit
+    #[clap(short, long)]
+    pub no_commit: bool,
+
+    /// Continue the cherry-pick after the conflicts are resolved
+    #[clap(long = "continue", group = "op", conflicts_with = "commits")]
+    pub continue_pick: bool,
+
+    /// Skip the current commit and continue the cherry-pick
+    #[clap(long, group = "op", conflicts_with = "commits")]
+    pub skip: bool,
+
+    /// Abort the cherry-pick and reset HEAD to the original commit
+    #[clap(long, group = "op", conflicts_with = "commits")]
+    pub abort: bool,
+}
+
+pub async fn execute(args: CherryPickArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    if args.continue_pick {
+        sequence_continue(Operation::CherryPick).await;
+    } else if args.skip {
+        sequence_skip(Operation::CherryPick).await;
+    } else if args.abort {
+        sequence_abort(Operation::CherryPick).await;
+    } else {
+        start(Operation::CherryPick, &args.commits, args.record_origin, args.no_commit).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    CherryPick,
+    Revert,
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::CherryPick => "cherry-pick",
+            Operation::Revert => "revert",
+        }
+    }
+
+    /// word in the todo list
+    fn todo_word(&self) -> &'static str {
+        match self {
+            Operation::CherryPick => "pick",
+            Operation::Revert => "revert",
+        }
+    }
+
+    /// `CHERRY_PICK_HEAD` or `REVERT_HEAD`
+    fn head_file(&self) -> PathBuf {
+        match self {
+            Operation::CherryPick => path::cherry_pick_head(),
+            Operation::Revert => path::revert_head(),
+        }
+    }
+}
+
+fn state_file(name: &str) -> PathBuf {
+    path::sequencer().join(name)
+}
+
+/// The commit stopped by conflicts, `None` if not stopped
+fn stopped() -> Option<(Operation, SHA1)> {
+    [Operation::CherryPick, Operation::Revert].into_iter().find_map(|op| {
+        let content = fs::read_to_string(op.head_file()).ok()?;
+        Some((op, SHA1::from_str(content.trim()).ok()?))
+    })
+}
+
+fn in_progress() -> bool {
+    path::sequencer().exists() || stopped().is_some()
+}
+
+/// Remove all the state files
+fn clear_state() {
+    for file in [path::cherry_pick_head(), path::revert_head(), path::merge_msg()] {
+        let _ = fs::remove_file(file);
+    }
+    if path::sequencer().exists() {
+        fs::remove_dir_all(path::sequencer()).unwrap();
+    }
+}
+
+/// Apply the commits one by one, stop when conflicts occur
+pub async fn start(op: Operation, commits: &[String], record_origin: bool, no_commit: bool) {
+    if in_progress() {
+        eprintln!("error: a cherry-pick or revert is already in progress");
+        eprintln!("hint: try \"libra {} (--continue | --skip | --abort)\"", op.name());
+        return;
+    }
+    if merge::merge_head().is_some() || rebase::in_progress() {
+        eprintln!("error: cannot {} during a merge or rebase", op.name());
+        return;
+    }
+    if !commit::check_identity().await {
+        return;
+    }
+    let unstaged = status::changes_to_be_staged().await;
+    if !unstaged.modified.is_empty()
+        || !unstaged.deleted.is_empty()
+        || !status::changes_to_be_committed().await.is_empty()
+    {
+        eprintln!("error: your local changes would be overwritten by {}.", op.name());
+        eprintln!("hint: commit your changes or stash them to proceed.");
+        return;
+    }
+    let head = match Head::current_commit().await {
+        Some(head) => head,
+        None => {
+            eprintln!("fatal: HEAD does not point to a commit");
+            return;
+        }
+    };
+
+    let mut commit_ids = Vec::new();
+    for commit in commits {
+        let commit_id = match get_target_commit(commit).await {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let parents = Commit::load(&commit_id).parent_commit_ids;
+        if parents.len() > 1 {
+            eprintln!("error: commit {} is a merge but no -m option was given.", commit_id.to_plain_str());
+            return;
+        }
+        if parents.is_empty() && op == Operation::Revert {
+            eprintln!("error: cannot revert the root commit {}", commit_id.to_plain_str());
+            return;
+        }
+        commit_ids.push(commit_id);
+    }
+
+    if no_commit {
+        for commit_id in commit_ids.iter() {
+            let index = Index::load(path::index()).unwrap();
+            let ours: Vec<_> = index
+                .tracked_entries(0)
+                .into_iter()
+                .map(|entry| (PathBuf::from(&entry.name), entry.hash))
+                .collect();
+            if apply(op, commit_id, &ours) {
+                print_conflict_hints(op, commit_id);
+                return;
+            }
+        }
+        return;
+    }
+
+    fs::create_dir_all(path::sequencer()).unwrap();
+    fs::write(state_file(HEAD), head.to_plain_str()).unwrap();
+    let todo: String = commit_ids
+        .iter()
+        .map(|id| format!("{} {}\n", op.todo_word(), id.to_plain_str()))
+        .collect();
+    fs::write(state_file(TODO), todo).unwrap();
+    fs::write(state_file(OPTS), if record_origin { RECORD_ORIGIN } else { "" }).unwrap();
+    run().await;
+}
+
+/// Execute the todo list until finished or stopped by conflicts
+async fn run() {
+    loop {
+        let todo = fs::read_to_string(state_file(TODO)).unwrap_or_default();
+        let mut lines = todo.lines().filter(|line| !line.trim().is_empty());
+        let (op, commit_id) = match lines.next() {
+            Some(line) => {
+                let mut words = line.split_whitespace();
+                let op = match words.next() {
+                    Some("revert") => Operation::Revert,
+                    _ => Operation::CherryPick,
+                };
+                (op, SHA1::from_str(words.next().unwrap_or_default()).unwrap())
+            }
+            None => break,
+        };
+        let rest: String = lines.map(|line| format!("{}\n", line)).collect();
+        fs::write(state_file(TODO), rest).unwrap();
+
+        let head = Head::current_commit().await.unwrap();
+        if apply(op, &commit_id, &merge::commit_tree_items(&head)) {
+            fs::write(op.head_file(), format!("{}\n", commit_id.to_plain_str())).unwrap();
+            fs::write(path::merge_msg(), message(op, &commit_id)).unwrap();
+            print_conflict_hints(op, &commit_id);
+            return;
+        }
+        commit_changes(op, &commit_id, &message(op, &commit_id)).await;
+    }
+    clear_state();
+}
+
+/// Apply the changes (or the inverse changes for `revert`) of the commit, return `true` if conflicted
+/// - `ours`: items of the current index & worktree
+fn apply(op: Operation, commit_id: &SHA1, ours: &[(PathBuf, SHA1)]) -> bool {
+    let commit = Commit::load(commit_id);
+    let short = &commit_id.to_plain_str()[..7];
+    let (labels, base, target) = match op {
+        Operation::CherryPick => (
+            format!("{} ({})", short, commit.format_message()),
+            commit.parent_commit_ids.first().copied(),
+            *commit_id,
+        ),
+        Operation::Revert => (
+            format!("parent of {} ({})", short, commit.format_message()),
+            Some(*commit_id),
+            commit.parent_commit_ids[0],
+        ),
+    };
+    let labels = MergeLabels {
+        ours: "HEAD".to_string(),
+        theirs: labels,
+    };
+    merge::apply_changes(base.as_ref(), &target, ours, &labels)
+}
+
+/// The message of the new commit
+fn message(op: Operation, commit_id: &SHA1) -> String {
+    let commit = Commit::load(commit_id);
+    match op {
+        Operation::CherryPick => {
+            let (message, _) = parse_commit_msg(&commit.message);
+            let mut message = message.trim_end().to_string();
+            if fs::read_to_string(state_file(OPTS)).is_ok_and(|opts| opts.contains(RECORD_ORIGIN)) {
+                message += &format!("\n\n(cherry picked from commit {})", commit_id.to_plain_str());
+            }
+            message
+        }
+        Operation::Revert => format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.",
+            commit.format_message(),
+            commit_id.to_plain_str()
+        ),
+    }
+}
+
+fn print_conflict_hints(op: Operation, commit_id: &SHA1) {
+    let verb = match op {
+        Operation::CherryPick => "apply",
+        Operation::Revert => "revert",
+    };
+    eprintln!(
+        "error: could not {} {}... {}",
+        verb,
+        &commit_id.to_plain_str()[..7],
+        Commit::load(commit_id).format_message()
+    );
+    eprintln!("hint: After resolving the conflicts, mark them with");
+    eprintln!("hint: \"libra add <paths>\", then run");
+    eprintln!("hint: \"libra {} --continue\".", op.name());
+    eprintln!("hint: You can instead skip this commit with \"libra {} --skip\".", op.name());
+    eprintln!("hint: To abort and get back to the state before \"libra {}\",", op.name());
+    eprintln!("hint: run \"libra {} --abort\".", op.name());
+}
+
+/// Commit the index, the author of the cherry-picked commit is kept
+async fn commit_changes(op: Operation, commit_id: &SHA1, message: &str) {
+    let head = Commit::load(&Head::current_commit().await.unwrap());
+    let index = Index::load(path::index()).unwrap();
+    let tree = commit::create_tree(&index, &util::objects_storage(), "".into()).await;
+    if tree.id == head.tree_id {
+        println!(
+            "The previous {} of {} is now empty, skipped.",
+            op.name(),
+            &commit_id.to_plain_str()[..7]
+        );
+        return;
+    }
+    let new_commit = match op {
+        Operation::CherryPick => Commit::new(
+            Commit::load(commit_id).author,
+            commit::committer().await,
+            tree.id,
+            vec![head.id],
+            &format_commit_msg(message, None),
+        ),
+        Operation::Revert => Commit::new(
+            commit::author().await,
+            commit::committer().await,
+            tree.id,
+            vec![head.id],
+            &format_commit_msg(message, None),
+        ),
+    };
+    save_object(&new_commit, &new_commit.id).unwrap();
+    commit::update_head(&new_commit.id.to_plain_str()).await;
+    let subject = format!("{}: {}", op.name(), new_commit.format_message());
+    Reflog::log_head_update(Some(head.id), new_commit.id, &subject).await;
+    println!("[{}] {}", &new_commit.id.to_plain_str()[..7], new_commit.format_message());
+}
+
+pub async fn sequence_continue(op: Operation) {
+    if !in_progress() {
+        eprintln!("error: no {} in progress", op.name());
+        return;
+    }
+    if !Index::load(path::index()).unwrap().unmerged_files().is_empty() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        return;
+    }
+    if let Some((op, commit_id)) = stopped() {
+        let message = fs::read_to_string(path::merge_msg()).unwrap_or_else(|_| message(op, &commit_id));
+        commit_changes(op, &commit_id, message.trim_end()).await;
+        let _ = fs::remove_file(op.head_file());
+        let _ = fs::remove_file(path::merge_msg());
+    }
+    run().await;
+}
+
+pub async fn sequence_skip(op: Operation) {
+    if !in_progress() {
+        eprintln!("error: no {} in progress", op.name());
+        return;
+    }
+    for file in [path::cherry_pick_head(), path::revert_head(), path::merge_msg()] {
+        let _ = fs::remove_file(file);
+    }
+    reset::reset_index(&Head::current_commit().await.unwrap(), true);
+    run().await;
+}
+
+pub async fn sequence_abort(op: Operation) {
+    if !in_progress() {
+        eprintln!("error: no {} in progress", op.name());
+        return;
+    }
+    let head = fs::read_to_string(state_file(HEAD))
+        .ok()
+        .and_then(|head| SHA1::from_str(head.trim()).ok());
+    let head = match head {
+        Some(head) => {
+            let old = Head::current_commit().await;
+            commit::update_head(&head.to_plain_str()).await;
+            Reflog::log_head_update(old, head, &format!("reset: moving to {}", head.to_plain_str())).await;
+            head
+        }
+        None => Head::current_commit().await.unwrap(),
+    };
+    reset::reset_index(&head, true);
+    clear_state();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::{branch, switch};
+    use crate::utils::test;
+
+    /// `master`: base, `feature`: base -> b -> a
+    async fn setup() -> (SHA1, SHA1) {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "base").await;
+        branch::create_branch("feature".to_string(), None).await;
+        switch::execute(switch::SwitchArgs::parse_from(["switch", "feature"])).await;
+        let b = test::commit_file("b.txt", "b\n", "add b").await;
+        let a = test::commit_file("a.txt", "feature\n", "change a").await;
+        switch::execute(switch::SwitchArgs::parse_from(["switch", "master"])).await;
+        (b, a)
+    }
+
+    #[tokio::test]
+    async fn test_cherry_pick() {
+        let (b, a) = setup().await;
+        let base = Head::current_commit().await.unwrap();
+        execute(CherryPickArgs::parse_from(["cherry-pick", "-x", &b.to_plain_str(), &a.to_plain_str()])).await;
+
+        assert!(!in_progress());
+        let head = Commit::load(&Head::current_commit().await.unwrap());
+        let (message, _) = parse_commit_msg(&head.message);
+        assert_eq!(message, format!("change a\n\n(cherry picked from commit {})", a.to_plain_str()));
+        assert_eq!(head.author.name, Commit::load(&a).author.name);
+        assert_eq!(Commit::load(&head.parent_commit_ids[0]).parent_commit_ids, vec![base]);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "feature\n");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "b\n");
+    }
+
+    #[tokio::test]
+    async fn test_cherry_pick_conflict() {
+        let (_, a) = setup().await;
+        let head = test::commit_file("a.txt", "master\n", "master").await;
+        execute(CherryPickArgs::parse_from(["cherry-pick", &a.to_plain_str()])).await;
+        assert!(path::cherry_pick_head().exists());
+        assert!(fs::read_to_string("a.txt").unwrap().contains("<<<<<<< HEAD"));
+
+        execute(CherryPickArgs::parse_from(["cherry-pick", "--abort"])).await;
+        assert!(!in_progress());
+        assert_eq!(Head::current_commit().await.unwrap(), head);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "master\n");
+
+        execute(CherryPickArgs::parse_from(["cherry-pick", &a.to_plain_str()])).await;
+        test::ensure_file("a.txt", Some("resolved\n"));
+        add::execute(AddArgs::parse_from(["add", "a.txt"])).await;
+        execute(CherryPickArgs::parse_from(["cherry-pick", "--continue"])).await;
+        assert!(!in_progress());
+        let commit = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(commit.parent_commit_ids, vec![head]);
+        assert_eq!(commit.format_message(), "change a");
+    }
+}
@@ -6,6 +6,8 @@ use crate::command::restore::RestoreArgs;
 use crate::internal::branch::Branch;
 use crate::internal::config::{Config, RemoteConfig};
 use crate::internal::head::Head;
+use crate::internal::protocol;
+use crate::internal::reflog::Reflog;
 use clap::Parser;
 
 use crate::utils::path_ext::PathExt;
@@ -17,16 +19,43 @@ const ORIGIN: &str = "origin"; // default remote name, prevent spelling mistakes
 
 #[derive(Parser, Debug)]
 pub struct CloneArgs {
-    /// The remote repository location to clone from, usually a URL with HTTPS or SSH
+    /// The remote repository location to clone from, a URL with HTTPS, SSH or `file://`, or a local path
     pub remote_repo: String,
 
     /// The local path to clone the repository to
     pub local_path: Option<String>,
+
+    /// Create a shallow clone with the history truncated to the specified number of commits
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub depth: Option<u64>,
+
+    /// Create a shallow clone with the history after the specified date
+    #[clap(long, value_name = "DATE", conflicts_with = "depth")]
+    pub shallow_since: Option<String>,
 }
 
 pub async fn execute(args: CloneArgs) {
+    let deepen = match fetch::parse_deepen(args.depth, args.shallow_since.as_deref(), None) {
+        Ok(deepen) => deepen,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
     let mut remote_repo = args.remote_repo; // https://gitee.com/caiqihang2024/image-viewer2.0.git
-                                            // must end with '/' or Url::join will work incorrectly
+    // a local repository is recorded by its absolute path, [current_dir] is changed below
+    if !remote_repo.contains("://")
+        && protocol::parse_url(&remote_repo).is_ok_and(|url| url.scheme() == "file")
+    {
+        match fs::canonicalize(&remote_repo) {
+            Ok(path) => remote_repo = path.to_string_or_panic(),
+            Err(_) => {
+                eprintln!("fatal: repository '{}' does not exist", remote_repo);
+                return;
+            }
+        }
+    }
+    // must end with '/' or Url::join will work incorrectly
     if !remote_repo.ends_with('/') {
         remote_repo.push('/');
     }
@@ -68,7 +97,10 @@ pub async fn execute(args: CloneArgs) {
         name: "origin".to_string(),
         url: remote_repo.clone(),
     };
-    fetch::fetch_repository(&remote_config).await;
+    if let Err(e) = fetch::fetch_repository(&remote_config, deepen).await {
+        eprintln!("{}", e);
+        return;
+    }
 
     /* setup */
     setup(remote_repo.clone()).await;
@@ -86,6 +118,8 @@ async fn setup(remote_repo: String) {
 
             Branch::update_branch(&name, &origin_head_branch.commit.to_plain_str(), None).await;
             Head::update(Head::Branch(name.to_owned()), None).await;
+            let message = format!("clone: from {}", remote_repo);
+            Reflog::log_head_update(None, origin_head_branch.commit, &message).await;
 
             // set config: remote.origin.url
             Config::insert("remote", Some(ORIGIN), "url", &remote_repo).await;
@@ -1,8 +1,12 @@
+use std::env;
 use std::str::FromStr;
 use std::{collections::HashSet, path::PathBuf};
 
 use crate::internal::branch::Branch;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::internal::signing;
 use crate::utils::client_storage::ClientStorage;
 use crate::utils::path;
 use crate::utils::util;
@@ -10,10 +14,11 @@ use mercury::internal::index::Index;
 use clap::Parser;
 use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::{Signature, SignatureType};
 use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
 use mercury::internal::object::ObjectTrait;
 
-use super::{format_commit_msg, save_object};
+use super::{format_commit_msg, merge, save_object};
 
 #[derive(Parser, Debug)]
 pub struct CommitArgs {
@@ -22,12 +27,29 @@ pub struct CommitArgs {
 
     #[arg(long)]
     pub allow_empty: bool,
+
+    /// GPG-sign the commit, with the key if given, otherwise `user.signingkey`
+    #[arg(short = 'S', long, value_name = "KEYID", num_args = 0..=1, default_missing_value = "")]
+    pub gpg_sign: Option<String>,
+
+    /// Don't sign the commit, overrides `commit.gpgsign`
+    #[arg(long, conflicts_with = "gpg_sign")]
+    pub no_gpg_sign: bool,
 }
 
 pub async fn execute(args: CommitArgs) {
     /* check args */
+    if !check_identity().await {
+        return;
+    }
     let index = Index::load(path::index()).unwrap();
     let storage = ClientStorage::init(path::objects());
+    if !index.unmerged_files().is_empty() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        eprintln!("fatal: Exiting because of an unresolved conflict.");
+        return;
+    }
     let tracked_entries = index.tracked_entries(0);
     if tracked_entries.is_empty() && !args.allow_empty {
         panic!("fatal: no changes added to commit, use --allow-empty to override");
@@ -39,9 +61,23 @@ pub async fn execute(args: CommitArgs) {
     /* Create & save commit objects */
     let parents_commit_ids = get_parents_ids().await;
     // There must be a `blank line`(\n) before `message`, or remote unpack failed
-    let commit = Commit::from_tree_id(tree.id, parents_commit_ids, &format_commit_msg(&args.message, None));
-
-    // TODO  default signature created in `from_tree_id`, wait `git config` to set correct user info
+    let mut commit = Commit::new(
+        author().await,
+        committer().await,
+        tree.id,
+        parents_commit_ids,
+        &format_commit_msg(&args.message, None),
+    );
+    if let Some(key) = signing_key(&args).await {
+        commit = match signing::sign_commit(&commit, key.as_deref()).await {
+            Ok(signed) => signed,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                eprintln!("fatal: failed to write commit object");
+                return;
+            }
+        };
+    }
 
     storage
         .put(&commit.id, &commit.to_data().unwrap(), commit.get_type())
@@ -49,10 +85,39 @@ pub async fn execute(args: CommitArgs) {
 
     /* update HEAD */
     update_head(&commit.id.to_plain_str()).await;
+    let action = match commit.parent_commit_ids.len() {
+        0 => "commit (initial)",
+        1 => "commit",
+        _ => "commit (merge)",
+    };
+    let subject = args.message.lines().next().unwrap_or_default();
+    Reflog::log_head_update(
+        commit.parent_commit_ids.first().copied(),
+        commit.id,
+        &format!("{}: {}", action, subject),
+    )
+    .await;
+    // merge is concluded
+    merge::clear_merge_state();
+}
+
+/// The key to sign the commit with if it should be signed, `Some(None)` for the default key.
+/// `-S` & `--no-gpg-sign` override `commit.gpgsign`
+async fn signing_key(args: &CommitArgs) -> Option<Option<String>> {
+    if args.no_gpg_sign {
+        return None;
+    }
+    match &args.gpg_sign {
+        Some(key) => Some(Some(key.clone()).filter(|key| !key.is_empty())),
+        None => match Config::get("commit", None, "gpgsign").await.as_deref() {
+            Some("true") => Some(None),
+            _ => None,
+        },
+    }
 }
 
 /// recursively create tree from index's tracked entries
-async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathBuf) -> Tree {
+pub async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathBuf) -> Tree {
     // blob created when add file to index
     let get_blob_entry = |path: &PathBuf| {
         let name = util::path_to_string(path);
@@ -126,17 +191,133 @@ async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathB
 }
 
 /// get current head commit id as parent, if in branch, get branch's commit id, if detached head, get head's commit id
+/// - if a merge is in progress, `MERGE_HEAD` is the second parent
 async fn get_parents_ids() -> Vec<SHA1> {
     // let current_commit_id = reference::Model::current_commit_hash(db).await.unwrap();
     let current_commit_id = Head::current_commit().await;
-    match current_commit_id {
+    let mut parents = match current_commit_id {
         Some(id) => vec![id],
         None => vec![], // first commit
+    };
+    parents.extend(merge::merge_head());
+    parents
+}
+
+/// Name & email of the author (`GIT_AUTHOR`) or committer (`GIT_COMMITTER`):
+/// `<prefix>_NAME` & `<prefix>_EMAIL`, then `user.name` & `user.email` of config, then `$EMAIL` for the email
+async fn identity(prefix: &str) -> (Option<String>, Option<String>) {
+    let var = |name: String| env::var(name).ok().filter(|value| !value.is_empty());
+    let name = match var(format!("{}_NAME", prefix)) {
+        Some(name) => Some(name),
+        None => Config::get("user", None, "name").await,
+    };
+    let email = match var(format!("{}_EMAIL", prefix)) {
+        Some(email) => Some(email),
+        None => Config::get("user", None, "email").await.or_else(|| var("EMAIL".to_string())),
+    };
+    (name, email)
+}
+
+/// Timezone offset in Git format, e.g. `+0800`
+fn format_timezone(offset_seconds: i32) -> String {
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let minutes = offset_seconds.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// Parse the date of `GIT_AUTHOR_DATE` & `GIT_COMMITTER_DATE`, return `(timestamp, timezone)`
+/// - Git internal format: `<unix timestamp> <timezone>`, `@` prefix is allowed
+/// - RFC 2822: `Thu, 07 Apr 2005 22:13:13 +0200`
+/// - ISO 8601: `2005-04-07T22:13:13+02:00` or `2005-04-07 22:13:13 +0200`
+pub fn parse_date(date: &str) -> Option<(usize, String)> {
+    let date = date.trim();
+    let internal = date.trim_start_matches('@');
+    if let Some((timestamp, timezone)) = internal.split_once(' ') {
+        let valid_timezone = timezone.len() == 5
+            && timezone.starts_with(['+', '-'])
+            && timezone[1..].chars().all(|c| c.is_ascii_digit());
+        if let (Ok(timestamp), true) = (timestamp.parse::<usize>(), valid_timezone) {
+            return Some((timestamp, timezone.to_string()));
+        }
+    }
+    if let Ok(timestamp) = internal.parse::<usize>() {
+        return Some((timestamp, "+0000".to_string()));
+    }
+    let datetime = chrono::DateTime::parse_from_rfc2822(date)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(date))
+        .or_else(|_| chrono::DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()?;
+    let timestamp = usize::try_from(datetime.timestamp()).ok()?;
+    Some((timestamp, format_timezone(datetime.offset().local_minus_utc())))
+}
+
+/// Signature of the author or committer, now (or `GIT_<type>_DATE`)
+/// - falls back to the login name if the identity is not configured, see [`check_identity`]
+async fn signature(signature_type: SignatureType) -> Signature {
+    let prefix = match signature_type {
+        SignatureType::Author => "GIT_AUTHOR",
+        _ => "GIT_COMMITTER",
+    };
+    let (name, email) = identity(prefix).await;
+    let name = name.unwrap_or_else(|| {
+        env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string())
+    });
+    let email = email.unwrap_or_else(|| format!("{}@localhost", name));
+    let now = chrono::Local::now();
+    let (timestamp, timezone) = env::var(format!("{}_DATE", prefix))
+        .ok()
+        .and_then(|date| parse_date(&date))
+        .unwrap_or_else(|| (now.timestamp() as usize, format_timezone(now.offset().local_minus_utc())));
+    Signature {
+        signature_type,
+        name,
+        email,
+        timestamp,
+        timezone,
+    }
+}
+
+/// Signature of the author, now
+pub async fn author() -> Signature {
+    signature(SignatureType::Author).await
+}
+
+/// Signature of the committer, now
+pub async fn committer() -> Signature {
+    signature(SignatureType::Committer).await
+}
+
+/// Check that the identity of the author & committer is known and the dates are valid,
+/// print the hints of setting `user.name` & `user.email` if not
+pub async fn check_identity() -> bool {
+    for prefix in ["GIT_AUTHOR", "GIT_COMMITTER"] {
+        let (name, email) = identity(prefix).await;
+        if name.is_none() || email.is_none() {
+            let who = if prefix == "GIT_AUTHOR" { "Author" } else { "Committer" };
+            eprintln!("{} identity unknown\n", who);
+            eprintln!("*** Please tell me who you are.\n");
+            eprintln!("Run\n");
+            eprintln!("  libra config --global user.email \"you@example.com\"");
+            eprintln!("  libra config --global user.name \"Your Name\"\n");
+            eprintln!("to set your account's default identity.");
+            eprintln!("Omit --global to set the identity only in this repository.\n");
+            eprintln!("fatal: unable to auto-detect the identity");
+            return false;
+        }
+        if let Ok(date) = env::var(format!("{}_DATE", prefix)) {
+            if parse_date(&date).is_none() {
+                eprintln!("fatal: invalid date format: {}", date);
+                return false;
+            }
+        }
     }
+    true
 }
 
 /// update HEAD to new commit, if in branch, update branch's commit id, if detached head, update head's commit id
-async fn update_head(commit_id: &str) {
+pub async fn update_head(commit_id: &str) {
     // let head = reference::Model::current_head(db).await.unwrap();
     match Head::current().await {
         Head::Branch(name) => {
@@ -162,6 +343,29 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1700000000 +0800"), Some((1700000000, "+0800".to_string())));
+        assert_eq!(parse_date("@1700000000"), Some((1700000000, "+0000".to_string())));
+        assert_eq!(
+            parse_date("Thu, 07 Apr 2005 22:13:13 +0200"),
+            Some((1112904793, "+0200".to_string()))
+        );
+        assert_eq!(parse_date("2005-04-07T22:13:13-05:30"), Some((1112931793, "-0530".to_string())));
+        assert_eq!(parse_date("2005-04-07 22:13:13 +0200"), Some((1112904793, "+0200".to_string())));
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[tokio::test]
+    async fn test_signature_from_config() {
+        test::setup_with_new_libra().await;
+        let author = author().await;
+        assert_eq!(author.signature_type, SignatureType::Author);
+        assert_eq!(author.name, "Libra Tester");
+        assert_eq!(author.email, "tester@libra.dev");
+        assert!(check_identity().await);
+    }
+
     #[tokio::test]
     async fn test_create_tree() {
         let index = Index::from_file("../tests/data/index/index-760").unwrap();
@@ -191,6 +395,8 @@ mod test {
         let args = CommitArgs {
             message: "init".to_string(),
             allow_empty: false,
+            gpg_sign: None,
+            no_gpg_sign: false,
         };
         execute(args).await;
     }
@@ -203,6 +409,8 @@ mod test {
             let args = CommitArgs {
                 message: "init".to_string(),
                 allow_empty: true,
+                gpg_sign: None,
+                no_gpg_sign: false,
             };
             execute(args).await;
 
@@ -230,6 +438,7 @@ mod test {
                 all: true,
                 update: false,
                 verbose: false,
+                force: false,
                 pathspec: vec![],
             };
             crate::command::add::execute(args).await;
@@ -239,6 +448,8 @@ mod test {
             let args = CommitArgs {
                 message: "add some files".to_string(),
                 allow_empty: false,
+                gpg_sign: None,
+                no_gpg_sign: false,
             };
             execute(args).await;
 
@@ -0,0 +1,196 @@
+use clap::Parser;
+
+use crate::internal::config::{Config, ConfigScope};
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    /// The key of the configuration, e.g. `user.name` or `remote.origin.url`
+    #[clap(required_unless_present("list"))]
+    pub key: Option<String>,
+
+    /// The value to set, get the value of the key if not specified
+    pub value: Option<String>,
+
+    /// Use the per-user config instead of the config of the repository
+    #[clap(long, group = "scope")]
+    pub global: bool,
+
+    /// Use the config of the repository (default when writing)
+    #[clap(long, group = "scope")]
+    pub local: bool,
+
+    /// Get the value of the key, the last one if it has multiple values
+    #[clap(long, group = "action", conflicts_with = "value")]
+    pub get: bool,
+
+    /// Get all values of the multi-valued key
+    #[clap(long, group = "action", conflicts_with = "value")]
+    pub get_all: bool,
+
+    /// Add a new value to the key without replacing the existing ones
+    #[clap(long, group = "action", requires = "value")]
+    pub add: bool,
+
+    /// Remove the key, fail if it has multiple values
+    #[clap(long, group = "action", conflicts_with = "value")]
+    pub unset: bool,
+
+    /// Remove all values of the key
+    #[clap(long, group = "action", conflicts_with = "value")]
+    pub unset_all: bool,
+
+    /// List all variables set in the config, with their values
+    #[clap(short, long, group = "action", conflicts_with_all(["key", "value"]))]
+    pub list: bool,
+}
+
+pub async fn execute(args: ConfigArgs) {
+    let writing = args.value.is_some() || args.unset || args.unset_all;
+    // `None`: read both, the local config overrides the global one; write to the local config
+    let scope = if args.global {
+        Some(ConfigScope::Global)
+    } else if args.local || writing {
+        Some(ConfigScope::Local)
+    } else {
+        None
+    };
+    if scope == Some(ConfigScope::Local) && !util::check_repo_exist() {
+        return;
+    }
+
+    if args.list {
+        let scopes = match scope {
+            Some(scope) => vec![scope],
+            None if util::try_get_storage_path().is_ok() => vec![ConfigScope::Global, ConfigScope::Local],
+            None => vec![ConfigScope::Global],
+        };
+        for scope in scopes {
+            for (key, value) in Config::list(scope).await {
+                println!("{}={}", key, value);
+            }
+        }
+        return;
+    }
+
+    let key = args.key.unwrap();
+    let (configuration, name, key) = match parse_key(&key) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let name = name.as_deref();
+
+    if args.unset || args.unset_all {
+        let scope = scope.unwrap();
+        if args.unset && Config::get_all_in(scope, &configuration, name, &key).await.len() > 1 {
+            eprintln!("warning: {} has multiple values", full_key(&configuration, name, &key));
+            return;
+        }
+        Config::unset(scope, &configuration, name, &key).await;
+        return;
+    }
+
+    match args.value {
+        Some(value) => {
+            let scope = scope.unwrap();
+            let result = if args.add {
+                Config::add(scope, &configuration, name, &key, &value).await
+            } else {
+                Config::set(scope, &configuration, name, &key, &value).await
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+            }
+        }
+        None => {
+            let values = match scope {
+                Some(scope) => Config::get_all_in(scope, &configuration, name, &key).await,
+                None => Config::get_all(&configuration, name, &key).await,
+            };
+            if args.get_all {
+                values.iter().for_each(|value| println!("{}", value));
+            } else if let Some(value) = values.last() {
+                println!("{}", value);
+            }
+        }
+    }
+}
+
+/// Split the key into `(configuration, name, key)`, e.g. `remote.origin.url` => `("remote", Some("origin"), "url")`
+/// - the configuration (section) & key are case-insensitive, they are lowercased; the name is kept
+fn parse_key(key: &str) -> Result<(String, Option<String>, String), String> {
+    let (section, rest) = match key.split_once('.') {
+        Some(split) => split,
+        None => return Err(format!("error: key does not contain a section: {}", key)),
+    };
+    let (name, var) = match rest.rsplit_once('.') {
+        Some((name, var)) => (Some(name.to_string()), var),
+        None => (None, rest),
+    };
+    if section.is_empty() || var.is_empty() || name.as_deref() == Some("") {
+        return Err(format!("error: invalid key: {}", key));
+    }
+    Ok((section.to_lowercase(), name, var.to_lowercase()))
+}
+
+fn full_key(configuration: &str, name: Option<&str>, key: &str) -> String {
+    match name {
+        Some(name) => format!("{}.{}.{}", configuration, name, key),
+        None => format!("{}.{}", configuration, key),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(
+            parse_key("User.Name").unwrap(),
+            ("user".to_string(), None, "name".to_string())
+        );
+        assert_eq!(
+            parse_key("remote.Origin.url").unwrap(),
+            ("remote".to_string(), Some("Origin".to_string()), "url".to_string())
+        );
+        assert_eq!(
+            parse_key("url.https://a.b/.insteadof").unwrap().1,
+            Some("https://a.b/".to_string())
+        );
+        assert!(parse_key("name").is_err());
+        assert!(parse_key("user.").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_config_scopes() {
+        test::setup_with_new_libra().await;
+        Config::unset(ConfigScope::Global, "test", None, "scope").await;
+
+        execute(ConfigArgs::parse_from(["config", "--global", "test.scope", "global"])).await;
+        assert_eq!(Config::get("test", None, "scope").await.unwrap(), "global");
+
+        // the local value overrides the global one
+        execute(ConfigArgs::parse_from(["config", "test.scope", "local"])).await;
+        assert_eq!(Config::get("test", None, "scope").await.unwrap(), "local");
+        execute(ConfigArgs::parse_from(["config", "test.scope", "local2"])).await;
+        assert_eq!(Config::get_all_in(ConfigScope::Local, "test", None, "scope").await, ["local2"]);
+
+        execute(ConfigArgs::parse_from(["config", "--add", "test.scope", "local3"])).await;
+        // `Config::get` returns the first value
+        assert_eq!(Config::get("test", None, "scope").await.unwrap(), "local2");
+        assert_eq!(Config::get_all("test", None, "scope").await, ["local2", "local3"]);
+        // multiple values can't be unset one by one
+        execute(ConfigArgs::parse_from(["config", "--unset", "test.scope"])).await;
+        assert_eq!(Config::get_all_in(ConfigScope::Local, "test", None, "scope").await.len(), 2);
+        execute(ConfigArgs::parse_from(["config", "--unset-all", "test.scope"])).await;
+        assert_eq!(Config::get("test", None, "scope").await.unwrap(), "global");
+
+        execute(ConfigArgs::parse_from(["config", "--global", "--unset", "test.scope"])).await;
+        assert!(Config::get("test", None, "scope").await.is_none());
+    }
+}
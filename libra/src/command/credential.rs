@@ -0,0 +1,49 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use crate::internal::credential::{self, cache, Credential};
+
+#[derive(Subcommand, Debug)]
+pub enum CredentialCmds {
+    /// Read the attributes from stdin, print the credential found by the helpers or the prompt
+    Fill,
+    /// Read a credential from stdin, tell the helpers to store it
+    Approve,
+    /// Read a credential from stdin, tell the helpers to erase it
+    Reject,
+    /// Run the daemon of the `cache` helper
+    #[command(hide = true)]
+    CacheDaemon {
+        /// The socket to listen on
+        socket: Option<PathBuf>,
+    },
+}
+
+pub async fn execute(command: CredentialCmds) {
+    match command {
+        CredentialCmds::Fill => match credential::fill(&read_credential()).await {
+            Ok(credential) => print!("{}", credential.to_text()),
+            Err(e) => eprintln!("fatal: {}", e),
+        },
+        CredentialCmds::Approve => credential::approve(&read_credential()).await,
+        CredentialCmds::Reject => credential::reject(&read_credential()).await,
+        CredentialCmds::CacheDaemon { socket } => {
+            let Some(socket) = socket.or_else(cache::default_socket) else {
+                eprintln!("fatal: cannot find the socket of the credential cache");
+                return;
+            };
+            if let Err(e) = cache::daemon(socket).await {
+                eprintln!("fatal: {}", e);
+            }
+        }
+    }
+}
+
+/// The attributes on stdin, until a blank line or the end
+fn read_credential() -> Credential {
+    let mut input = String::new();
+    let _ = io::stdin().read_to_string(&mut input);
+    Credential::parse(&input)
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use colored::Colorize;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+
+use crate::command::{branch::get_target_commit, status};
+use crate::internal::head::Head;
+use crate::utils::diff::{self, DiffLine};
+use crate::utils::merge::is_binary;
+use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::utils::{path, util};
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    /// commits to compare, followed by files or dirs to limit the diff
+    /// - no commit: worktree vs index, or index vs HEAD with `--staged`
+    /// - one commit: worktree (or index with `--staged`) vs the commit
+    /// - two commits: the first commit vs the second one
+    #[clap(value_name = "COMMIT|PATH")]
+    args: Vec<String>,
+
+    /// files or dirs to limit the diff, all args after `--` are paths
+    #[clap(last = true)]
+    pathspec: Vec<String>,
+
+    /// compare the index (staged changes) instead of the worktree
+    #[clap(long, alias = "cached")]
+    staged: bool,
+
+    /// show the number of changed lines of each file instead of the patch
+    #[clap(long, group = "format")]
+    stat: bool,
+
+    /// show only names of changed files
+    #[clap(long, group = "format")]
+    name_only: bool,
+
+    /// show only names and status (A/M/D) of changed files
+    #[clap(long, group = "format")]
+    name_status: bool,
+}
+
+/// Blob & mode (e.g. `0o100644`) of each file (to workdir) in a side of the diff
+type DiffItems = HashMap<PathBuf, (SHA1, u32)>;
+
+/// A changed file, `None` means the file doesn't exist in that side
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub path: PathBuf, // to workdir
+    pub old: Option<SHA1>,
+    pub new: Option<SHA1>,
+    /// the modes, `0` if the file doesn't exist in that side
+    pub old_mode: u32,
+    pub new_mode: u32,
+}
+
+impl FileDiff {
+    fn status(&self) -> char {
+        match (self.old, self.new) {
+            (None, _) => 'A',
+            (_, None) => 'D',
+            _ => 'M',
+        }
+    }
+}
+
+/// Content of the new side, the worktree files are read from disk
+struct DiffContent {
+    from_worktree: bool,
+}
+
+impl DiffContent {
+    fn old_data(&self, hash: Option<SHA1>) -> Vec<u8> {
+        hash.map(|h| Blob::load(&h).data).unwrap_or_default()
+    }
+
+    fn new_data(&self, file: &FileDiff) -> Vec<u8> {
+        match file.new {
+            None => Vec::new(),
+            Some(_) if self.from_worktree => fs::read(util::workdir_to_absolute(&file.path)).unwrap(),
+            Some(hash) => Blob::load(&hash).data,
+        }
+    }
+}
+
+pub async fn execute(args: DiffArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+
+    // leading args that can be resolved as commits are revisions, the rest are paths
+    let mut commits = Vec::new();
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for arg in args.args.iter() {
+        if paths.is_empty() && commits.len() < 2 && !PathBuf::from(arg).exists() {
+            if let Ok(commit) = get_target_commit(arg).await {
+                commits.push(commit);
+                continue;
+            }
+        }
+        if !PathBuf::from(arg).exists() {
+            eprintln!("fatal: ambiguous argument '{}': unknown revision or path not in the working tree.", arg);
+            return;
+        }
+        paths.push(PathBuf::from(arg));
+    }
+    paths.extend(args.pathspec.iter().map(PathBuf::from));
+    if args.staged && commits.len() > 1 {
+        eprintln!("fatal: --staged accepts at most one commit");
+        return;
+    }
+
+    let index = Index::load(path::index()).unwrap();
+    let (old, new, from_worktree) = match (commits.as_slice(), args.staged) {
+        ([], true) => {
+            let head = match Head::current_commit().await {
+                Some(commit) => commit_items(&commit),
+                None => HashMap::new(),
+            };
+            (head, index_items(&index), false)
+        }
+        ([commit], true) => (commit_items(commit), index_items(&index), false),
+        ([], false) => (index_items(&index), worktree_items(index_items(&index)).await, true),
+        ([commit], false) => (commit_items(commit), worktree_items(index_items(&index)).await, true),
+        ([old, new], _) => (commit_items(old), commit_items(new), false),
+        _ => unreachable!(),
+    };
+
+    let unmerged = index.unmerged_files();
+    if !args.staged && commits.is_empty() {
+        for file in unmerged.iter() {
+            println!("* Unmerged path {}", file.display());
+        }
+    }
+    let files: Vec<FileDiff> = changed_files(&old, &new)
+        .into_iter()
+        .filter(|file| !from_worktree || !unmerged.contains(&file.path))
+        .filter(|file| paths.is_empty() || util::is_sub_of_paths(util::workdir_to_absolute(&file.path), &paths))
+        .collect();
+
+    let content = DiffContent { from_worktree };
+    let output = if args.name_only {
+        files.iter().map(|f| format!("{}\n", f.path.display())).collect()
+    } else if args.name_status {
+        files.iter().map(|f| format!("{}\t{}\n", f.status(), f.path.display())).collect()
+    } else if args.stat {
+        format_stat(&files, &content)
+    } else {
+        files.iter().map(|f| format_patch(f, &content)).collect::<String>()
+    };
+    print!("{}", output);
+}
+
+/// Changes between two commits (`None` for nothing, e.g. before a root commit),
+/// `--stat` output if `stat`, otherwise the patch
+pub fn format_commit_diff(old: Option<&SHA1>, new: &SHA1, stat: bool) -> String {
+    let old_items = old.map(commit_items).unwrap_or_default();
+    let files = changed_files(&old_items, &commit_items(new));
+    let content = DiffContent { from_worktree: false };
+    if stat {
+        format_stat(&files, &content)
+    } else {
+        files.iter().map(|f| format_patch(f, &content)).collect()
+    }
+}
+
+/// all blobs of the index (stage 0)
+fn index_items(index: &Index) -> DiffItems {
+    index
+        .tracked_entries(0)
+        .into_iter()
+        .map(|entry| (PathBuf::from(&entry.name), (entry.hash, entry.mode)))
+        .collect()
+}
+
+/// all blobs of the commit (to workdir)
+fn commit_items(commit: &SHA1) -> DiffItems {
+    let mut items = HashMap::new();
+    tree_items(&Commit::load(commit).tree_id, Path::new(""), &mut items);
+    items
+}
+
+fn tree_items(tree: &SHA1, dir: &Path, items: &mut DiffItems) {
+    for item in Tree::load(tree).tree_items {
+        let path = dir.join(&item.name);
+        match item.mode {
+            TreeItemMode::Tree => tree_items(&item.id, &path, items),
+            mode => {
+                let mode = u32::from_str_radix(&String::from_utf8_lossy(mode.to_bytes()), 8).unwrap();
+                items.insert(path, (item.id, mode));
+            }
+        }
+    }
+}
+
+/// blobs of the tracked files in the worktree, only the modified files are hashed
+async fn worktree_items(mut index_items: DiffItems) -> DiffItems {
+    let changes = status::changes_to_be_staged().await;
+    for file in changes.deleted {
+        index_items.remove(&file);
+    }
+    for file in changes.modified {
+        let hash = util::calc_file_blob_hash(util::workdir_to_absolute(&file)).unwrap();
+        index_items.insert(file, (hash, 0o100644)); // the mode is read below
+    }
+    // the mode may change without the content, e.g. `chmod +x`
+    for (file, (_, mode)) in index_items.iter_mut() {
+        *mode = status::file_mode(file).unwrap_or(*mode);
+    }
+    index_items
+}
+
+/// Compare two sides, return the changed files (content or mode) sorted by path
+pub fn changed_files(old: &DiffItems, new: &DiffItems) -> Vec<FileDiff> {
+    let paths: BTreeSet<&PathBuf> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter(|path| old.get(*path) != new.get(*path))
+        .map(|path| FileDiff {
+            path: path.clone(),
+            old: old.get(path).map(|(hash, _)| *hash),
+            new: new.get(path).map(|(hash, _)| *hash),
+            old_mode: old.get(path).map_or(0, |(_, mode)| *mode),
+            new_mode: new.get(path).map_or(0, |(_, mode)| *mode),
+        })
+        .collect()
+}
+
+fn short_hash(hash: Option<SHA1>) -> String {
+    hash.unwrap_or_default().to_plain_str()[..7].to_string()
+}
+
+/// Unified diff of a file, same format as `git diff`
+fn format_patch(file: &FileDiff, content: &DiffContent) -> String {
+    let name = file.path.display();
+    let mut out = format!("{}\n", format!("diff --git a/{} b/{}", name, name).bold());
+    let mut mode = String::new();
+    match (file.old, file.new) {
+        (None, _) => out += &format!("{}\n", format!("new file mode {:06o}", file.new_mode).bold()),
+        (_, None) => out += &format!("{}\n", format!("deleted file mode {:06o}", file.old_mode).bold()),
+        _ if file.old_mode != file.new_mode => {
+            out += &format!("{}\n", format!("old mode {:06o}", file.old_mode).bold());
+            out += &format!("{}\n", format!("new mode {:06o}", file.new_mode).bold());
+        }
+        _ => mode = format!(" {:06o}", file.new_mode),
+    }
+    if file.old == file.new {
+        return out; // only the mode is changed
+    }
+    let new_hash = match (file.new, content.from_worktree) {
+        // the worktree file is not saved as blob
+        (Some(_), true) => Some(util::calc_file_blob_hash(util::workdir_to_absolute(&file.path)).unwrap()),
+        (hash, _) => hash,
+    };
+    out += &format!("{}\n", format!("index {}..{}{}", short_hash(file.old), short_hash(new_hash), mode).bold());
+
+    let old_data = content.old_data(file.old);
+    let new_data = content.new_data(file);
+    let old_name = file.old.map_or("/dev/null".to_string(), |_| format!("a/{}", name));
+    let new_name = file.new.map_or("/dev/null".to_string(), |_| format!("b/{}", name));
+    if is_binary(&old_data) || is_binary(&new_data) {
+        out += &format!("Binary files {} and {} differ\n", old_name, new_name);
+        return out;
+    }
+    out += &format!("{}\n{}\n", format!("--- {}", old_name).bold(), format!("+++ {}", new_name).bold());
+
+    let old_lines = diff::split_lines(&old_data);
+    let new_lines = diff::split_lines(&new_data);
+    for hunk in diff::hunks(&old_lines, &new_lines, CONTEXT_LINES) {
+        out += &format!("{}\n", hunk.header().cyan());
+        for line in hunk.lines {
+            let (prefix, data) = match line {
+                DiffLine::Context(data) => (" ", data),
+                DiffLine::Delete(data) => ("-", data),
+                DiffLine::Insert(data) => ("+", data),
+            };
+            let text = format!("{}{}", prefix, String::from_utf8_lossy(data).trim_end_matches('\n'));
+            let text = match prefix {
+                "-" => text.red().to_string(),
+                "+" => text.green().to_string(),
+                _ => text,
+            };
+            out += &text;
+            out += "\n";
+            if !data.ends_with(b"\n") {
+                out += "\\ No newline at end of file\n";
+            }
+        }
+    }
+    out
+}
+
+/// `--stat` output: changed lines of each file and a summary
+fn format_stat(files: &[FileDiff], content: &DiffContent) -> String {
+    const MAX_BAR: usize = 50;
+    // (name, changes, insertions, deletions), `None` for binary files
+    let stats: Vec<(String, Option<(usize, usize)>)> = files
+        .iter()
+        .map(|file| {
+            let old_data = content.old_data(file.old);
+            let new_data = content.new_data(file);
+            let stat = if is_binary(&old_data) || is_binary(&new_data) {
+                None
+            } else {
+                Some(diff::line_stats(&diff::split_lines(&old_data), &diff::split_lines(&new_data)))
+            };
+            (file.path.display().to_string(), stat)
+        })
+        .collect();
+
+    let name_width = stats.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let max_changes = stats.iter().filter_map(|(_, s)| s.map(|(i, d)| i + d)).max().unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+    let scale = |n: usize| {
+        if max_changes <= MAX_BAR {
+            n
+        } else {
+            // keep at least one char for a non-zero count
+            (n * MAX_BAR / max_changes).max(n.min(1))
+        }
+    };
+
+    let mut out = String::new();
+    let (mut total_ins, mut total_del) = (0, 0);
+    for (name, stat) in stats.iter() {
+        match stat {
+            Some((ins, del)) => {
+                total_ins += ins;
+                total_del += del;
+                out += &format!(
+                    " {:<name_width$} | {:>count_width$} {}{}\n",
+                    name,
+                    ins + del,
+                    "+".repeat(scale(*ins)).green(),
+                    "-".repeat(scale(*del)).red(),
+                );
+            }
+            None => out += &format!(" {:<name_width$} | Bin\n", name),
+        }
+    }
+    if !files.is_empty() {
+        let plural = |n: usize, word: &str| format!("{} {}{}", n, word, if n == 1 { "" } else { "s" });
+        out += &format!(" {} changed", plural(files.len(), "file"));
+        if total_ins > 0 || total_del == 0 {
+            out += &format!(", {}(+)", plural(total_ins, "insertion"));
+        }
+        if total_del > 0 || total_ins == 0 {
+            out += &format!(", {}(-)", plural(total_del, "deletion"));
+        }
+        out += "\n";
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_changed_files() {
+        let item = |s: &str, mode: u32| (PathBuf::from(s.to_lowercase()), (SHA1::new(&s.as_bytes().to_vec()), mode));
+        let old = HashMap::from([item("a", 0o100644), item("b", 0o100644), item("d", 0o100644)]);
+        let new = HashMap::from([item("B", 0o100644), item("c", 0o100644), item("d", 0o100755)]);
+        let status: Vec<(char, PathBuf)> = changed_files(&old, &new)
+            .iter()
+            .map(|f| (f.status(), f.path.clone()))
+            .collect();
+        assert_eq!(
+            status,
+            vec![('D', "a".into()), ('M', "b".into()), ('A', "c".into()), ('M', "d".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_format_worktree_patch() {
+        test::setup_with_new_libra().await;
+        colored::control::set_override(false);
+        test::commit_file("diff.txt", "1\n2\n3\n", "init").await;
+        test::ensure_file("diff.txt", Some("1\ntwo\n3"));
+
+        let index = Index::load(path::index()).unwrap();
+        let old = HashMap::from([(PathBuf::from("diff.txt"), (index.get_hash("diff.txt", 0).unwrap(), 0o100644))]);
+        let new = worktree_items(old.clone()).await;
+        let files = changed_files(&old, &new);
+        assert_eq!(files.len(), 1);
+
+        let content = DiffContent { from_worktree: true };
+        let patch = format_patch(&files[0], &content);
+        let hunk = patch.split_once("@@ -1,3 +1,3 @@\n").unwrap().1;
+        assert_eq!(hunk, " 1\n-2\n-3\n+two\n+3\n\\ No newline at end of file\n");
+        assert!(patch.contains("--- a/diff.txt\n+++ b/diff.txt\n"));
+        assert!(patch.contains(" 100644\n"));
+
+        let stat = format_stat(&files, &content);
+        assert_eq!(stat, " diff.txt | 4 ++--\n 1 file changed, 2 insertions(+), 2 deletions(-)\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_format_mode_patch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        test::setup_with_new_libra().await;
+        colored::control::set_override(false);
+        let head = test::commit_file("run.sh", "echo 1\n", "init").await;
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+
+        // only the mode is changed
+        let index = Index::load(path::index()).unwrap();
+        let files = changed_files(&commit_items(&head), &worktree_items(index_items(&index)).await);
+        assert_eq!(files.len(), 1);
+        let patch = format_patch(&files[0], &DiffContent { from_worktree: true });
+        assert_eq!(patch, "diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n");
+
+        // the content & the mode are changed
+        let second = test::commit_file("run.sh", "echo 2\n", "exec").await;
+        let patch = format_commit_diff(Some(&head), &second, false);
+        assert!(patch.starts_with("diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\nindex "));
+        assert!(patch.contains("-echo 1\n+echo 2\n"));
+        let patch = format_commit_diff(None, &second, false);
+        assert!(patch.contains("new file mode 100755\n"));
+    }
+}
@@ -1,22 +1,29 @@
-use std::{collections::HashSet, fs, io::Write};
+use std::str::FromStr;
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use ceres::protocol::smart::read_pkt_line;
 
 use ceres::protocol::ServiceType::UploadPack;
 use clap::Parser;
 use futures::StreamExt;
 use mercury::internal::object::commit::Commit;
-use mercury::{errors::GitError, hash::SHA1};
-use url::Url;
+use mercury::hash::SHA1;
 
-use crate::command::{ask_basic_auth, load_object};
+use crate::command::{commit, load_object};
+use crate::internal::protocol::https_client::{Deepen, ShallowRequest};
+use crate::internal::shallow::{self, Shallow};
+use crate::utils::progress::Progress;
 use crate::{
-    command::index_pack::{self, IndexPackArgs},
     internal::{
         branch::Branch,
         config::{Config, RemoteConfig},
+        credential,
         head::Head,
-        protocol::{https_client::HttpsClient, ProtocolClient},
+        tag::Tag,
+        protocol::{self, RemoteClient},
     },
-    utils::{self, path_ext::PathExt},
+    utils::{self, client_storage::ClientStorage},
 };
 
 #[derive(Parser, Debug)]
@@ -26,15 +33,35 @@ pub struct FetchArgs {
 
     #[clap(long, short, group = "sub")]
     all: bool,
+
+    /// Limit fetching to the specified number of commits from the tip of each remote branch
+    #[clap(long, group = "shallow", value_parser = clap::value_parser!(u64).range(1..))]
+    depth: Option<u64>,
+
+    /// Deepen or shorten the history of a shallow repository to include all reachable commits after the date
+    #[clap(long, group = "shallow", value_name = "DATE")]
+    shallow_since: Option<String>,
+
+    /// Deepen the history of a shallow repository by the number of commits from the current shallow boundary
+    #[clap(long, group = "shallow", value_parser = clap::value_parser!(u64).range(1..))]
+    deepen: Option<u64>,
 }
 
 pub async fn execute(args: FetchArgs) {
     tracing::debug!("`fetch` args: {:?}", args);
-    tracing::warn!("didn't test yet");
+    let deepen = match parse_deepen(args.depth, args.shallow_since.as_deref(), args.deepen) {
+        Ok(deepen) => deepen,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
     if args.all {
         let remotes = Config::all_remote_configs().await;
         let tasks = remotes.into_iter().map(|remote| async move {
-            fetch_repository(&remote).await;
+            if let Err(e) = fetch_repository(&remote, deepen).await {
+                eprintln!("{}", e);
+            }
         });
         futures::future::join_all(tasks).await;
     } else {
@@ -44,7 +71,11 @@ pub async fn execute(args: FetchArgs) {
         };
         let remote_config = Config::remote_config(&remote).await;
         match remote_config {
-            Some(remote_config) => fetch_repository(&remote_config).await,
+            Some(remote_config) => {
+                if let Err(e) = fetch_repository(&remote_config, deepen).await {
+                    eprintln!("{}", e);
+                }
+            }
             None => {
                 tracing::error!("remote config '{}' not found", remote);
                 eprintln!("fatal: '{}' does not appear to be a git repository", remote);
@@ -53,61 +84,113 @@ pub async fn execute(args: FetchArgs) {
     }
 }
 
-pub async fn fetch_repository(remote_config: &RemoteConfig) {
+/// The deepen request of `--depth`, `--shallow-since` & `--deepen`, they are exclusive
+pub fn parse_deepen(depth: Option<u64>, shallow_since: Option<&str>, deepen: Option<u64>) -> Result<Option<Deepen>, String> {
+    if let Some(depth) = depth {
+        return Ok(Some(Deepen::Depth(depth as usize)));
+    }
+    if let Some(deepen) = deepen {
+        return Ok(Some(Deepen::Relative(deepen as usize)));
+    }
+    match shallow_since {
+        Some(date) => match parse_since(date) {
+            Some(timestamp) => Ok(Some(Deepen::Since(timestamp))),
+            None => Err(format!("fatal: invalid date: '{}'", date)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The date of `--shallow-since`: `YYYY-MM-DD`, relative like `2.weeks.ago` (or `2 weeks ago`),
+/// or the formats of [`commit::parse_date`]
+pub(crate) fn parse_since(date: &str) -> Option<i64> {
+    let relative = date.trim().replace(' ', ".");
+    if let Some(relative) = relative.strip_suffix(".ago") {
+        let (count, unit) = relative.split_once('.')?;
+        let count: i64 = count.parse().ok()?;
+        let unit_secs = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 24 * 3600,
+            "week" => 7 * 24 * 3600,
+            "month" => 30 * 24 * 3600,
+            "year" => 365 * 24 * 3600,
+            _ => return None,
+        };
+        return Some(chrono::Utc::now().timestamp() - count * unit_secs);
+    }
+    if let Some((timestamp, _)) = commit::parse_date(date) {
+        return Some(timestamp as i64);
+    }
+    let date = chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+}
+
+/// Fetch the refs & objects of the remote, `deepen` limits the history (shallow fetch)
+/// - the error is returned for the callers to stop, e.g. `pull` mustn't merge the stale remote branches
+pub async fn fetch_repository(remote_config: &RemoteConfig, deepen: Option<Deepen>) -> Result<(), String> {
     println!("fetching from {}", remote_config.name);
 
     // fetch remote
-    let url = match Url::parse(&remote_config.url) {
-        Ok(url) => url,
-        Err(e) => {
-            eprintln!("fatal: invalid URL '{}': {}", remote_config.url, e);
-            return;
-        }
+    let client = protocol::parse_url(&remote_config.url)
+        .and_then(|url| RemoteClient::from_url(&url))
+        .map_err(|e| e.to_string())?;
+
+    let (refs, auth) = match credential::with_credential(&remote_config.url, |auth| {
+        client.discovery_reference(UploadPack, auth)
+    })
+    .await
+    {
+        Ok(refs) => refs,
+        Err(e) => return Err(format!("fatal: {}", e)),
     };
-    let http_client = HttpsClient::from_url(&url);
-
-    let mut refs = http_client.discovery_reference(UploadPack, None).await;
-    let mut auth = None;
-    while let Err(e) = refs {
-        if let GitError::UnAuthorized(_) = e {
-            auth = Some(ask_basic_auth());
-            refs = http_client
-                .discovery_reference(UploadPack, auth.clone())
-                .await;
-        } else {
-            eprintln!("fatal: {}", e);
-            return;
-        }
-    }
-    let refs = refs.unwrap();
     if refs.is_empty() {
         tracing::warn!("fetch empty, no refs found");
-        return;
+        return Ok(());
     }
 
+    // peeled tags (`refs/tags/v1^{}`) point to the same objects of annotated tags
+    let tags: Vec<_> = refs
+        .iter()
+        .filter(|r| r._ref.starts_with("refs/tags/") && !r._ref.ends_with("^{}"))
+        .collect();
     let want = refs
         .iter()
         .filter(|r| r._ref.starts_with("refs/heads"))
+        .chain(tags.iter().copied())
         .map(|r| r._hash.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
         .collect();
-    let have = current_have().await;
+    let shallow = Shallow::list().await;
+    let have = current_have(&shallow).await;
+    let shallow_request = ShallowRequest {
+        shallow: shallow.iter().map(|id| id.to_plain_str()).collect(),
+        deepen,
+    };
 
-    let mut result_stream = http_client
-        .fetch_objects(&have, &want, auth.to_owned())
+    let mut result_stream = client
+        .fetch_objects(&have, &want, &shallow_request, auth.to_owned())
         .await
-        .unwrap();
+        .map_err(|e| format!("fatal: {}", e))?;
 
     let mut buffer = vec![];
+    let progress = Progress::new("Receiving objects", None);
     while let Some(item) = result_stream.next().await {
-        let item = item.unwrap();
+        let item = item.map_err(|e| format!("fatal: {}", e))?;
+        progress.add_bytes(item.len());
         buffer.extend(item);
     }
+    progress.finish();
 
     // pase pkt line
+    let shallow_update;
     if let Some(pack_pos) = buffer.windows(4).position(|w| w == b"PACK") {
         tracing::info!("pack data found at: {}", pack_pos);
         let readable_output = std::str::from_utf8(&buffer[..pack_pos]).unwrap();
         tracing::debug!("stdout readable: \n{}", readable_output);
+        shallow_update = parse_shallow_update(&buffer[..pack_pos]);
         tracing::info!("pack length: {}", buffer.len() - pack_pos);
         assert!(buffer[pack_pos..pack_pos + 4].eq(b"PACK"));
 
@@ -115,35 +198,19 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
     } else {
         tracing::error!(
             "no pack data found, stdout is: \n{}",
-            std::str::from_utf8(&buffer).unwrap()
+            String::from_utf8_lossy(&buffer)
         );
-        panic!("no pack data found");
+        return Err("fatal: no pack data found in the response of the remote".to_string());
     }
 
-    /* save pack file */
-    let pack_file = {
-        let hash = SHA1::new(&buffer[..buffer.len() - 20].to_vec());
+    /* save pack file & build its .idx, nothing to save if the pack has no objects */
+    ClientStorage::init(utils::path::objects())
+        .save_pack(&buffer)
+        .map_err(|e| format!("fatal: cannot save the pack: {}", e))?;
 
-        let checksum = SHA1::from_bytes(&buffer[buffer.len() - 20..]);
-        assert_eq!(hash, checksum);
-        let checksum = checksum.to_plain_str();
-        println!("checksum: {}", checksum);
-
-        let pack_file = utils::path::objects()
-            .join("pack")
-            .join(format!("pack-{}.pack", checksum));
-        let mut file = fs::File::create(pack_file.clone()).unwrap();
-        file.write_all(&buffer).expect("write failed");
-
-        pack_file.to_string_or_panic()
-    };
-
-    /* build .idx file from PACK */
-    index_pack::execute(IndexPackArgs {
-        pack_file,
-        index_file: None,
-        index_version: None,
-    });
+    /* record the new shallow boundary, the parents of these commits are not fetched */
+    let (shallow, unshallow) = shallow_update;
+    Shallow::update(&shallow, &unshallow).await;
 
     /* update reference  */
     for reference in refs.iter().filter(|r| r._ref.starts_with("refs/heads")) {
@@ -151,6 +218,19 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
         let remote = Some(remote_config.name.as_str());
         Branch::update_branch(&branch_name, &reference._hash, remote).await;
     }
+    for reference in tags {
+        let tag_name = reference._ref.replace("refs/tags/", "");
+        match Tag::find_tag(&tag_name).await {
+            None => {
+                Tag::update_tag(&tag_name, &reference._hash).await;
+                println!(" * [new tag]         {} -> {}", tag_name, tag_name);
+            }
+            Some(tag) if tag.object.to_plain_str() != reference._hash => {
+                println!(" ! [rejected]        {} -> {} (would clobber existing tag)", tag_name, tag_name);
+            }
+            _ => {}
+        }
+    }
     let remote_head = refs.iter().find(|r| r._ref == "HEAD");
     match remote_head {
         Some(remote_head) => {
@@ -172,9 +252,31 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
             tracing::warn!("fetch empty, remote HEAD not found");
         }
     }
+    Ok(())
+}
+
+/// The `shallow` & `unshallow` lines before the pack
+fn parse_shallow_update(preamble: &[u8]) -> (Vec<SHA1>, Vec<SHA1>) {
+    let mut preamble = Bytes::copy_from_slice(preamble);
+    let (mut shallow, mut unshallow) = (Vec::new(), Vec::new());
+    while !preamble.is_empty() {
+        let (len, line) = read_pkt_line(&mut preamble);
+        if len == 0 {
+            continue; // flush-pkt after the shallow update
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        if let Some(id) = line.strip_prefix("shallow ") {
+            shallow.extend(SHA1::from_str(id).ok());
+        } else if let Some(id) = line.strip_prefix("unshallow ") {
+            unshallow.extend(SHA1::from_str(id).ok());
+        }
+    }
+    (shallow, unshallow)
 }
 
-async fn current_have() -> Vec<String> {
+/// The recent commits of the local & remote-tracking branches, the history stops at the `shallow` commits
+async fn current_have(shallow: &HashSet<SHA1>) -> Vec<String> {
     #[derive(PartialEq, Eq, PartialOrd, Ord)]
     struct QueueItem {
         priority: usize,
@@ -215,7 +317,7 @@ async fn current_have() -> Vec<String> {
         have.push(item.commit.to_plain_str());
 
         let commit: Commit = load_object(&item.commit).unwrap();
-        for parent in commit.parent_commit_ids {
+        for parent in shallow::parents(&commit, shallow) {
             let parent: Commit = load_object(&parent).unwrap();
             check_and_insert(&parent, &mut inserted, &mut c_pending);
         }
@@ -223,3 +325,76 @@ async fn current_have() -> Vec<String> {
 
     have
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::utils::{test, util};
+
+    #[test]
+    fn test_parse_deepen() {
+        assert_eq!(parse_deepen(Some(2), None, None).unwrap(), Some(Deepen::Depth(2)));
+        assert_eq!(parse_deepen(None, None, Some(3)).unwrap(), Some(Deepen::Relative(3)));
+        assert_eq!(
+            parse_deepen(None, Some("2024-01-02"), None).unwrap(),
+            Some(Deepen::Since(1704153600))
+        );
+        assert_eq!(
+            parse_deepen(None, Some("2024-01-02T00:00:00+01:00"), None).unwrap(),
+            Some(Deepen::Since(1704150000))
+        );
+        assert!(parse_deepen(None, Some("yesterday-ish"), None).is_err());
+        assert_eq!(parse_deepen(None, None, None).unwrap(), None);
+        assert!(FetchArgs::try_parse_from(["fetch", "--depth", "0"]).is_err());
+        assert!(FetchArgs::try_parse_from(["fetch", "--depth", "1", "--deepen", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_shallow_update() {
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let preamble = format!("0035shallow {}\n0037unshallow {}\n00000008NAK\n", a, b);
+        let (shallow, unshallow) = parse_shallow_update(preamble.as_bytes());
+        assert_eq!(shallow, vec![SHA1::from_str(&a).unwrap()]);
+        assert_eq!(unshallow, vec![SHA1::from_str(&b).unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_shallow_fetch() {
+        test::setup_with_new_libra().await;
+        let mut commits = Vec::new();
+        for content in ["1\n", "2\n", "3\n"] {
+            commits.push(test::commit_file("a.txt", content, content.trim()).await);
+        }
+        // the repository is copied as the remote, the test repository is re-created
+        let remote = std::env::temp_dir().join("libra_test_shallow_remote");
+        let _ = fs::remove_dir_all(&remote);
+        test::copy_dir(&util::storage_path(), &remote.join(utils::util::ROOT_DIR));
+        test::setup_with_new_libra().await;
+        let remote_config = RemoteConfig {
+            name: "origin".to_string(),
+            url: remote.to_str().unwrap().to_string(),
+        };
+        Config::insert("remote", Some("origin"), "url", &remote_config.url).await;
+        let storage = util::objects_storage();
+
+        fetch_repository(&remote_config, Some(Deepen::Depth(1))).await.unwrap();
+        assert_eq!(Shallow::list().await, HashSet::from([commits[2]]));
+        assert_eq!(Branch::find_branch("master", Some("origin")).await.unwrap().commit, commits[2]);
+        assert!(!storage.exist(&commits[1]));
+
+        fetch_repository(&remote_config, Some(Deepen::Relative(1))).await.unwrap();
+        assert_eq!(Shallow::list().await, HashSet::from([commits[1]]));
+        assert!(storage.exist(&commits[1]) && !storage.exist(&commits[0]));
+
+        // a normal fetch keeps the boundary
+        fetch_repository(&remote_config, None).await.unwrap();
+        assert_eq!(Shallow::list().await, HashSet::from([commits[1]]));
+
+        fetch_repository(&remote_config, Some(Deepen::Depth(10))).await.unwrap();
+        assert!(Shallow::list().await.is_empty());
+        assert!(storage.exist(&commits[0]));
+    }
+}
@@ -0,0 +1,913 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::pack::utils::calculate_object_hash;
+use sha1::{Digest, Sha1};
+
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::reachable;
+use crate::internal::shallow::Shallow;
+use crate::internal::tag::Tag;
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::path;
+use crate::utils::progress::Progress;
+use crate::utils::util;
+
+/// The mode of a submodule entry (gitlink), the commit is not in this repository
+const GITLINK_MODE: u32 = 0o160000;
+
+#[derive(Parser, Debug)]
+pub struct FsckArgs {
+    /// Print all unreachable objects, instead of only the dangling ones (not referenced by any other object)
+    #[clap(long)]
+    pub unreachable: bool,
+    /// Do not print the dangling objects
+    #[clap(long)]
+    pub no_dangling: bool,
+    /// Only check that the reachable objects are present, without verifying their hashes & content
+    #[clap(long)]
+    pub connectivity_only: bool,
+}
+
+pub async fn execute(args: FsckArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let report = fsck(&args).await;
+    for error in report.errors {
+        eprintln!("{}", error);
+    }
+    for line in report.unreachable {
+        println!("{}", line);
+    }
+}
+
+/// What `fsck` found: the problems for stderr, the unreachable (or dangling) objects for stdout
+#[derive(Default, Debug)]
+struct Report {
+    errors: Vec<String>,
+    unreachable: Vec<String>,
+}
+
+/// An object that passed the checks, with the objects it refers to & their expected types
+struct Object {
+    obj_type: ObjectType,
+    links: Vec<Link>,
+}
+
+type Link = (SHA1, ObjectType);
+
+/// A problem in the content of an object, named by the message id of `git fsck`, e.g. `treeNotSorted`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Problem {
+    error: bool,
+    id: &'static str,
+    message: &'static str,
+}
+
+impl Problem {
+    const fn error(id: &'static str, message: &'static str) -> Self {
+        Problem {
+            error: true,
+            id,
+            message,
+        }
+    }
+
+    const fn warning(id: &'static str, message: &'static str) -> Self {
+        Problem {
+            error: false,
+            id,
+            message,
+        }
+    }
+}
+
+async fn fsck(args: &FsckArgs) -> Report {
+    let storage = util::objects_storage();
+    let mut report = Report::default();
+    let objects = check_objects(&storage, args, &mut report);
+
+    let index_ok = check_index(&mut report);
+    let mut roots = Vec::new();
+    for (name, id) in refs(&mut report).await {
+        match objects.contains_key(&id) {
+            true => roots.push(id),
+            false => report.errors.push(format!(
+                "error: {}: invalid sha1 pointer {}",
+                name,
+                id.to_plain_str()
+            )),
+        }
+    }
+    if index_ok {
+        match reachable::roots(&storage).await {
+            Ok(extra) => roots.extend(extra),
+            Err(e) => report.errors.push(format!("error: {}", e)),
+        }
+        if let Ok(index) = Index::load(path::index()) {
+            for entry in (0..=3).flat_map(|stage| index.tracked_entries(stage)) {
+                if !objects.contains_key(&entry.hash) && entry.mode != GITLINK_MODE {
+                    report.errors.push(format!(
+                        "error: {}: invalid sha1 pointer in index",
+                        entry.name
+                    ));
+                }
+            }
+        }
+    }
+
+    let reachable = check_connectivity(&objects, &roots, &Shallow::list().await, &mut report);
+    report_unreachable(&objects, &reachable, args, &mut report);
+    report
+}
+
+/// Verify & parse all loose and packed objects, return the good ones
+fn check_objects(
+    storage: &ClientStorage,
+    args: &FsckArgs,
+    report: &mut Report,
+) -> HashMap<SHA1, Object> {
+    let loose = storage.list_objects_loose();
+    let packs = match storage.packs() {
+        Ok(packs) => Some(packs),
+        Err(e) => {
+            report.errors.push(format!("error: {}", e));
+            None
+        }
+    };
+    let packed = match packs.as_ref().map(|packs| packs.objects()) {
+        Some(Ok(packed)) => packed,
+        Some(Err(e)) => {
+            report.errors.push(format!("error: {}", e));
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    let progress = Progress::new("Checking objects", Some(loose.len() + packed.len()));
+    let mut objects = HashMap::new();
+
+    for id in loose {
+        progress.inc(1);
+        let Some((obj_type, data)) = read_loose(storage, &id, report) else {
+            continue;
+        };
+        if !args.connectivity_only && calculate_object_hash(obj_type, &data) != id {
+            report.errors.push(format!(
+                "error: hash mismatch for loose object {}",
+                id.to_plain_str()
+            ));
+            continue;
+        }
+        objects.insert(id, check_content(&id, obj_type, &data, args, report));
+    }
+
+    if let Some(packs) = packs {
+        if !args.connectivity_only {
+            report.errors.extend(
+                packs
+                    .verify_checksums()
+                    .into_iter()
+                    .map(|e| format!("error: {}", e)),
+            );
+        }
+        for (location, id) in packed {
+            progress.inc(1);
+            if objects.contains_key(&id) {
+                continue; // already checked as a loose object
+            }
+            if args.connectivity_only
+                && packs.type_at(location).is_ok_and(|t| t == ObjectType::Blob)
+            {
+                objects.insert(
+                    id,
+                    Object {
+                        obj_type: ObjectType::Blob,
+                        links: Vec::new(),
+                    },
+                );
+                continue; // no need to read the content
+            }
+            let (data, obj_type) = match packs.read_at(location) {
+                Ok(object) => object,
+                Err(e) => {
+                    report.errors.push(format!(
+                        "error: {}: object corrupt or missing: {}",
+                        id.to_plain_str(),
+                        e
+                    ));
+                    continue;
+                }
+            };
+            if !args.connectivity_only && calculate_object_hash(obj_type, &data) != id {
+                report.errors.push(format!(
+                    "error: hash mismatch for packed object {}",
+                    id.to_plain_str()
+                ));
+                continue;
+            }
+            objects.insert(id, check_content(&id, obj_type, &data, args, report));
+        }
+    }
+    progress.finish();
+    objects
+}
+
+/// Read a loose object & check its header `<type> <size>\0`
+fn read_loose(
+    storage: &ClientStorage,
+    id: &SHA1,
+    report: &mut Report,
+) -> Option<(ObjectType, Vec<u8>)> {
+    let corrupt = |report: &mut Report, reason: &str| {
+        report.errors.push(format!(
+            "error: {}: object corrupt or missing: {}",
+            id.to_plain_str(),
+            reason
+        ));
+    };
+    let mut raw = match storage.read_loose_raw(id) {
+        Ok(raw) => raw,
+        Err(e) => {
+            corrupt(report, &e.to_string());
+            return None;
+        }
+    };
+    let header = raw
+        .iter()
+        .position(|&b| b == 0)
+        .and_then(|nul| std::str::from_utf8(&raw[..nul]).ok())
+        .and_then(|header| header.split_once(' '))
+        .and_then(|(t, size)| {
+            Some((
+                ObjectType::from_string(t).ok()?,
+                size.parse::<usize>().ok()?,
+                t.len() + size.len() + 2,
+            ))
+        });
+    let Some((obj_type, size, header_len)) = header else {
+        corrupt(report, "bad header");
+        return None;
+    };
+    if raw.len() - header_len != size {
+        corrupt(report, "size mismatch");
+        return None;
+    }
+    raw.drain(..header_len);
+    Some((obj_type, raw))
+}
+
+/// Check the content of a commit, tree or tag, report the problems
+fn check_content(
+    id: &SHA1,
+    obj_type: ObjectType,
+    data: &[u8],
+    args: &FsckArgs,
+    report: &mut Report,
+) -> Object {
+    let (links, problems) = match obj_type {
+        ObjectType::Commit => check_commit(data),
+        ObjectType::Tree => check_tree(data),
+        ObjectType::Tag => check_tag(data),
+        _ => (Vec::new(), Vec::new()),
+    };
+    if !args.connectivity_only {
+        for problem in problems {
+            let severity = if problem.error { "error" } else { "warning" };
+            report.errors.push(format!(
+                "{} in {} {}: {}: {}",
+                severity,
+                obj_type,
+                id.to_plain_str(),
+                problem.id,
+                problem.message
+            ));
+        }
+    }
+    Object { obj_type, links }
+}
+
+/// Parse a tree entry by entry like Git: `<mode> <name>\0<20-byte hash>`, each problem is reported once
+fn check_tree(data: &[u8]) -> (Vec<Link>, Vec<Problem>) {
+    let mut links = Vec::new();
+    let mut problems = Vec::new();
+    let mut add = |problem: Problem| {
+        if !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    };
+    let mut names = HashSet::new();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ');
+        let nul = rest.iter().position(|&b| b == 0);
+        let (Some(space), Some(nul)) = (space, nul) else {
+            add(Problem::error("badTree", "cannot be parsed as a tree"));
+            break;
+        };
+        let mode = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|m| u32::from_str_radix(m, 8).ok());
+        let (Some(mode), true) = (mode, space < nul && nul + 21 <= rest.len()) else {
+            add(Problem::error("badTree", "cannot be parsed as a tree"));
+            break;
+        };
+        let (mode_str, name) = (&rest[..space], &rest[space + 1..nul]);
+        let id = SHA1::from_bytes(&rest[nul + 1..nul + 21]);
+        rest = &rest[nul + 21..];
+
+        if mode_str.len() > 1 && mode_str[0] == b'0' {
+            add(Problem::warning(
+                "zeroPaddedFilemode",
+                "contains zero-padded file modes",
+            ));
+        }
+        // 100664 was written by the old versions of Git, it's accepted unless strict
+        if ![
+            0o100644,
+            0o100755,
+            0o100664,
+            0o120000,
+            0o040000,
+            GITLINK_MODE,
+        ]
+        .contains(&mode)
+        {
+            add(Problem::warning("badFilemode", "contains bad file modes"));
+        }
+        let is_dir = mode & 0o170000 == 0o040000;
+        match mode & 0o170000 {
+            0o040000 => links.push((id, ObjectType::Tree)),
+            GITLINK_MODE => {} // submodule, not in this repository
+            _ => links.push((id, ObjectType::Blob)),
+        }
+
+        match name {
+            b"" => add(Problem::warning("emptyName", "contains empty pathname")),
+            b"." => add(Problem::warning("hasDot", "contains '.'")),
+            b".." => add(Problem::warning("hasDotdot", "contains '..'")),
+            _ if name.eq_ignore_ascii_case(b".git") => {
+                add(Problem::warning("hasDotgit", "contains '.git'"))
+            }
+            _ if name.contains(&b'/') => {
+                add(Problem::warning("fullPathname", "contains full pathnames"))
+            }
+            _ => {}
+        }
+        if id == SHA1::default() {
+            add(Problem::warning(
+                "nullSha1",
+                "contains entries pointing to null sha1",
+            ));
+        }
+
+        // the directories are sorted as if their names end with `/`
+        let mut key = name.to_vec();
+        if is_dir {
+            key.push(b'/');
+        }
+        if !names.insert(name) {
+            add(Problem::error(
+                "duplicateEntries",
+                "contains duplicate file entries",
+            ));
+        } else if previous.as_ref().is_some_and(|previous| *previous > key) {
+            add(Problem::error("treeNotSorted", "not properly sorted"));
+        }
+        previous = Some(key);
+    }
+    (links, problems)
+}
+
+/// Check the header of a commit: `tree`, `parent`s, `author` & `committer`; stop at the first problem like Git
+fn check_commit(data: &[u8]) -> (Vec<Link>, Vec<Problem>) {
+    let mut links = Vec::new();
+    let problem = check_commit_header(data, &mut links).err();
+    (links, problem.into_iter().collect())
+}
+
+fn check_commit_header(data: &[u8], links: &mut Vec<Link>) -> Result<(), Problem> {
+    let mut lines = header_lines(data);
+    let mut line = lines.next();
+    let tree = line
+        .and_then(|l| l.strip_prefix(b"tree "))
+        .ok_or(Problem::error(
+            "missingTree",
+            "invalid format - expected 'tree' line",
+        ))?;
+    let tree = parse_hex(tree).ok_or(Problem::error(
+        "badTreeSha1",
+        "invalid 'tree' line format - bad sha1",
+    ))?;
+    links.push((tree, ObjectType::Tree));
+
+    line = lines.next();
+    while let Some(parent) = line.and_then(|l| l.strip_prefix(b"parent ")) {
+        let parent = parse_hex(parent).ok_or(Problem::error(
+            "badParentSha1",
+            "invalid 'parent' line format - bad sha1",
+        ))?;
+        links.push((parent, ObjectType::Commit));
+        line = lines.next();
+    }
+
+    let author = line
+        .and_then(|l| l.strip_prefix(b"author "))
+        .ok_or(Problem::error(
+            "missingAuthor",
+            "invalid format - expected 'author' line",
+        ))?;
+    check_ident(author)?;
+    line = lines.next();
+    if line.is_some_and(|l| l.starts_with(b"author ")) {
+        return Err(Problem::error(
+            "multipleAuthors",
+            "invalid format - multiple 'author' lines",
+        ));
+    }
+    let committer = line
+        .and_then(|l| l.strip_prefix(b"committer "))
+        .ok_or(Problem::error(
+            "missingCommitter",
+            "invalid format - expected 'committer' line",
+        ))?;
+    check_ident(committer)
+}
+
+/// Check the header of a tag: `object`, `type`, `tag` & the optional `tagger`
+fn check_tag(data: &[u8]) -> (Vec<Link>, Vec<Problem>) {
+    let mut links = Vec::new();
+    let problem = check_tag_header(data, &mut links).err();
+    (links, problem.into_iter().collect())
+}
+
+fn check_tag_header(data: &[u8], links: &mut Vec<Link>) -> Result<(), Problem> {
+    let mut lines = header_lines(data);
+    let object = lines
+        .next()
+        .and_then(|l| l.strip_prefix(b"object "))
+        .ok_or(Problem::error(
+            "missingObject",
+            "invalid format - expected 'object' line",
+        ))?;
+    let object = parse_hex(object).ok_or(Problem::error(
+        "badObjectSha1",
+        "invalid 'object' line format - bad sha1",
+    ))?;
+    let obj_type = lines
+        .next()
+        .and_then(|l| l.strip_prefix(b"type "))
+        .ok_or(Problem::error(
+            "missingTypeEntry",
+            "invalid format - expected 'type' line",
+        ))?;
+    let obj_type = std::str::from_utf8(obj_type)
+        .ok()
+        .and_then(|t| ObjectType::from_string(t).ok())
+        .ok_or(Problem::error("badType", "invalid 'type' value"))?;
+    links.push((object, obj_type));
+    lines
+        .next()
+        .filter(|l| l.starts_with(b"tag "))
+        .ok_or(Problem::error(
+            "missingTagEntry",
+            "invalid format - expected 'tag' line",
+        ))?;
+    match lines.next().and_then(|l| l.strip_prefix(b"tagger ")) {
+        Some(tagger) => check_ident(tagger),
+        None => Ok(()), // the old tags have no tagger
+    }
+}
+
+/// The header lines of a commit or tag, until the empty line before the message
+fn header_lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty())
+}
+
+fn parse_hex(hex: &[u8]) -> Option<SHA1> {
+    if hex.len() != 40 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    SHA1::from_str(std::str::from_utf8(hex).ok()?).ok()
+}
+
+/// Check an identity line like Git: `Name <email> 1234567890 +0800`
+fn check_ident(ident: &[u8]) -> Result<(), Problem> {
+    macro_rules! problem {
+        ($id:literal, $reason:literal) => {
+            Problem::error($id, concat!("invalid author/committer line - ", $reason))
+        };
+    }
+    if ident.first() == Some(&b'<') {
+        return Err(problem!(
+            "missingNameBeforeEmail",
+            "missing name before email"
+        ));
+    }
+    let open = ident.iter().position(|&b| b == b'<' || b == b'>');
+    let open = match open {
+        None => return Err(problem!("missingEmail", "missing email")),
+        Some(pos) if ident[pos] == b'>' => return Err(problem!("badName", "bad name")),
+        Some(pos) => pos,
+    };
+    if ident[open - 1] != b' ' {
+        return Err(problem!(
+            "missingSpaceBeforeEmail",
+            "missing space before email"
+        ));
+    }
+    let rest = &ident[open + 1..];
+    let close = rest.iter().position(|&b| b == b'<' || b == b'>');
+    let close = close
+        .filter(|&pos| rest[pos] == b'>')
+        .ok_or(problem!("badEmail", "bad email"))?;
+    let date = rest[close + 1..].strip_prefix(b" ").ok_or(problem!(
+        "missingSpaceBeforeDate",
+        "missing space before date"
+    ))?;
+    if date.first() == Some(&b'0') && date.get(1) != Some(&b' ') {
+        return Err(problem!("zeroPaddedDate", "zero-padded date"));
+    }
+    let digits = date.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 || date.get(digits) != Some(&b' ') {
+        return Err(problem!("badDate", "bad date"));
+    }
+    if std::str::from_utf8(&date[..digits])
+        .unwrap()
+        .parse::<u64>()
+        .is_err()
+    {
+        return Err(problem!("badDateOverflow", "date causes integer overflow"));
+    }
+    let zone = &date[digits + 1..];
+    if zone.len() != 5
+        || !matches!(zone[0], b'+' | b'-')
+        || !zone[1..].iter().all(u8::is_ascii_digit)
+    {
+        return Err(problem!("badTimezone", "bad time zone"));
+    }
+    Ok(())
+}
+
+/// The refs to check: a detached `HEAD`, the local & remote branches and the tags
+async fn refs(report: &mut Report) -> Vec<(String, SHA1)> {
+    let mut refs = Vec::new();
+    match Head::current().await {
+        Head::Detached(commit) => refs.push(("HEAD".to_string(), commit)),
+        Head::Branch(name) => {
+            if Branch::find_branch(&name, None).await.is_none() {
+                report.errors.push(format!(
+                    "notice: HEAD points to an unborn branch ({})",
+                    name
+                ));
+            }
+        }
+    }
+    for branch in Branch::list_branches(None).await {
+        refs.push((format!("refs/heads/{}", branch.name), branch.commit));
+    }
+    for remote in Config::all_remote_configs().await {
+        for branch in Branch::list_branches(Some(&remote.name)).await {
+            refs.push((
+                format!("refs/remotes/{}/{}", remote.name, branch.name),
+                branch.commit,
+            ));
+        }
+    }
+    for tag in Tag::list_tags().await {
+        refs.push((format!("refs/tags/{}", tag.name), tag.object));
+    }
+    refs
+}
+
+/// Verify the trailing checksum of the index file, which is the SHA-1 of the rest
+fn check_index(report: &mut Report) -> bool {
+    let Ok(data) = fs::read(path::index()) else {
+        return true; // no index yet
+    };
+    let ok =
+        data.len() >= 32 && Sha1::digest(&data[..data.len() - 20])[..] == data[data.len() - 20..];
+    if !ok {
+        report
+            .errors
+            .push("error: bad index file sha1 signature".to_string());
+        report.errors.push("fatal: index file corrupt".to_string());
+    }
+    ok
+}
+
+/// Walk the objects from the `roots` & report the broken links, return the reachable objects
+fn check_connectivity(
+    objects: &HashMap<SHA1, Object>,
+    roots: &[SHA1],
+    shallow: &HashSet<SHA1>,
+    report: &mut Report,
+) -> HashSet<SHA1> {
+    let mut reachable = HashSet::new();
+    let mut missing = BTreeSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(id) = stack.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        let Some(object) = objects.get(&id) else {
+            continue;
+        };
+        for &(link, expected) in &object.links {
+            let is_parent = object.obj_type == ObjectType::Commit && expected == ObjectType::Commit;
+            if is_parent && shallow.contains(&id) {
+                continue; // the history is cut here
+            }
+            match objects.get(&link) {
+                None => {
+                    report.errors.push(format!(
+                        "broken link from {:>7} {}\n              to {:>7} {}",
+                        object.obj_type.to_string(),
+                        id.to_plain_str(),
+                        expected.to_string(),
+                        link.to_plain_str()
+                    ));
+                    missing.insert((link.to_plain_str(), expected.to_string()));
+                }
+                Some(target) if target.obj_type != expected => {
+                    report.errors.push(format!(
+                        "error: object {} is a {}, not a {}",
+                        link.to_plain_str(),
+                        target.obj_type,
+                        expected
+                    ));
+                }
+                Some(_) => stack.push(link),
+            }
+        }
+    }
+    for (id, obj_type) in missing {
+        report.errors.push(format!("missing {} {}", obj_type, id));
+    }
+    reachable
+}
+
+/// List the unreachable objects, or only the dangling ones: not referenced by other unreachable objects
+fn report_unreachable(
+    objects: &HashMap<SHA1, Object>,
+    reachable: &HashSet<SHA1>,
+    args: &FsckArgs,
+    report: &mut Report,
+) {
+    let mut unreachable: Vec<_> = objects
+        .iter()
+        .filter(|(id, _)| !reachable.contains(id))
+        .collect();
+    unreachable.sort_by_key(|(id, _)| id.to_plain_str());
+    if args.unreachable {
+        for (id, object) in unreachable {
+            report.unreachable.push(format!(
+                "unreachable {} {}",
+                object.obj_type,
+                id.to_plain_str()
+            ));
+        }
+    } else if !args.no_dangling {
+        let referenced: HashSet<_> = unreachable
+            .iter()
+            .flat_map(|(_, object)| object.links.iter().map(|(link, _)| *link))
+            .collect();
+        for (id, object) in unreachable
+            .into_iter()
+            .filter(|(id, _)| !referenced.contains(id))
+        {
+            report.unreachable.push(format!(
+                "dangling {} {}",
+                object.obj_type,
+                id.to_plain_str()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::object::commit::Commit;
+    use mercury::internal::object::ObjectTrait;
+
+    use super::*;
+    use crate::command::repack::{self, RepackArgs};
+    use crate::utils::test;
+
+    const IDENT: &[u8] = b"A U Thor <author@example.com> 1234567890 +0800";
+
+    fn tree_entry(mode: &str, name: &str, id: u8) -> Vec<u8> {
+        let mut entry = format!("{} {}\0", mode, name).into_bytes();
+        entry.extend([id; 20]);
+        entry
+    }
+
+    fn problem_ids(problems: Vec<Problem>) -> Vec<&'static str> {
+        problems.into_iter().map(|p| p.id).collect()
+    }
+
+    #[test]
+    fn test_check_tree() {
+        let good = [
+            tree_entry("100644", "a-b", 1),
+            tree_entry("100644", "a.txt", 2),
+            tree_entry("40000", "a", 3),
+        ]
+        .concat();
+        let (links, problems) = check_tree(&good);
+        assert!(problems.is_empty());
+        assert_eq!(links[2], (SHA1::from_bytes(&[3; 20]), ObjectType::Tree));
+
+        let unsorted = [tree_entry("100644", "b", 1), tree_entry("100644", "a", 2)].concat();
+        assert_eq!(problem_ids(check_tree(&unsorted).1), vec!["treeNotSorted"]);
+        let duplicate = [tree_entry("100644", "a", 1), tree_entry("40000", "a", 2)].concat();
+        assert_eq!(
+            problem_ids(check_tree(&duplicate).1),
+            vec!["duplicateEntries"]
+        );
+        let bad_names = [
+            tree_entry("100644", ".git", 1),
+            tree_entry("040000", "b", 2),
+            tree_entry("100600", "c/d", 3),
+        ];
+        assert_eq!(
+            problem_ids(check_tree(&bad_names.concat()).1),
+            vec![
+                "hasDotgit",
+                "zeroPaddedFilemode",
+                "badFilemode",
+                "fullPathname"
+            ]
+        );
+        let gitlink = tree_entry("160000", "sub", 1);
+        assert!(check_tree(&gitlink).0.is_empty());
+        assert_eq!(problem_ids(check_tree(&gitlink[..10]).1), vec!["badTree"]);
+        assert_eq!(
+            problem_ids(check_tree(&tree_entry("10x644", "a", 1)).1),
+            vec!["badTree"]
+        );
+    }
+
+    #[test]
+    fn test_check_ident() {
+        assert!(check_ident(IDENT).is_ok());
+        let cases: [(&[u8], &str); 8] = [
+            (b"<a@b.c> 1 +0000", "missingNameBeforeEmail"),
+            (b"A U Thor 1 +0000", "missingEmail"),
+            (b"A U Thor> 1 +0000", "badName"),
+            (b"A U Thor<a@b.c> 1 +0000", "missingSpaceBeforeEmail"),
+            (b"A U Thor <a@b.c>1 +0000", "missingSpaceBeforeDate"),
+            (b"A U Thor <a@b.c> 01 +0000", "zeroPaddedDate"),
+            (b"A U Thor <a@b.c> x +0000", "badDate"),
+            (b"A U Thor <a@b.c> 1 0800", "badTimezone"),
+        ];
+        for (ident, id) in cases {
+            assert_eq!(check_ident(ident).unwrap_err().id, id);
+        }
+    }
+
+    #[test]
+    fn test_check_commit_and_tag() {
+        let hex = "1".repeat(40);
+        let ident = String::from_utf8(IDENT.to_vec()).unwrap();
+        let commit =
+            format!("tree {hex}\nparent {hex}\nauthor {ident}\ncommitter {ident}\n\nmessage\n");
+        let (links, problems) = check_commit(commit.as_bytes());
+        assert!(problems.is_empty());
+        assert_eq!(
+            links.iter().map(|l| l.1).collect::<Vec<_>>(),
+            vec![ObjectType::Tree, ObjectType::Commit]
+        );
+        let no_committer = format!("tree {hex}\nauthor {ident}\n\nmessage\n");
+        assert_eq!(
+            problem_ids(check_commit(no_committer.as_bytes()).1),
+            vec!["missingCommitter"]
+        );
+        let bad_parent = format!("tree {hex}\nparent 123\nauthor {ident}\ncommitter {ident}\n");
+        assert_eq!(
+            problem_ids(check_commit(bad_parent.as_bytes()).1),
+            vec!["badParentSha1"]
+        );
+        assert_eq!(
+            problem_ids(check_commit(b"author x\n").1),
+            vec!["missingTree"]
+        );
+
+        let tag = format!("object {hex}\ntype blob\ntag v1\ntagger {ident}\n\nmessage\n");
+        let (links, problems) = check_tag(tag.as_bytes());
+        assert!(problems.is_empty());
+        assert_eq!(
+            links,
+            vec![(SHA1::from_str(&hex).unwrap(), ObjectType::Blob)]
+        );
+        let bad_type = format!("object {hex}\ntype file\ntag v1\n");
+        assert_eq!(
+            problem_ids(check_tag(bad_type.as_bytes()).1),
+            vec!["badType"]
+        );
+        let no_tag = format!("object {hex}\ntype blob\n\nmessage\n");
+        assert_eq!(
+            problem_ids(check_tag(no_tag.as_bytes()).1),
+            vec!["missingTagEntry"]
+        );
+    }
+
+    fn loose_path(id: &SHA1) -> std::path::PathBuf {
+        let hex = id.to_plain_str();
+        path::objects().join(&hex[..2]).join(&hex[2..])
+    }
+
+    #[tokio::test]
+    async fn test_fsck() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "add a").await;
+        let args = FsckArgs::parse_from(["fsck"]);
+        let report = fsck(&args).await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.unreachable.is_empty());
+
+        let storage = util::objects_storage();
+        let garbage = Blob::from_content("garbage");
+        storage
+            .put(&garbage.id, &garbage.data, ObjectType::Blob)
+            .unwrap();
+        let dangling = format!("dangling blob {}", garbage.id.to_plain_str());
+        assert_eq!(fsck(&args).await.unreachable, vec![dangling]);
+        assert!(fsck(&FsckArgs::parse_from(["fsck", "--no-dangling"]))
+            .await
+            .unreachable
+            .is_empty());
+        let unreachable = fsck(&FsckArgs::parse_from(["fsck", "--unreachable"]))
+            .await
+            .unreachable;
+        assert_eq!(
+            unreachable,
+            vec![format!("unreachable blob {}", garbage.id.to_plain_str())]
+        );
+
+        // the same checks on the packed objects
+        repack::execute(RepackArgs::parse_from(["repack", "-d"])).await;
+        let report = fsck(&args).await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.unreachable.len(), 1);
+
+        // a loose object whose content is another object
+        let head = Head::current_commit().await.unwrap();
+        let commit = Commit::from_bytes(&storage.get(&head).unwrap(), head).unwrap();
+        let blob = Blob::from_content("a\n");
+        fs::create_dir_all(loose_path(&blob.id).parent().unwrap()).unwrap();
+        let other = Blob::from_content("b\n");
+        storage
+            .put(&other.id, &other.data, ObjectType::Blob)
+            .unwrap();
+        fs::copy(loose_path(&other.id), loose_path(&blob.id)).unwrap();
+        let errors = fsck(&args).await.errors;
+        assert!(errors.contains(&format!(
+            "error: hash mismatch for loose object {}",
+            blob.id.to_plain_str()
+        )));
+        assert!(!errors.iter().any(|e| e.starts_with("missing"))); // the packed one is good
+
+        // a missing blob: only the commit & the tree are left
+        let report = fsck(&FsckArgs::parse_from(["fsck", "--connectivity-only"])).await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let tree_data = storage.get(&commit.tree_id).unwrap();
+        let commit_data = storage.get(&head).unwrap();
+        for pack in storage.list_all_packs() {
+            storage.remove_pack(&pack).unwrap();
+        }
+        fs::remove_file(loose_path(&blob.id)).unwrap();
+        storage
+            .put(&commit.tree_id, &tree_data, ObjectType::Tree)
+            .unwrap();
+        storage
+            .put(&head, &commit_data, ObjectType::Commit)
+            .unwrap();
+        let errors = fsck(&args).await.errors;
+        let broken_link = format!(
+            "broken link from    tree {}\n              to    blob {}",
+            commit.tree_id.to_plain_str(),
+            blob.id.to_plain_str()
+        );
+        assert!(errors.contains(&broken_link), "{:?}", errors);
+        assert!(errors.contains(&format!("missing blob {}", blob.id.to_plain_str())));
+        assert!(errors.contains(&"error: a.txt: invalid sha1 pointer in index".to_string()));
+
+        // a corrupt index
+        let mut index = fs::read(path::index()).unwrap();
+        *index.last_mut().unwrap() ^= 0xff;
+        fs::write(path::index(), index).unwrap();
+        let errors = fsck(&args).await.errors;
+        assert!(errors.contains(&"error: bad index file sha1 signature".to_string()));
+    }
+}
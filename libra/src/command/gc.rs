@@ -0,0 +1,144 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+
+use crate::command::fetch;
+use crate::command::reflog;
+use crate::command::repack::{self, RepackArgs};
+use crate::internal::reflog::Reflog;
+use crate::utils::util;
+
+/// Grace period of the unreachable objects, same as `gc.pruneExpire` of Git
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+#[derive(Parser, Debug)]
+pub struct GcArgs {
+    /// Prune the unreachable loose objects older than the date, e.g. `2.weeks.ago`, `now` or `never`
+    #[clap(long, value_name = "DATE", default_value = DEFAULT_PRUNE_EXPIRE, default_missing_value = DEFAULT_PRUNE_EXPIRE, num_args = 0..=1, require_equals = true)]
+    pub prune: String,
+    /// Do not prune any unreachable objects, same as `--prune=never`
+    #[clap(long)]
+    pub no_prune: bool,
+}
+
+/// Expire the old reflog entries, pack all reachable objects into one pack (`repack -a -d`),
+/// and prune the unreachable objects older than the grace period
+pub async fn execute(args: GcArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let expire = match args.no_prune {
+        true => None,
+        false => match parse_prune(&args.prune) {
+            Ok(expire) => expire,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+    };
+
+    if let Ok(Some(before)) = reflog::parse_expire(None) {
+        if let Err(e) = Reflog::expire(None, before).await {
+            eprintln!("warning: unable to expire the reflog: {}", e);
+        }
+    }
+
+    let repack_args = RepackArgs {
+        all: true,
+        delete: true,
+        window: 10,
+    };
+    // the unreachable objects in the old packs are kept loose until they expire
+    let reachable = match repack::repack(&repack_args, Some(expire.unwrap_or(UNIX_EPOCH))).await {
+        Ok(reachable) => reachable,
+        Err(e) => {
+            eprintln!("fatal: failed to repack: {}", e);
+            return;
+        }
+    };
+
+    let Some(expire) = expire else {
+        return;
+    };
+    let storage = util::objects_storage();
+    for id in storage.list_objects_loose() {
+        if reachable.contains(&id) {
+            continue;
+        }
+        let expired = storage.loose_mtime(&id).is_ok_and(|mtime| mtime < expire);
+        if expired {
+            if let Err(e) = storage.remove_loose(&id) {
+                eprintln!("error: failed to prune {}: {}", id.to_plain_str(), e);
+            }
+        }
+    }
+}
+
+/// The time before which the unreachable objects are pruned, `None` if never
+/// - `now`, `never` or a date of [`fetch::parse_since`], e.g. `2.weeks.ago`
+fn parse_prune(date: &str) -> Result<Option<SystemTime>, String> {
+    match date {
+        "never" | "false" => return Ok(None),
+        // `+1` so that the objects created in this second are pruned too
+        "now" | "all" => return Ok(Some(SystemTime::now() + Duration::from_secs(1))),
+        _ => {}
+    }
+    let invalid = || format!("fatal: invalid prune date: '{}'", date);
+    let timestamp = fetch::parse_since(date).ok_or_else(invalid)?;
+    Ok(Some(UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64)))
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::object::commit::Commit;
+    use mercury::internal::object::types::ObjectType;
+    use mercury::internal::object::ObjectTrait;
+
+    use super::*;
+    use crate::internal::branch::Branch;
+    use crate::utils::test;
+
+    #[test]
+    fn test_parse_prune() {
+        let now = SystemTime::now();
+        assert_eq!(parse_prune("never").unwrap(), None);
+        assert!(parse_prune("now").unwrap().unwrap() > now);
+        let two_weeks = parse_prune(DEFAULT_PRUNE_EXPIRE).unwrap().unwrap();
+        assert!(two_weeks < now - Duration::from_secs(13 * 24 * 3600));
+        assert!(two_weeks > now - Duration::from_secs(15 * 24 * 3600));
+        assert!(parse_prune("1.hour.ago").unwrap().unwrap() < now);
+        assert_eq!(parse_prune("2005-04-07").unwrap().unwrap(), UNIX_EPOCH + Duration::from_secs(1112832000));
+        assert!(parse_prune("3.fortnights.ago").is_err());
+        assert!(parse_prune("soon").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gc() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "1\n", "1").await;
+        let second = test::commit_file("a.txt", "2\n", "2").await;
+        let storage = util::objects_storage();
+        let garbage = Blob::from_content("garbage");
+        storage.put(&garbage.id, &garbage.data, ObjectType::Blob).unwrap();
+
+        // recent garbage is kept, reachable objects are packed
+        execute(GcArgs::parse_from(["gc"])).await;
+        assert_eq!(storage.list_all_packs().len(), 1);
+        assert_eq!(storage.list_objects_loose(), vec![garbage.id]);
+
+        // the second commit is only reachable from the reflog, then from nothing
+        let first = Commit::from_bytes(&storage.get(&second).unwrap(), second).unwrap().parent_commit_ids[0];
+        Branch::update_branch("master", &first.to_plain_str(), None).await;
+        execute(GcArgs::parse_from(["gc"])).await;
+        assert!(storage.exist(&second));
+        Reflog::expire(None, i64::MAX).await.unwrap();
+        execute(GcArgs::parse_from(["gc", "--prune=never"])).await;
+        assert!(storage.exist_loosely(&second)); // taken out of the pack
+        execute(GcArgs::parse_from(["gc", "--prune=now"])).await;
+        assert!(!storage.exist(&second));
+        assert!(!storage.exist(&garbage.id));
+        assert!(storage.exist(&first));
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::BTreeMap;
-use std::io::Write;
+use std::io::{Read, Seek, Write};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use clap::Parser;
 use sha1::{Digest, Sha1};
 
-use mercury::internal::pack::Pack;
 use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::pack::Pack;
+
+use crate::utils::progress::Progress;
 
 #[derive(Parser, Debug)]
 pub struct IndexPackArgs {
@@ -43,44 +46,55 @@ pub fn execute(args: IndexPackArgs) {
         return;
     }
 
-    if let Some(version) = args.index_version {
-        match version {
-            1 => build_index_v1(&pack_file, &index_file).unwrap(),
-            2 => println!("support later"),
-            _ => eprintln!("fatal: unsupported index version"),
+    let result = match args.index_version {
+        Some(1) => build_index_v1(&pack_file, &index_file),
+        // default version = 2, same as Git
+        Some(2) | None => build_index_v2(&pack_file, &index_file),
+        Some(_) => {
+            eprintln!("fatal: unsupported index version");
+            return;
         }
-    } else {
-        // default version = 1
-        build_index_v1(&pack_file, &index_file).unwrap();
+    };
+    if let Err(e) = result {
+        eprintln!("fatal: {}", e);
     }
 }
 
-/// Build index file for pack file, version 1
-/// [pack-format](https://git-scm.com/docs/pack-format)
-pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+/// Decode the pack, return the offsets of the objects sorted by hash, and the pack checksum
+fn decode_pack(pack_file: &str) -> Result<(BTreeMap<SHA1, usize>, SHA1), GitError> {
     let pack_path = PathBuf::from(pack_file);
     let tmp_path = pack_path.parent().unwrap();
-    let pack_file = std::fs::File::open(pack_file)?;
+    let mut pack_file = std::fs::File::open(pack_file)?;
+    // the number of objects in the header, for the progress
+    let mut header = [0u8; 12];
+    pack_file.read_exact(&mut header)?;
+    pack_file.rewind()?;
+    let progress = Arc::new(Progress::new("Indexing objects", Some(BigEndian::read_u32(&header[8..]) as usize)));
+    let progress_c = progress.clone();
     let mut pack_reader = std::io::BufReader::new(pack_file);
     let obj_map = Arc::new(Mutex::new(BTreeMap::new())); // sorted by hash
     let obj_map_c = obj_map.clone();
     let mut pack = Pack::new(Some(8), Some(1024 * 1024 * 1024), Some(tmp_path.to_path_buf()), true);
     pack.decode(&mut pack_reader, move |entry, offset| {
         obj_map_c.lock().unwrap().insert(entry.hash, offset);
+        progress_c.inc(1);
     })?;
+    progress.finish();
 
-    let mut index_hash = Sha1::new();
-    let mut index_file = std::fs::File::create(index_file)?;
-    // fan-out table
-    // The header consists of 256 4-byte network byte order integers.
-    // N-th entry of this table records the number of objects in the corresponding pack,
-    // the first byte of whose object name is less than or equal to N.
-    // This is called the first-level fan-out table.
+    let obj_map = Arc::try_unwrap(obj_map).unwrap().into_inner().unwrap();
+    Ok((obj_map, pack.signature))
+}
+
+/// The fan-out table of the index.
+/// The header consists of 256 4-byte network byte order integers.
+/// N-th entry of this table records the number of objects in the corresponding pack,
+/// the first byte of whose object name is less than or equal to N.
+/// This is called the first-level fan-out table.
+fn fan_out(obj_map: &BTreeMap<SHA1, usize>) -> Result<Vec<u8>, GitError> {
     let mut i: u8 = 0;
     let mut cnt: u32 = 0;
     let mut fan_out = Vec::with_capacity(256 * 4);
-    let obj_map = Arc::try_unwrap(obj_map).unwrap().into_inner().unwrap();
-    for (hash, _) in obj_map.iter() { // sorted
+    for hash in obj_map.keys() { // sorted
         let first_byte = hash.0[0];
         while first_byte > i { // `while` rather than `if` to fill the gap, e.g. 0, 1, 2, 2, 2, 6
             fan_out.write_u32::<BigEndian>(cnt)?;
@@ -96,6 +110,17 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
         }
         i += 1;
     }
+    Ok(fan_out)
+}
+
+/// Build index file for pack file, version 1
+/// [pack-format](https://git-scm.com/docs/pack-format)
+pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_pack(pack_file)?;
+
+    let mut index_hash = Sha1::new();
+    let mut index_file = std::fs::File::create(index_file)?;
+    let fan_out = fan_out(&obj_map)?;
     index_hash.update(&fan_out);
     index_file.write_all(&fan_out)?;
 
@@ -111,13 +136,62 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
         index_file.write_all(&buf)?;
     }
 
-    index_hash.update(pack.signature.0);
+    index_hash.update(signature.0);
     // A copy of the pack checksum at the end of the corresponding pack-file.
-    index_file.write_all(&pack.signature.0)?;
+    index_file.write_all(&signature.0)?;
     let index_hash:[u8; 20] = index_hash.finalize().into();
     // Index checksum of all of the above.
     index_file.write_all(&index_hash)?;
 
     tracing::debug!("Index file is written to {:?}", index_file);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Build index file for pack file, version 2, which is the default of Git:
+/// - the header: `\377tOc` & version number
+/// - the fan-out table, the sorted object names, the CRC32 of the packed data of the objects
+/// - 4-byte offsets, the MSB is set if it's an index into the 8-byte offsets (for packs larger than 2GB)
+/// - the pack checksum & the index checksum
+pub fn build_index_v2(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_pack(pack_file)?;
+    let pack_data = std::fs::File::open(pack_file)?;
+    // SAFETY: the pack is not modified while indexing
+    let pack_data = unsafe { memmap2::Mmap::map(&pack_data)? };
+
+    // the packed data of an object ends at where the next one starts, the last one ends at the checksum
+    let mut ends = BTreeMap::new();
+    let mut offsets: Vec<usize> = obj_map.values().copied().collect();
+    offsets.sort_unstable();
+    offsets.push(pack_data.len() - 20);
+    for pair in offsets.windows(2) {
+        ends.insert(pair[0], pair[1]);
+    }
+
+    let mut index = Vec::new();
+    index.write_all(&[0xff, b't', b'O', b'c'])?;
+    index.write_u32::<BigEndian>(2)?;
+    index.write_all(&fan_out(&obj_map)?)?;
+    for hash in obj_map.keys() {
+        index.write_all(&hash.0)?;
+    }
+    for offset in obj_map.values() {
+        index.write_u32::<BigEndian>(crc32fast::hash(&pack_data[*offset..ends[offset]]))?;
+    }
+    let mut large_offsets = Vec::new();
+    for offset in obj_map.values() {
+        if *offset < 0x8000_0000 {
+            index.write_u32::<BigEndian>(*offset as u32)?;
+        } else {
+            index.write_u32::<BigEndian>(0x8000_0000 | (large_offsets.len() / 8) as u32)?;
+            large_offsets.write_u64::<BigEndian>(*offset as u64)?;
+        }
+    }
+    index.write_all(&large_offsets)?;
+    index.write_all(&signature.0)?;
+    let index_hash: [u8; 20] = Sha1::digest(&index).into();
+    index.write_all(&index_hash)?;
+
+    std::fs::write(index_file, index)?;
+    tracing::debug!("Index file is written to {:?}", index_file);
+    Ok(())
+}
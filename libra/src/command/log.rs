@@ -1,9 +1,15 @@
 use std::cmp::min;
-use std::collections::HashSet;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::PathBuf;
 
 use crate::command::load_object;
 use crate::internal::branch::Branch;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
+use crate::internal::shallow::{self, Shallow};
+use crate::internal::signing;
+use crate::internal::tag::Tag;
+use crate::utils::{revision, util};
 use clap::Parser;
 use colored::Colorize;
 #[cfg(unix)]
@@ -11,22 +17,68 @@ use std::io::Write;
 #[cfg(unix)]
 use std::process::{Command, Stdio};
 
-use std::collections::VecDeque;
-use std::str::FromStr;
 use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::Signature;
+use mercury::internal::object::types::ObjectType;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use super::{fetch, parse_commit_msg};
+
+/// The date format of the default output, like `Tue Nov 14 22:13:20 2023 +0800`
+const DEFAULT_DATE: &str = "%a %b %-d %H:%M:%S %Y %z";
+/// The date format of `%ai` & `%ci`, like `2023-11-14 22:13:20 +0800`
+const ISO_DATE: &str = "%Y-%m-%d %H:%M:%S %z";
 
-use super::parse_commit_msg;
 #[derive(Parser, Debug)]
 pub struct LogArgs {
     /// Limit the number of output
     #[clap(short, long)]
     pub number: Option<usize>,
+    /// Show each commit on one line: the abbreviated hash & the title, same as `--format=oneline`
+    #[clap(long)]
+    pub oneline: bool,
+    /// Draw the history graph (the branches & merges) on the left side
+    #[clap(long)]
+    pub graph: bool,
+    /// Show the branches & tags pointing to the commits: `short` (default), `full` ref names or `no`
+    #[clap(long, value_name = "STYLE", num_args = 0..=1, require_equals = true, default_missing_value = "short")]
+    pub decorate: Option<String>,
+    /// Don't show the branches & tags pointing to the commits
+    #[clap(long)]
+    pub no_decorate: bool,
+    /// `oneline`, `short`, `medium`, `full`, or `format:<string>` with placeholders like `%h %an %s`
+    #[clap(long, alias = "pretty", value_name = "FORMAT")]
+    pub format: Option<String>,
+    /// Check the signatures of the signed commits and show the results
+    #[clap(long)]
+    pub show_signature: bool,
+    /// Only the commits whose author (`name <email>`) contains the pattern
+    #[clap(long)]
+    pub author: Option<String>,
+    /// Only the commits whose message contains the pattern
+    #[clap(long)]
+    pub grep: Option<String>,
+    /// Only the commits more recent than the date, e.g. `2024-01-01` or `2.weeks.ago`
+    #[clap(long, alias = "after", value_name = "DATE")]
+    pub since: Option<String>,
+    /// Only the commits older than the date
+    #[clap(long, alias = "before", value_name = "DATE")]
+    pub until: Option<String>,
+    /// The commits to start from (`HEAD` by default); `^<rev>` or `<rev1>..<rev2>` excludes the history of a revision
+    #[clap(value_name = "REVISION")]
+    pub revisions: Vec<String>,
+    /// Only the commits that change the paths
+    #[clap(last = true, value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
 }
 
-///  Get all reachable commits from the given commit hash
+///  Get all reachable commits from the given commit hash, the history stops at the shallow commits
 ///  **didn't consider the order of the commits**
+#[allow(dead_code)]
 pub async fn get_reachable_commits(commit_hash: String) -> Vec<Commit> {
+    let shallow = Shallow::list().await;
     let mut queue = VecDeque::new();
     let mut commit_set: HashSet<String> = HashSet::new(); // to avoid duplicate commits because of circular reference
     let mut reachable_commits: Vec<Commit> = Vec::new();
@@ -42,7 +94,7 @@ pub async fn get_reachable_commits(commit_hash: String) -> Vec<Commit> {
         }
         commit_set.insert(commit_id);
 
-        let parent_commit_ids = commit.parent_commit_ids.clone();
+        let parent_commit_ids = shallow::parents(&commit, &shallow);
         for parent_commit_id in parent_commit_ids {
             queue.push_back(parent_commit_id.to_plain_str());
         }
@@ -52,78 +104,708 @@ pub async fn get_reachable_commits(commit_hash: String) -> Vec<Commit> {
 }
 
 pub async fn execute(args: LogArgs) {
+    if args.revisions.is_empty() {
+        // check if the current branch has any commits
+        if let Head::Branch(branch_name) = Head::current().await {
+            if Branch::find_branch(&branch_name, None).await.is_none() {
+                eprintln!(
+                    "fatal: your current branch '{}' does not have any commits yet",
+                    branch_name
+                );
+                return;
+            }
+        }
+    }
+    let output = match log(&args).await {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
     #[cfg(unix)]
-    let mut process = Command::new("less") // create a pipe to less
-        .arg("-R") // raw control characters
-        .stdin(Stdio::piped())
-        .stdout(Stdio::inherit())
-        .spawn()
-        .expect("failed to execute process");
+    {
+        let mut process = Command::new("less") // create a pipe to less
+            .arg("-R") // raw control characters
+            .arg("-F") // quit if the output fits on one screen
+            .arg("-X") // keep the output on the screen after quitting
+            .stdin(Stdio::piped())
+            .stdout(Stdio::inherit())
+            .spawn()
+            .expect("failed to execute process");
+        if let Some(ref mut stdin) = process.stdin {
+            let _ = stdin.write_all(output.as_bytes());
+        } else {
+            eprintln!("Failed to capture stdin");
+        }
+        drop(process.stdin.take());
+        let _ = process.wait().expect("failed to wait on child");
+    }
+    #[cfg(not(unix))]
+    {
+        print!("{}", output);
+    }
+}
 
-    let head = Head::current().await;
-    // check if the current branch has any commits
-    if let Head::Branch(branch_name) = head.to_owned() {
-        let branch = Branch::find_branch(&branch_name, None).await;
-        if branch.is_none() {
-            panic!(
-                "fatal: your current branch '{}' does not have any commits yet ",
-                branch_name
-            );
-        }
-    }
-
-    let commit_hash = Head::current_commit().await.unwrap().to_plain_str();
-
-    let mut reachable_commits = get_reachable_commits(commit_hash.clone()).await;
-    // default sort with signature time
-    reachable_commits.sort_by(|a, b| b.committer.timestamp.cmp(&a.committer.timestamp));
-
-    let max_output_number = min(args.number.unwrap_or(usize::MAX), reachable_commits.len());
-    let mut output_number = 0;
-    for commit in reachable_commits {
-        if output_number >= max_output_number {
-            break;
-        }
-        output_number += 1;
-        let mut message = {
-            let mut message = format!(
-                "{} {}",
-                "commit".yellow(),
-                &commit.id.to_plain_str().yellow()
-            );
-
-            // TODO other branch's head should shown branch name
-            if output_number == 1 {
-                message = format!("{} {}{}", message, "(".yellow(), "HEAD".blue());
-                if let Head::Branch(name) = head.to_owned() {
-                    // message += &"-> ".blue();
-                    // message += &head.name.as_ref().unwrap().green();
-                    message = format!("{}{}{}", message, " -> ".blue(), name.green());
+/// The formats of `--format` (or `--pretty`)
+#[derive(Debug, Clone, PartialEq)]
+enum Format {
+    OneLine,
+    Short,
+    Medium,
+    Full,
+    /// a template with placeholders, each commit ends with a new line
+    Custom(String),
+}
+
+impl Format {
+    fn parse(args: &LogArgs) -> Result<Self, String> {
+        let Some(format) = &args.format else {
+            return Ok(if args.oneline {
+                Format::OneLine
+            } else {
+                Format::Medium
+            });
+        };
+        match format.as_str() {
+            "oneline" => Ok(Format::OneLine),
+            "short" => Ok(Format::Short),
+            "medium" => Ok(Format::Medium),
+            "full" => Ok(Format::Full),
+            _ => match format
+                .strip_prefix("format:")
+                .or_else(|| format.strip_prefix("tformat:"))
+            {
+                Some(template) => Ok(Format::Custom(template.to_string())),
+                None if format.contains('%') => Ok(Format::Custom(format.to_string())),
+                None => Err(format!("fatal: invalid --pretty format: {}", format)),
+            },
+        }
+    }
+}
+
+/// The commits of the log and their parents in it, after applying the revisions, the filters and the paths
+struct History {
+    /// from the newest, a commit is always before its parents
+    commits: Vec<Commit>,
+    /// the parents in the history, through the commits that are not shown
+    parents: HashMap<SHA1, Vec<SHA1>>,
+}
+
+/// Render the log
+async fn log(args: &LogArgs) -> Result<String, String> {
+    let format = Format::parse(args)?;
+    let decorate = match (args.no_decorate, args.decorate.as_deref()) {
+        (true, _) | (_, Some("no")) => None,
+        (_, Some("full")) => Some(true),
+        (_, Some("short")) | (_, None) => Some(false),
+        (_, Some(style)) => return Err(format!("fatal: invalid --decorate option: {}", style)),
+    };
+    let decorations = match decorate {
+        Some(full) => decorations(full).await,
+        None => HashMap::new(),
+    };
+    let shallow = Shallow::list().await;
+    let history = history(args, &shallow).await?;
+
+    let max_output_number = min(args.number.unwrap_or(usize::MAX), history.commits.len());
+    let mut graph = Graph::default();
+    let mut output = String::new();
+    // the blank line between the commits of the multi-line formats
+    let separated = matches!(format, Format::Short | Format::Medium | Format::Full);
+    for (i, commit) in history.commits.iter().take(max_output_number).enumerate() {
+        let mut labels = decorations.get(&commit.id).cloned().unwrap_or_default();
+        // the parents of a shallow commit are not fetched
+        if shallow.contains(&commit.id) {
+            labels.push("grafted".yellow().bold().to_string());
+        }
+        let mut text = format_commit(commit, &format, &labels);
+        if args.show_signature {
+            // after the first line, like Git
+            let end = text.find('\n').map_or(text.len(), |end| end + 1);
+            text.insert_str(end, &signature_report(commit).await);
+        }
+        if !args.graph {
+            if separated && i > 0 {
+                output.push('\n');
+            }
+            output.push_str(&text);
+            continue;
+        }
+        let prefixes = graph.next(commit.id, &history.parents[&commit.id]);
+        if separated && i > 0 {
+            output.push_str(&prefixes.before);
+            output.push('\n');
+        }
+        let mut lines = text.lines();
+        output.push_str(&format!(
+            "{}{}\n",
+            prefixes.commit,
+            lines.next().unwrap_or_default()
+        ));
+        let mut edges = prefixes.edges.iter();
+        for line in lines {
+            let prefix = edges.next().unwrap_or(&prefixes.padding);
+            output.push_str(&format!("{}{}\n", prefix, line));
+        }
+        for edge in edges {
+            output.push_str(&format!("{}\n", edge));
+        }
+    }
+    Ok(output)
+}
+
+/// The revisions to include & to exclude
+async fn parse_revisions(revisions: &[String]) -> Result<(Vec<SHA1>, Vec<SHA1>), String> {
+    let (mut include, mut exclude) = (Vec::new(), Vec::new());
+    for rev in revisions {
+        if let Some((from, to)) = rev.split_once("..") {
+            let or_head = |rev: &str| {
+                if rev.is_empty() {
+                    "HEAD".to_string()
+                } else {
+                    rev.to_string()
+                }
+            };
+            exclude.push(revision::resolve_commit(&or_head(from)).await?);
+            include.push(revision::resolve_commit(&or_head(to)).await?);
+        } else if let Some(rev) = rev.strip_prefix('^') {
+            exclude.push(revision::resolve_commit(rev).await?);
+        } else {
+            include.push(revision::resolve_commit(rev).await?);
+        }
+    }
+    if include.is_empty() {
+        include.push(revision::resolve_commit("HEAD").await?);
+    }
+    Ok((include, exclude))
+}
+
+/// The filters of the commits to show, except the paths
+struct Filter {
+    author: Option<String>,
+    grep: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+impl Filter {
+    fn parse(args: &LogArgs) -> Result<Self, String> {
+        let date = |date: &Option<String>| match date {
+            Some(date) => fetch::parse_since(date)
+                .map(Some)
+                .ok_or_else(|| format!("fatal: invalid date: '{}'", date)),
+            None => Ok(None),
+        };
+        Ok(Filter {
+            author: args.author.clone(),
+            grep: args.grep.clone(),
+            since: date(&args.since)?,
+            until: date(&args.until)?,
+        })
+    }
+
+    fn matches(&self, commit: &Commit) -> bool {
+        let time = commit.committer.timestamp as i64;
+        let author = format!("{} <{}>", commit.author.name, commit.author.email);
+        self.author
+            .as_ref()
+            .is_none_or(|pattern| author.contains(pattern.as_str()))
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|pattern| commit.message.contains(pattern.as_str()))
+            && self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+/// Walk the history from the revisions.
+///
+/// With paths, the history is simplified like Git: a commit is shown only if it changes the paths
+/// (compared with all of its parents), and a merge that takes them from one parent only follows this parent.
+async fn history(args: &LogArgs, shallow: &HashSet<SHA1>) -> Result<History, String> {
+    let (include, exclude) = parse_revisions(&args.revisions).await?;
+    let filter = Filter::parse(args)?;
+    let paths: Vec<PathBuf> = args.paths.iter().map(util::to_workdir_path).collect();
+    let load = |id: &SHA1| {
+        load_object::<Commit>(id).map_err(|e| format!("fatal: {}: {}", id.to_plain_str(), e))
+    };
+
+    let mut hidden = HashSet::new();
+    let mut stack = exclude;
+    while let Some(id) = stack.pop() {
+        if hidden.insert(id) {
+            stack.extend(shallow::parents(&load(&id)?, shallow));
+        }
+    }
+
+    let mut path_ids: HashMap<SHA1, Vec<Option<SHA1>>> = HashMap::new();
+    let mut path_ids_of = |commit: &Commit| -> Vec<Option<SHA1>> {
+        path_ids
+            .entry(commit.id)
+            .or_insert_with(|| {
+                let tree = commit.tree_id;
+                paths
+                    .iter()
+                    .map(|path| revision::lookup_path(&tree, path).map(|(_, id)| id))
+                    .collect()
+            })
+            .clone()
+    };
+
+    let mut commits: HashMap<SHA1, Commit> = HashMap::new();
+    // the parents to walk through, & whether to show the commit
+    let mut walk_parents: HashMap<SHA1, Vec<SHA1>> = HashMap::new();
+    let mut shown = HashSet::new();
+    let mut stack: Vec<SHA1> = include
+        .into_iter()
+        .filter(|id| !hidden.contains(id))
+        .collect();
+    while let Some(id) = stack.pop() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+        let commit = load(&id)?;
+        let parents = shallow::parents(&commit, shallow);
+        let (parents, changed) = if paths.is_empty() {
+            (parents, true)
+        } else {
+            let ids = path_ids_of(&commit);
+            let mut same_parent = None;
+            for parent in &parents {
+                if path_ids_of(&load(parent)?) == ids {
+                    same_parent = Some(*parent);
+                    break;
+                }
+            }
+            match same_parent {
+                Some(parent) => (vec![parent], false),
+                None => {
+                    let changed = !parents.is_empty() || ids.iter().any(Option::is_some);
+                    (parents, changed)
                 }
-                message = format!("{}{}", message, ")".yellow());
             }
-            message
         };
-        message.push_str(&format!("\nAuthor: {}", commit.author));
-        let (msg, _) = parse_commit_msg(&commit.message);
-        message.push_str(&format!("\n{}\n", msg));
-
-        #[cfg(unix)]
-        {
-            if let Some(ref mut stdin) = process.stdin {
-                writeln!(stdin, "{}", message).unwrap();
-            } else {
-                eprintln!("Failed to capture stdin");
+        if changed && filter.matches(&commit) {
+            shown.insert(id);
+        }
+        let parents: Vec<SHA1> = parents
+            .into_iter()
+            .filter(|p| !hidden.contains(p))
+            .collect();
+        stack.extend(parents.iter().copied());
+        walk_parents.insert(id, parents);
+        commits.insert(id, commit);
+    }
+
+    // a commit before its parents, by the committer time;
+    // the graph takes a branch as far as possible before another, like `--topo-order` of Git
+    let mut children_count: HashMap<SHA1, usize> = HashMap::new();
+    for parents in walk_parents.values() {
+        for parent in parents {
+            *children_count.entry(*parent).or_default() += 1;
+        }
+    }
+    let mut ready: BinaryHeap<(usize, SHA1)> = commits
+        .values()
+        .filter(|c| !children_count.contains_key(&c.id))
+        .map(|c| (c.committer.timestamp, c.id))
+        .collect();
+    let mut stack = Vec::new();
+    let mut order = Vec::new();
+    while let Some(id) = stack.pop().or_else(|| ready.pop().map(|(_, id)| id)) {
+        order.push(id);
+        for parent in &walk_parents[&id] {
+            let count = children_count.get_mut(parent).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                match args.graph {
+                    true => stack.push(*parent),
+                    false => ready.push((commits[parent].committer.timestamp, *parent)),
+                }
             }
         }
-        #[cfg(not(unix))]
-        {
-            println!("{}", message);
+    }
+
+    // the nearest shown ancestors, from the oldest commits
+    let mut nearest: HashMap<SHA1, Vec<SHA1>> = HashMap::new();
+    let mut parents = HashMap::new();
+    for id in order.iter().rev() {
+        let mut ancestors = Vec::new();
+        for parent in &walk_parents[id] {
+            for ancestor in &nearest[parent] {
+                if !ancestors.contains(ancestor) {
+                    ancestors.push(*ancestor);
+                }
+            }
+        }
+        if shown.contains(id) {
+            nearest.insert(*id, vec![*id]);
+            parents.insert(*id, ancestors);
+        } else {
+            nearest.insert(*id, ancestors);
         }
     }
-    #[cfg(unix)]
-    {
-        let _ = process.wait().expect("failed to wait on child");
+    let commits = order
+        .into_iter()
+        .filter(|id| shown.contains(id))
+        .map(|id| commits.remove(&id).unwrap())
+        .collect();
+    Ok(History { commits, parents })
+}
+
+/// The branches & tags pointing to each commit, colored like Git, e.g. `HEAD -> master`, `tag: v1.0`
+async fn decorations(full: bool) -> HashMap<SHA1, Vec<String>> {
+    let mut decorations: HashMap<SHA1, Vec<String>> = HashMap::new();
+    let name = |prefix: &str, name: &str| match full {
+        true => format!("{}{}", prefix, name),
+        false => name.to_string(),
+    };
+    let head = Head::current().await;
+    match &head {
+        Head::Detached(commit) => decorations
+            .entry(*commit)
+            .or_default()
+            .push("HEAD".cyan().bold().to_string()),
+        Head::Branch(branch) => {
+            if let Some(branch) = Branch::find_branch(branch, None).await {
+                let label = format!(
+                    "{} {}",
+                    "HEAD ->".cyan().bold(),
+                    name("refs/heads/", &branch.name).green().bold()
+                );
+                decorations.entry(branch.commit).or_default().push(label);
+            }
+        }
+    }
+    for tag in Tag::list_tags().await {
+        if let Ok(commit) = revision::peel(tag.object, ObjectType::Commit) {
+            let label = format!("tag: {}", name("refs/tags/", &tag.name))
+                .yellow()
+                .bold();
+            decorations
+                .entry(commit)
+                .or_default()
+                .push(label.to_string());
+        }
+    }
+    for remote in Config::all_remote_configs().await {
+        for branch in Branch::list_branches(Some(&remote.name)).await {
+            let label = name("refs/remotes/", &format!("{}/{}", remote.name, branch.name));
+            decorations
+                .entry(branch.commit)
+                .or_default()
+                .push(label.red().bold().to_string());
+        }
+    }
+    for branch in Branch::list_branches(None).await {
+        if !matches!(&head, Head::Branch(current) if *current == branch.name) {
+            let label = name("refs/heads/", &branch.name).green().bold();
+            decorations
+                .entry(branch.commit)
+                .or_default()
+                .push(label.to_string());
+        }
+    }
+    decorations
+}
+
+/// ` (HEAD -> master, tag: v1)`, or empty if no labels
+fn format_decorations(labels: &[String]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let separator = ", ".yellow().to_string();
+    format!(
+        " {}{}{}",
+        "(".yellow(),
+        labels.join(&separator),
+        ")".yellow()
+    )
+}
+
+/// Indent each line of the message with 4 spaces
+fn indent_message(message: &str) -> String {
+    message
+        .trim_end()
+        .lines()
+        .map(|line| match line.is_empty() {
+            true => "    \n".to_string(),
+            false => format!("    {}\n", line),
+        })
+        .collect()
+}
+
+fn short_hash(id: &SHA1) -> String {
+    id.to_plain_str()[..7].to_string()
+}
+
+/// Format a commit, each line ends with a new line
+fn format_commit(commit: &Commit, format: &Format, labels: &[String]) -> String {
+    let (message, _) = parse_commit_msg(&commit.message);
+    let message = message.trim();
+    let subject = message.lines().next().unwrap_or_default();
+    let header = |hash: String| {
+        format!(
+            "{}{}\n",
+            format!("commit {}", hash).yellow(),
+            format_decorations(labels)
+        )
+    };
+    let merge = match commit.parent_commit_ids.len() {
+        0 | 1 => String::new(),
+        _ => {
+            let parents: Vec<_> = commit.parent_commit_ids.iter().map(short_hash).collect();
+            format!("Merge: {}\n", parents.join(" "))
+        }
+    };
+    let person = |label: &str, signature: &Signature| {
+        format!("{}: {} <{}>\n", label, signature.name, signature.email)
+    };
+    let date = |signature: &Signature| {
+        util::format_time(signature.timestamp, &signature.timezone, DEFAULT_DATE)
+    };
+    match format {
+        Format::OneLine => format!(
+            "{}{} {}\n",
+            short_hash(&commit.id).yellow(),
+            format_decorations(labels),
+            subject
+        ),
+        Format::Short => format!(
+            "{}{}{}\n{}",
+            header(commit.id.to_plain_str()),
+            merge,
+            person("Author", &commit.author),
+            indent_message(subject)
+        ),
+        Format::Medium => format!(
+            "{}{}{}Date:   {}\n\n{}",
+            header(commit.id.to_plain_str()),
+            merge,
+            person("Author", &commit.author),
+            date(&commit.author),
+            indent_message(message)
+        ),
+        Format::Full => format!(
+            "{}{}{}{}\n{}",
+            header(commit.id.to_plain_str()),
+            merge,
+            person("Author", &commit.author),
+            person("Commit", &commit.committer),
+            indent_message(message)
+        ),
+        Format::Custom(template) => format!("{}\n", expand_placeholders(template, commit, labels)),
+    }
+}
+
+/// The result of checking the signature of the commit, empty if not signed
+async fn signature_report(commit: &Commit) -> String {
+    let Some((payload, signature)) = signing::commit_signature(commit) else {
+        return String::new();
+    };
+    match signing::verify(&payload, &signature).await {
+        Ok(verification) => verification.output,
+        Err(e) => format!("error: {}\n", e),
+    }
+}
+
+/// A relative time like Git, e.g. `3 days ago`
+fn relative_time(timestamp: usize) -> String {
+    let diff = chrono::Utc::now().timestamp() - timestamp as i64;
+    if diff < 0 {
+        return "in the future".to_string();
+    }
+    let (count, unit) = match diff {
+        0..90 => (diff, "second"),
+        90..5400 => ((diff + 30) / 60, "minute"),
+        5400..129_600 => ((diff + 1800) / 3600, "hour"),
+        129_600..1_209_600 => ((diff + 43_200) / 86_400, "day"),
+        1_209_600..6_048_000 => ((diff + 302_400) / 604_800, "week"),
+        6_048_000..31_536_000 => ((diff + 1_296_000) / 2_592_000, "month"),
+        _ => ((diff + 15_768_000) / 31_536_000, "year"),
+    };
+    match count {
+        1 => format!("1 {} ago", unit),
+        _ => format!("{} {}s ago", count, unit),
+    }
+}
+
+/// Expand the placeholders of a `--format` template, the unknown ones are kept as is:
+/// - `%H` `%h` hash, `%T` `%t` tree, `%P` `%p` parents, `%d` `%D` decorations
+/// - `%an` `%ae` `%ad` `%ar` `%at` `%ai` author, and the same with `%c` for the committer
+/// - `%s` subject, `%b` body, `%B` raw message, `%n` new line, `%%` `%`
+/// - `%Cred` `%Cgreen` `%Cblue` `%Creset` colors
+fn expand_placeholders(template: &str, commit: &Commit, labels: &[String]) -> String {
+    let (message, _) = parse_commit_msg(&commit.message);
+    let message = message.trim_start();
+    let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+    let color = |code: &str| match colored::control::SHOULD_COLORIZE.should_colorize() {
+        true => code.to_string(),
+        false => String::new(),
+    };
+    let person = |signature: &Signature, field: char| -> Option<String> {
+        let value = match field {
+            'n' => signature.name.clone(),
+            'e' => signature.email.clone(),
+            'd' => util::format_time(signature.timestamp, &signature.timezone, DEFAULT_DATE),
+            'r' => relative_time(signature.timestamp),
+            't' => signature.timestamp.to_string(),
+            'i' => util::format_time(signature.timestamp, &signature.timezone, ISO_DATE),
+            _ => return None,
+        };
+        Some(value)
+    };
+    let join = |ids: &[SHA1], short: bool| -> String {
+        let ids: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                if short {
+                    short_hash(id)
+                } else {
+                    id.to_plain_str()
+                }
+            })
+            .collect();
+        ids.join(" ")
+    };
+
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('%') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let named_colors = [
+            ("Cred", "\x1b[31m"),
+            ("Cgreen", "\x1b[32m"),
+            ("Cblue", "\x1b[34m"),
+            ("Creset", "\x1b[m"),
+        ];
+        if let Some((name, code)) = named_colors.iter().find(|(name, _)| rest.starts_with(name)) {
+            output.push_str(&color(code));
+            rest = &rest[name.len()..];
+            continue;
+        }
+        let mut chars = rest.chars();
+        let (value, used) = match (chars.next(), chars.next()) {
+            (Some('%'), _) => (Some("%".to_string()), 1),
+            (Some('n'), _) => (Some("\n".to_string()), 1),
+            (Some('H'), _) => (Some(commit.id.to_plain_str()), 1),
+            (Some('h'), _) => (Some(short_hash(&commit.id)), 1),
+            (Some('T'), _) => (Some(commit.tree_id.to_plain_str()), 1),
+            (Some('t'), _) => (Some(short_hash(&commit.tree_id)), 1),
+            (Some('P'), _) => (Some(join(&commit.parent_commit_ids, false)), 1),
+            (Some('p'), _) => (Some(join(&commit.parent_commit_ids, true)), 1),
+            (Some('d'), _) => (Some(format_decorations(labels)), 1),
+            (Some('D'), _) => (Some(labels.join(", ")), 1),
+            (Some('s'), _) => (Some(subject.trim_end().to_string()), 1),
+            (Some('b'), _) => (Some(body.trim_start_matches('\n').to_string()), 1),
+            (Some('B'), _) => (Some(message.to_string()), 1),
+            (Some('a'), Some(field)) => (person(&commit.author, field), 2),
+            (Some('c'), Some(field)) => (person(&commit.committer, field), 2),
+            _ => (None, 0),
+        };
+        match value {
+            Some(value) => {
+                output.push_str(&value);
+                rest = &rest[used..];
+            }
+            None => output.push('%'),
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The prefixes of the lines of a commit in the graph
+struct GraphLines {
+    /// the row of the blank line before the commit
+    before: String,
+    /// the row of the commit, with `*`
+    commit: String,
+    /// the rows moving the edges to their new columns, e.g. `|\` for a merge, `|/` for the branches joining
+    edges: Vec<String>,
+    /// the row after the edges are moved
+    padding: String,
+}
+
+/// The ASCII graph of `--graph`: each column is an edge to the commit expected there
+#[derive(Default)]
+struct Graph {
+    columns: Vec<SHA1>,
+}
+
+impl Graph {
+    fn next(&mut self, id: SHA1, parents: &[SHA1]) -> GraphLines {
+        let old_len = self.columns.len();
+        let col = match self.columns.iter().position(|c| *c == id) {
+            Some(col) => col,
+            None => {
+                self.columns.push(id);
+                self.columns.len() - 1
+            }
+        };
+
+        // the edges after the commit: `(commit, from column)`, the parents start from the column of the commit
+        let mut slots = Vec::new();
+        for (i, c) in self.columns.iter().enumerate() {
+            match i == col {
+                true => slots.extend(parents.iter().map(|p| (*p, col))),
+                false => slots.push((*c, i)),
+            }
+        }
+        let mut columns: Vec<SHA1> = Vec::new();
+        for (c, _) in &slots {
+            if !columns.contains(c) {
+                columns.push(*c);
+            }
+        }
+        let targets: Vec<usize> = slots
+            .iter()
+            .map(|(c, _)| columns.iter().position(|n| n == c).unwrap())
+            .collect();
+        let width = 2 * self.columns.len().max(columns.len()).max(1);
+
+        let row = |marks: &[(usize, char)]| -> String {
+            let mut row = vec![' '; width];
+            for &(pos, mark) in marks {
+                row[pos] = mark;
+            }
+            row.into_iter().collect()
+        };
+        let commit_marks: Vec<_> = (0..self.columns.len())
+            .map(|i| (2 * i, if i == col { '*' } else { '|' }))
+            .collect();
+        let commit = row(&commit_marks);
+        let before = row(&(0..old_len).map(|i| (2 * i, '|')).collect::<Vec<_>>());
+
+        let mut positions: Vec<usize> = slots.iter().map(|(_, from)| *from).collect();
+        let mut edges = Vec::new();
+        while positions != targets {
+            let mut marks = Vec::new();
+            for (pos, target) in positions.iter_mut().zip(&targets) {
+                if *pos < *target {
+                    marks.push((2 * *pos + 1, '\\'));
+                    *pos += 1;
+                } else if *pos > *target {
+                    marks.push((2 * *pos - 1, '/'));
+                    *pos -= 1;
+                } else {
+                    marks.push((2 * *pos, '|'));
+                }
+            }
+            edges.push(row(&marks));
+        }
+        let padding = row(&(0..columns.len()).map(|i| (2 * i, '|')).collect::<Vec<_>>());
+        self.columns = columns;
+        GraphLines {
+            before,
+            commit,
+            edges,
+            padding,
+        }
     }
 }
 
@@ -131,7 +813,12 @@ pub async fn execute(args: LogArgs) {
 mod tests {
 
     use super::*;
-    use crate::{command::save_object, utils::test};
+    use crate::command::add::{self, AddArgs};
+    use crate::command::commit::{self, CommitArgs};
+    use crate::{
+        command::{format_commit_msg, save_object},
+        utils::test,
+    };
     use mercury::{hash::SHA1, internal::object::commit::Commit};
 
     #[tokio::test]
@@ -148,10 +835,126 @@ mod tests {
         test::setup_with_new_libra().await;
         let _ = create_test_commit_tree().await;
 
-        let args = LogArgs { number: Some(6) };
+        let args = LogArgs::parse_from(["log", "-n", "6"]);
         execute(args).await;
     }
 
+    fn messages(history: &History) -> Vec<String> {
+        history
+            .commits
+            .iter()
+            .map(|c| c.message.trim().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_history_ranges_and_filters() {
+        test::setup_with_new_libra().await;
+        let _ = create_test_commit_tree().await;
+        let shallow = HashSet::new();
+
+        let all = history(&LogArgs::parse_from(["log"]), &shallow)
+            .await
+            .unwrap();
+        assert_eq!(
+            messages(&all),
+            ["Commit_6", "Commit_5", "Commit_4", "Commit_3", "Commit_2", "Commit_1"]
+        );
+        let range = history(&LogArgs::parse_from(["log", "HEAD~1..HEAD"]), &shallow)
+            .await
+            .unwrap();
+        assert_eq!(messages(&range), ["Commit_6", "Commit_5", "Commit_4"]);
+        let excluded = history(&LogArgs::parse_from(["log", "^HEAD^2", "HEAD"]), &shallow)
+            .await
+            .unwrap();
+        assert_eq!(messages(&excluded), ["Commit_6", "Commit_3"]);
+
+        // the parents are rewritten to the shown commits
+        let grep = history(&LogArgs::parse_from(["log", "--grep", "_4"]), &shallow)
+            .await
+            .unwrap();
+        assert_eq!(messages(&grep), ["Commit_4"]);
+        let since = history(&LogArgs::parse_from(["log", "--since", "5"]), &shallow)
+            .await
+            .unwrap();
+        assert_eq!(messages(&since), ["Commit_6", "Commit_5"]);
+        assert_eq!(since.parents[&since.commits[0].id], [since.commits[1].id]);
+        assert!(history(&LogArgs::parse_from(["log", "nothing"]), &shallow)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_history_paths() {
+        test::setup_with_new_libra().await;
+        for (file, content) in [("a.txt", "1"), ("b.txt", "2"), ("a.txt", "3")] {
+            test::ensure_file(file, Some(content));
+            add::execute(AddArgs::parse_from(["add", file])).await;
+            commit::execute(CommitArgs {
+                message: format!("{} {}", file, content),
+                allow_empty: false,
+                gpg_sign: None,
+                no_gpg_sign: false,
+            })
+            .await;
+        }
+        let args = LogArgs::parse_from(["log", "--", "a.txt"]);
+        let history = history(&args, &HashSet::new()).await.unwrap();
+        assert_eq!(messages(&history), ["a.txt 3", "a.txt 1"]);
+        assert_eq!(
+            history.parents[&history.commits[0].id],
+            [history.commits[1].id]
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mut commit = Commit::from_tree_id(
+            SHA1::new(&vec![1; 20]),
+            vec![],
+            &format_commit_msg("subject\n\nbody", None),
+        );
+        commit.author.name = "Alice".to_string();
+        let hash = commit.id.to_plain_str();
+        let labels = ["tag: v1".to_string()];
+        assert_eq!(
+            expand_placeholders("%h %an%d: %s|%b|%%|%x", &commit, &labels),
+            format!("{} Alice (tag: v1): subject|body|%|%x", &hash[..7])
+        );
+        assert_eq!(
+            format_commit(&commit, &Format::OneLine, &[]),
+            format!("{} subject\n", &hash[..7])
+        );
+        let medium = format_commit(&commit, &Format::Medium, &[]);
+        assert!(medium.ends_with("\n\n    subject\n    \n    body\n"));
+
+        let args = |format: &str| LogArgs::parse_from(["log", &format!("--format={}", format)]);
+        assert_eq!(Format::parse(&args("oneline")), Ok(Format::OneLine));
+        assert_eq!(
+            Format::parse(&args("format:%h")),
+            Ok(Format::Custom("%h".to_string()))
+        );
+        assert_eq!(
+            Format::parse(&args("%h")),
+            Ok(Format::Custom("%h".to_string()))
+        );
+        assert!(Format::parse(&args("unknown")).is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let [a, b, c, d] = [1, 2, 3, 4].map(|i| SHA1::new(&vec![i; 20]));
+        // d is a merge of c & b, both from a
+        let mut graph = Graph::default();
+        let mut rows = Vec::new();
+        for (id, parents) in [(d, vec![c, b]), (c, vec![a]), (b, vec![a]), (a, vec![])] {
+            let lines = graph.next(id, &parents);
+            rows.push(lines.commit);
+            rows.extend(lines.edges);
+        }
+        assert_eq!(rows, ["*   ", "|\\  ", "* | ", "| * ", "|/  ", "* "]);
+    }
+
     /// create a test commit tree structure as graph and create branch (master) head to commit 6
     /// return a commit hash of commit 6
     ///            3   6
@@ -1,90 +1,439 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
 use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::{Index, IndexEntry};
+use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::Tree;
 
 use crate::{
-    internal::{branch::Branch, head::Head},
-    utils::util,
+    internal::{
+        branch::Branch,
+        head::Head,
+        reflog::Reflog,
+        shallow::{self, Shallow},
+    },
+    utils::{
+        merge::{self as merge_util, ConflictKind, MergeLabels, MergedEntry},
+        object_ext::{BlobExt, CommitExt, TreeExt},
+        path, util,
+    },
 };
 
 use super::{
     branch::get_target_commit,
-    load_object, log,
+    commit::{self, CommitArgs},
     restore::{self, RestoreArgs},
+    status,
 };
 
 #[derive(Parser, Debug)]
 pub struct MergeArgs {
     /// The branch to merge into the current branch
-    pub branch: String,
+    #[clap(required_unless_present_any(["continue_merge", "abort"]))]
+    pub branch: Option<String>,
+
+    /// The message of the merge commit
+    #[clap(short, long)]
+    pub message: Option<String>,
+
+    /// Refuse to merge unless the current branch can be fast-forwarded
+    #[clap(long, conflicts_with = "no_ff")]
+    pub ff_only: bool,
+
+    /// Create a merge commit even when the merge resolves as a fast-forward
+    #[clap(long)]
+    pub no_ff: bool,
+
+    /// Conclude the merge after the conflicts are resolved
+    #[clap(long = "continue", group = "op", conflicts_with = "branch")]
+    pub continue_merge: bool,
+
+    /// Abort the current merge and restore the pre-merge state
+    #[clap(long, group = "op", conflicts_with = "branch")]
+    pub abort: bool,
 }
 
 pub async fn execute(args: MergeArgs) {
-    let target_commit_hash = get_target_commit(&args.branch).await;
+    if !util::check_repo_exist() {
+        return;
+    }
+    if args.continue_merge {
+        merge_continue().await;
+        return;
+    }
+    if args.abort {
+        merge_abort().await;
+        return;
+    }
+    if path::merge_head().exists() {
+        eprintln!("fatal: You have not concluded your merge (MERGE_HEAD exists).");
+        eprintln!("Please, commit your changes before you merge.");
+        return;
+    }
+
+    let branch = args.branch.unwrap();
+    let target_commit_hash = get_target_commit(&branch).await;
     if target_commit_hash.is_err() {
         eprintln!("{}", target_commit_hash.err().unwrap());
         return;
     }
-    let commit_hash = target_commit_hash.unwrap();
-
-    let target_commit: Commit = load_object(&commit_hash).unwrap();
-    let current_commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
-    let lca = lca_commit(&current_commit, &target_commit).await;
+    let target_commit = Commit::load(&target_commit_hash.unwrap());
 
-    if lca.is_none() {
-        eprintln!("fatal: fatal: refusing to merge unrelated histories");
+    let current_commit = match Head::current_commit().await {
+        Some(commit_id) => Commit::load(&commit_id),
+        None => {
+            // no commit yet, just move to the target
+            merge_ff(target_commit, &branch).await;
+            return;
+        }
+    };
+    let bases = merge_bases(&current_commit.id, &target_commit.id, &Shallow::list().await);
+    if bases.is_empty() {
+        eprintln!("fatal: refusing to merge unrelated histories");
         return;
     }
-    let lca = lca.unwrap();
 
-    if lca.id == target_commit.id {
+    if bases.contains(&target_commit.id) {
         // no need to merge
         println!("Already up to date.");
-    } else if lca.id == current_commit.id {
+    } else if bases.contains(&current_commit.id) && !args.no_ff {
         println!(
             "Updating {}..{}",
             &current_commit.id.to_plain_str()[..6],
             &target_commit.id.to_plain_str()[..6]
         );
         // fast-forward merge
-        merge_ff(target_commit).await;
+        merge_ff(target_commit, &branch).await;
+    } else if args.ff_only {
+        eprintln!("fatal: Not possible to fast-forward, aborting.");
     } else {
-        // didn't support yet
-        eprintln!("fatal: Not possible to fast-forward merge, try merge manually");
+        let message = match args.message {
+            Some(message) => message,
+            None => merge_message(&branch).await,
+        };
+        merge_three_way(&current_commit, &target_commit, &bases, &branch, message).await;
     }
 }
 
-async fn lca_commit(lhs: &Commit, rhs: &Commit) -> Option<Commit> {
-    let lhs_reachable = log::get_reachable_commits(lhs.id.to_plain_str()).await;
-    let rhs_reachable = log::get_reachable_commits(rhs.id.to_plain_str()).await;
+/// All ancestors of the commit, include itself; the history stops at the `shallow` commits
+pub fn ancestors(commit_id: &SHA1, shallow: &HashSet<SHA1>) -> HashSet<SHA1> {
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([*commit_id]);
+    while let Some(id) = queue.pop_front() {
+        if visited.insert(id) {
+            queue.extend(shallow::parents(&Commit::load(&id), shallow));
+        }
+    }
+    visited
+}
 
-    // Commit `eq` is based on tree_id, so we shouldn't use it here
+/// Find the best common ancestors of two commits, there may be more than one (criss-cross merge)
+/// - a common ancestor is not the best if it's reachable from another common ancestor
+/// - in a shallow repository, the history beyond the `shallow` commits is unknown
+pub fn merge_bases(lhs: &SHA1, rhs: &SHA1, shallow: &HashSet<SHA1>) -> Vec<SHA1> {
+    let lhs_ancestors = ancestors(lhs, shallow);
+    let mut candidates: Vec<Commit> = ancestors(rhs, shallow)
+        .into_iter()
+        .filter(|id| lhs_ancestors.contains(id))
+        .map(|id| Commit::load(&id))
+        .collect();
+    // newer commits first, so most of the older candidates are excluded without walking their history
+    candidates.sort_by_key(|commit| Reverse(commit.committer.timestamp));
 
-    for commit in lhs_reachable.iter() {
-        if commit.id == rhs.id {
-            return Some(commit.to_owned());
+    let mut bases: HashSet<SHA1> = candidates.iter().map(|commit| commit.id).collect();
+    for commit in candidates.iter() {
+        if !bases.contains(&commit.id) {
+            continue; // its ancestors have been excluded
+        }
+        for parent in shallow::parents(commit, shallow).iter() {
+            for id in ancestors(parent, shallow) {
+                bases.remove(&id);
+            }
         }
     }
+    candidates
+        .into_iter()
+        .map(|commit| commit.id)
+        .filter(|id| bases.contains(id))
+        .collect()
+}
+
+/// Get all items (to workdir) of the commit tree
+pub fn commit_tree_items(commit_id: &SHA1) -> Vec<(PathBuf, SHA1)> {
+    Tree::load(&Commit::load(commit_id).tree_id).get_plain_items()
+}
+
+/// Items of the merge base, multiple bases are merged into a virtual one recursively (like Git's `recursive` strategy)
+fn merge_base_items(bases: &[SHA1], shallow: &HashSet<SHA1>) -> Vec<(PathBuf, SHA1)> {
+    let labels = MergeLabels {
+        ours: "Temporary merge branch 1".to_string(),
+        theirs: "Temporary merge branch 2".to_string(),
+    };
+    let mut items = commit_tree_items(&bases[0]);
+    for base in &bases[1..] {
+        let inner_bases = merge_bases(&bases[0], base, shallow);
+        let inner_items = if inner_bases.is_empty() {
+            Vec::new()
+        } else {
+            merge_base_items(&inner_bases, shallow)
+        };
+        let entries = merge_util::merge_trees(&inner_items, &items, &commit_tree_items(base), &labels);
+        items = merge_util::merged_items(&entries);
+    }
+    items
+}
 
-    for commit in rhs_reachable.iter() {
-        if commit.id == lhs.id {
-            return Some(commit.to_owned());
+async fn merge_message(branch: &str) -> String {
+    if Branch::find_branch(branch, None).await.is_some() {
+        format!("Merge branch '{}'", branch)
+    } else if !Branch::search_branch(branch).await.is_empty() {
+        format!("Merge remote-tracking branch '{}'", branch)
+    } else {
+        format!("Merge commit '{}'", branch)
+    }
+}
+
+/// check whether there are uncommitted changes, print the status if any
+async fn check_clean_worktree() -> bool {
+    let unstaged = status::changes_to_be_staged().await;
+    if !unstaged.deleted.is_empty()
+        || !unstaged.modified.is_empty()
+        || !status::changes_to_be_committed().await.is_empty()
+    {
+        status::execute(status::StatusArgs::default()).await;
+        eprintln!("error: Your local changes would be overwritten by merge.");
+        eprintln!("Please commit your changes or stash them before you merge.");
+        return false;
+    }
+    true
+}
+
+/// Untracked files in the worktree which would be overwritten by the merge result
+/// - `ours`: items of the current commit (`HEAD`)
+pub fn untracked_overwritten(entries: &[MergedEntry], ours: &[(PathBuf, SHA1)]) -> Vec<PathBuf> {
+    let ours: HashSet<&PathBuf> = ours.iter().map(|(path, _)| path).collect();
+    entries
+        .iter()
+        .filter(|entry| entry.hash.is_some() && !ours.contains(&entry.path))
+        .filter(|entry| util::workdir_to_absolute(&entry.path).exists())
+        .map(|entry| entry.path.clone())
+        .collect()
+}
+
+async fn merge_three_way(current: &Commit, target: &Commit, bases: &[SHA1], branch: &str, message: String) {
+    if !check_clean_worktree().await {
+        return;
+    }
+    let labels = MergeLabels {
+        ours: "HEAD".to_string(),
+        theirs: branch.to_string(),
+    };
+    let base_items = merge_base_items(bases, &Shallow::list().await);
+    let ours_items = Tree::load(&current.tree_id).get_plain_items();
+    let theirs_items = Tree::load(&target.tree_id).get_plain_items();
+    let entries = merge_util::merge_trees(&base_items, &ours_items, &theirs_items, &labels);
+
+    let overwritten = untracked_overwritten(&entries, &ours_items);
+    if !overwritten.is_empty() {
+        eprintln!("error: The following untracked working tree files would be overwritten by merge:");
+        for file in overwritten {
+            eprintln!("\t{}", util::workdir_to_current(file).display());
         }
+        eprintln!("Please move or remove them before you merge.");
+        eprintln!("Aborting");
+        return;
     }
 
-    for lhs_parent in lhs_reachable.iter() {
-        for rhs_parent in rhs_reachable.iter() {
-            if lhs_parent.id == rhs_parent.id {
-                return Some(lhs_parent.to_owned());
+    let conflicted = apply_merge_result(&entries, &ours_items, &labels);
+    // `commit` will take `MERGE_HEAD` as the second parent
+    fs::write(path::merge_head(), format!("{}\n", target.id.to_plain_str())).unwrap();
+    fs::write(path::merge_msg(), format!("{}\n", message)).unwrap();
+    if conflicted {
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+    } else {
+        commit::execute(CommitArgs {
+            message,
+            allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
+        })
+        .await;
+        println!("Merge made by the 'recursive' strategy.");
+    }
+}
+
+/// Write the merge result to the worktree & index, return `true` if there are conflicts
+/// - `ours`: items of the current commit (`HEAD`), the worktree & index should be clean
+/// - conflicted files are written with conflict markers, the index keeps stage 1/2/3 for them
+pub fn apply_merge_result(entries: &[MergedEntry], ours: &[(PathBuf, SHA1)], labels: &MergeLabels) -> bool {
+    let workdir = util::working_dir();
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    let ours: HashMap<&PathBuf, &SHA1> = ours.iter().map(|(path, hash)| (path, hash)).collect();
+
+    let mut conflicted = false;
+    for entry in entries {
+        let path_str = util::path_to_string(&entry.path);
+        let file_abs = util::workdir_to_absolute(&entry.path);
+        let hash = match entry.hash {
+            Some(hash) => hash,
+            None => {
+                // deleted by the other side
+                if ours.contains_key(&entry.path) {
+                    index.remove(&path_str, 0);
+                    if file_abs.exists() {
+                        fs::remove_file(&file_abs).unwrap();
+                        util::clear_empty_dir(&file_abs);
+                    }
+                }
+                continue;
             }
+        };
+
+        let changed = ours.get(&entry.path) != Some(&&hash);
+        if changed {
+            util::write_file(&Blob::load(&hash).data, &file_abs).unwrap();
+        }
+        if entry.auto_merged {
+            println!("Auto-merging {}", path_str);
+        }
+        match &entry.conflict {
+            None => {
+                if changed {
+                    index.update(IndexEntry::new_from_file(&entry.path, hash, &workdir).unwrap());
+                }
+            }
+            Some(conflict) => {
+                conflicted = true;
+                index.remove(&path_str, 0);
+                for (stage, hash) in [(1, conflict.base), (2, conflict.ours), (3, conflict.theirs)] {
+                    if let Some(hash) = hash {
+                        let size = Blob::load(&hash).data.len() as u32;
+                        let mut index_entry = IndexEntry::new_from_blob(path_str.clone(), hash, size);
+                        index_entry.flags.stage = stage;
+                        index.add(index_entry);
+                    }
+                }
+                match conflict.kind {
+                    ConflictKind::Content => {
+                        println!("CONFLICT (content): Merge conflict in {}", path_str)
+                    }
+                    ConflictKind::AddAdd => {
+                        println!("CONFLICT (add/add): Merge conflict in {}", path_str)
+                    }
+                    ConflictKind::ModifyDelete { deleted_by_us } => {
+                        let (deleted, modified) = if deleted_by_us {
+                            (&labels.ours, &labels.theirs)
+                        } else {
+                            (&labels.theirs, &labels.ours)
+                        };
+                        println!(
+                            "CONFLICT (modify/delete): {} deleted in {} and modified in {}. Version {} of {} left in tree.",
+                            path_str, deleted, modified, modified, path_str
+                        );
+                    }
+                }
+            }
+        }
+    }
+    index.save(&index_file).unwrap();
+    conflicted
+}
+
+/// Apply the changes from commit `base` to `target` on `ours` (3-way merge), return `true` if conflicted
+/// - used to replay a commit (`base` is its parent) or revert it (`base` is the commit itself)
+/// - `base`: `None` for the root commit, which means all files are added
+/// - `ours`: items of the current index & worktree, which should be clean
+pub fn apply_changes(base: Option<&SHA1>, target: &SHA1, ours: &[(PathBuf, SHA1)], labels: &MergeLabels) -> bool {
+    let base_items = base.map(commit_tree_items).unwrap_or_default();
+    let target_items = commit_tree_items(target);
+    let entries = merge_util::merge_trees(&base_items, ours, &target_items, labels);
+    apply_merge_result(&entries, ours, labels)
+}
+
+/// Get the commit being merged (`MERGE_HEAD`), `None` if no merge in progress
+pub fn merge_head() -> Option<SHA1> {
+    let content = fs::read_to_string(path::merge_head()).ok()?;
+    content.trim().parse().ok()
+}
+
+/// Remove `MERGE_HEAD` & `MERGE_MSG`
+pub fn clear_merge_state() {
+    for file in [path::merge_head(), path::merge_msg()] {
+        if file.exists() {
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
+
+async fn merge_continue() {
+    if merge_head().is_none() {
+        eprintln!("fatal: There is no merge in progress (MERGE_HEAD missing).");
+        return;
+    }
+    let index = Index::load(path::index()).unwrap();
+    if !index.unmerged_files().is_empty() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        eprintln!("fatal: Exiting because of an unresolved conflict.");
+        return;
+    }
+    let message = fs::read_to_string(path::merge_msg()).unwrap_or_default();
+    commit::execute(CommitArgs {
+        message: message.trim_end().to_string(),
+        allow_empty: true,
+        gpg_sign: None,
+        no_gpg_sign: false,
+    })
+    .await;
+}
+
+async fn merge_abort() {
+    if merge_head().is_none() {
+        eprintln!("fatal: There is no merge to abort (MERGE_HEAD missing).");
+        return;
+    }
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    let head_files: HashSet<PathBuf> = match Head::current_commit().await {
+        Some(commit_id) => commit_tree_items(&commit_id).into_iter().map(|(path, _)| path).collect(),
+        None => HashSet::new(),
+    };
+    for file in index.unmerged_files() {
+        let file_str = util::path_to_string(&file);
+        for stage in 1..=3 {
+            index.remove(&file_str, stage);
+        }
+        // conflicted files are not tracked (stage 0), `restore` won't remove the ones not in HEAD
+        let file_abs = util::workdir_to_absolute(&file);
+        if !head_files.contains(&file) && file_abs.exists() {
+            fs::remove_file(&file_abs).unwrap();
+            util::clear_empty_dir(&file_abs);
         }
     }
-    None
+    index.save(&index_file).unwrap();
+
+    // restore all files to worktree & index from HEAD
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: None,
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+    clear_merge_state();
 }
 
 /// try merge in fast-forward mode, if it's not possible, do nothing
-async fn merge_ff(commit: Commit) {
+async fn merge_ff(commit: Commit, branch: &str) {
     println!("Fast-forward");
     // fast-forward merge
+    let old = Head::current_commit().await;
     let head = Head::current().await;
     match head {
         Head::Branch(branch_name) => {
@@ -94,6 +443,7 @@ async fn merge_ff(commit: Commit) {
             Head::update(Head::Detached(commit.id), None).await;
         }
     }
+    Reflog::log_head_update(old, commit.id, &format!("merge {}: Fast-forward", branch)).await;
     // change the working directory to the commit
     // restore all files to worktree from HEAD
     restore::execute(RestoreArgs {
@@ -104,3 +454,125 @@ async fn merge_ff(commit: Commit) {
     })
     .await;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::{add, add::AddArgs, branch, switch};
+    use crate::utils::test;
+
+    async fn switch_to(branch: &str) {
+        switch::execute(switch::SwitchArgs::parse_from(["switch", branch])).await;
+    }
+
+    fn merge_args(branch: &str) -> MergeArgs {
+        MergeArgs::parse_from(["merge", branch])
+    }
+
+    /// `master`: base -> ours, `feature`: base -> theirs
+    async fn setup_diverged(ours: &str, theirs: &str) {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "1\n2\n3\n4\n5\n", "base").await;
+        branch::create_branch("feature".to_string(), None).await;
+
+        switch_to("feature").await;
+        test::commit_file("b.txt", "b\n", "add b").await;
+        test::commit_file("a.txt", theirs, "theirs").await;
+
+        switch_to("master").await;
+        test::commit_file("a.txt", ours, "ours").await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_bases() {
+        setup_diverged("0\n1\n2\n3\n4\n5\n", "1\n2\n3\n4\n5\n6\n").await;
+        let ours = Head::current_commit().await.unwrap();
+        let theirs = Branch::find_branch("feature", None).await.unwrap().commit;
+        let base = Commit::load(&ours).parent_commit_ids[0];
+        let shallow = HashSet::new();
+        assert_eq!(merge_bases(&ours, &theirs, &shallow), vec![base]);
+        assert_eq!(merge_bases(&ours, &base, &shallow), vec![base]);
+        // the history beyond a shallow commit is unknown
+        assert!(merge_bases(&ours, &theirs, &HashSet::from([ours, theirs])).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_merge_three_way_clean() {
+        setup_diverged("0\n1\n2\n3\n4\n5\n", "1\n2\n3\n4\n5\n6\n").await;
+        let ours = Head::current_commit().await.unwrap();
+        let theirs = Branch::find_branch("feature", None).await.unwrap().commit;
+        execute(merge_args("feature")).await;
+
+        let merge_commit = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(merge_commit.parent_commit_ids, vec![ours, theirs]);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "0\n1\n2\n3\n4\n5\n6\n");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "b\n");
+        assert!(!path::merge_head().exists());
+        assert!(status::changes_to_be_staged().await.modified.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_merge_conflict_continue() {
+        setup_diverged("1\n2\nours\n4\n5\n", "1\n2\ntheirs\n4\n5\n").await;
+        let ours = Head::current_commit().await.unwrap();
+        execute(merge_args("feature")).await;
+
+        assert_eq!(Head::current_commit().await.unwrap(), ours); // not committed
+        assert_eq!(
+            fs::read_to_string("a.txt").unwrap(),
+            "1\n2\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n4\n5\n"
+        );
+        let index = Index::load(path::index()).unwrap();
+        assert_eq!(index.unmerged_files(), vec![PathBuf::from("a.txt")]);
+        for stage in 1..=3 {
+            assert!(index.tracked("a.txt", stage));
+        }
+        assert!(!index.tracked("a.txt", 0));
+        assert!(index.tracked("b.txt", 0));
+
+        // can't continue before resolving the conflict
+        execute(MergeArgs::parse_from(["merge", "--continue"])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), ours);
+
+        test::ensure_file("a.txt", Some("1\n2\nresolved\n4\n5\n"));
+        add::execute(AddArgs::parse_from(["add", "a.txt"])).await;
+        execute(MergeArgs::parse_from(["merge", "--continue"])).await;
+        let merge_commit = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(merge_commit.parent_commit_ids.len(), 2);
+        assert!(merge_commit.message.contains("Merge branch 'feature'"));
+        assert!(!path::merge_head().exists());
+    }
+
+    #[tokio::test]
+    async fn test_merge_conflict_abort() {
+        setup_diverged("1\n2\nours\n4\n5\n", "1\n2\ntheirs\n4\n5\n").await;
+        let ours = Head::current_commit().await.unwrap();
+        execute(merge_args("feature")).await;
+        execute(MergeArgs::parse_from(["merge", "--abort"])).await;
+
+        assert_eq!(Head::current_commit().await.unwrap(), ours);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "1\n2\nours\n4\n5\n");
+        assert!(!PathBuf::from("b.txt").exists());
+        assert!(Index::load(path::index()).unwrap().unmerged_files().is_empty());
+        assert!(!path::merge_head().exists());
+    }
+
+    #[tokio::test]
+    async fn test_merge_ff_options() {
+        setup_diverged("0\n1\n2\n3\n4\n5\n", "1\n2\n3\n4\n5\n6\n").await;
+        let ours = Head::current_commit().await.unwrap();
+        execute(MergeArgs::parse_from(["merge", "--ff-only", "feature"])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), ours);
+
+        // `feature` is behind `master` after the merge, so it can be fast-forwarded
+        execute(merge_args("feature")).await;
+        let merged = Head::current_commit().await.unwrap();
+        switch_to("feature").await;
+        let theirs = Head::current_commit().await.unwrap();
+        execute(MergeArgs::parse_from(["merge", "--no-ff", "master"])).await;
+        let merge_commit = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(merge_commit.parent_commit_ids, vec![theirs, merged]);
+        assert_eq!(merge_commit.tree_id, Commit::load(&merged).tree_id);
+        assert!(MergeArgs::try_parse_from(["merge", "--ff-only", "--no-ff", "master"]).is_err());
+    }
+}
@@ -1,26 +1,40 @@
 pub mod add;
+pub mod blame;
 pub mod branch;
+pub mod cat_file;
+pub mod cherry_pick;
 pub mod clone;
 pub mod commit;
+pub mod config;
+pub mod credential;
+pub mod diff;
 pub mod fetch;
+pub mod fsck;
+pub mod gc;
 pub mod index_pack;
 pub mod init;
 pub mod log;
 pub mod merge;
 pub mod pull;
+pub mod rebase;
+pub mod reflog;
 pub mod push;
 pub mod remote;
 pub mod remove;
+pub mod repack;
+pub mod reset;
 pub mod restore;
+pub mod revert;
+pub mod show;
+pub mod stash;
 pub mod status;
 pub mod switch;
+pub mod tag;
+pub mod verify_commit;
+pub mod verify_tag;
 
-use crate::internal::protocol::https_client::BasicAuth;
 use crate::utils::util;
 use mercury::{errors::GitError, hash::SHA1, internal::object::ObjectTrait};
-use rpassword::read_password;
-use std::io;
-use std::io::Write;
 
 // impl load for all objects
 fn load_object<T>(hash: &SHA1) -> Result<T, GitError>
@@ -43,28 +57,6 @@ where
     Ok(())
 }
 
-/// Ask for username and password (CLI interaction)
-fn ask_username_password() -> (String, String) {
-    print!("username: ");
-    // Normally your OS will buffer output by line when it's connected to a terminal,
-    // which is why it usually flushes when a newline is written to stdout.
-    io::stdout().flush().unwrap(); // ensure the prompt is shown
-    let mut username = String::new();
-    io::stdin().read_line(&mut username).unwrap();
-    username = username.trim().to_string();
-
-    print!("password: ");
-    io::stdout().flush().unwrap();
-    let password = read_password().unwrap(); // hide password
-    (username, password)
-}
-
-/// same as ask_username_password, but return BasicAuth
-pub fn ask_basic_auth() -> BasicAuth {
-    let (username, password) = ask_username_password();
-    BasicAuth { username, password }
-}
-
 /// Format commit message with GPG signature<br>
 /// There must be a `blank line`(\n) before `message`, or remote unpack failed.<br>
 /// If there is `GPG signature`,
@@ -79,22 +71,20 @@ pub fn format_commit_msg(msg: &str, gpg_sig: Option<&str>) -> String {
         }
     }
 }
-/// parse commit message
+/// parse commit message, the signature may be OpenPGP or SSH
 pub fn parse_commit_msg(msg_gpg: &str) -> (String, Option<String>) {
-    const GPG_SIG_START: &str = "gpgsig -----BEGIN PGP SIGNATURE-----";
-    const GPG_SIG_END: &str = "-----END PGP SIGNATURE-----";
-    let gpg_start = msg_gpg.find(GPG_SIG_START);
-    let gpg_end = msg_gpg.find(GPG_SIG_END).map(|end| end + GPG_SIG_END.len());
-    let gpg_sig = match (gpg_start, gpg_end) {
-        (Some(start), Some(end)) => {
-            if start < end {
-                Some(msg_gpg[start..end].to_string())
-            } else {
-                None
-            }
-        }
-        _ => None,
-    };
+    const GPG_SIG_MARKERS: [(&str, &str); 2] = [
+        ("gpgsig -----BEGIN PGP SIGNATURE-----", "-----END PGP SIGNATURE-----"),
+        ("gpgsig -----BEGIN SSH SIGNATURE-----", "-----END SSH SIGNATURE-----"),
+    ];
+    let (gpg_sig, gpg_end) = GPG_SIG_MARKERS
+        .iter()
+        .find_map(|(start_marker, end_marker)| {
+            let start = msg_gpg.find(start_marker)?;
+            let end = msg_gpg.find(end_marker).map(|end| end + end_marker.len())?;
+            (start < end).then(|| (msg_gpg[start..end].to_string(), end))
+        })
+        .unzip();
     match gpg_sig {
         Some(gpg) => {
             // skip the leading '\n\n' (blank line)
@@ -1,31 +1,170 @@
-use crate::internal::{config::Config, head::Head};
-
-use super::{fetch, merge};
 use clap::Parser;
+
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::utils::util;
+
+use super::{fetch, merge, rebase};
+
+const NO_TRACKING: &str = "There is no tracking information for the current branch.\n\
+    hint: set up a tracking branch with `libra branch --set-upstream-to=<remote>/<branch>`";
+
 #[derive(Parser, Debug)]
-pub struct PullArgs;
+pub struct PullArgs {
+    /// The remote to pull from, the remote of the upstream by default
+    pub repository: Option<String>,
+
+    /// The remote branch to integrate, the upstream of the current branch by default
+    #[clap(requires = "repository")]
+    pub branch: Option<String>,
+
+    /// Rebase the current branch onto the remote branch instead of merging it
+    #[clap(short, long, group = "mode")]
+    pub rebase: bool,
+
+    /// Refuse to integrate unless the current branch can be fast-forwarded
+    #[clap(long, group = "mode")]
+    pub ff_only: bool,
+
+    /// Create a merge commit even when the remote branch can be fast-forwarded to
+    #[clap(long, group = "mode")]
+    pub no_ff: bool,
+}
 
 pub async fn execute(args: PullArgs) {
-    let _ = args;
-    let fetch_args = fetch::FetchArgs::parse_from(Vec::<String>::new());
-    fetch::execute(fetch_args).await;
-
-    let head = Head::current().await;
-    match head {
-        Head::Branch(name) => match Config::branch_config(&name).await {
-            Some(branch_config) => {
-                let merge_args = merge::MergeArgs {
-                    branch: format!("{}/{}", branch_config.remote, branch_config.merge),
-                };
-                merge::execute(merge_args).await;
-            }
-            None => {
-                eprintln!("There is no tracking information for the current branch.");
-                eprintln!("hint: set up a tracking branch with `libra branch --set-upstream-to=<remote>/<branch>`")
-            }
-        },
-        _ => {
-            eprintln!("You are not currently on a branch.");
+    if !util::check_repo_exist() {
+        return;
+    }
+    let (remote, branch) = match resolve_upstream(args.repository, args.branch).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let remote_config = match Config::remote_config(&remote).await {
+        Some(config) => config,
+        None => {
+            eprintln!("fatal: '{}' does not appear to be a git repository", remote);
+            return;
+        }
+    };
+    // the remote-tracking branch is stale if the fetch failed
+    if let Err(e) = fetch::fetch_repository(&remote_config, None).await {
+        eprintln!("{}", e);
+        return;
+    }
+    if Branch::find_branch(&branch, Some(&remote)).await.is_none() {
+        eprintln!("fatal: couldn't find remote ref {}", branch);
+        return;
+    }
+
+    let tracking = format!("{}/{}", remote, branch);
+    // there is nothing to rebase without a commit, just take the remote branch
+    if args.rebase && Head::current_commit().await.is_some() {
+        rebase::execute(rebase::RebaseArgs {
+            upstream: Some(tracking),
+            interactive: false,
+            continue_rebase: false,
+            skip: false,
+            abort: false,
+        })
+        .await;
+    } else {
+        merge::execute(merge::MergeArgs {
+            branch: Some(tracking),
+            message: Some(format!(
+                "Merge branch '{}' of {}",
+                branch, remote_config.url
+            )),
+            continue_merge: false,
+            abort: false,
+            ff_only: args.ff_only,
+            no_ff: args.no_ff,
+        })
+        .await;
+    }
+}
+
+/// The remote & the branch to pull, from the arguments or the upstream of the current branch
+async fn resolve_upstream(
+    repository: Option<String>,
+    branch: Option<String>,
+) -> Result<(String, String), String> {
+    let upstream = match Head::current().await {
+        Head::Branch(name) => Config::branch_config(&name).await.ok_or(NO_TRACKING),
+        Head::Detached(_) => Err("You are not currently on a branch."),
+    };
+    match (repository, branch, upstream) {
+        (Some(remote), Some(branch), _) => Ok((remote, branch)),
+        // the upstream branch is used only if it's on the remote
+        (Some(remote), None, Ok(upstream)) if upstream.remote == remote => {
+            Ok((remote, upstream.merge))
         }
+        (Some(remote), None, _) => Err(format!(
+            "You asked to pull from the remote '{}', but did not specify a branch.",
+            remote
+        )),
+        (None, _, upstream) => upstream
+            .map(|upstream| (upstream.remote, upstream.merge))
+            .map_err(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::internal::config::ConfigScope;
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_resolve_upstream() {
+        test::setup_with_new_libra().await;
+        assert!(resolve_upstream(None, None).await.is_err());
+        assert!(resolve_upstream(Some("origin".to_string()), None)
+            .await
+            .is_err());
+        assert_eq!(
+            resolve_upstream(Some("origin".to_string()), Some("dev".to_string())).await,
+            Ok(("origin".to_string(), "dev".to_string()))
+        );
+
+        // malformed upstream configs are ignored
+        Config::insert("branch", Some("master"), "remote", "upstream").await;
+        assert!(resolve_upstream(None, None).await.is_err());
+        Config::insert("branch", Some("master"), "merge", "main").await;
+        assert!(resolve_upstream(None, None).await.is_err());
+        Config::unset(ConfigScope::Local, "branch", Some("master"), "merge").await;
+
+        Config::insert("branch", Some("master"), "merge", "refs/heads/main").await;
+        let expected = Ok(("upstream".to_string(), "main".to_string()));
+        assert_eq!(resolve_upstream(None, None).await, expected);
+        assert_eq!(
+            resolve_upstream(Some("upstream".to_string()), None).await,
+            expected
+        );
+        assert!(resolve_upstream(Some("origin".to_string()), None)
+            .await
+            .is_err());
+        assert!(PullArgs::try_parse_from(["pull", "--rebase", "--no-ff"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pull_abort_on_fetch_failure() {
+        test::setup_with_new_libra().await;
+        let base = test::commit_file("a.txt", "base\n", "base").await;
+        let remote_commit = test::commit_file("a.txt", "remote\n", "remote").await;
+        // a stale `origin/master` ahead of the local branch, the remote itself doesn't exist
+        Branch::update_branch("master", &base.to_string(), None).await;
+        Branch::update_branch("master", &remote_commit.to_string(), Some("origin")).await;
+        Config::insert("remote", Some("origin"), "url", "/nonexistent/libra_pull_remote").await;
+        Config::insert("branch", Some("master"), "remote", "origin").await;
+        Config::insert("branch", Some("master"), "merge", "refs/heads/master").await;
+
+        let remote_config = Config::remote_config("origin").await.unwrap();
+        assert!(fetch::fetch_repository(&remote_config, None).await.is_err());
+        execute(PullArgs::try_parse_from(["pull"]).unwrap()).await;
+        assert_eq!(Head::current_commit().await, Some(base));
     }
 }
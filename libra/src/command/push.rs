@@ -1,64 +1,119 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::IsTerminal;
 use std::str::FromStr;
-use bytes::BytesMut;
-use clap::Parser;
-use tokio::sync::mpsc;
-use url::Url;
+use bytes::{Bytes, BytesMut};
+use clap::{ArgGroup, Parser};
 use ceres::protocol::ServiceType::ReceivePack;
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
-use mercury::errors::GitError;
 use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
-use mercury::internal::pack::encode::PackEncoder;
 use mercury::internal::pack::entry::Entry;
-use crate::command::{ask_basic_auth, branch};
+use crate::command::{branch, load_object, merge, tag};
 use crate::internal::branch::Branch;
 use crate::internal::config::Config;
+use crate::internal::credential;
 use crate::internal::head::Head;
-use crate::internal::protocol::https_client::{BasicAuth, HttpsClient};
-use crate::internal::protocol::ProtocolClient;
+use crate::internal::shallow::{self, Shallow};
+use crate::internal::tag::Tag;
+use crate::internal::protocol::https_client::DiscoveredReference;
+use crate::internal::protocol::local_client::{encode_pack, DEFAULT_WINDOW};
+use crate::internal::protocol::{self, RemoteClient};
 use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::utils::progress::Progress;
+use crate::utils::util;
 
 #[derive(Parser, Debug)]
-pub struct PushArgs { // TODO --force
+#[clap(group(ArgGroup::new("what").args(["refspec", "tags"]).multiple(true)))]
+pub struct PushArgs {
     /// repository, e.g. origin
-    #[clap(requires("refspec"))]
+    #[clap(requires("what"))]
     repository: Option<String>,
-    /// ref to push, e.g. master
+    /// refs to push, `[+]<src>[:<dst>]`, e.g. master, a tag name or `master:main`
+    /// - `+` allows a non-fast-forward update, an empty `<src>` deletes `<dst>` from the remote
     #[clap(requires("repository"))]
-    refspec: Option<String>,
+    refspec: Vec<String>,
 
     #[clap(long, short = 'u', requires("refspec"), requires("repository"))]
     set_upstream: bool,
+
+    /// allow non-fast-forward updates and overwriting tags, may lose the commits of the remote
+    #[clap(long, short)]
+    force: bool,
+
+    /// force only if the remote ref is still the expected value, `<ref>:<expect>`;
+    /// the remote-tracking branch is expected if not specified
+    #[clap(long, value_name = "REF[:EXPECT]", num_args(0..=1), require_equals(true), default_missing_value(""), conflicts_with("force"))]
+    force_with_lease: Option<String>,
+
+    /// delete the refs from the remote, the refspecs are the remote refs
+    #[clap(long, short, requires("refspec"), conflicts_with_all(["tags", "set_upstream"]))]
+    delete: bool,
+
+    /// push all tags, existing remote tags are not overwritten without `--force`
+    #[clap(long)]
+    tags: bool,
+}
+
+/// An update of a remote ref, parsed from the refspec
+#[derive(Debug, Clone, PartialEq)]
+struct RefUpdate {
+    /// local name shown in the report, `None` if deleting
+    src: Option<String>,
+    /// remote ref, e.g. `refs/heads/master`, may be short before [`qualify_dst`]
+    dst: String,
+    /// the commit or tag object to push, zero if deleting
+    new: SHA1,
+    /// `+` in the refspec or `--force`
+    force: bool,
+}
+
+/// The result of an update, printed like Git
+#[derive(Debug, PartialEq)]
+enum RefStatus {
+    UpToDate,
+    /// rejected locally before sending, e.g. `non-fast-forward`
+    Rejected(&'static str),
+    /// rejected by the server, with its reason
+    RemoteRejected(String),
+    Ok { old: SHA1, forced: bool },
 }
 
 pub async fn execute(args: PushArgs) {
-    if args.repository.is_some() ^ args.refspec.is_some() { // must provide both or none
+    if args.repository.is_some() && args.refspec.is_empty() && !args.tags { // must provide both or none
         eprintln!("fatal: both repository and refspec should be provided");
         return;
     }
-    if args.set_upstream && args.refspec.is_none() {
+    if args.set_upstream && args.refspec.is_empty() {
         eprintln!("fatal: --set-upstream requires a branch name");
         return;
     }
 
-    let branch = match Head::current().await {
-        Head::Branch(name) => name,
-        Head::Detached(_) => panic!("fatal: HEAD is detached while pushing"),
+    let current_branch = match Head::current().await {
+        Head::Branch(name) => Some(name),
+        Head::Detached(_) => None,
     };
 
     let repository = match args.repository {
         Some(repo) => repo,
         None => {
             // e.g. [branch "master"].remote = origin
-            let remote = Config::get("branch", Some(&branch), "remote").await;
-            if let Some(remote) = remote {
-                remote
-            } else {
-                eprintln!("fatal: no remote configured for branch '{}'", branch);
-                return;
+            let remote = match &current_branch {
+                Some(branch) => Config::get("branch", Some(branch), "remote").await,
+                None => None,
+            };
+            match (remote, &current_branch) {
+                (Some(remote), _) => remote,
+                (None, Some(branch)) => {
+                    eprintln!("fatal: no remote configured for branch '{}'", branch);
+                    return;
+                }
+                (None, None) => {
+                    eprintln!("fatal: You are not currently on a branch.");
+                    return;
+                }
             }
         }
     };
@@ -69,102 +124,446 @@ pub async fn execute(args: PushArgs) {
     }
     let repo_url = repo_url.unwrap();
 
-    let branch = args.refspec.unwrap_or(branch);
-    let commit_hash = Branch::find_branch(&branch, None).await.unwrap().commit.to_plain_str();
+    let mut refspecs = args.refspec.clone();
+    if refspecs.is_empty() && !args.tags {
+        match &current_branch {
+            Some(branch) => refspecs.push(branch.clone()),
+            None => {
+                eprintln!("fatal: You are not currently on a branch.");
+                return;
+            }
+        }
+    }
+    let mut updates = Vec::new();
+    for refspec in &refspecs {
+        let update = match args.delete {
+            true => Ok(RefUpdate {
+                src: None,
+                dst: refspec.clone(),
+                new: SHA1::default(),
+                force: false,
+            }),
+            false => parse_refspec(refspec, args.force).await,
+        };
+        match update {
+            Ok(update) => updates.push(update),
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+    }
+    if args.tags {
+        for tag in Tag::list_tags().await {
+            let dst = format!("refs/tags/{}", tag.name);
+            if updates.iter().all(|update| update.dst != dst) {
+                updates.push(RefUpdate {
+                    src: Some(tag.name),
+                    dst,
+                    new: tag.object,
+                    force: args.force,
+                });
+            }
+        }
+    }
 
-    println!("pushing {}({}) to {}({})", branch, commit_hash, repository, repo_url);
+    let lease = match &args.force_with_lease {
+        Some(lease) => match parse_lease(lease).await {
+            Ok(lease) => Some(lease),
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+        None => None,
+    };
 
-    let url = Url::parse(&repo_url).unwrap();
-    let client = HttpsClient::from_url(&url);
-    let mut refs = client.discovery_reference(ReceivePack, None).await;
-    let mut auth: Option<BasicAuth> = None;
-    while let Err(e) = refs { // retry if unauthorized
-        if let GitError::UnAuthorized(_) = e {
-            auth = Some(ask_basic_auth());
-            refs = client.discovery_reference(ReceivePack, auth.clone()).await;
-        } else {
+    let client = match protocol::parse_url(&repo_url).and_then(|url| RemoteClient::from_url(&url)) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let (refs, auth) = match credential::with_credential(&repo_url, |auth| {
+        client.discovery_reference(ReceivePack, auth)
+    })
+    .await
+    {
+        Ok(refs) => refs,
+        Err(e) => {
             eprintln!("fatal: {}", e);
             return;
         }
+    };
+    let remote_ref = |name: &str| {
+        refs.iter()
+            .find(|r| r._ref == name)
+            .and_then(|r| SHA1::from_str(&r._hash).ok())
+            .unwrap_or_default()
+    };
+
+    for update in updates.iter_mut() {
+        let remote_names = refs.iter().map(|r| r._ref.as_str()).collect::<Vec<_>>();
+        match qualify_dst(update, &remote_names) {
+            Ok(dst) => update.dst = dst,
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("error: failed to push some refs to '{}'", repo_url);
+                client.disconnect().await;
+                return;
+            }
+        }
+    }
+    if updates.is_empty() {
+        println!("Everything up-to-date");
+        client.disconnect().await;
+        return;
     }
-    let refs = refs.unwrap();
 
-    let tracked_branch = Config::get("branch", Some(&branch), "merge")
-        .await // New branch may not have tracking branch
-        .unwrap_or_else(|| format!("refs/heads/{}", branch));
+    let shallow = Shallow::list().await;
+    let storage = util::objects_storage();
+    let mut statuses = Vec::new();
+    for update in &updates {
+        let old = remote_ref(&update.dst);
+        let expect = match &lease {
+            Some((None, _)) => Some(tracking_commit(&repository, &update.dst).await),
+            Some((Some(name), expect)) if short_ref(name) == short_ref(&update.dst) => match expect {
+                Some(expect) => Some(*expect),
+                None => Some(tracking_commit(&repository, &update.dst).await),
+            },
+            _ => None,
+        };
+        let status = if old == update.new {
+            RefStatus::UpToDate
+        } else if expect.is_some_and(|expect| expect != old) {
+            RefStatus::Rejected("stale info")
+        } else if update.new == SHA1::default() || old == SHA1::default() {
+            RefStatus::Ok { old, forced: false }
+        } else if update.dst.starts_with("refs/tags/") {
+            match update.force || expect.is_some() {
+                true => RefStatus::Ok { old, forced: true },
+                false => RefStatus::Rejected("already exists"),
+            }
+        } else if !storage.exist(&old) {
+            match update.force || expect.is_some() {
+                true => RefStatus::Ok { old, forced: true },
+                false => RefStatus::Rejected("fetch first"),
+            }
+        } else {
+            let fast_forward = merge::ancestors(&tag::peel_to_commit(update.new), &shallow).contains(&old);
+            match fast_forward || update.force || expect.is_some() {
+                true => RefStatus::Ok { old, forced: !fast_forward },
+                false => RefStatus::Rejected("non-fast-forward"),
+            }
+        };
+        statuses.push(status);
+    }
 
-    let tracked_ref = refs.iter().find(|r| r._ref == tracked_branch);
-    // [0; 20] if new branch
-    let remote_hash = tracked_ref.map(|r| r._hash.clone()).unwrap_or(SHA1::default().to_plain_str());
-    if remote_hash == commit_hash {
-        println!("Everything up-to-date");
+    let pending = updates
+        .iter()
+        .zip(statuses.iter())
+        .filter(|(_, status)| matches!(status, RefStatus::Ok { .. }))
+        .map(|(update, _)| update)
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        print_report(&repo_url, &updates, &statuses);
+        client.disconnect().await;
         return;
     }
 
+    // the progress of the server is useless if not shown
+    let capabilities = match std::io::stderr().is_terminal() {
+        true => "report-status side-band-64k",
+        false => "report-status side-band-64k quiet",
+    };
     let mut data = BytesMut::new();
-    add_pkt_line_string(&mut data, format!("{} {} {}\0report-status\n",
-                                           remote_hash,
-                                           commit_hash,
-                                           tracked_branch));
+    for (i, update) in pending.iter().enumerate() {
+        let line = format!(
+            "{} {} {}",
+            remote_ref(&update.dst).to_plain_str(),
+            update.new.to_plain_str(),
+            update.dst
+        );
+        match i {
+            0 => add_pkt_line_string(&mut data, format!("{}\0{}\n", line, capabilities)),
+            _ => add_pkt_line_string(&mut data, format!("{}\n", line)),
+        }
+    }
     data.extend_from_slice(b"0000");
     tracing::debug!("{:?}", data);
 
-    // TODO 考虑remote有多个refs，可以少发一点commits
-    let objs = incremental_objs(
-        SHA1::from_str(&commit_hash).unwrap(),
-        SHA1::from_str(&remote_hash).unwrap()
-    );
-    println!("Counting objects: {}", objs.len());
+    // no pack is sent if only deleting
+    if pending.iter().any(|update| update.new != SHA1::default()) {
+        let mut objs = HashSet::new();
+        let progress = Progress::new("Counting objects", None);
+        for update in pending.iter().filter(|update| update.new != SHA1::default()) {
+            let new_objs = ref_objs(update.new, remote_ref(&update.dst), &refs, &shallow);
+            progress.inc(new_objs.len());
+            objs.extend(new_objs);
+        }
+        progress.finish();
+        let pack_data = match encode_pack(objs.into_iter().collect(), DEFAULT_WINDOW).await {
+            Ok(pack_data) => pack_data,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                client.disconnect().await;
+                return;
+            }
+        };
+        data.extend_from_slice(&pack_data);
+    }
+
+    let data = match client.send_pack(data.freeze(), auth).await {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let report = match parse_report(data) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("error: failed to push some refs to '{}'", repo_url);
+            return;
+        }
+    };
+    for (update, status) in updates.iter().zip(statuses.iter_mut()) {
+        if !matches!(status, RefStatus::Ok { .. }) {
+            continue;
+        }
+        match report.get(&update.dst) {
+            Some(Ok(())) => {}
+            Some(Err(reason)) => *status = RefStatus::RemoteRejected(reason.clone()),
+            None => *status = RefStatus::RemoteRejected("no report from the remote".to_string()),
+        }
+        if !matches!(status, RefStatus::Ok { .. }) {
+            continue;
+        }
+        // keep the remote-tracking branch in sync, as if fetched
+        if let Some(name) = update.dst.strip_prefix("refs/heads/") {
+            if update.new == SHA1::default() {
+                if Branch::find_branch(name, Some(&repository)).await.is_some() {
+                    Branch::delete_branch(name, Some(&repository)).await;
+                }
+            } else {
+                Branch::update_branch(name, &update.new.to_plain_str(), Some(&repository)).await;
+            }
+        }
+    }
+    let success = print_report(&repo_url, &updates, &statuses);
 
-    // let (tx, rx) = mpsc::channel::<Entry>();
-    let (entry_tx, entry_rx) = mpsc::channel(1_000_000);
-    let (stream_tx, mut stream_rx) = mpsc::channel(1_000_000);
-    
-    let encoder = PackEncoder::new(objs.len(), 5, stream_tx);
-    encoder.encode_async(entry_rx).await.unwrap();
+    // set after push success
+    if args.set_upstream && success {
+        if let Some(RefUpdate { src: Some(src), dst, .. }) = updates.first() {
+            if let Some(dst) = dst.strip_prefix("refs/heads/") {
+                branch::set_upstream(src, &format!("{}/{}", repository, dst)).await;
+            }
+        }
+    }
+}
 
-    for entry in objs {
-        // TODO progress bar
-        entry_tx.send(entry).await.unwrap();
+/// Parse `[+]<src>[:<dst>]`, `<src>` is a local branch or tag, an empty `<src>` deletes `<dst>`
+/// - `<dst>` defaults to the upstream (`branch.<name>.merge`) of the branch or the same name
+async fn parse_refspec(refspec: &str, force: bool) -> Result<RefUpdate, String> {
+    let (force, refspec) = match refspec.strip_prefix('+') {
+        Some(refspec) => (true, refspec),
+        None => (force, refspec),
+    };
+    let (src, dst) = match refspec.split_once(':') {
+        Some((src, dst)) => (src, Some(dst)),
+        None => (refspec, None),
+    };
+    if src.is_empty() {
+        return match dst {
+            Some(dst) if !dst.is_empty() => Ok(RefUpdate {
+                src: None,
+                dst: dst.to_string(),
+                new: SHA1::default(),
+                force,
+            }),
+            _ => Err(format!("fatal: invalid refspec '{}'", refspec)),
+        };
     }
-    drop(entry_tx);
-    println!("Delta compression done.");
 
-    let mut pack_data = Vec::new();
-    while let Some(chunk) = stream_rx.recv().await {
-        pack_data.extend(chunk);
+    let branch_name = src.strip_prefix("refs/heads/").unwrap_or(src);
+    let tag_name = src.strip_prefix("refs/tags/").unwrap_or(src);
+    let (src, new, is_tag) = if let Some(branch) = Branch::find_branch(branch_name, None).await.filter(|_| !src.starts_with("refs/tags/")) {
+        (branch_name, branch.commit, false)
+    } else if let Some(tag) = Tag::find_tag(tag_name).await.filter(|_| !src.starts_with("refs/heads/")) {
+        (tag_name, tag.object, true)
+    } else {
+        return Err(format!("error: src refspec {} does not match any", src));
+    };
+    let dst = match dst {
+        Some("") => return Err(format!("fatal: invalid refspec '{}'", refspec)),
+        Some(dst) if dst.starts_with("refs/") => dst.to_string(),
+        Some(dst) if is_tag => format!("refs/tags/{}", dst),
+        Some(dst) => format!("refs/heads/{}", dst),
+        None if is_tag => format!("refs/tags/{}", src),
+        None => Config::get("branch", Some(src), "merge")
+            .await // New branch may not have tracking branch
+            .unwrap_or_else(|| format!("refs/heads/{}", src)),
+    };
+    Ok(RefUpdate {
+        src: Some(src.to_string()),
+        dst,
+        new,
+        force,
+    })
+}
+
+/// The full name of the remote ref to delete, a short name is looked up in the remote refs
+fn qualify_dst(update: &RefUpdate, remote_refs: &[&str]) -> Result<String, String> {
+    if update.src.is_some() || update.dst.starts_with("refs/") {
+        return Ok(update.dst.clone());
     }
-    data.extend_from_slice(&pack_data);
+    [format!("refs/heads/{}", update.dst), format!("refs/tags/{}", update.dst)]
+        .into_iter()
+        .find(|name| remote_refs.contains(&name.as_str()))
+        .ok_or_else(|| format!("error: unable to delete '{}': remote ref does not exist", update.dst))
+}
 
-    let res = client.send_pack(data.freeze(), auth).await.unwrap();
+/// Parse the value of `--force-with-lease`: `""` (all refs), `<ref>` or `<ref>:<expect>`
+/// - `None` of the expected value means the remote-tracking branch, an empty `<expect>` means the ref must not exist
+async fn parse_lease(lease: &str) -> Result<(Option<String>, Option<SHA1>), String> {
+    if lease.is_empty() {
+        return Ok((None, None));
+    }
+    match lease.split_once(':') {
+        None => Ok((Some(lease.to_string()), None)),
+        Some((name, "")) => Ok((Some(name.to_string()), Some(SHA1::default()))),
+        Some((name, expect)) => {
+            let expect = match SHA1::from_str(expect) {
+                Ok(expect) => expect,
+                Err(_) => util::get_commit_base(expect).await?,
+            };
+            Ok((Some(name.to_string()), Some(expect)))
+        }
+    }
+}
 
-    if res.status() != 200 {
-        eprintln!("status code: {}", res.status());
+/// The remote-tracking branch of the remote ref, zero if not tracked
+async fn tracking_commit(repository: &str, dst: &str) -> SHA1 {
+    match dst.strip_prefix("refs/heads/") {
+        Some(name) => Branch::find_branch(name, Some(repository))
+            .await
+            .map(|branch| branch.commit)
+            .unwrap_or_default(),
+        None => SHA1::default(),
     }
-    let mut data = res.bytes().await.unwrap();
+}
+
+/// `refs/heads/master` -> `master`, `refs/tags/v1` -> `v1`
+fn short_ref(name: &str) -> &str {
+    name.strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/tags/"))
+        .unwrap_or(name)
+}
+
+/// Parse the report-status of the server: `unpack ok`, then `ok <ref>` or `ng <ref> <reason>` of each ref
+fn parse_report(mut data: Bytes) -> Result<HashMap<String, Result<(), String>>, String> {
     let (_, pkt_line) = read_pkt_line(&mut data);
-    if pkt_line != "unpack ok\n" {
-        eprintln!("fatal: unpack failed");
-        return;
+    let unpack = String::from_utf8_lossy(&pkt_line);
+    let unpack = unpack.trim_end();
+    if unpack != "unpack ok" {
+        return Err(format!(
+            "error: remote unpack failed: {}",
+            unpack.strip_prefix("unpack ").unwrap_or(unpack)
+        ));
     }
-    let (_, pkt_line) = read_pkt_line(&mut data);
-    if !pkt_line.starts_with("ok".as_ref()) {
-        eprintln!("fatal: ref update failed [{:?}]", pkt_line);
-        return;
+    let mut report = HashMap::new();
+    loop {
+        let (len, pkt_line) = read_pkt_line(&mut data);
+        if len == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&pkt_line);
+        let line = line.trim_end();
+        if let Some(name) = line.strip_prefix("ok ") {
+            report.insert(name.to_string(), Ok(()));
+        } else if let Some(rest) = line.strip_prefix("ng ") {
+            let (name, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+            report.insert(name.to_string(), Err(reason.to_string()));
+        }
     }
-    let (len, _) = read_pkt_line(&mut data);
-    assert_eq!(len, 0);
+    Ok(report)
+}
+
+/// Print the result of each ref like Git, return `false` if any ref failed
+fn print_report(repo_url: &str, updates: &[RefUpdate], statuses: &[RefStatus]) -> bool {
+    if statuses.iter().all(|status| *status == RefStatus::UpToDate) {
+        println!("Everything up-to-date");
+        return true;
+    }
+    println!("To {}", repo_url);
+    let mut success = true;
+    for (update, status) in updates.iter().zip(statuses) {
+        let dst = short_ref(&update.dst);
+        let refs = match &update.src {
+            Some(src) => format!("{} -> {}", src, dst),
+            None => dst.to_string(),
+        };
+        let kind = match update.dst.starts_with("refs/tags/") {
+            true => "tag",
+            false => "branch",
+        };
+        let short = |id: &SHA1| id.to_plain_str()[..7].to_string();
+        match status {
+            RefStatus::UpToDate => {}
+            RefStatus::Rejected(reason) => {
+                success = false;
+                eprintln!(" ! [rejected]        {} ({})", refs, reason);
+            }
+            RefStatus::RemoteRejected(reason) => {
+                success = false;
+                eprintln!(" ! [remote rejected] {} ({})", refs, reason);
+            }
+            RefStatus::Ok { .. } if update.new == SHA1::default() => println!(" - [deleted]         {}", refs),
+            RefStatus::Ok { old, .. } if *old == SHA1::default() => println!(" * [new {}]{:width$} {}", kind, "", refs, width = 11 - kind.len()),
+            RefStatus::Ok { old, forced: true } => {
+                println!(" + {}...{} {} (forced update)", short(old), short(&update.new), refs)
+            }
+            RefStatus::Ok { old, forced: false } => println!("   {}..{}  {}", short(old), short(&update.new), refs),
+        }
+    }
+    if !success {
+        eprintln!("error: failed to push some refs to '{}'", repo_url);
+    }
+    success
+}
 
-    println!("Push success");
+/// objects needed to update a remote ref from `old` to `new` (a commit or tag): the tag object (if annotated)
+/// and the history it points to
+/// - use `old` as the base if it exists locally, otherwise a remote branch that exists locally
+fn ref_objs(new: SHA1, old: SHA1, refs: &[DiscoveredReference], shallow: &HashSet<SHA1>) -> HashSet<Entry> {
+    let storage = util::objects_storage();
+    let remote_base = match storage.exist(&old) {
+        true => tag::peel_to_commit(old),
+        false => refs
+            .iter()
+            .filter(|r| r._ref.starts_with("refs/heads/"))
+            .filter_map(|r| SHA1::from_str(&r._hash).ok())
+            .find(|hash| storage.exist(hash))
+            .unwrap_or_default(),
+    };
 
-    // set after push success
-    if args.set_upstream {
-        branch::set_upstream(&branch, &format!("{}/{}", repository, branch)).await;
+    let commit_id = tag::peel_to_commit(new);
+    let mut objs = if collect_history_commits(&remote_base, shallow).contains(&commit_id) {
+        HashSet::new() // the commit exists in remote
+    } else {
+        incremental_objs(commit_id, remote_base, shallow)
+    };
+    if new != commit_id {
+        let tag_object: TagObject = load_object(&new).unwrap();
+        objs.insert(tag_object.into());
     }
+    objs
 }
 
-/// collect all commits from `commit_id` to root commit
-fn collect_history_commits(commit_id: &SHA1) -> HashSet<SHA1> {
+/// collect all commits from `commit_id` to root commit (or the shallow commits)
+fn collect_history_commits(commit_id: &SHA1, shallow: &HashSet<SHA1>) -> HashSet<SHA1> {
     if commit_id == &SHA1::default() { // 0000...0000 means not exist
         return HashSet::new();
     }
@@ -176,14 +575,15 @@ fn collect_history_commits(commit_id: &SHA1) -> HashSet<SHA1> {
         commits.insert(commit);
 
         let commit = Commit::load(&commit);
-        for parent in commit.parent_commit_ids.iter() {
-            queue.push_back(*parent);
+        for parent in shallow::parents(&commit, shallow) {
+            queue.push_back(parent);
         }
     }
     commits
 }
 
-fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
+/// objects in the history of `local_ref` but not `remote_ref`, a shallow commit is treated as a root commit
+fn incremental_objs(local_ref: SHA1, remote_ref: SHA1, shallow: &HashSet<SHA1>) -> HashSet<Entry> {
     // just fast-forward optimization
     if remote_ref != SHA1::default() { // remote exists
         let mut commit = Commit::load(&local_ref);
@@ -194,12 +594,13 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
             if commit.id == remote_ref {
                 break;
             }
-            if commit.parent_commit_ids.len() != 1 { // merge commit or root commit
+            let parents = shallow::parents(&commit, shallow);
+            if parents.len() != 1 { // merge commit or root commit
                 ok = false;
                 break;
             }
             // update commit to it's only parent
-            commit = Commit::load(&commit.parent_commit_ids[0]);
+            commit = Commit::load(&parents[0]);
         }
         if ok { // fast-forward
             let mut objs = HashSet::new();
@@ -216,25 +617,23 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
 
 
     let mut objs = HashSet::new();
-    let exist_commits = collect_history_commits(&remote_ref);
+    let exist_commits = collect_history_commits(&remote_ref, shallow);
     let mut queue = VecDeque::new();
     if !exist_commits.contains(&local_ref) {
         queue.push_back(local_ref);
     }
-    let mut root_commit = None;
+    // a shallow repository may have multiple boundary commits
+    let mut root_commits = Vec::new();
 
     while let Some(commit) = queue.pop_front() {
         let commit = Commit::load(&commit);
-        let parents = &commit.parent_commit_ids;
+        let parents = &shallow::parents(&commit, shallow);
         if parents.is_empty() {
-            if root_commit.is_none() {
-                root_commit = Some(commit.id);
-            } else {
-                eprintln!("fatal: multiple root commits");
-            }
+            root_commits.push(commit.id);
         }
         for parent in parents.iter() {
-            objs.extend(diff_tree_objs(Some(parent), &commit.tree_id));
+            let parent_tree = Commit::load(parent).tree_id;
+            objs.extend(diff_tree_objs(Some(&parent_tree), &commit.tree_id));
             if !exist_commits.contains(parent) {
                 queue.push_back(*parent);
             }
@@ -243,7 +642,7 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
     }
 
     // root commit has no parent
-    if let Some(root_commit) = root_commit {
+    for root_commit in root_commits {
         let root_tree = Commit::load(&root_commit).tree_id;
         objs.extend(diff_tree_objs(None, &root_tree));
     }
@@ -293,26 +692,114 @@ fn diff_tree_objs(old_tree: Option<&SHA1>, new_tree: &SHA1) -> HashSet<Entry> {
 
 #[cfg(test)]
 mod test{
+    use std::fs;
     use super::*;
+    use crate::internal::protocol::local_client::LocalClient;
+    use crate::internal::protocol::ProtocolClient;
+    use crate::utils::test;
+
+    async fn remote_ref(url: &str, name: &str) -> Option<SHA1> {
+        let client = LocalClient::from_url(&protocol::parse_url(url).unwrap());
+        let refs = client.discovery_reference(ReceivePack).await.unwrap();
+        refs.iter().find(|r| r._ref == name).map(|r| SHA1::from_str(&r._hash).unwrap())
+    }
+
+    async fn push(args: &[&str]) {
+        execute(PushArgs::parse_from([&["push"], args].concat())).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_force_and_delete() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "1\n", "commit 1").await;
+        // the repository is copied as the remote, its checked out `master` can't be updated
+        let remote = std::env::temp_dir().join("libra_test_push_remote");
+        let _ = fs::remove_dir_all(&remote);
+        test::copy_dir(&util::storage_path(), &remote.join(util::ROOT_DIR));
+        let url = remote.to_str().unwrap();
+        Config::insert("remote", Some("origin"), "url", url).await;
+        let second = test::commit_file("a.txt", "2\n", "commit 2").await;
+
+        push(&["origin", "master:topic"]).await;
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, Some(second));
+        assert_eq!(Branch::find_branch("topic", Some("origin")).await.unwrap().commit, second);
+
+        Branch::update_branch("old", &first.to_plain_str(), None).await;
+        push(&["origin", "old:topic"]).await; // non-fast-forward
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, Some(second));
+        push(&["origin", "+old:topic"]).await;
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, Some(first));
+
+        let lease = format!("--force-with-lease=topic:{}", second.to_plain_str());
+        push(&[&lease, "origin", "master:topic"]).await; // stale info
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, Some(first));
+        // the remote-tracking branch is up to date after the forced push
+        push(&["--force-with-lease", "origin", "master:topic"]).await;
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, Some(second));
+
+        tag::create_tag("v1", None, None, None, false).await;
+        push(&["origin", "--tags"]).await;
+        assert_eq!(remote_ref(url, "refs/tags/v1").await, Some(second));
+
+        push(&["--delete", "origin", "topic", "v1"]).await;
+        assert_eq!(remote_ref(url, "refs/heads/topic").await, None);
+        assert_eq!(remote_ref(url, "refs/tags/v1").await, None);
+        assert!(Branch::find_branch("topic", Some("origin")).await.is_none());
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let data = Bytes::from("000eunpack ok\n0019ok refs/heads/master\n0027ng refs/heads/dev non-fast-forward\n0000");
+        let report = parse_report(data).unwrap();
+        assert_eq!(report["refs/heads/master"], Ok(()));
+        assert_eq!(report["refs/heads/dev"], Err("non-fast-forward".to_string()));
+
+        let data = Bytes::from("0024unpack index-pack abnormal exit\n0000");
+        assert!(parse_report(data).is_err());
+    }
+
+    #[test]
+    fn test_qualify_dst() {
+        let deletion = |dst: &str| RefUpdate {
+            src: None,
+            dst: dst.to_string(),
+            new: SHA1::default(),
+            force: false,
+        };
+        let remote_refs = ["refs/heads/dev", "refs/tags/v1"];
+        assert_eq!(qualify_dst(&deletion("dev"), &remote_refs).unwrap(), "refs/heads/dev");
+        assert_eq!(qualify_dst(&deletion("v1"), &remote_refs).unwrap(), "refs/tags/v1");
+        assert!(qualify_dst(&deletion("feature"), &remote_refs).is_err());
+        assert_eq!(qualify_dst(&deletion("refs/heads/x"), &remote_refs).unwrap(), "refs/heads/x");
+    }
+
     #[test]
     fn test_parse_args_success() {
         let args = vec!["push"];
         let args = PushArgs::parse_from(args);
         assert_eq!(args.repository, None);
-        assert_eq!(args.refspec, None);
+        assert!(args.refspec.is_empty());
         assert!(!args.set_upstream);
 
         let args = vec!["push", "origin", "master"];
         let args = PushArgs::parse_from(args);
         assert_eq!(args.repository, Some("origin".to_string()));
-        assert_eq!(args.refspec, Some("master".to_string()));
+        assert_eq!(args.refspec, vec!["master".to_string()]);
         assert!(!args.set_upstream);
 
         let args = vec!["push", "-u", "origin", "master"];
         let args = PushArgs::parse_from(args);
         assert_eq!(args.repository, Some("origin".to_string()));
-        assert_eq!(args.refspec, Some("master".to_string()));
+        assert_eq!(args.refspec, vec!["master".to_string()]);
         assert!(args.set_upstream);
+
+        let args = PushArgs::parse_from(["push", "--force-with-lease=master:abc", "origin", "master", ":dev"]);
+        assert_eq!(args.force_with_lease, Some("master:abc".to_string()));
+        assert_eq!(args.refspec.len(), 2);
+        let args = PushArgs::parse_from(["push", "--force-with-lease", "origin", "master"]);
+        assert_eq!(args.force_with_lease, Some("".to_string()));
+        let args = PushArgs::parse_from(["push", "origin", "--tags"]);
+        assert!(args.tags && args.refspec.is_empty());
     }
 
     #[test]
@@ -332,6 +819,9 @@ mod test{
         let args = vec!["push", "origin"];
         let args = PushArgs::try_parse_from(args);
         assert!(args.is_err());
+
+        assert!(PushArgs::try_parse_from(["push", "-f", "--force-with-lease", "origin", "master"]).is_err());
+        assert!(PushArgs::try_parse_from(["push", "-d", "origin", "--tags"]).is_err());
     }
 
 }
\ No newline at end of file
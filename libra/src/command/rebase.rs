@@ -0,0 +1,643 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use std::{env, fmt};
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::branch::Branch;
+use crate::internal::head::Head;
+use crate::internal::reflog::{self, Reflog};
+use crate::internal::shallow::{self, Shallow};
+use crate::utils::merge::MergeLabels;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::branch::get_target_commit;
+use super::{commit, format_commit_msg, merge, parse_commit_msg, reset, save_object, status};
+
+/// files in `.libra/rebase-merge`
+const HEAD_NAME: &str = "head-name";
+const ONTO: &str = "onto";
+const ORIG_HEAD: &str = "orig-head";
+const TODO: &str = "git-rebase-todo";
+const DONE: &str = "done";
+/// the todo line stopped by conflicts
+const STOPPED: &str = "stopped";
+const MESSAGE: &str = "message";
+
+#[derive(Parser, Debug)]
+pub struct RebaseArgs {
+    /// The branch (or commit) to rebase the current branch onto
+    #[clap(required_unless_present_any(["continue_rebase", "skip", "abort"]))]
+    pub upstream: Option<String>,
+
+    /// Edit the list of commits to be rebased (pick, reword, squash, fixup, drop)
+    #[clap(short, long, conflicts_with_all(["continue_rebase", "skip", "abort"]))]
+    pub interactive: bool,
+
+    /// Continue the rebase after the conflicts are resolved
+    #[clap(long = "continue", group = "op", conflicts_with = "upstream")]
+    pub continue_rebase: bool,
+
+    /// Skip the current commit and continue the rebase
+    #[clap(long, group = "op", conflicts_with = "upstream")]
+    pub skip: bool,
+
+    /// Abort the rebase and reset HEAD to the original branch
+    #[clap(long, group = "op", conflicts_with = "upstream")]
+    pub abort: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Pick,
+    Reword,
+    Squash,
+    Fixup,
+    Drop,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::Pick => "pick",
+            Action::Reword => "reword",
+            Action::Squash => "squash",
+            Action::Fixup => "fixup",
+            Action::Drop => "drop",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p" | "pick" => Ok(Action::Pick),
+            "r" | "reword" => Ok(Action::Reword),
+            "s" | "squash" => Ok(Action::Squash),
+            "f" | "fixup" => Ok(Action::Fixup),
+            "d" | "drop" => Ok(Action::Drop),
+            _ => Err(format!("error: invalid command '{}'", s)),
+        }
+    }
+}
+
+/// A line of the todo list: `<action> <commit> <subject>`
+#[derive(Debug, Clone)]
+struct TodoItem {
+    action: Action,
+    commit: SHA1,
+}
+
+impl TodoItem {
+    async fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let action: Action = words.next().unwrap_or_default().parse()?;
+        let commit = match words.next() {
+            Some(hash) => util::get_commit_base(hash).await.map_err(|_| format!("error: invalid line: {}", line))?,
+            None => return Err(format!("error: missing commit: {}", line)),
+        };
+        Ok(TodoItem { action, commit })
+    }
+
+    fn to_line(&self) -> String {
+        let commit = Commit::load(&self.commit);
+        format!("{} {} {}", self.action, &self.commit.to_plain_str()[..7], commit.format_message())
+    }
+}
+
+/// Parse the todo list, the empty & comment lines are ignored
+async fn parse_todo(todo: &str) -> Result<Vec<TodoItem>, String> {
+    let mut items = Vec::new();
+    for line in todo.lines().map(str::trim) {
+        if !line.is_empty() && !line.starts_with('#') {
+            items.push(TodoItem::parse(line).await?);
+        }
+    }
+    if let Some(item) = items.iter().find(|item| item.action != Action::Drop) {
+        if matches!(item.action, Action::Squash | Action::Fixup) {
+            return Err(format!("error: cannot '{}' without a previous commit", item.action));
+        }
+    }
+    Ok(items)
+}
+
+fn state_file(name: &str) -> std::path::PathBuf {
+    path::rebase_merge().join(name)
+}
+
+fn read_state(name: &str) -> String {
+    fs::read_to_string(state_file(name)).unwrap_or_default().trim_end().to_string()
+}
+
+fn write_state(name: &str, content: &str) {
+    fs::write(state_file(name), content).unwrap();
+}
+
+pub fn in_progress() -> bool {
+    path::rebase_merge().exists()
+}
+
+pub async fn execute(args: RebaseArgs) {
+    rebase(args, |todo| launch_editor(todo, true)).await;
+}
+
+/// `edit_todo` lets the user edit the todo list of `--interactive`, return `false` if failed
+async fn rebase(args: RebaseArgs, edit_todo: impl FnOnce(&Path) -> bool) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    if args.continue_rebase || args.skip || args.abort {
+        if !in_progress() {
+            eprintln!("fatal: No rebase in progress?");
+            return;
+        }
+        if args.continue_rebase {
+            rebase_continue().await;
+        } else if args.skip {
+            rebase_skip().await;
+        } else {
+            rebase_abort().await;
+        }
+        return;
+    }
+    if in_progress() {
+        eprintln!("fatal: It seems that there is already a rebase-merge directory.");
+        eprintln!("Use \"libra rebase --continue\" or \"libra rebase --abort\".");
+        return;
+    }
+    if merge::merge_head().is_some() {
+        eprintln!("fatal: You have not concluded your merge (MERGE_HEAD exists).");
+        return;
+    }
+    let unstaged = status::changes_to_be_staged().await;
+    if !unstaged.modified.is_empty() || !unstaged.deleted.is_empty() {
+        eprintln!("error: cannot rebase: You have unstaged changes.");
+        eprintln!("error: Please commit or stash them.");
+        return;
+    }
+    if !status::changes_to_be_committed().await.is_empty() {
+        eprintln!("error: cannot rebase: Your index contains uncommitted changes.");
+        eprintln!("error: Please commit or stash them.");
+        return;
+    }
+
+    if !commit::check_identity().await {
+        return;
+    }
+
+    let upstream = args.upstream.unwrap();
+    let onto = match get_target_commit(&upstream).await {
+        Ok(commit) => commit,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let head = match Head::current_commit().await {
+        Some(head) => head,
+        None => {
+            eprintln!("fatal: HEAD does not point to a commit");
+            return;
+        }
+    };
+    let head_name = match Head::current().await {
+        Head::Branch(name) => name,
+        Head::Detached(_) => "detached HEAD".to_string(),
+    };
+
+    let shallow = Shallow::list().await;
+    if merge::ancestors(&head, &shallow).contains(&onto) && !args.interactive {
+        println!("Current branch {} is up to date.", head_name);
+        return;
+    }
+
+    let commits = commits_to_replay(&head, &merge::ancestors(&onto, &shallow), &shallow);
+    let mut todo: String = commits
+        .iter()
+        .map(|commit| TodoItem { action: Action::Pick, commit: *commit }.to_line() + "\n")
+        .collect();
+
+    fs::create_dir_all(path::rebase_merge()).unwrap();
+    write_state(HEAD_NAME, &head_name);
+    write_state(ONTO, &onto.to_plain_str());
+    write_state(ORIG_HEAD, &head.to_plain_str());
+    write_state(DONE, "");
+
+    if args.interactive {
+        todo += &todo_help(commits.len(), &head, &onto);
+        write_state(TODO, &todo);
+        if !edit_todo(&state_file(TODO)) {
+            eprintln!("error: there was a problem with the editor");
+            fs::remove_dir_all(path::rebase_merge()).unwrap();
+            return;
+        }
+        todo = fs::read_to_string(state_file(TODO)).unwrap();
+        match parse_todo(&todo).await {
+            Ok(items) if items.is_empty() => {
+                println!("Nothing to do");
+                fs::remove_dir_all(path::rebase_merge()).unwrap();
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                fs::remove_dir_all(path::rebase_merge()).unwrap();
+                return;
+            }
+        }
+    }
+    write_state(TODO, &todo);
+
+    // replay the commits on the detached `onto`, the branch is updated when finished
+    Head::update(Head::Detached(onto), None).await;
+    Reflog::insert(reflog::HEAD, Some(head), onto, &format!("rebase (start): checkout {}", upstream)).await;
+    reset::reset_index(&onto, true);
+    run().await;
+}
+
+/// Commits reachable from `head` but not from `onto`, parents first, merge commits are dropped
+fn commits_to_replay(head: &SHA1, onto_ancestors: &HashSet<SHA1>, shallow: &HashSet<SHA1>) -> Vec<SHA1> {
+    let mut commits = Vec::new();
+    let mut visited = HashSet::new();
+    // post-order DFS, a commit is pushed after all its parents
+    let mut stack = vec![(*head, false)];
+    while let Some((id, expanded)) = stack.pop() {
+        if expanded {
+            if Commit::load(&id).parent_commit_ids.len() <= 1 {
+                commits.push(id);
+            }
+            continue;
+        }
+        if onto_ancestors.contains(&id) || !visited.insert(id) {
+            continue;
+        }
+        stack.push((id, true));
+        for parent in shallow::parents(&Commit::load(&id), shallow).iter().rev() {
+            stack.push((*parent, false));
+        }
+    }
+    commits
+}
+
+fn todo_help(count: usize, head: &SHA1, onto: &SHA1) -> String {
+    format!(
+        "
+# Rebase {}..{} onto {} ({} command{})
+#
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's log message
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+",
+        &onto.to_plain_str()[..7],
+        &head.to_plain_str()[..7],
+        &onto.to_plain_str()[..7],
+        count,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+/// Let the user edit the file, return `false` if the editor failed
+/// - editor: `GIT_SEQUENCE_EDITOR` (todo list only), `GIT_EDITOR`, `EDITOR`, or `vi`
+fn launch_editor(file: &Path, sequence: bool) -> bool {
+    let vars: &[&str] = if sequence {
+        &["GIT_SEQUENCE_EDITOR", "GIT_EDITOR", "EDITOR"]
+    } else {
+        &["GIT_EDITOR", "EDITOR"]
+    };
+    let editor = vars
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|editor| !editor.is_empty()))
+        .unwrap_or_else(|| "vi".to_string());
+    // the editor may have arguments, run it with shell
+    let command = format!("{} \"{}\"", editor, file.display());
+    let status = if cfg!(windows) {
+        Command::new("cmd").args(["/C", &command]).status()
+    } else {
+        Command::new("sh").args(["-c", &command]).status()
+    };
+    status.is_ok_and(|status| status.success())
+}
+
+/// Edit the commit message with editor, the comment lines are removed
+fn edit_message(message: &str) -> String {
+    let file = state_file(MESSAGE);
+    fs::write(&file, message).unwrap();
+    if !launch_editor(&file, false) {
+        eprintln!("warning: there was a problem with the editor, the message is kept");
+        return message.to_string();
+    }
+    let edited: Vec<String> = fs::read_to_string(&file)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    let edited = edited.join("\n").trim().to_string();
+    if edited.is_empty() {
+        message.to_string()
+    } else {
+        edited
+    }
+}
+
+/// Execute the todo list until finished or stopped by conflicts
+async fn run() {
+    loop {
+        let todo = read_state(TODO);
+        let mut lines = todo.lines();
+        let line = match lines.find(|line| !line.trim().is_empty() && !line.trim().starts_with('#')) {
+            Some(line) => line.to_string(),
+            None => break,
+        };
+        let rest: Vec<&str> = lines.collect();
+        write_state(TODO, &rest.join("\n"));
+        let mut done = fs::read_to_string(state_file(DONE)).unwrap_or_default();
+        done += &format!("{}\n", line);
+        write_state(DONE, &done);
+
+        let item = match TodoItem::parse(&line).await {
+            Ok(item) => item,
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("hint: fix the todo list with \"libra rebase --skip\" or \"libra rebase --abort\"");
+                write_state(STOPPED, &line);
+                return;
+            }
+        };
+        if item.action == Action::Drop {
+            continue;
+        }
+        if !apply_commit(&item.commit).await {
+            write_state(STOPPED, &line);
+            let commit = Commit::load(&item.commit);
+            eprintln!(
+                "error: could not apply {}... {}",
+                &item.commit.to_plain_str()[..7],
+                commit.format_message()
+            );
+            eprintln!("hint: Resolve all conflicts manually, mark them as resolved with");
+            eprintln!("hint: \"libra add <conflicted_files>\", then run \"libra rebase --continue\".");
+            eprintln!("hint: You can instead skip this commit: run \"libra rebase --skip\".");
+            eprintln!("hint: To abort and get back to the state before \"libra rebase\", run \"libra rebase --abort\".");
+            return;
+        }
+        commit_item(&item).await;
+    }
+    finish().await;
+}
+
+/// Apply the changes of the commit on `HEAD` (3-way merge), return `false` if conflicted
+async fn apply_commit(commit_id: &SHA1) -> bool {
+    let commit = Commit::load(commit_id);
+    let head = Head::current_commit().await.unwrap();
+    let labels = MergeLabels {
+        ours: "HEAD".to_string(),
+        theirs: format!("{} ({})", &commit_id.to_plain_str()[..7], commit.format_message()),
+    };
+    let ours_items = merge::commit_tree_items(&head);
+    !merge::apply_changes(commit.parent_commit_ids.first(), commit_id, &ours_items, &labels)
+}
+
+/// Create the commit of the todo item from the index, `squash` & `fixup` amend `HEAD`
+async fn commit_item(item: &TodoItem) {
+    let commit = Commit::load(&item.commit);
+    let head = Commit::load(&Head::current_commit().await.unwrap());
+    let index = Index::load(path::index()).unwrap();
+    let tree = commit::create_tree(&index, &util::objects_storage(), "".into()).await;
+    let (message, _) = parse_commit_msg(&commit.message);
+
+    let new_commit = match item.action {
+        Action::Pick | Action::Reword => {
+            if tree.id == head.tree_id {
+                // the changes are already in `onto`
+                println!(
+                    "dropping {} {} -- patch contents already upstream",
+                    item.commit.to_plain_str(),
+                    commit.format_message()
+                );
+                return;
+            }
+            let message = if item.action == Action::Reword {
+                edit_message(&message)
+            } else {
+                message
+            };
+            Commit::new(
+                commit.author,
+                commit::committer().await,
+                tree.id,
+                vec![head.id],
+                &format_commit_msg(&message, None),
+            )
+        }
+        Action::Squash | Action::Fixup => {
+            let (head_message, _) = parse_commit_msg(&head.message);
+            let message = if item.action == Action::Squash {
+                edit_message(&format!(
+                    "# This is a combination of 2 commits.\n{}\n\n{}",
+                    head_message.trim_end(),
+                    message
+                ))
+            } else {
+                head_message
+            };
+            Commit::new(
+                head.author,
+                commit::committer().await,
+                tree.id,
+                head.parent_commit_ids,
+                &format_commit_msg(&message, None),
+            )
+        }
+        Action::Drop => return,
+    };
+    save_object(&new_commit, &new_commit.id).unwrap();
+    Head::update(Head::Detached(new_commit.id), None).await;
+    let message = format!("rebase ({}): {}", item.action, new_commit.format_message());
+    Reflog::insert(reflog::HEAD, Some(head.id), new_commit.id, &message).await;
+}
+
+/// Move the branch to the rebased `HEAD`, then clean the state
+async fn finish() {
+    let head_name = read_state(HEAD_NAME);
+    let head = Head::current_commit().await.unwrap();
+    if head_name != "detached HEAD" {
+        let branch_ref = reflog::branch_ref(&head_name);
+        let orig_head = SHA1::from_str(&read_state(ORIG_HEAD)).unwrap();
+        Branch::update_branch(&head_name, &head.to_plain_str(), None).await;
+        Head::update(Head::Branch(head_name.clone()), None).await;
+        let message = format!("rebase (finish): {} onto {}", branch_ref, read_state(ONTO));
+        Reflog::insert(&branch_ref, Some(orig_head), head, &message).await;
+        let message = format!("rebase (finish): returning to {}", branch_ref);
+        Reflog::insert(reflog::HEAD, Some(head), head, &message).await;
+    }
+    fs::remove_dir_all(path::rebase_merge()).unwrap();
+    if head_name == "detached HEAD" {
+        println!("Successfully rebased and updated detached HEAD.");
+    } else {
+        println!("Successfully rebased and updated refs/heads/{}.", head_name);
+    }
+}
+
+async fn rebase_continue() {
+    let index = Index::load(path::index()).unwrap();
+    if !index.unmerged_files().is_empty() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        return;
+    }
+    let stopped = read_state(STOPPED);
+    if !stopped.is_empty() {
+        let _ = fs::remove_file(state_file(STOPPED));
+        if let Ok(item) = TodoItem::parse(&stopped).await {
+            commit_item(&item).await;
+        }
+    }
+    run().await;
+}
+
+async fn rebase_skip() {
+    let _ = fs::remove_file(state_file(STOPPED));
+    let head = Head::current_commit().await.unwrap();
+    reset::reset_index(&head, true);
+    run().await;
+}
+
+async fn rebase_abort() {
+    let head_name = read_state(HEAD_NAME);
+    let orig_head = SHA1::from_str(&read_state(ORIG_HEAD)).unwrap();
+    let head = Head::current_commit().await;
+    let message = if head_name == "detached HEAD" {
+        Head::update(Head::Detached(orig_head), None).await;
+        format!("rebase (abort): returning to {}", orig_head.to_plain_str())
+    } else {
+        // the branch isn't moved until the rebase is finished
+        let message = format!("rebase (abort): returning to {}", reflog::branch_ref(&head_name));
+        Head::update(Head::Branch(head_name), None).await;
+        message
+    };
+    Reflog::insert(reflog::HEAD, head, orig_head, &message).await;
+    reset::reset_index(&orig_head, true);
+    fs::remove_dir_all(path::rebase_merge()).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::{branch, switch};
+    use crate::utils::test;
+
+    async fn switch_to(branch: &str) {
+        switch::execute(switch::SwitchArgs::parse_from(["switch", branch])).await;
+    }
+
+    /// `master`: base -> master, `feature`: base -> feature 1 -> feature 2
+    async fn setup(master_a: &str) -> (SHA1, SHA1) {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "base").await;
+        branch::create_branch("feature".to_string(), None).await;
+        let master = test::commit_file("a.txt", master_a, "master").await;
+
+        switch_to("feature").await;
+        test::commit_file("b.txt", "b\n", "feature 1").await;
+        let feature = test::commit_file("a.txt", "feature\n", "feature 2").await;
+        (master, feature)
+    }
+
+    fn messages(head: &SHA1, count: usize) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut commit = Commit::load(head);
+        messages.push(commit.format_message());
+        while messages.len() < count {
+            commit = Commit::load(&commit.parent_commit_ids[0]);
+            messages.push(commit.format_message());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!("s".parse::<Action>().unwrap(), Action::Squash);
+        assert_eq!("fixup".parse::<Action>().unwrap(), Action::Fixup);
+        assert!("edit".parse::<Action>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rebase() {
+        let (master, _) = setup("a\n").await;
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+
+        assert!(!in_progress());
+        let head = Branch::find_branch("feature", None).await.unwrap().commit;
+        assert_eq!(messages(&head, 3), vec!["feature 2", "feature 1", "master"]);
+        assert_eq!(Commit::load(&Commit::load(&head).parent_commit_ids[0]).parent_commit_ids, vec![master]);
+        assert!(matches!(Head::current().await, Head::Branch(name) if name == "feature"));
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "feature\n");
+    }
+
+    #[tokio::test]
+    async fn test_rebase_conflict() {
+        let (_, feature) = setup("master\n").await;
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+        assert!(in_progress());
+        assert!(!Index::load(path::index()).unwrap().unmerged_files().is_empty());
+
+        // abort: back to the original branch
+        execute(RebaseArgs::parse_from(["rebase", "--abort"])).await;
+        assert!(!in_progress());
+        assert_eq!(Head::current_commit().await.unwrap(), feature);
+        assert!(matches!(Head::current().await, Head::Branch(name) if name == "feature"));
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "feature\n");
+
+        // resolve & continue
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+        test::ensure_file("a.txt", Some("resolved\n"));
+        add::execute(AddArgs::parse_from(["add", "a.txt"])).await;
+        execute(RebaseArgs::parse_from(["rebase", "--continue"])).await;
+        assert!(!in_progress());
+        let head = Head::current_commit().await.unwrap();
+        assert_eq!(messages(&head, 3), vec!["feature 2", "feature 1", "master"]);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "resolved\n");
+    }
+
+    #[tokio::test]
+    async fn test_rebase_interactive() {
+        let (_, _) = setup("a\n").await;
+        let base = Commit::load(&Branch::find_branch("master", None).await.unwrap().commit).parent_commit_ids[0];
+        // the editor squashes the 2nd commit into the 1st
+        let fixup_second = |todo: &Path| {
+            let content = fs::read_to_string(todo).unwrap();
+            let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+            lines[1] = lines[1].replacen("pick", "fixup", 1);
+            fs::write(todo, lines.join("\n")).is_ok()
+        };
+        rebase(RebaseArgs::parse_from(["rebase", "-i", &base.to_plain_str()]), fixup_second).await;
+
+        assert!(!in_progress());
+        let head = Head::current_commit().await.unwrap();
+        assert_eq!(messages(&head, 2), vec!["feature 1", "base"]);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "feature\n");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "b\n");
+    }
+}
@@ -0,0 +1,134 @@
+use clap::{Parser, Subcommand};
+
+use crate::internal::reflog::{self, Reflog};
+use crate::utils::util;
+
+/// Days to keep the reflog entries by default, same as `gc.reflogExpire` of Git
+const DEFAULT_EXPIRE_DAYS: i64 = 90;
+
+#[derive(Parser, Debug)]
+pub struct ReflogArgs {
+    /// `show` if not specified
+    #[command(subcommand)]
+    pub command: Option<ReflogCmds>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReflogCmds {
+    /// Show the log of the reference, `HEAD` by default
+    Show {
+        /// `HEAD`, a branch or a full ref name, e.g. `refs/heads/master`
+        reference: Option<String>,
+    },
+    /// Prune the reflog entries older than the expire time
+    Expire {
+        /// Days to keep the entries, or `now`/`all` to remove all, `never` to keep all
+        #[clap(long, value_name = "TIME")]
+        expire: Option<String>,
+        /// Process the reflogs of all references
+        #[clap(long, conflicts_with = "refs")]
+        all: bool,
+        /// References to process, `HEAD` by default
+        refs: Vec<String>,
+    },
+}
+
+pub async fn execute(args: ReflogArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    match args.command.unwrap_or(ReflogCmds::Show { reference: None }) {
+        ReflogCmds::Show { reference } => show(reference.as_deref().unwrap_or(reflog::HEAD)).await,
+        ReflogCmds::Expire { expire, all, refs } => {
+            let before = match parse_expire(expire.as_deref()) {
+                Ok(Some(before)) => before,
+                Ok(None) => return, // never
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let refs = match (all, refs.is_empty()) {
+                (true, _) => vec![None],
+                (false, true) => vec![Some(reflog::HEAD.to_string())],
+                (false, false) => refs.iter().map(|r| Some(reflog::full_ref_name(r))).collect(),
+            };
+            for reference in refs {
+                if let Err(e) = Reflog::expire(reference.as_deref(), before).await {
+                    eprintln!("fatal: unable to expire the reflog: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn show(reference: &str) {
+    let entries = match Reflog::list(&reflog::full_ref_name(reference)).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("fatal: unable to read the reflog: {}", e);
+            return;
+        }
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            &entry.new_oid.to_plain_str()[..7],
+            reference,
+            i,
+            entry.message
+        );
+    }
+}
+
+/// The unix timestamp before which the entries are expired, `None` if never expire
+pub(crate) fn parse_expire(expire: Option<&str>) -> Result<Option<i64>, String> {
+    let now = chrono::Utc::now().timestamp();
+    let days = match expire {
+        None => DEFAULT_EXPIRE_DAYS,
+        Some("never") | Some("false") => return Ok(None),
+        // `+1` so that the entries created in this second are removed too
+        Some("now") | Some("all") => return Ok(Some(now + 1)),
+        Some(days) => days
+            .trim_end_matches(".days")
+            .parse()
+            .map_err(|_| format!("fatal: invalid expire time: '{}'", days))?,
+    };
+    Ok(Some(now - days * 24 * 3600))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_parse_expire() {
+        assert_eq!(parse_expire(Some("never")).unwrap(), None);
+        let now = chrono::Utc::now().timestamp();
+        assert!(parse_expire(Some("now")).unwrap().unwrap() > now);
+        assert!(parse_expire(None).unwrap().unwrap() < now - 89 * 24 * 3600);
+        assert!(parse_expire(Some("30.days")).unwrap().unwrap() > now - 31 * 24 * 3600);
+        assert!(parse_expire(Some("soon")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reflog_selector() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "1\n", "first").await;
+        let second = test::commit_file("a.txt", "2\n", "second").await;
+        let first = util::get_commit_base("HEAD@{1}").await.unwrap();
+        assert_eq!(util::get_commit_base("HEAD@{0}").await.unwrap(), second);
+        assert_eq!(util::get_commit_base("master@{1}").await.unwrap(), first);
+        assert!(util::get_commit_base("HEAD@{2}").await.is_err());
+
+        let log = Reflog::list(reflog::HEAD).await.unwrap();
+        assert_eq!(log[0].message, "commit: second");
+        assert_eq!(log[1].message, "commit (initial): first");
+
+        execute(ReflogArgs::parse_from(["reflog", "expire", "--expire=now", "--all"])).await;
+        assert!(Reflog::list(reflog::HEAD).await.unwrap().is_empty());
+        assert!(Reflog::list(&reflog::branch_ref("master")).await.unwrap().is_empty());
+    }
+}
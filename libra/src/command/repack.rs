@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use clap::Parser;
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::pack::entry::Entry;
+
+use crate::internal::protocol::local_client::encode_pack;
+use crate::internal::reachable;
+use crate::internal::shallow::Shallow;
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct RepackArgs {
+    /// Pack all reachable objects into a single pack, instead of only the loose ones
+    #[clap(short = 'a')]
+    pub all: bool,
+    /// Remove the redundant packs & the loose objects that are packed
+    #[clap(short = 'd')]
+    pub delete: bool,
+    /// Number of objects to try as the delta base of each object, `0` to disable deltas
+    #[clap(long, default_value_t = 10)]
+    pub window: usize,
+}
+
+pub async fn execute(args: RepackArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    if let Err(e) = repack(&args, None).await {
+        eprintln!("fatal: {}", e);
+    }
+}
+
+/// Pack the reachable objects, return them.
+///
+/// When the old packs are removed (`-a -d`), their unreachable objects are dropped,
+/// unless the pack is modified after `loosen_since`: they are kept as loose objects,
+/// with the time of the pack, so that `gc` prunes them after the grace period.
+pub async fn repack(args: &RepackArgs, loosen_since: Option<SystemTime>) -> Result<HashSet<SHA1>, GitError> {
+    let storage = util::objects_storage();
+    let shallow = Shallow::list().await;
+    let roots = reachable::roots(&storage).await?;
+    let old_packs = storage.list_all_packs();
+
+    let mut reachable = HashSet::new();
+    let mut objs = Vec::new();
+    if args.all {
+        for root in roots {
+            reachable::walk_objects(&storage, root, &mut reachable, &shallow, Some(&mut objs))?;
+        }
+    } else {
+        reachable = reachable::reachable_objects(&storage, &roots, &shallow)?;
+        let packed = storage.list_objects_pack();
+        for id in storage.list_objects_loose() {
+            if reachable.contains(&id) && !packed.contains(&id) {
+                let obj_type = storage.get_object_type(&id)?;
+                objs.push(Entry { obj_type, data: storage.get(&id)?, hash: id });
+            }
+        }
+    }
+
+    let new_pack = if objs.is_empty() {
+        println!("Nothing new to pack.");
+        None
+    } else {
+        sort_for_delta(&mut objs);
+        let pack = encode_pack(objs, args.window).await?;
+        storage.save_pack(&pack)?
+    };
+
+    if args.delete {
+        if args.all {
+            let redundant: Vec<_> = old_packs.into_iter().filter(|pack| Some(pack) != new_pack.as_ref()).collect();
+            if let Some(since) = loosen_since {
+                for pack in &redundant {
+                    loosen_unreachable(&storage, pack, &reachable, since)?;
+                }
+            }
+            for pack in &redundant {
+                storage.remove_pack(pack)?;
+            }
+        }
+        // the loose objects that are packed are redundant, like `git prune-packed`
+        let packed = storage.list_objects_pack();
+        for id in storage.list_objects_loose() {
+            if packed.contains(&id) {
+                storage.remove_loose(&id)?;
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+/// Order the objects like Git, so that similar objects are close in the delta window:
+/// grouped by type, and the larger ones first (a delta to remove data is smaller than to add)
+fn sort_for_delta(objs: &mut [Entry]) {
+    let type_order = |t: &ObjectType| match t {
+        ObjectType::Commit => 0,
+        ObjectType::Tag => 1,
+        ObjectType::Tree => 2,
+        _ => 3,
+    };
+    objs.sort_by(|a, b| {
+        type_order(&a.obj_type)
+            .cmp(&type_order(&b.obj_type))
+            .then(b.data.len().cmp(&a.data.len()))
+    });
+}
+
+/// Write the unreachable objects of the pack as loose objects if the pack is modified after `since`
+fn loosen_unreachable(
+    storage: &ClientStorage,
+    pack: &Path,
+    reachable: &HashSet<SHA1>,
+    since: SystemTime,
+) -> Result<(), GitError> {
+    let mtime = fs::metadata(pack)?.modified()?;
+    if mtime < since {
+        return Ok(());
+    }
+    for id in ClientStorage::list_idx_objects(&pack.with_extension("idx"))? {
+        if reachable.contains(&id) || storage.exist_loosely(&id) {
+            continue;
+        }
+        let obj_type = storage.get_object_type(&id)?;
+        storage.put(&id, &storage.get(&id)?, obj_type)?;
+        storage.set_loose_mtime(&id, mtime)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::internal::head::Head;
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_repack() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "add a.txt").await;
+        let storage = util::objects_storage();
+
+        // only the loose objects are packed, the old ones are kept
+        execute(RepackArgs::parse_from(["repack"])).await;
+        assert_eq!(storage.list_all_packs().len(), 1);
+        assert!(!storage.list_objects_loose().is_empty());
+        test::commit_file("b.txt", "b\n", "add b.txt").await;
+        execute(RepackArgs::parse_from(["repack", "-d"])).await;
+        assert_eq!(storage.list_all_packs().len(), 2);
+        assert!(storage.list_objects_loose().is_empty());
+
+        // all objects in one pack, and again without changes (the same pack)
+        for _ in 0..2 {
+            execute(RepackArgs::parse_from(["repack", "-a", "-d"])).await;
+            assert_eq!(storage.list_all_packs().len(), 1);
+            assert!(storage.list_objects_loose().is_empty());
+            assert_eq!(storage.list_objects_pack().len(), 6); // 2 commits, 2 trees, 2 blobs
+        }
+        let head = Head::current_commit().await.unwrap();
+        assert!(storage.is_object_type(&head, ObjectType::Commit));
+    }
+
+    #[test]
+    fn test_repack_args() {
+        let args = RepackArgs::parse_from(["repack", "-ad", "--window", "0"]);
+        assert!(args.all && args.delete);
+        assert_eq!(args.window, 0);
+    }
+}
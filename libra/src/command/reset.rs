@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::branch::Branch;
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::{branch::get_target_commit, merge, parse_commit_msg, restore, status};
+
+#[derive(Parser, Debug)]
+pub struct ResetArgs {
+    /// `<commit>` to reset to (`HEAD` by default), and/or `<pathspec>` to unstage
+    #[clap(value_name = "COMMIT|PATH")]
+    pub args: Vec<String>,
+
+    /// paths to unstage, after `--`
+    #[clap(last = true)]
+    pub pathspec: Vec<String>,
+
+    /// Only move `HEAD` (and the branch it points to), the index & worktree are untouched
+    #[clap(long, group = "mode")]
+    pub soft: bool,
+
+    /// Move `HEAD` and reset the index, but not the worktree (default)
+    #[clap(long, group = "mode")]
+    pub mixed: bool,
+
+    /// Move `HEAD`, reset the index and the worktree, all changes of the tracked files are discarded
+    #[clap(long, group = "mode")]
+    pub hard: bool,
+}
+
+pub async fn execute(args: ResetArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+
+    // the first arg is a commit if it can be resolved and isn't a path
+    let mut rest = args.args.as_slice();
+    let mut commit = None;
+    if let Some(first) = rest.first() {
+        if !PathBuf::from(first).exists() || !args.pathspec.is_empty() {
+            match get_target_commit(first).await {
+                Ok(commit_id) => {
+                    commit = Some(commit_id);
+                    rest = &rest[1..];
+                }
+                Err(e) if rest.len() == 1 && args.pathspec.is_empty() => {
+                    eprintln!("{}", e);
+                    return;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    let mut paths: Vec<PathBuf> = rest.iter().map(PathBuf::from).collect();
+    paths.extend(args.pathspec.iter().map(PathBuf::from));
+
+    let commit = match commit {
+        Some(commit) => Some(commit),
+        None => Head::current_commit().await,
+    };
+
+    if !paths.is_empty() {
+        if args.soft || args.hard {
+            let mode = if args.soft { "soft" } else { "hard" };
+            eprintln!("fatal: Cannot do {} reset with paths.", mode);
+            return;
+        }
+        // unstage the paths, on an unborn branch all of them are removed from the index
+        let items = commit.map(|commit| merge::commit_tree_items(&commit)).unwrap_or_default();
+        restore::restore_index(&paths, &items);
+        print_unstaged_changes().await;
+        return;
+    }
+
+    let commit = match commit {
+        Some(commit) => commit,
+        None => {
+            eprintln!("fatal: ambiguous argument 'HEAD': unknown revision or path not in the working tree.");
+            return;
+        }
+    };
+    let target = args.args.first().map_or("HEAD", String::as_str);
+    let message = format!("reset: moving to {}", target);
+    if args.soft {
+        if merge::merge_head().is_some() {
+            eprintln!("fatal: Cannot do a soft reset in the middle of a merge.");
+            return;
+        }
+        update_head(&commit, &message).await;
+    } else if args.hard {
+        update_head(&commit, &message).await;
+        reset_index(&commit, true);
+        merge::clear_merge_state();
+        let (message, _) = parse_commit_msg(&Commit::load(&commit).message);
+        println!(
+            "HEAD is now at {} {}",
+            &commit.to_plain_str()[..7],
+            message.lines().next().unwrap_or_default()
+        );
+    } else {
+        update_head(&commit, &message).await;
+        reset_index(&commit, false);
+        merge::clear_merge_state();
+        print_unstaged_changes().await;
+    }
+}
+
+/// Move `HEAD` to the commit, the current branch is moved if not detached
+async fn update_head(commit: &SHA1, message: &str) {
+    let old = Head::current_commit().await;
+    match Head::current().await {
+        Head::Branch(name) => Branch::update_branch(&name, &commit.to_plain_str(), None).await,
+        Head::Detached(_) => Head::update(Head::Detached(*commit), None).await,
+    }
+    Reflog::log_head_update(old, *commit, message).await;
+}
+
+/// Reset the index (and the worktree if `worktree`) to the commit, conflicted entries are dropped
+pub fn reset_index(commit: &SHA1, worktree: bool) {
+    let items = merge::commit_tree_items(commit);
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    let targets: HashSet<&PathBuf> = items.iter().map(|(path, _)| path).collect();
+    for file in index.unmerged_files() {
+        let file_str = util::path_to_string(&file);
+        for stage in 1..=3 {
+            index.remove(&file_str, stage);
+        }
+        // conflicted files are not tracked (stage 0), `restore_worktree` won't remove them
+        let file_abs = util::workdir_to_absolute(&file);
+        if worktree && !targets.contains(&file) && file_abs.exists() {
+            fs::remove_file(&file_abs).unwrap();
+            util::clear_empty_dir(&file_abs);
+        }
+    }
+    index.save(&index_file).unwrap();
+
+    let paths = vec![util::working_dir()];
+    // the worktree must be restored first, it removes the files tracked by the old index
+    if worktree {
+        restore::restore_worktree(&paths, &items);
+    }
+    restore::restore_index(&paths, &items);
+}
+
+async fn print_unstaged_changes() {
+    let changes = status::changes_to_be_staged().await.to_relative();
+    if changes.modified.is_empty() && changes.deleted.is_empty() {
+        return;
+    }
+    println!("Unstaged changes after reset:");
+    for file in changes.modified.iter() {
+        println!("M\t{}", file.display());
+    }
+    for file in changes.deleted.iter() {
+        println!("D\t{}", file.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::utils::test;
+
+    fn index_hash(file: &str) -> Option<SHA1> {
+        Index::load(path::index()).unwrap().get(file, 0).map(|entry| entry.hash)
+    }
+
+    #[tokio::test]
+    async fn test_reset_modes() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "a1\n", "first").await;
+        let first_hash = index_hash("a.txt");
+        let second = test::commit_file("a.txt", "a2\n", "second").await;
+        let second_hash = index_hash("a.txt");
+        let _ = test::commit_file("b.txt", "b\n", "third").await;
+
+        // soft: only the branch is moved
+        execute(ResetArgs::parse_from(["reset", "--soft", &second.to_plain_str()])).await;
+        assert_eq!(Branch::find_branch("master", None).await.unwrap().commit, second);
+        assert!(index_hash("b.txt").is_some());
+        assert!(PathBuf::from("b.txt").exists());
+
+        // mixed: the index is reset, the worktree is kept
+        execute(ResetArgs::parse_from(["reset", &first.to_plain_str()])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), first);
+        assert_eq!(index_hash("a.txt"), first_hash);
+        assert!(index_hash("b.txt").is_none());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a2\n");
+        assert!(PathBuf::from("b.txt").exists());
+
+        // hard: the worktree is reset too, untracked `b.txt` is kept
+        add::execute(AddArgs::parse_from(["add", "a.txt"])).await;
+        assert_eq!(index_hash("a.txt"), second_hash);
+        execute(ResetArgs::parse_from(["reset", "--hard"])).await;
+        assert_eq!(index_hash("a.txt"), first_hash);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a1\n");
+        assert!(PathBuf::from("b.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_reset_pathspec() {
+        test::setup_with_new_libra().await;
+        let _ = test::commit_file("a.txt", "a1\n", "first").await;
+        let first_hash = index_hash("a.txt");
+
+        test::ensure_file("a.txt", Some("a2\n"));
+        test::ensure_file("c.txt", Some("c\n"));
+        add::execute(AddArgs::parse_from(["add", "a.txt", "c.txt"])).await;
+        assert_ne!(index_hash("a.txt"), first_hash);
+
+        execute(ResetArgs::parse_from(["reset", "a.txt", "c.txt"])).await;
+        assert_eq!(index_hash("a.txt"), first_hash);
+        assert!(index_hash("c.txt").is_none());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a2\n");
+    }
+}
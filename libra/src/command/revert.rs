@@ -0,0 +1,75 @@
+use clap::Parser;
+
+use crate::utils::util;
+
+use super::cherry_pick::{self, Operation};
+
+#[derive(Parser, Debug)]
+pub struct RevertArgs {
+    /// Commits to revert, applied in order
+    #[clap(required_unless_present_any(["continue_revert", "skip", "abort"]))]
+    pub commits: Vec<String>,
+
+    /// Apply the inverse changes to the index & worktree, but don't commit
+    #[clap(short, long)]
+    pub no_commit: bool,
+
+    /// Continue the revert after the conflicts are resolved
+    #[clap(long = "continue", group = "op", conflicts_with = "commits")]
+    pub continue_revert: bool,
+
+    /// Skip the current commit and continue the revert
+    #[clap(long, group = "op", conflicts_with = "commits")]
+    pub skip: bool,
+
+    /// Abort the revert and reset HEAD to the original commit
+    #[clap(long, group = "op", conflicts_with = "commits")]
+    pub abort: bool,
+}
+
+pub async fn execute(args: RevertArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    if args.continue_revert {
+        cherry_pick::sequence_continue(Operation::Revert).await;
+    } else if args.skip {
+        cherry_pick::sequence_skip(Operation::Revert).await;
+    } else if args.abort {
+        cherry_pick::sequence_abort(Operation::Revert).await;
+    } else {
+        cherry_pick::start(Operation::Revert, &args.commits, false, args.no_commit).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use mercury::internal::object::commit::Commit;
+
+    use super::*;
+    use crate::command::parse_commit_msg;
+    use crate::internal::head::Head;
+    use crate::utils::object_ext::CommitExt;
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_revert() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "1\n2\n3\n", "base").await;
+        test::commit_file("a.txt", "1\nchanged\n3\n", "change 2").await;
+        let changed = Head::current_commit().await.unwrap();
+        test::commit_file("b.txt", "b\n", "add b").await;
+
+        execute(RevertArgs::parse_from(["revert", &changed.to_plain_str()])).await;
+        let head = Commit::load(&Head::current_commit().await.unwrap());
+        let (message, _) = parse_commit_msg(&head.message);
+        assert_eq!(
+            message,
+            format!("Revert \"change 2\"\n\nThis reverts commit {}.", changed.to_plain_str())
+        );
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "1\n2\n3\n");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "b\n");
+    }
+}
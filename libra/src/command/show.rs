@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use clap::Parser;
+use colored::Colorize;
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::Signature;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+
+use crate::command::cat_file::read_object;
+use crate::command::{diff, load_object, parse_commit_msg};
+use crate::utils::{revision, util};
+
+/// The date format of Git's default output, like `Tue Nov 14 22:13:20 2023 +0800`
+const DEFAULT_DATE: &str = "%a %b %-d %H:%M:%S %Y %z";
+
+#[derive(Parser, Debug)]
+pub struct ShowArgs {
+    /// The objects to show: commits with their diffs, annotated tags, trees or blobs
+    #[clap(default_value = "HEAD")]
+    pub objects: Vec<String>,
+    /// Show the diffstat of the commits instead of the patch
+    #[clap(long)]
+    pub stat: bool,
+    /// Don't show the diff of the commits
+    #[clap(short = 's', long)]
+    pub no_patch: bool,
+}
+
+pub async fn execute(args: ShowArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let mut stdout = io::stdout();
+    for name in &args.objects {
+        let id = match revision::resolve(name).await {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        match show(name, &id, &args) {
+            Ok(output) => {
+                let _ = stdout.write_all(&output);
+            }
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Format an object like `git show`; a tag is followed by the object it points to
+fn show(name: &str, id: &SHA1, args: &ShowArgs) -> Result<Vec<u8>, String> {
+    let (obj_type, data) = read_object(id)?;
+    let output = match obj_type {
+        ObjectType::Commit => {
+            format_commit(&load_object::<Commit>(id).map_err(|e| e.to_string())?, args).into_bytes()
+        }
+        ObjectType::Tag => {
+            let tag = load_object::<TagObject>(id).map_err(|e| e.to_string())?;
+            let mut output = format_tag(&tag).into_bytes();
+            output.extend(show(name, &tag.object_hash, args)?);
+            output
+        }
+        ObjectType::Tree => {
+            let tree = load_object::<Tree>(id).map_err(|e| e.to_string())?;
+            format_tree(name, &tree).into_bytes()
+        }
+        _ => data,
+    };
+    Ok(output)
+}
+
+fn format_signature(label: &str, signature: &Signature) -> String {
+    format!(
+        "{}: {} <{}>\nDate:   {}\n",
+        label,
+        signature.name,
+        signature.email,
+        util::format_time(signature.timestamp, &signature.timezone, DEFAULT_DATE)
+    )
+}
+
+/// Indent each line of the message with 4 spaces
+fn indent_message(message: &str) -> String {
+    message
+        .trim_end()
+        .lines()
+        .map(|line| match line.is_empty() {
+            true => "\n".to_string(),
+            false => format!("    {}\n", line),
+        })
+        .collect()
+}
+
+/// The header & the message of a commit, then its changes from the first parent
+fn format_commit(commit: &Commit, args: &ShowArgs) -> String {
+    let mut output = format!(
+        "{}\n",
+        format!("commit {}", commit.id.to_plain_str()).yellow()
+    );
+    if commit.parent_commit_ids.len() > 1 {
+        let parents: Vec<_> = commit
+            .parent_commit_ids
+            .iter()
+            .map(|p| p.to_plain_str()[..7].to_string())
+            .collect();
+        output.push_str(&format!("Merge: {}\n", parents.join(" ")));
+    }
+    output.push_str(&format_signature("Author", &commit.author));
+    let (message, _) = parse_commit_msg(&commit.message);
+    output.push_str(&format!("\n{}", indent_message(&message)));
+
+    // the changes of a merge are in its parents
+    if args.no_patch || commit.parent_commit_ids.len() > 1 {
+        return output;
+    }
+    let changes = diff::format_commit_diff(commit.parent_commit_ids.first(), &commit.id, args.stat);
+    if !changes.is_empty() {
+        output.push('\n');
+        output.push_str(&changes);
+    }
+    output
+}
+
+/// The header & the message of an annotated tag
+fn format_tag(tag: &TagObject) -> String {
+    let mut output = format!("{}\n", format!("tag {}", tag.tag_name).yellow());
+    output.push_str(&format_signature("Tagger", &tag.tagger));
+    output.push_str(&format!("\n{}\n\n", tag.message.trim()));
+    output
+}
+
+/// The entries of a tree, the sub trees end with `/`
+fn format_tree(name: &str, tree: &Tree) -> String {
+    let mut output = format!("{}\n\n", format!("tree {}", name).yellow());
+    for item in &tree.tree_items {
+        match item.mode {
+            TreeItemMode::Tree => output.push_str(&format!("{}/\n", item.name)),
+            _ => output.push_str(&format!("{}\n", item.name)),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::tag::{self, TagArgs};
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_show() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "first line\n\nbody").await;
+        tag::execute(TagArgs::parse_from(["tag", "-m", "release", "v1"])).await;
+        let args = ShowArgs::parse_from(["show"]);
+
+        let head = revision::resolve("HEAD").await.unwrap();
+        let output = String::from_utf8(show("HEAD", &head, &args).unwrap()).unwrap();
+        assert!(output.contains(&format!("commit {}", head.to_plain_str())));
+        assert!(output.contains("\n\n    first line\n\n    body\n\n"));
+        assert!(output.contains("new file mode 100644"));
+        assert!(output.ends_with("+a\n"));
+        let output =
+            String::from_utf8(show("HEAD", &head, &ShowArgs::parse_from(["show", "-s"])).unwrap())
+                .unwrap();
+        assert!(output.ends_with("    body\n"));
+
+        let tag = revision::resolve("v1").await.unwrap();
+        let output = String::from_utf8(show("v1", &tag, &args).unwrap()).unwrap();
+        assert!(output.contains("tag v1"));
+        assert!(output.contains("\n\nrelease\n\n"));
+        assert!(output.contains(&format!("commit {}", head.to_plain_str())));
+
+        let tree = revision::resolve("HEAD^{tree}").await.unwrap();
+        let output = String::from_utf8(show("HEAD^{tree}", &tree, &args).unwrap()).unwrap();
+        assert!(output.ends_with("\n\na.txt\n"));
+        let blob = revision::resolve("HEAD:a.txt").await.unwrap();
+        assert_eq!(show("HEAD:a.txt", &blob, &args).unwrap(), b"a\n");
+    }
+}
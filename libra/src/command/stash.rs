@@ -0,0 +1,406 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use mercury::hash::SHA1;
+use mercury::internal::index::{Index, IndexEntry};
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::Tree;
+
+use crate::internal::head::Head;
+use crate::internal::stash::Stash;
+use crate::utils::merge::{self as merge_util, MergeLabels};
+use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::utils::{path, util};
+
+use super::restore::{self, RestoreArgs};
+use super::{commit, diff, format_commit_msg, merge, parse_commit_msg, save_object, status};
+
+#[derive(Parser, Debug)]
+pub struct StashArgs {
+    /// `push` if not specified
+    #[command(subcommand)]
+    pub command: Option<StashCmds>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StashCmds {
+    /// Save the local modifications to a new stash entry and revert them to HEAD
+    Push {
+        /// The description of the stash entry
+        #[clap(short, long)]
+        message: Option<String>,
+    },
+    /// List the stash entries
+    List,
+    /// Show the changes recorded in the stash entry, `--stat` by default
+    Show {
+        /// The stash entry, `stash@{0}` by default
+        stash: Option<String>,
+        /// Show the changes as a patch
+        #[clap(short, long)]
+        patch: bool,
+    },
+    /// Apply the stash entry on top of the current worktree and remove it from the stash list
+    Pop {
+        /// The stash entry, `stash@{0}` by default
+        stash: Option<String>,
+    },
+    /// Apply the stash entry on top of the current worktree, but keep it in the stash list
+    Apply {
+        /// The stash entry, `stash@{0}` by default
+        stash: Option<String>,
+    },
+    /// Remove the stash entry from the stash list
+    Drop {
+        /// The stash entry, `stash@{0}` by default
+        stash: Option<String>,
+    },
+}
+
+pub async fn execute(args: StashArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    match args.command.unwrap_or(StashCmds::Push { message: None }) {
+        StashCmds::Push { message } => push(message).await,
+        StashCmds::List => match Stash::list().await {
+            Ok(list) => {
+                for (i, stash) in list.iter().enumerate() {
+                    println!("stash@{{{}}}: {}", i, stash.message);
+                }
+            }
+            Err(e) => eprintln!("fatal: unable to read the stash list: {}", e),
+        },
+        StashCmds::Show { stash, patch } => {
+            if let Some((_, stash)) = find_stash(stash.as_deref()).await {
+                let commit = Commit::load(&stash.commit);
+                print!("{}", diff::format_commit_diff(Some(&commit.parent_commit_ids[0]), &commit.id, !patch));
+            }
+        }
+        StashCmds::Pop { stash } => {
+            if let Some((n, stash)) = find_stash(stash.as_deref()).await {
+                if apply(&stash).await {
+                    drop_stash(n, &stash).await;
+                } else {
+                    println!("The stash entry is kept in case you need it again.");
+                }
+            }
+        }
+        StashCmds::Apply { stash } => {
+            if let Some((_, stash)) = find_stash(stash.as_deref()).await {
+                apply(&stash).await;
+            }
+        }
+        StashCmds::Drop { stash } => {
+            if let Some((n, stash)) = find_stash(stash.as_deref()).await {
+                drop_stash(n, &stash).await;
+            }
+        }
+    }
+}
+
+/// Parse `stash@{n}` or `n` to the stash index
+fn parse_stash_ref(stash: &str) -> Option<usize> {
+    stash
+        .strip_prefix("stash@{")
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or(stash)
+        .parse()
+        .ok()
+}
+
+/// Find the stash entry by `stash@{n}`, the latest one if `None`, print the error if not found
+async fn find_stash(stash: Option<&str>) -> Option<(usize, Stash)> {
+    let n = match stash {
+        None => 0,
+        Some(stash) => match parse_stash_ref(stash) {
+            Some(n) => n,
+            None => {
+                eprintln!("error: '{}' is not a stash-like commit", stash);
+                return None;
+            }
+        },
+    };
+    match Stash::get(n).await {
+        Ok(Some(entry)) => Some((n, entry)),
+        Err(e) => {
+            eprintln!("fatal: unable to read the stash list: {}", e);
+            None
+        }
+        Ok(None) if n == 0 => {
+            eprintln!("No stash entries found.");
+            None
+        }
+        Ok(None) => {
+            eprintln!("error: stash@{{{}}} is not a valid reference", n);
+            None
+        }
+    }
+}
+
+/// Record the index & worktree as commits (same layout as Git), then reset them to `HEAD`
+/// - index commit: tree of the index, parent is `HEAD`
+/// - worktree commit: tree of the tracked files in worktree, parents are `HEAD` & the index commit
+async fn push(message: Option<String>) {
+    if !commit::check_identity().await {
+        return;
+    }
+    let head = match Head::current_commit().await {
+        Some(head) => head,
+        None => {
+            eprintln!("fatal: You do not have the initial commit yet");
+            return;
+        }
+    };
+    let mut index = Index::load(path::index()).unwrap();
+    let unmerged = index.unmerged_files();
+    if !unmerged.is_empty() {
+        for file in unmerged {
+            eprintln!("{}: needs merge", file.display());
+        }
+        eprintln!("fatal: could not save index tree");
+        return;
+    }
+    let unstaged = status::changes_to_be_staged().await;
+    if status::changes_to_be_committed().await.is_empty()
+        && unstaged.modified.is_empty()
+        && unstaged.deleted.is_empty()
+    {
+        println!("No local changes to save");
+        return;
+    }
+
+    let branch = match Head::current().await {
+        Head::Branch(name) => name,
+        Head::Detached(_) => "(no branch)".to_string(),
+    };
+    let (head_msg, _) = parse_commit_msg(&Commit::load(&head).message);
+    let summary = format!("{} {}", &head.to_plain_str()[..7], head_msg.lines().next().unwrap_or_default());
+
+    let storage = util::objects_storage();
+    let index_tree = commit::create_tree(&index, &storage, "".into()).await;
+    let index_commit = Commit::new(
+        commit::author().await,
+        commit::committer().await,
+        index_tree.id,
+        vec![head],
+        &format_commit_msg(&format!("index on {}: {}", branch, summary), None),
+    );
+    save_object(&index_commit, &index_commit.id).unwrap();
+
+    // the index is not saved, just used to build the worktree tree
+    let workdir = util::working_dir();
+    for file in unstaged.modified.iter() {
+        let blob = Blob::from_file(util::workdir_to_absolute(file));
+        blob.save();
+        index.update(IndexEntry::new_from_file(file, blob.id, &workdir).unwrap());
+    }
+    for file in unstaged.deleted.iter() {
+        index.remove(&util::path_to_string(file), 0);
+    }
+    let worktree_tree = commit::create_tree(&index, &storage, "".into()).await;
+    let stash_msg = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}: {}", branch, summary),
+    };
+    let worktree_commit = Commit::new(
+        commit::author().await,
+        commit::committer().await,
+        worktree_tree.id,
+        vec![head, index_commit.id],
+        &format_commit_msg(&stash_msg, None),
+    );
+    save_object(&worktree_commit, &worktree_commit.id).unwrap();
+
+    // the worktree is reset only after the changes are recorded
+    if let Err(e) = Stash::push(&worktree_commit.id, &stash_msg).await {
+        eprintln!("fatal: cannot save the current status: {}", e);
+        return;
+    }
+    println!("Saved working directory and index state {}", stash_msg);
+
+    // reset the worktree & index to HEAD, untracked files are kept
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: None,
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+}
+
+/// Merge the stashed changes into the worktree, return `false` if failed or conflicted
+/// - the base of the merge is the commit the stash was created on
+/// - like Git, the changes of the tracked files are left unstaged, new files are staged
+async fn apply(stash: &Stash) -> bool {
+    let head = match Head::current_commit().await {
+        Some(head) => head,
+        None => {
+            eprintln!("fatal: You do not have the initial commit yet");
+            return false;
+        }
+    };
+    let index_file = path::index();
+    if merge::merge_head().is_some() || !Index::load(&index_file).unwrap().unmerged_files().is_empty() {
+        eprintln!("error: Cannot apply a stash in the middle of a merge");
+        return false;
+    }
+    if !status::changes_to_be_committed().await.is_empty() {
+        eprintln!("error: Cannot apply stash: Your index contains uncommitted changes.");
+        return false;
+    }
+
+    let stash_commit = Commit::load(&stash.commit);
+    let labels = MergeLabels {
+        ours: "Updated upstream".to_string(),
+        theirs: "Stashed changes".to_string(),
+    };
+    let base_items = merge::commit_tree_items(&stash_commit.parent_commit_ids[0]);
+    let ours_items = merge::commit_tree_items(&head);
+    let theirs_items = Tree::load(&stash_commit.tree_id).get_plain_items();
+    let entries = merge_util::merge_trees(&base_items, &ours_items, &theirs_items, &labels);
+
+    // local changes are kept, unless the stash changes the same files
+    let ours: HashMap<&PathBuf, &SHA1> = ours_items.iter().map(|(path, hash)| (path, hash)).collect();
+    let touched: HashSet<&PathBuf> = entries
+        .iter()
+        .filter(|entry| entry.conflict.is_some() || entry.hash.as_ref() != ours.get(&entry.path).copied())
+        .map(|entry| &entry.path)
+        .collect();
+    let unstaged = status::changes_to_be_staged().await;
+    let overwritten: Vec<&PathBuf> = unstaged
+        .modified
+        .iter()
+        .chain(unstaged.deleted.iter())
+        .filter(|file| touched.contains(file))
+        .collect();
+    if !overwritten.is_empty() {
+        eprintln!("error: Your local changes to the following files would be overwritten by merge:");
+        for file in overwritten {
+            eprintln!("\t{}", util::workdir_to_current(file).display());
+        }
+        eprintln!("Please commit your changes or stash them before you merge.");
+        eprintln!("Aborting");
+        return false;
+    }
+    let overwritten = merge::untracked_overwritten(&entries, &ours_items);
+    if !overwritten.is_empty() {
+        eprintln!("error: The following untracked working tree files would be overwritten by merge:");
+        for file in overwritten {
+            eprintln!("\t{}", util::workdir_to_current(file).display());
+        }
+        eprintln!("Please move or remove them before you merge.");
+        eprintln!("Aborting");
+        return false;
+    }
+
+    if merge::apply_merge_result(&entries, &ours_items, &labels) {
+        return false;
+    }
+    // unstage the changes of the files in `HEAD`
+    let mut index = Index::load(&index_file).unwrap();
+    for entry in entries.iter().filter(|entry| touched.contains(&entry.path)) {
+        if let Some(hash) = ours.get(&entry.path) {
+            let size = Blob::load(hash).data.len() as u32;
+            let index_entry = IndexEntry::new_from_blob(util::path_to_string(&entry.path), **hash, size);
+            match entry.hash {
+                Some(_) => index.update(index_entry),
+                None => index.add(index_entry),
+            }
+        }
+    }
+    index.save(&index_file).unwrap();
+    status::execute(status::StatusArgs::default()).await;
+    true
+}
+
+async fn drop_stash(n: usize, stash: &Stash) {
+    if let Err(e) = stash.remove().await {
+        eprintln!("fatal: unable to drop stash@{{{}}}: {}", n, e);
+        return;
+    }
+    println!("Dropped refs/stash@{{{}}} ({})", n, stash.commit.to_plain_str());
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::utils::test;
+
+    async fn stash(args: &[&str]) {
+        execute(StashArgs::parse_from([&["stash"], args].concat())).await;
+    }
+
+    #[test]
+    fn test_parse_stash_ref() {
+        assert_eq!(parse_stash_ref("stash@{2}"), Some(2));
+        assert_eq!(parse_stash_ref("1"), Some(1));
+        assert_eq!(parse_stash_ref("stash@{x}"), None);
+        assert_eq!(parse_stash_ref("master"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stash_push_pop() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "add a").await;
+        let head = test::commit_file("b.txt", "b\n", "add b").await;
+
+        fs::write("a.txt", "a changed\n").unwrap();
+        fs::remove_file("b.txt").unwrap();
+        test::ensure_file("c.txt", Some("c\n"));
+        add::execute(AddArgs::parse_from(["add", "c.txt"])).await;
+
+        stash(&[]).await;
+        let entry = Stash::get(0).await.unwrap().unwrap();
+        assert!(entry.message.starts_with("WIP on master: "));
+        let stash_commit = Commit::load(&entry.commit);
+        assert_eq!(stash_commit.parent_commit_ids[0], head);
+        let index_commit = Commit::load(&stash_commit.parent_commit_ids[1]);
+        assert_eq!(index_commit.parent_commit_ids, vec![head]);
+        // worktree & index are reset to HEAD
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\n");
+        assert!(PathBuf::from("b.txt").exists());
+        assert!(!PathBuf::from("c.txt").exists());
+        assert!(status::changes_to_be_committed().await.is_empty());
+
+        stash(&["pop"]).await;
+        assert!(Stash::list().await.unwrap().is_empty());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a changed\n");
+        assert!(!PathBuf::from("b.txt").exists());
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "c\n");
+        // new file is staged, modifications are not
+        let staged = status::changes_to_be_committed().await;
+        assert_eq!(staged.new, vec![PathBuf::from("c.txt")]);
+        assert!(staged.modified.is_empty());
+        let unstaged = status::changes_to_be_staged().await;
+        assert_eq!(unstaged.modified, vec![PathBuf::from("a.txt")]);
+        assert_eq!(unstaged.deleted, vec![PathBuf::from("b.txt")]);
+    }
+
+    #[tokio::test]
+    async fn test_stash_pop_conflict() {
+        test::setup_with_new_libra().await;
+        test::commit_file("a.txt", "a\n", "init").await;
+
+        fs::write("a.txt", "stashed\n").unwrap();
+        stash(&["push", "-m", "first"]).await;
+        assert_eq!(Stash::get(0).await.unwrap().unwrap().message, "On master: first");
+
+        test::commit_file("a.txt", "upstream\n", "update a").await;
+
+        stash(&["pop"]).await;
+        // the entry is kept when conflicted
+        assert_eq!(Stash::list().await.unwrap().len(), 1);
+        let content = fs::read_to_string("a.txt").unwrap();
+        assert!(content.contains("<<<<<<< Updated upstream"));
+        assert!(content.contains(">>>>>>> Stashed changes"));
+
+        stash(&["drop", "stash@{0}"]).await;
+        assert!(Stash::list().await.unwrap().is_empty());
+    }
+}
@@ -1,16 +1,39 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use clap::Parser;
 use colored::Colorize;
 use path_abs::PathInfo;
 
+use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::Tree;
 
+use crate::command::load_object;
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
-use mercury::internal::index::Index;
+use crate::internal::shallow::{self, Shallow};
+use mercury::internal::index::{Index, IndexEntry};
 use crate::utils::object_ext::{CommitExt, TreeExt};
-use crate::utils::{path, util};
+use crate::utils::{path, revision, util};
+
+#[derive(Parser, Debug, Default)]
+pub struct StatusArgs {
+    /// Give the output in the short format: `XY <path>` for each changed path
+    #[clap(short, long)]
+    pub short: bool,
+    /// Give the output in a stable format for scripts: `v1` (like `--short`, by default) or `v2`
+    #[clap(long, value_name = "VERSION", value_parser = ["v1", "v2"], num_args = 0..=1, require_equals = true, default_missing_value = "v1")]
+    pub porcelain: Option<String>,
+    /// Show the branch and its tracking info in the short & the porcelain formats
+    #[clap(short, long)]
+    pub branch: bool,
+    /// Show the untracked files: `no`, `normal` (a directory without tracked files as a whole, by default) or `all`
+    #[clap(short = 'u', long, value_name = "MODE", value_parser = ["no", "normal", "all"], num_args = 0..=1, default_missing_value = "all")]
+    pub untracked_files: Option<String>,
+}
 
 /// path: to workdir
 #[derive(Debug, Default, Clone)]
@@ -41,17 +64,31 @@ impl Changes {
  * 1. unstaged
  * 2. staged to be committed
  */
-pub async fn execute() {
+pub async fn execute(args: StatusArgs) {
     if !util::check_repo_exist() {
         return;
     }
-    // TODO .gitignore
+    let untracked_mode = args.untracked_files.as_deref().unwrap_or("normal");
+    if args.short || args.porcelain.is_some() {
+        let version = args.porcelain.as_deref();
+        let mut output = String::new();
+        if args.branch {
+            output.push_str(&branch_header(version).await);
+        }
+        output.push_str(&short_status(version, untracked_mode).await);
+        print!("{}", output);
+        return;
+    }
+
     match Head::current().await {
         Head::Detached(commit) => {
-            println!("HEAD detached at {}", String::from_utf8_lossy(&commit.0[0..7]));
+            println!("HEAD detached at {}", &commit.to_plain_str()[..7]);
         }
         Head::Branch(branch) => {
             println!("On branch {}", branch);
+            if let Some(tracking) = tracking_info(&branch).await {
+                println!("{}", tracking);
+            }
         }
     }
 
@@ -59,10 +96,26 @@ pub async fn execute() {
         println!("\nNo commits yet\n");
     }
 
+    if path::merge_head().exists() {
+        println!("You have unmerged paths.");
+        println!("  (fix conflicts and run \"libra commit\")");
+        println!("  (use \"libra merge --abort\" to abort the merge)\n");
+    }
+
+    let index = Index::load(path::index()).unwrap();
+    let unmerged = index.unmerged_files();
     // to cur_dir relative path
     let staged = changes_to_be_committed().await.to_relative();
-    let unstaged = changes_to_be_staged().to_relative();
-    if staged.is_empty() && unstaged.is_empty() {
+    let mut unstaged = changes_to_be_staged().await;
+    // unmerged files are shown in their own section
+    unstaged.modified.retain(|f| !unmerged.contains(f));
+    unstaged.deleted.retain(|f| !unmerged.contains(f));
+    if untracked_mode == "no" {
+        unstaged.new.clear();
+    }
+    let untracked = untracked_paths(&unstaged.new, &index, untracked_mode);
+    let unstaged = unstaged.to_relative();
+    if staged.is_empty() && unstaged.is_empty() && unmerged.is_empty() {
         println!("nothing to commit, working tree clean");
         return;
     }
@@ -84,6 +137,16 @@ pub async fn execute() {
         });
     }
 
+    if !unmerged.is_empty() {
+        println!("Unmerged paths:");
+        println!("  use \"libra add <file>...\" to mark resolution");
+        unmerged.iter().for_each(|f| {
+            let (_, state) = unmerged_state(&index, f);
+            let str = format!("\t{}: {}", state, util::workdir_to_current(f).display());
+            println!("{}", str.bright_red());
+        });
+    }
+
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
         println!("Changes not staged for commit:");
         println!("  use \"libra add <file>...\" to update what will be committed");
@@ -97,16 +160,316 @@ pub async fn execute() {
             println!("{}", str.bright_red());
         });
     }
-    if !unstaged.new.is_empty() {
+    if !untracked.is_empty() {
         println!("Untracked files:");
         println!("  use \"libra add <file>...\" to include in what will be committed");
-        unstaged.new.iter().for_each(|f| {
-            let str = format!("\t{}", f.display());
+        untracked.iter().for_each(|(f, is_dir)| {
+            let str = format!("\t{}", display_untracked(&util::workdir_to_current(f), *is_dir));
             println!("{}", str.bright_red());
         });
     }
 }
 
+/// The short code (like `UU`) & the description of a conflicted file, from the stages in the index
+fn unmerged_state(index: &Index, file: &Path) -> (&'static str, &'static str) {
+    let stages: Vec<bool> = (1..=3).map(|stage| index.tracked(file.to_str().unwrap(), stage)).collect();
+    match stages[..] {
+        [true, true, true] => ("UU", "both modified"),
+        [false, true, true] => ("AA", "both added"),
+        [true, false, true] => ("DU", "deleted by us"),
+        [false, false, true] => ("UA", "added by them"),
+        [true, true, false] => ("UD", "deleted by them"),
+        [false, true, false] => ("AU", "added by us"),
+        _ => ("DD", "both deleted"),
+    }
+}
+
+/// The untracked files to show (to workdir), with whether it's a directory.
+/// In `normal` mode, a directory without any tracked files is shown as a whole.
+fn untracked_paths(new: &[PathBuf], index: &Index, mode: &str) -> Vec<(PathBuf, bool)> {
+    if mode == "no" {
+        return Vec::new();
+    }
+    let tracked_files = index.tracked_files();
+    let tracked_dirs: HashSet<&Path> = tracked_files
+        .iter()
+        .flat_map(|file| file.ancestors().skip(1))
+        .collect();
+    let mut untracked: Vec<(PathBuf, bool)> = Vec::new();
+    for file in new {
+        let collapsed = match mode {
+            "all" => None,
+            _ => file
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .filter(|dir| !tracked_dirs.contains(dir))
+                .last(),
+        };
+        let entry = match collapsed {
+            Some(dir) => (dir.to_path_buf(), true),
+            None => (file.clone(), false),
+        };
+        if !untracked.contains(&entry) {
+            untracked.push(entry);
+        }
+    }
+    untracked.sort();
+    untracked
+}
+
+/// A directory ends with `/`
+fn display_untracked(path: &Path, is_dir: bool) -> String {
+    match is_dir {
+        true => format!("{}/", path.display()),
+        false => path.display().to_string(),
+    }
+}
+
+/// The upstream of the branch like `origin/master`, and its commit (`None` if gone)
+pub async fn upstream(branch: &str) -> Option<(String, Option<SHA1>)> {
+    let config = Config::branch_config(branch).await?;
+    let tracking = Branch::find_branch(&config.merge, Some(&config.remote)).await;
+    Some((format!("{}/{}", config.remote, config.merge), tracking.map(|b| b.commit)))
+}
+
+/// The numbers of commits reachable from `local` but not `upstream`, and the reverse
+pub async fn ahead_behind(local: &SHA1, upstream: &SHA1) -> (usize, usize) {
+    let shallow = Shallow::list().await;
+    let ancestors = |start: &SHA1| {
+        let mut ancestors = HashSet::new();
+        let mut stack = vec![*start];
+        while let Some(id) = stack.pop() {
+            if ancestors.insert(id) {
+                let commit = load_object::<Commit>(&id).expect("fatal: storage broken, object not found");
+                stack.extend(shallow::parents(&commit, &shallow));
+            }
+        }
+        ancestors
+    };
+    let (local, upstream) = (ancestors(local), ancestors(upstream));
+    (local.difference(&upstream).count(), upstream.difference(&local).count())
+}
+
+/// The relation with the upstream for the long format, `None` if the branch has no upstream
+async fn tracking_info(branch: &str) -> Option<String> {
+    let (name, tracking) = upstream(branch).await?;
+    let local = Branch::find_branch(branch, None).await?.commit;
+    let Some(tracking) = tracking else {
+        return Some(format!("Your branch is based on '{}', but the upstream is gone.\n", name));
+    };
+    let plural = |n: usize| if n == 1 { "commit" } else { "commits" };
+    let info = match ahead_behind(&local, &tracking).await {
+        (0, 0) => format!("Your branch is up to date with '{}'.\n", name),
+        (ahead, 0) => format!(
+            "Your branch is ahead of '{}' by {} {}.\n  (use \"libra push\" to publish your local commits)\n",
+            name,
+            ahead,
+            plural(ahead)
+        ),
+        (0, behind) => format!(
+            "Your branch is behind '{}' by {} {}, and can be fast-forwarded.\n  (use \"libra pull\" to update your local branch)\n",
+            name,
+            behind,
+            plural(behind)
+        ),
+        (ahead, behind) => format!(
+            "Your branch and '{}' have diverged,\nand have {} and {} different commits each, respectively.\n  (use \"libra pull\" to merge the remote branch into yours)\n",
+            name, ahead, behind
+        ),
+    };
+    Some(info)
+}
+
+/// The branch header of the short formats:
+/// - `## master...origin/master [ahead 1, behind 2]` for `--short` & `--porcelain=v1`
+/// - `# branch.oid`, `# branch.head`, `# branch.upstream` & `# branch.ab` lines for `--porcelain=v2`
+async fn branch_header(porcelain: Option<&str>) -> String {
+    let head = Head::current().await;
+    let commit = Head::current_commit().await;
+    let tracking = match &head {
+        Head::Branch(branch) if commit.is_some() => match upstream(branch).await {
+            Some((name, Some(tracking))) => Some((name, Some(ahead_behind(&commit.unwrap(), &tracking).await))),
+            Some((name, None)) => Some((name, None)),
+            None => None,
+        },
+        _ => None,
+    };
+
+    if porcelain == Some("v2") {
+        let oid = commit.map_or("(initial)".to_string(), |c| c.to_plain_str());
+        let branch = match &head {
+            Head::Branch(branch) => branch.as_str(),
+            Head::Detached(_) => "(detached)",
+        };
+        let mut header = format!("# branch.oid {}\n# branch.head {}\n", oid, branch);
+        if let Some((name, ab)) = tracking {
+            header.push_str(&format!("# branch.upstream {}\n", name));
+            if let Some((ahead, behind)) = ab {
+                header.push_str(&format!("# branch.ab +{} -{}\n", ahead, behind));
+            }
+        }
+        return header;
+    }
+
+    let colored = porcelain.is_none();
+    let paint = |text: &str, color: &str| match colored {
+        true => text.color(color).to_string(),
+        false => text.to_string(),
+    };
+    let branch = match (&head, commit) {
+        (Head::Detached(_), _) => paint("HEAD (no branch)", "red"),
+        (Head::Branch(branch), None) => format!("No commits yet on {}", paint(branch, "green")),
+        (Head::Branch(branch), Some(_)) => paint(branch, "green"),
+    };
+    let mut header = format!("## {}", branch);
+    if let Some((name, ab)) = tracking {
+        header.push_str(&format!("...{}", paint(&name, "red")));
+        let counts = match ab {
+            None => vec!["gone".to_string()],
+            Some((ahead, behind)) => [("ahead", ahead), ("behind", behind)]
+                .iter()
+                .filter(|(_, n)| *n > 0)
+                .map(|(label, n)| format!("{} {}", label, paint(&n.to_string(), "green")))
+                .collect(),
+        };
+        if !counts.is_empty() {
+            header.push_str(&format!(" [{}]", counts.join(", ")));
+        }
+    }
+    header.push('\n');
+    header
+}
+
+/// A changed path in the short formats
+struct ShortEntry {
+    /// to workdir
+    path: PathBuf,
+    /// `XY` like Git: X for the index, Y for the work tree, ` ` if unchanged; `UU` etc. if unmerged
+    code: String,
+    unmerged: bool,
+}
+
+/// The changed paths & the untracked files, a line for each:
+/// - `--short`: `XY <path>`, the paths relative to the current directory & colored
+/// - `--porcelain=v1`: `XY <path>`, the paths relative to the workdir
+/// - `--porcelain=v2`: `1 XY N... <mH> <mI> <mW> <hH> <hI> <path>`, `u XY N... <m1> <m2> <m3> <mW> <h1> <h2> <h3> <path>`
+///   for the unmerged paths, and `? <path>` for the untracked files
+async fn short_status(porcelain: Option<&str>, untracked_mode: &str) -> String {
+    let index = Index::load(path::index()).unwrap();
+    let unmerged: HashSet<PathBuf> = index.unmerged_files().into_iter().collect();
+    let staged = changes_to_be_committed().await;
+    let unstaged = changes_to_be_staged().await;
+
+    let mut entries: BTreeMap<PathBuf, [char; 2]> = BTreeMap::new();
+    let mut mark = |paths: &[PathBuf], side: usize, code: char| {
+        for path in paths.iter().filter(|p| !unmerged.contains(*p)) {
+            entries.entry(path.clone()).or_insert([' ', ' '])[side] = code;
+        }
+    };
+    mark(&staged.new, 0, 'A');
+    mark(&staged.modified, 0, 'M');
+    mark(&staged.deleted, 0, 'D');
+    mark(&unstaged.modified, 1, 'M');
+    mark(&unstaged.deleted, 1, 'D');
+    let mut entries: Vec<ShortEntry> = entries
+        .into_iter()
+        .map(|(path, code)| ShortEntry { path, code: code.iter().collect(), unmerged: false })
+        .collect();
+    entries.extend(unmerged.iter().map(|path| ShortEntry {
+        path: path.clone(),
+        code: unmerged_state(&index, path).0.to_string(),
+        unmerged: true,
+    }));
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let untracked = untracked_paths(&unstaged.new, &index, untracked_mode);
+
+    let mut output = String::new();
+    if porcelain == Some("v2") {
+        let head_tree = Head::current_commit()
+            .await
+            .map(|commit| Commit::load(&commit).tree_id);
+        let zero = SHA1::default().to_plain_str();
+        let stage = |path: &Path, stage: u8| match index.get(path.to_str().unwrap(), stage) {
+            Some(entry) => (format!("{:06o}", entry.mode), entry.hash.to_plain_str()),
+            None => ("000000".to_string(), zero.clone()),
+        };
+        for entry in &entries {
+            let worktree = worktree_mode(&entry.path);
+            let path = entry.path.display();
+            if entry.unmerged {
+                let [(m1, h1), (m2, h2), (m3, h3)] = [1, 2, 3].map(|n| stage(&entry.path, n));
+                output.push_str(&format!(
+                    "u {} N... {} {} {} {} {} {} {} {}\n",
+                    entry.code, m1, m2, m3, worktree, h1, h2, h3, path
+                ));
+                continue;
+            }
+            let head = head_tree
+                .and_then(|tree| revision::lookup_path(&tree, &entry.path))
+                .map_or(("000000".to_string(), zero.clone()), |(mode, id)| {
+                    (format!("{:0>6}", String::from_utf8_lossy(mode.to_bytes())), id.to_plain_str())
+                });
+            let (index_mode, index_hash) = stage(&entry.path, 0);
+            // the file removed from the index is untracked in the work tree
+            let worktree = match index_mode.as_str() {
+                "000000" => index_mode.clone(),
+                _ => worktree,
+            };
+            output.push_str(&format!(
+                "1 {} N... {} {} {} {} {} {}\n",
+                entry.code.replace(' ', "."),
+                head.0,
+                index_mode,
+                worktree,
+                head.1,
+                index_hash,
+                path
+            ));
+        }
+        for (path, is_dir) in &untracked {
+            output.push_str(&format!("? {}\n", display_untracked(path, *is_dir)));
+        }
+        return output;
+    }
+
+    let colored = porcelain.is_none();
+    let relative = |path: &Path| match colored {
+        true => util::workdir_to_current(path),
+        false => path.to_path_buf(),
+    };
+    for entry in &entries {
+        let code = match (colored, entry.unmerged) {
+            (false, _) => entry.code.clone(),
+            (true, true) => entry.code.red().to_string(),
+            (true, false) => {
+                let (x, y) = entry.code.split_at(1);
+                format!("{}{}", x.green(), y.red())
+            }
+        };
+        output.push_str(&format!("{} {}\n", code, relative(&entry.path).display()));
+    }
+    for (path, is_dir) in &untracked {
+        let code = match colored {
+            true => "??".red().to_string(),
+            false => "??".to_string(),
+        };
+        output.push_str(&format!("{} {}\n", code, display_untracked(&relative(path), *is_dir)));
+    }
+    output
+}
+
+/// The mode of a file in the work tree like the index, `000000` if deleted
+fn worktree_mode(file: &Path) -> String {
+    format!("{:06o}", file_mode(file).unwrap_or(0))
+}
+
+/// The mode of a file (to workdir) in the work tree like the index, e.g. `0o100755`, `None` if deleted
+pub fn file_mode(file: &Path) -> Option<u32> {
+    let meta = fs::symlink_metadata(util::workdir_to_absolute(file)).ok()?;
+    Some(IndexEntry::new(&meta, SHA1::default(), file.to_string_lossy().to_string()).mode)
+}
+
 /**
  * Compare the difference between `index` and the last `Commit Tree`
  */
@@ -125,9 +488,13 @@ pub async fn changes_to_be_committed() -> Changes {
     let commit = Commit::load(&head_commit);
     let tree = Tree::load(&commit.tree_id);
     let tree_files = tree.get_plain_items();
+    let unmerged = index.unmerged_files();
 
     for (item_path, item_hash) in tree_files.iter() {
         let item_str = item_path.to_str().unwrap();
+        if unmerged.contains(item_path) {
+            continue; // conflicts are not staged yet
+        }
         if index.tracked(item_str, 0) {
             if !index.verify_hash(item_str, 0, item_hash) {
                 changes.modified.push(item_path.clone());
@@ -147,7 +514,7 @@ pub async fn changes_to_be_committed() -> Changes {
 }
 
 /// Compare the difference between `index` and the `workdir`
-pub fn changes_to_be_staged() -> Changes {
+pub async fn changes_to_be_staged() -> Changes {
     let mut changes = Changes::default();
     let workdir = util::working_dir();
     let index = Index::load(path::index()).unwrap();
@@ -165,12 +532,97 @@ pub fn changes_to_be_staged() -> Changes {
             }
         }
     }
-    let files = util::list_workdir_files().unwrap(); // to workdir
+    // conflicted files need to be added to mark resolution
+    for file in index.unmerged_files() {
+        if util::workdir_to_absolute(&file).exists() {
+            changes.modified.push(file);
+        } else {
+            changes.deleted.push(file);
+        }
+    }
+    let files = util::list_workdir_files().await.unwrap(); // to workdir, except ignored files
     for file in files.iter() {
-        if !index.tracked(file.to_str().unwrap(), 0) {
+        if !index.tracked(file.to_str().unwrap(), 0) && !changes.modified.contains(file) {
             // file not tracked in `index`
             changes.new.push(file.clone());
         }
     }
     changes
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::branch;
+    use crate::command::commit::{self, CommitArgs};
+    use crate::command::switch::{self, SwitchArgs};
+    use crate::utils::test;
+
+    async fn commit(message: &str) -> SHA1 {
+        commit::execute(CommitArgs {
+            message: message.to_string(),
+            allow_empty: true,
+            gpg_sign: None,
+            no_gpg_sign: false,
+        })
+        .await;
+        Head::current_commit().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_short_status() {
+        test::setup_with_new_libra().await;
+        let status = |porcelain: &'static str, mode: &'static str| short_status(Some(porcelain), mode);
+        test::ensure_file("st/a.txt", Some("a"));
+        test::ensure_file("st/dir/b.txt", Some("b"));
+        add::execute(AddArgs::parse_from(["add", "st/a.txt", "st/dir/b.txt"])).await;
+        commit("init").await;
+
+        test::ensure_file("st/a.txt", Some("a2"));
+        test::ensure_file("st/dir/b.txt", Some("b2"));
+        test::ensure_file("st/c.txt", Some("c"));
+        add::execute(AddArgs::parse_from(["add", "st/dir/b.txt", "st/c.txt"])).await;
+        test::ensure_file("st/c.txt", Some("c2"));
+        test::ensure_file("st/dir/new.txt", Some("new"));
+        test::ensure_file("st/other/sub/d.txt", Some("d"));
+
+        assert_eq!(
+            status("v1", "normal").await,
+            " M st/a.txt\nAM st/c.txt\nM  st/dir/b.txt\n?? st/dir/new.txt\n?? st/other/\n"
+        );
+        assert_eq!(
+            status("v1", "all").await,
+            " M st/a.txt\nAM st/c.txt\nM  st/dir/b.txt\n?? st/dir/new.txt\n?? st/other/sub/d.txt\n"
+        );
+        assert_eq!(
+            status("v1", "no").await,
+            " M st/a.txt\nAM st/c.txt\nM  st/dir/b.txt\n"
+        );
+
+        let v2 = status("v2", "normal").await;
+        let lines: Vec<&str> = v2.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("1 .M N... 100644 100644 100644 "));
+        assert!(lines[1].starts_with(
+            "1 AM N... 000000 100644 100644 0000000000000000000000000000000000000000 "
+        ));
+        assert!(lines[1].ends_with(" st/c.txt"));
+        assert_eq!(lines[4], "? st/other/");
+    }
+
+    #[tokio::test]
+    async fn test_ahead_behind() {
+        test::setup_with_new_libra().await;
+        let base = commit("base").await;
+        commit("local 1").await;
+        let local = commit("local 2").await;
+        branch::create_branch("other".to_string(), Some(base.to_plain_str())).await;
+        switch::execute(SwitchArgs::parse_from(["switch", "other"])).await;
+        let upstream = commit("upstream").await;
+
+        assert_eq!(ahead_behind(&local, &upstream).await, (2, 1));
+        assert_eq!(ahead_behind(&upstream, &local).await, (1, 2));
+        assert_eq!(ahead_behind(&local, &local).await, (0, 0));
+        assert_eq!(ahead_behind(&local, &base).await, (2, 0));
+    }
+}
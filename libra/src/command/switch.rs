@@ -3,7 +3,11 @@ use mercury::hash::SHA1;
 
 use crate::{
     command::branch,
-    internal::{branch::Branch, head::Head},
+    internal::{
+        branch::Branch,
+        head::Head,
+        reflog::{self, Reflog},
+    },
     utils::util::{self, get_commit_base},
 };
 
@@ -26,13 +30,13 @@ pub struct SwitchArgs {
 
 pub async fn execute(args: SwitchArgs) {
     // check status
-    let unstaged = status::changes_to_be_staged();
+    let unstaged = status::changes_to_be_staged().await;
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
-        status::execute().await;
+        status::execute(status::StatusArgs::default()).await;
         eprintln!("fatal: uncommitted changes, can't switch branch");
         return;
     } else if !status::changes_to_be_committed().await.is_empty() {
-        status::execute().await;
+        status::execute(status::StatusArgs::default()).await;
         eprintln!("fatal: unstaged changes, can't switch branch");
         return;
     }
@@ -44,7 +48,7 @@ pub async fn execute(args: SwitchArgs) {
         }
         None => match args.detach {
             true => {
-                let commit_base = get_commit_base(&args.branch.unwrap());
+                let commit_base = get_commit_base(&args.branch.unwrap()).await;
                 if commit_base.is_err() {
                     eprintln!("{}", commit_base.unwrap());
                     return;
@@ -60,10 +64,14 @@ pub async fn execute(args: SwitchArgs) {
 
 /// change the working directory to the version of commit_hash
 async fn switch_to_commit(commit_hash: SHA1) {
+    let from = head_name().await;
+    let old = Head::current_commit().await;
     restore_to_commit(commit_hash).await;
     // update HEAD
     let head = Head::Detached(commit_hash);
     Head::update(head, None).await;
+    let message = format!("checkout: moving from {} to {}", from, commit_hash.to_plain_str());
+    Reflog::insert(reflog::HEAD, old, commit_hash, &message).await;
 }
 
 async fn switch_to_branch(branch_name: String) {
@@ -80,11 +88,23 @@ async fn switch_to_branch(branch_name: String) {
         return;
     }
     let commit_id = target_branch.unwrap().commit;
+    let from = head_name().await;
+    let old = Head::current_commit().await;
     restore_to_commit(commit_id).await;
     // update HEAD
     // let mut head: ActiveModel = reference::Model::current_head(db).await.unwrap().into();
+    let message = format!("checkout: moving from {} to {}", from, branch_name);
     let head = Head::Branch(branch_name);
     Head::update(head, None).await;
+    Reflog::insert(reflog::HEAD, old, commit_id, &message).await;
+}
+
+/// Name of the current branch, or the commit hash if `HEAD` is detached
+async fn head_name() -> String {
+    match Head::current().await {
+        Head::Branch(name) => name,
+        Head::Detached(commit) => commit.to_plain_str(),
+    }
 }
 
 async fn restore_to_commit(commit_id: SHA1) {
@@ -0,0 +1,225 @@
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::signature::SignatureType;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::command::{branch::get_target_commit, commit, load_object, save_object};
+use crate::internal::head::Head;
+use crate::internal::signing;
+use crate::internal::tag::Tag;
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct TagArgs {
+    /// name of the tag to create or delete
+    name: Option<String>,
+
+    /// the commit (branch or commit hash) the new tag will refer to, default is HEAD
+    #[clap(requires = "name", conflicts_with = "delete")]
+    commit: Option<String>,
+
+    /// make an annotated tag object, requires a message
+    #[clap(short, long, requires = "message")]
+    annotate: bool,
+
+    /// message of the annotated tag, implies `-a`
+    #[clap(short, long, requires = "name")]
+    message: Option<String>,
+
+    /// make a signed tag with the default key (`user.signingkey`), requires a message
+    #[clap(short, long, requires = "message")]
+    sign: bool,
+
+    /// make a signed tag with the key, implies `-s`
+    #[clap(short = 'u', long, value_name = "KEYID", requires = "message")]
+    local_user: Option<String>,
+
+    /// replace the existing tag
+    #[clap(short, long, requires = "name")]
+    force: bool,
+
+    /// delete the tag
+    #[clap(short, long, requires = "name", conflicts_with_all = ["annotate", "message", "force"])]
+    delete: bool,
+
+    /// list all tags
+    #[clap(short, long, conflicts_with_all = ["delete", "annotate", "message", "force"])]
+    list: bool,
+}
+
+pub async fn execute(args: TagArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    match args.name {
+        Some(name) if args.delete => delete_tag(&name).await,
+        Some(name) if !args.list => {
+            let signing_key = match args.local_user {
+                Some(key) => Some(Some(key)),
+                None if args.sign => Some(None),
+                None => None,
+            };
+            create_tag(&name, args.commit, args.message, signing_key, args.force).await
+        }
+        _ => list_tags().await,
+    }
+}
+
+async fn list_tags() {
+    for tag in Tag::list_tags().await {
+        println!("{}", tag.name);
+    }
+}
+
+/// Create a lightweight tag, or an annotated tag object if `message` is given.
+/// The tag object is signed if `signing_key` is given, `Some(None)` for the default key
+pub async fn create_tag(
+    name: &str,
+    commit: Option<String>,
+    message: Option<String>,
+    signing_key: Option<Option<String>>,
+    force: bool,
+) {
+    if !is_valid_tag_name(name) {
+        eprintln!("fatal: '{}' is not a valid tag name.", name);
+        return;
+    }
+    if !force && Tag::exists(name).await {
+        eprintln!("fatal: tag '{}' already exists", name);
+        return;
+    }
+
+    let commit_id = match commit {
+        Some(commit) => match get_target_commit(&commit).await {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+        None => match Head::current_commit().await {
+            Some(commit_id) => commit_id,
+            None => {
+                eprintln!("fatal: Failed to resolve 'HEAD' as a valid ref.");
+                return;
+            }
+        },
+    };
+
+    let object = match message {
+        Some(message) => {
+            if !commit::check_identity().await {
+                return;
+            }
+            let mut tag = new_tag_object(name, commit_id, &message).await;
+            if let Some(key) = signing_key {
+                tag = match signing::sign_tag(&tag, key.as_deref()).await {
+                    Ok(signed) => signed,
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        eprintln!("error: unable to sign the tag");
+                        return;
+                    }
+                };
+            }
+            save_object(&tag, &tag.id).unwrap();
+            tag.id
+        }
+        None => commit_id,
+    };
+    Tag::update_tag(name, &object.to_plain_str()).await;
+}
+
+/// Build an annotated tag object which points to a commit
+async fn new_tag_object(name: &str, commit_id: SHA1, message: &str) -> TagObject {
+    let mut tagger = commit::committer().await;
+    tagger.signature_type = SignatureType::Tagger;
+    let mut tag = TagObject {
+        id: SHA1::default(),
+        object_hash: commit_id,
+        object_type: ObjectType::Commit,
+        tag_name: name.to_string(),
+        tagger,
+        // blank line between header & message, same as commit
+        message: format!("\n{}\n", message.trim_end()),
+    };
+    tag.id = SHA1::from_type_and_data(ObjectType::Tag, &tag.to_data().unwrap());
+    tag
+}
+
+async fn delete_tag(name: &str) {
+    match Tag::find_tag(name).await {
+        Some(tag) => {
+            Tag::delete_tag(name).await;
+            println!("Deleted tag '{}' (was {})", name, &tag.object.to_plain_str()[..7]);
+        }
+        None => eprintln!("error: tag '{}' not found.", name),
+    }
+}
+
+/// Get the commit which the tag finally points to, annotated tags are dereferenced
+pub fn peel_to_commit(object: SHA1) -> SHA1 {
+    let storage = util::objects_storage();
+    let mut object = object;
+    while storage.is_object_type(&object, ObjectType::Tag) {
+        let tag: TagObject = load_object(&object).unwrap();
+        object = tag.object_hash;
+    }
+    object
+}
+
+/// same rules as branch name, see `git check-ref-format`
+fn is_valid_tag_name(name: &str) -> bool {
+    !(name.is_empty()
+        || name.starts_with('-')
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.ends_with(".lock")
+        || name.contains("..")
+        || name.contains("//")
+        || name.contains("@{")
+        || name.chars().any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_is_valid_tag_name() {
+        assert!(is_valid_tag_name("v1.0.0"));
+        assert!(is_valid_tag_name("release/v1"));
+        assert!(!is_valid_tag_name("v1..0"));
+        assert!(!is_valid_tag_name("v1 0"));
+        assert!(!is_valid_tag_name("-v1"));
+    }
+
+    #[tokio::test]
+    async fn test_create_tags() {
+        test::setup_with_new_libra().await;
+        let head = test::commit_file("a.txt", "a\n", "init").await;
+
+        execute(TagArgs::parse_from(["tag", "v1"])).await;
+        let tag = Tag::find_tag("v1").await.unwrap();
+        assert_eq!(tag.object, head);
+
+        execute(TagArgs::parse_from(["tag", "-m", "release v2", "v2"])).await;
+        let tag = Tag::find_tag("v2").await.unwrap();
+        let tag_object: TagObject = load_object(&tag.object).unwrap();
+        assert_eq!(tag_object.object_hash, head);
+        assert_eq!(tag_object.tag_name, "v2");
+        assert_eq!(tag_object.message.trim(), "release v2");
+        assert_eq!(peel_to_commit(tag.object), head);
+
+        // existing tag is not replaced without `--force`
+        execute(TagArgs::parse_from(["tag", "v2"])).await;
+        assert_eq!(Tag::find_tag("v2").await.unwrap().object, tag.object);
+
+        execute(TagArgs::parse_from(["tag", "-d", "v1"])).await;
+        assert!(!Tag::exists("v1").await);
+    }
+}
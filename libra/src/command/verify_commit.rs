@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+use std::process;
+
+use clap::Parser;
+use mercury::internal::object::commit::Commit;
+
+use crate::command::load_object;
+use crate::internal::signing;
+use crate::utils::{revision, util};
+
+#[derive(Parser, Debug)]
+pub struct VerifyCommitArgs {
+    /// The commits to verify
+    #[clap(required = true)]
+    pub commits: Vec<String>,
+    /// Print the contents of the commits before verifying them
+    #[clap(short, long)]
+    pub verbose: bool,
+}
+
+pub async fn execute(args: VerifyCommitArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let mut all_good = true;
+    for name in &args.commits {
+        let commit = match revision::resolve_commit(name).await {
+            Ok(id) => load_object::<Commit>(&id).map_err(|e| format!("fatal: {}", e)),
+            Err(e) => Err(e),
+        };
+        let commit = match commit {
+            Ok(commit) => commit,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        let Some((payload, signature)) = signing::commit_signature(&commit) else {
+            eprintln!("error: {}: no signature found", name);
+            all_good = false;
+            continue;
+        };
+        if args.verbose {
+            let _ = io::stdout().write_all(&payload);
+        }
+        all_good &= report(&payload, &signature).await;
+    }
+    // the exit status tells the scripts like the checks of CI
+    if !all_good {
+        process::exit(1);
+    }
+}
+
+/// Verify the signature & print the report of the program, return whether it's good
+pub async fn report(payload: &[u8], signature: &str) -> bool {
+    match signing::verify(payload, signature).await {
+        Ok(verification) => {
+            eprint!("{}", verification.output);
+            verification.good
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+use std::process;
+
+use clap::Parser;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::types::ObjectType;
+
+use crate::command::cat_file::read_object;
+use crate::command::load_object;
+use crate::command::verify_commit::report;
+use crate::internal::signing;
+use crate::utils::{revision, util};
+
+#[derive(Parser, Debug)]
+pub struct VerifyTagArgs {
+    /// The annotated tags to verify
+    #[clap(required = true)]
+    pub tags: Vec<String>,
+    /// Print the contents of the tags before verifying them
+    #[clap(short, long)]
+    pub verbose: bool,
+}
+
+pub async fn execute(args: VerifyTagArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let mut all_good = true;
+    for name in &args.tags {
+        let id = match revision::resolve(name).await {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        // a lightweight tag points to the commit directly
+        match read_object(&id) {
+            Ok((ObjectType::Tag, _)) => {}
+            Ok((obj_type, _)) => {
+                eprintln!(
+                    "error: {}: cannot verify a non-tag object of type {}.",
+                    name, obj_type
+                );
+                all_good = false;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                process::exit(1);
+            }
+        }
+        let tag = load_object::<TagObject>(&id).unwrap();
+        let Some((payload, signature)) = signing::tag_signature(&tag) else {
+            eprintln!("error: {}: no signature found", name);
+            all_good = false;
+            continue;
+        };
+        if args.verbose {
+            let _ = io::stdout().write_all(&payload);
+        }
+        all_good &= report(&payload, &signature).await;
+    }
+    if !all_good {
+        process::exit(1);
+    }
+}
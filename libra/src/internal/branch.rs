@@ -17,7 +17,7 @@ pub struct Branch {
 }
 
 async fn query_reference(branch_name: &str, remote: Option<&str>) -> Option<reference::Model> {
-    let db_conn = get_db_conn_instance().await;
+    let db_conn = &get_db_conn_instance().await;
     reference::Entity::find()
         .filter(reference::Column::Name.eq(branch_name))
         .filter(reference::Column::Kind.eq(reference::ConfigKind::Branch))
@@ -33,7 +33,7 @@ async fn query_reference(branch_name: &str, remote: Option<&str>) -> Option<refe
 impl Branch {
     /// list all remote branches
     pub async fn list_branches(remote: Option<&str>) -> Vec<Self> {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
 
         let branches = reference::Entity::find()
             .filter(reference::Column::Kind.eq(reference::ConfigKind::Branch))
@@ -101,7 +101,7 @@ impl Branch {
     }
 
     pub async fn update_branch(branch_name: &str, commit_hash: &str, remote: Option<&str>) {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
         // check if branch exists
         let branch = query_reference(branch_name, remote).await;
 
@@ -127,7 +127,7 @@ impl Branch {
     }
 
     pub async fn delete_branch(branch_name: &str, remote: Option<&str>) {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
         let branch: reference::ActiveModel =
             query_reference(branch_name, remote).await.unwrap().into();
         branch.delete(db_conn).await.unwrap();
@@ -1,17 +1,38 @@
 use std::collections::HashSet;
-use std::mem::swap;
 
 use sea_orm::ActiveValue::Set;
-use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::sea_query::SimpleExpr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DbConn, EntityTrait, QueryFilter, QueryOrder};
 
-use crate::internal::db::get_db_conn_instance;
+use crate::internal::db::{get_db_conn_instance, get_global_db_conn_instance};
 use crate::internal::model::config;
 use crate::internal::model::config::Model;
 
+use crate::utils::util;
+
 use super::model::config::ActiveModel;
 
 pub struct Config;
 
+/// Where the configuration is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigScope {
+    /// `.libra/libra.db` of the current repo
+    Local,
+    /// the per-user database, see [`path::global_database`](crate::utils::path::global_database)
+    Global,
+}
+
+impl ConfigScope {
+    /// Connection of the scope, `None` if the global database doesn't exist and isn't `create`d
+    async fn db(self, create: bool) -> Option<DbConn> {
+        match self {
+            ConfigScope::Local => Some(get_db_conn_instance().await),
+            ConfigScope::Global => get_global_db_conn_instance(create).await.cloned(),
+        }
+    }
+}
+
 pub struct RemoteConfig {
     pub name: String,
     pub url: String,
@@ -26,7 +47,7 @@ pub struct BranchConfig {
 impl Config {
     // todo accept a db connect or a transaction from outside
     pub async fn insert(configuration: &str, name: Option<&str>, key: &str, value: &str) {
-        let db = get_db_conn_instance().await;
+        let db = &get_db_conn_instance().await;
         let config = ActiveModel {
             configuration: Set(configuration.to_owned()),
             name: Set(name.map(|s| s.to_owned())),
@@ -37,35 +58,134 @@ impl Config {
         config.save(db).await.unwrap();
     }
 
-    async fn query(configuration: &str, name: Option<&str>, key: &str) -> Vec<Model> {
-        let db = get_db_conn_instance().await;
+    async fn query(db: &DbConn, configuration: &str, name: Option<&str>, key: &str) -> Vec<Model> {
         config::Entity::find()
             .filter(config::Column::Configuration.eq(configuration))
-            .filter(config::Column::Name.eq(name))
+            .filter(Self::name_filter(name))
             .filter(config::Column::Key.eq(key))
+            .order_by_asc(config::Column::Id)
             .all(db)
             .await
             .unwrap()
     }
 
-    /// Get one configuration value
+    /// `name = NULL` is never true in SQL, so `None` must be matched by `IS NULL`
+    fn name_filter(name: Option<&str>) -> SimpleExpr {
+        match name {
+            Some(name) => config::Column::Name.eq(name),
+            None => config::Column::Name.is_null(),
+        }
+    }
+
+    /// Scopes to look up in order when reading: the local one (if in a repo) overrides the global one
+    fn read_scopes() -> Vec<ConfigScope> {
+        match util::try_get_storage_path() {
+            Ok(_) => vec![ConfigScope::Local, ConfigScope::Global],
+            Err(_) => vec![ConfigScope::Global],
+        }
+    }
+
+    /// Get one configuration value, the local value takes precedence over the global one
+    /// - the first value if the key has multiple values
     pub async fn get(configuration: &str, name: Option<&str>, key: &str) -> Option<String> {
-        let values = Self::query(configuration, name, key).await;
-        values.first().map(|c| c.value.to_owned())
+        Self::get_all(configuration, name, key).await.into_iter().next()
     }
 
-    /// Get all configuration values
+    /// Get all configuration values of the first scope which has the key
     /// - e.g. remote.origin.url can be multiple
     pub async fn get_all(configuration: &str, name: Option<&str>, key: &str) -> Vec<String> {
-        Self::query(configuration, name, key)
+        for scope in Self::read_scopes() {
+            let values = Self::get_all_in(scope, configuration, name, key).await;
+            if !values.is_empty() {
+                return values;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Get all configuration values in the scope, in the order they were added
+    pub async fn get_all_in(scope: ConfigScope, configuration: &str, name: Option<&str>, key: &str) -> Vec<String> {
+        match scope.db(false).await {
+            Some(db) => Self::query(&db, configuration, name, key)
+                .await
+                .into_iter()
+                .map(|c| c.value)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Set the configuration value in the scope, replace the existing one
+    /// - error if there are multiple values, use [`Config::add`] or [`Config::unset`] instead
+    pub async fn set(scope: ConfigScope, configuration: &str, name: Option<&str>, key: &str, value: &str) -> Result<(), String> {
+        let db = &scope.db(true).await.ok_or("fatal: unable to open the global config database")?;
+        let mut entries = Self::query(db, configuration, name, key).await;
+        match entries.len() {
+            0 => Self::add(scope, configuration, name, key, value).await?,
+            1 => {
+                let mut entry: ActiveModel = entries.pop().unwrap().into();
+                entry.value = Set(value.to_owned());
+                entry.update(db).await.unwrap();
+            }
+            _ => return Err("warning: cannot overwrite multiple values with a single value".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Add a new value to the configuration key in the scope, the existing values are kept
+    pub async fn add(scope: ConfigScope, configuration: &str, name: Option<&str>, key: &str, value: &str) -> Result<(), String> {
+        let db = &scope.db(true).await.ok_or("fatal: unable to open the global config database")?;
+        let config = ActiveModel {
+            configuration: Set(configuration.to_owned()),
+            name: Set(name.map(|s| s.to_owned())),
+            key: Set(key.to_owned()),
+            value: Set(value.to_owned()),
+            ..Default::default()
+        };
+        config.insert(db).await.unwrap();
+        Ok(())
+    }
+
+    /// Remove the values of the configuration key in the scope, return the number of removed values
+    pub async fn unset(scope: ConfigScope, configuration: &str, name: Option<&str>, key: &str) -> u64 {
+        let db = match scope.db(false).await {
+            Some(db) => db,
+            None => return 0,
+        };
+        config::Entity::delete_many()
+            .filter(config::Column::Configuration.eq(configuration))
+            .filter(Self::name_filter(name))
+            .filter(config::Column::Key.eq(key))
+            .exec(&db)
             .await
-            .iter()
-            .map(|c| c.value.to_owned())
+            .unwrap()
+            .rows_affected
+    }
+
+    /// All configuration entries in the scope as `(key, value)`, the key is `configuration[.name].key`
+    pub async fn list(scope: ConfigScope) -> Vec<(String, String)> {
+        let db = match scope.db(false).await {
+            Some(db) => db,
+            None => return Vec::new(),
+        };
+        config::Entity::find()
+            .order_by_asc(config::Column::Id)
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| {
+                let key = match c.name {
+                    Some(name) => format!("{}.{}.{}", c.configuration, name, c.key),
+                    None => format!("{}.{}", c.configuration, c.key),
+                };
+                (key, c.value)
+            })
             .collect()
     }
 
     pub async fn remove_remote(name: &str) -> Result<(), String> {
-        let db = get_db_conn_instance().await;
+        let db = &get_db_conn_instance().await;
         let remote = config::Entity::find()
             .filter(config::Column::Configuration.eq("remote"))
             .filter(config::Column::Name.eq(name))
@@ -83,7 +203,7 @@ impl Config {
     }
 
     pub async fn all_remote_configs() -> Vec<RemoteConfig> {
-        let db = get_db_conn_instance().await;
+        let db = &get_db_conn_instance().await;
         let remotes = config::Entity::find()
             .filter(config::Column::Configuration.eq("remote"))
             .all(db)
@@ -112,7 +232,7 @@ impl Config {
     }
 
     pub async fn remote_config(name: &str) -> Option<RemoteConfig> {
-        let db = get_db_conn_instance().await;
+        let db = &get_db_conn_instance().await;
         let remote = config::Entity::find()
             .filter(config::Column::Configuration.eq("remote"))
             .filter(config::Column::Name.eq(name))
@@ -125,42 +245,16 @@ impl Config {
         })
     }
 
+    /// The upstream of the branch: `branch.<name>.remote` & `branch.<name>.merge`
+    /// - `None` if either is missing, or the merge ref isn't a branch like `refs/heads/<branch>`
     pub async fn branch_config(name: &str) -> Option<BranchConfig> {
-        let db = get_db_conn_instance().await;
-        let config_entries = config::Entity::find()
-            .filter(config::Column::Configuration.eq("branch"))
-            .filter(config::Column::Name.eq(name))
-            .all(db)
-            .await
-            .unwrap();
-        if config_entries.is_empty() {
-            None
-        } else {
-            assert_eq!(config_entries.len(), 2);
-            // if branch_config[0].key == "merge" {
-            //     Some(BranchConfig {
-            //         name: name.to_owned(),
-            //         merge: branch_config[0].value.clone(),
-            //         remote: branch_config[1].value.clone(),
-            //     })
-            // } else {
-            //     Some(BranchConfig {
-            //         name: name.to_owned(),
-            //         merge: branch_config[1].value.clone(),
-            //         remote: branch_config[0].value.clone(),
-            //     })
-            // }
-            let mut branch_config = BranchConfig {
-                name: name.to_owned(),
-                merge: config_entries[0].value.clone(),
-                remote: config_entries[1].value.clone(),
-            };
-            if config_entries[0].key == "remote" {
-                swap(&mut branch_config.merge, &mut branch_config.remote);
-            }
-            branch_config.merge = branch_config.merge[11..].into(); // cut refs/heads/
-
-            Some(branch_config)
-        }
+        let remote = Self::get("branch", Some(name), "remote").await?;
+        let merge = Self::get("branch", Some(name), "merge").await?;
+        let merge = merge.strip_prefix("refs/heads/")?.to_owned();
+        Some(BranchConfig {
+            name: name.to_owned(),
+            merge,
+            remote,
+        })
     }
 }
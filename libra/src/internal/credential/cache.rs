@@ -0,0 +1,303 @@
+//! The built-in `cache` helper: the credentials are kept in the memory of a daemon for a while.
+//!
+//! The daemon listens on a Unix socket, `credential/socket` next to the global config
+//! (or `--socket <path>`); it's started by the first `store` and exits when the cache gets empty.
+//! The directory of the socket must be private (`0700`), it's created so if missing.
+//! The credentials expire after `--timeout <seconds>`, 900 by default.
+
+use std::path::PathBuf;
+
+use super::{Credential, Operation};
+use crate::utils::path;
+
+/// The seconds to keep a credential
+const DEFAULT_TIMEOUT: u64 = 900;
+
+/// Answer the operation with the daemon, `args` are the options of the helper
+pub async fn run(
+    args: &[&str],
+    operation: Operation,
+    query: &Credential,
+) -> Result<Option<Credential>, String> {
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut socket = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (*arg, args.next()) {
+            ("--timeout", Some(value)) => {
+                timeout = value
+                    .parse()
+                    .map_err(|_| format!("invalid timeout of credential cache: {}", value))?
+            }
+            ("--socket", Some(value)) => socket = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option of credential cache: {}", arg)),
+        }
+    }
+    let socket = match socket {
+        Some(socket) => socket,
+        None => {
+            default_socket().ok_or("cannot find the home directory for the credential cache")?
+        }
+    };
+    imp::request(&socket, operation, timeout, query).await
+}
+
+pub fn default_socket() -> Option<PathBuf> {
+    path::global_database().and_then(|db| db.parent().map(|dir| dir.join("credential").join("socket")))
+}
+
+/// Run the daemon until the cache gets empty
+pub async fn daemon(socket: PathBuf) -> Result<(), String> {
+    imp::daemon(socket).await
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::time::{self, Instant};
+
+    use super::super::{Credential, Operation};
+
+    /// How long the daemon waits for the first request
+    const IDLE: Duration = Duration::from_secs(10);
+    /// How long a client may take to send the request & read the answer, the others wait meanwhile
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub async fn request(
+        socket: &Path,
+        operation: Operation,
+        timeout: u64,
+        query: &Credential,
+    ) -> Result<Option<Credential>, String> {
+        let request = format!(
+            "action={}\ntimeout={}\n{}",
+            operation.as_str(),
+            timeout,
+            query.to_text()
+        );
+        let stream = match UnixStream::connect(socket).await {
+            Ok(stream) => stream,
+            // nothing cached without the daemon
+            Err(_) if operation != Operation::Store => return Ok(None),
+            Err(_) => spawn_daemon(socket).await?,
+        };
+        let answer = exchange(stream, &request)
+            .await
+            .map_err(|e| format!("credential cache: {}", e))?;
+        match operation {
+            Operation::Get => Ok(Some(Credential::parse(&answer))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn exchange(mut stream: UnixStream, request: &str) -> std::io::Result<String> {
+        stream.write_all(request.as_bytes()).await?;
+        stream.shutdown().await?;
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).await?;
+        Ok(answer)
+    }
+
+    /// Start `libra credential cache-daemon <socket>` & wait for it to listen
+    async fn spawn_daemon(socket: &Path) -> Result<UnixStream, String> {
+        let exe = env::current_exe().map_err(|e| e.to_string())?;
+        Command::new(exe)
+            .args(["credential", "cache-daemon"])
+            .arg(socket)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot start the credential cache daemon: {}", e))?;
+        for _ in 0..50 {
+            time::sleep(Duration::from_millis(20)).await;
+            if let Ok(stream) = UnixStream::connect(socket).await {
+                return Ok(stream);
+            }
+        }
+        Err("cannot connect to the credential cache daemon".to_string())
+    }
+
+    struct Entry {
+        credential: Credential,
+        expiry: Instant,
+    }
+
+    /// Create the directory of the socket only accessible by the user, like `~/.cache/git/credential`,
+    /// so that no one else can connect even before the socket is `chmod`ed
+    fn check_socket_dir(dir: &Path) -> Result<(), String> {
+        if !dir.exists() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        }
+        let mode = fs::metadata(dir).map_err(|e| e.to_string())?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(format!(
+                "the permissions on your socket directory are too loose; other users may be able to \
+                 read your cached credentials. Consider running:\n\n\tchmod 0700 {}",
+                dir.display()
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn daemon(socket: std::path::PathBuf) -> Result<(), String> {
+        if let Some(dir) = socket.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            check_socket_dir(dir)?;
+        }
+        // the socket of a dead daemon
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).map_err(|e| e.to_string())?;
+        fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("cannot set the permissions of {}: {}", socket.display(), e))?;
+
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut deadline = Instant::now() + IDLE;
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    if let Ok((mut stream, _)) = accepted {
+                        // a client that never finishes its request mustn't block the others
+                        let _ = time::timeout(REQUEST_TIMEOUT, serve(&mut stream, &mut entries)).await;
+                    }
+                }
+                _ = time::sleep_until(deadline) => {}
+            }
+            let now = Instant::now();
+            entries.retain(|entry| entry.expiry > now);
+            if entries.is_empty() && now >= deadline {
+                break;
+            }
+            deadline = entries
+                .iter()
+                .map(|entry| entry.expiry)
+                .min()
+                .unwrap_or(now + IDLE);
+        }
+        let _ = fs::remove_file(&socket);
+        Ok(())
+    }
+
+    async fn serve(stream: &mut UnixStream, entries: &mut Vec<Entry>) -> std::io::Result<()> {
+        let mut request = String::new();
+        stream.read_to_string(&mut request).await?;
+        let answer = handle(entries, &request);
+        stream.write_all(answer.as_bytes()).await
+    }
+
+    /// Answer a request of `action=<operation>`, `timeout=<seconds>` & the credential
+    fn handle(entries: &mut Vec<Entry>, request: &str) -> String {
+        let query = Credential::parse(request);
+        let field = |key: &str| {
+            request
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .unwrap_or_default()
+        };
+        let now = Instant::now();
+        entries.retain(|entry| entry.expiry > now);
+        match field("action") {
+            "get" => {
+                return entries
+                    .iter()
+                    .find(|entry| entry.credential.matches(&query))
+                    .map(|entry| entry.credential.to_text())
+                    .unwrap_or_default();
+            }
+            "store" if query.username.is_some() && query.password.is_some() => {
+                let timeout = field("timeout").parse().unwrap_or(super::DEFAULT_TIMEOUT);
+                entries.retain(|entry| {
+                    !(entry.credential.matches(&query) && entry.credential.path == query.path)
+                });
+                entries.push(Entry {
+                    credential: query,
+                    expiry: now + Duration::from_secs(timeout),
+                });
+            }
+            "erase" => entries.retain(|entry| !entry.credential.matches(&query)),
+            _ => {}
+        }
+        String::new()
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_handle() {
+            let mut entries = Vec::new();
+            let target = "protocol=https\nhost=example.com\n";
+            let store = |timeout: u64| {
+                format!("action=store\ntimeout={}\n{}username=alice\npassword=secret\n", timeout, target)
+            };
+            let get = format!("action=get\n{}", target);
+            assert_eq!(handle(&mut entries, &get), "");
+
+            handle(&mut entries, &store(60));
+            let found = Credential::parse(&handle(&mut entries, &get));
+            assert_eq!(found.password.as_deref(), Some("secret"));
+            assert_eq!(handle(&mut entries, "action=get\nprotocol=https\nhost=other.com\n"), "");
+
+            handle(&mut entries, &format!("action=erase\n{}", target));
+            assert_eq!(handle(&mut entries, &get), "");
+
+            // expired at once
+            handle(&mut entries, &store(0));
+            assert_eq!(handle(&mut entries, &get), "");
+            assert!(entries.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_serve_timeout() {
+            let (mut server, mut client) = UnixStream::pair().unwrap();
+            let mut entries = Vec::new();
+            // the client doesn't finish the request
+            client.write_all(b"action=get\n").await.unwrap();
+            let served = time::timeout(REQUEST_TIMEOUT, serve(&mut server, &mut entries)).await;
+            assert!(served.is_err());
+        }
+
+        #[test]
+        fn test_check_socket_dir() {
+            let dir = std::env::temp_dir().join(format!("libra-credential-cache-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            check_socket_dir(&dir).unwrap();
+            assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(check_socket_dir(&dir).is_err());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use super::super::{Credential, Operation};
+
+    pub async fn request(
+        _: &Path,
+        _: Operation,
+        _: u64,
+        _: &Credential,
+    ) -> Result<Option<Credential>, String> {
+        Err("credential cache is only supported on Unix".to_string())
+    }
+
+    pub async fn daemon(_: PathBuf) -> Result<(), String> {
+        Err("credential cache is only supported on Unix".to_string())
+    }
+}
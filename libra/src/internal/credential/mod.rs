@@ -0,0 +1,383 @@
+//! Credentials of the remote repositories, compatible with the Git credential helper protocol.
+//!
+//! When the remote asks for authentication, the credential is looked up from:
+//! 1. `LIBRA_TOKEN` (with the optional `LIBRA_USERNAME`), for the non-interactive jobs like CI
+//! 2. the helpers of `credential.helper`, in order; an empty value clears the helpers before it
+//!    - `store [--file <path>]`: the built-in encrypted store, see [`store`]
+//!    - `cache [--timeout <seconds>]`: the built-in memory cache, see [`cache`]
+//!    - `!<shell command>`, an absolute path, or `<name>` for `git-credential-<name>`
+//! 3. the prompt on the terminal
+//!
+//! The helpers are told to `store` the credential accepted by the remote, and to `erase` the rejected one.
+
+pub mod cache;
+pub mod store;
+
+use std::env;
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::Stdio;
+
+use mercury::errors::GitError;
+use rpassword::read_password;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use url::Url;
+
+use crate::internal::config::Config;
+use crate::internal::protocol::https_client::BasicAuth;
+
+/// The attributes of a credential, written as `key=value` lines in the helper protocol
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credential {
+    pub protocol: Option<String>,
+    /// with the port if any, like `example.com:8000`
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `quit=1` from a helper stops the lookup
+    pub quit: bool,
+}
+
+impl Credential {
+    /// The credential to look up for the url, the password in the url is ignored
+    pub fn from_url(url: &str) -> Self {
+        let Ok(url) = Url::parse(url) else {
+            return Credential::default();
+        };
+        let host = url.host_str().map(|host| match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        });
+        Credential {
+            protocol: Some(url.scheme().to_string()),
+            host,
+            username: Some(url.username().to_string()).filter(|name| !name.is_empty()),
+            ..Default::default()
+        }
+    }
+
+    /// Parse the `key=value` lines until a blank line, the unknown keys are ignored
+    pub fn parse(text: &str) -> Self {
+        let mut credential = Credential::default();
+        for line in text.lines() {
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = Some(value.to_string());
+            match key {
+                "protocol" => credential.protocol = value,
+                "host" => credential.host = value,
+                "path" => credential.path = value,
+                "username" => credential.username = value,
+                "password" => credential.password = value,
+                "quit" => credential.quit = matches!(line, "quit=1" | "quit=true"),
+                "url" => {
+                    let url = Credential::from_url(&line[4..]);
+                    credential.protocol = url.protocol;
+                    credential.host = url.host;
+                    credential.username = url.username.or(credential.username);
+                }
+                _ => {}
+            }
+        }
+        credential
+    }
+
+    /// The `key=value` lines of the known attributes
+    pub fn to_text(&self) -> String {
+        [
+            ("protocol", &self.protocol),
+            ("host", &self.host),
+            ("path", &self.path),
+            ("username", &self.username),
+            ("password", &self.password),
+        ]
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}\n", key, value)))
+        .collect()
+    }
+
+    /// Whether a stored credential answers the query: the same protocol & host,
+    /// and the same path & username if the query has them
+    pub fn matches(&self, query: &Credential) -> bool {
+        let same =
+            |stored: &Option<String>, asked: &Option<String>| asked.is_none() || stored == asked;
+        self.protocol == query.protocol
+            && self.host == query.host
+            && same(&self.path, &query.path)
+            && same(&self.username, &query.username)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.username.is_some() && self.password.is_some()
+    }
+
+    /// `https://example.com`, for the prompts & the messages
+    fn url(&self) -> String {
+        format!(
+            "{}://{}",
+            self.protocol.as_deref().unwrap_or_default(),
+            self.host.as_deref().unwrap_or_default()
+        )
+    }
+
+    pub fn to_basic_auth(&self) -> BasicAuth {
+        BasicAuth {
+            username: self.username.clone().unwrap_or_default(),
+            password: self.password.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// The operations of the helper protocol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Get,
+    Store,
+    Erase,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Get => "get",
+            Operation::Store => "store",
+            Operation::Erase => "erase",
+        }
+    }
+}
+
+/// The credential from `LIBRA_TOKEN`, the username is `LIBRA_USERNAME`, the one in the url or `token`
+pub fn from_env(query: &Credential) -> Option<Credential> {
+    let token = env::var("LIBRA_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())?;
+    let username = env::var("LIBRA_USERNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .or_else(|| query.username.clone())
+        .unwrap_or_else(|| "token".to_string());
+    Some(Credential {
+        username: Some(username),
+        password: Some(token),
+        ..query.clone()
+    })
+}
+
+/// The helpers of `credential.helper`, an empty value clears the helpers before it
+async fn helpers() -> Vec<String> {
+    let mut helpers = Vec::new();
+    for helper in Config::get_all("credential", None, "helper").await {
+        match helper.trim() {
+            "" => helpers.clear(),
+            helper => helpers.push(helper.to_string()),
+        }
+    }
+    helpers
+}
+
+/// Run a helper, return the answer of `get`
+async fn run_helper(
+    helper: &str,
+    operation: Operation,
+    credential: &Credential,
+) -> Result<Option<Credential>, String> {
+    let mut words = helper.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let mut command = match name {
+        "store" => return store::run(&args, operation, credential),
+        "cache" => return cache::run(&args, operation, credential).await,
+        _ if helper.starts_with('!') => {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{} {}", &helper[1..], operation.as_str()));
+            command
+        }
+        _ => {
+            let program = match Path::new(name).is_absolute() {
+                true => name.to_string(),
+                false => format!("git-credential-{}", name),
+            };
+            let mut command = Command::new(program);
+            command.args(&args).arg(operation.as_str());
+            command
+        }
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run credential helper '{}': {}", helper, e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    // a helper may exit without reading the input
+    let _ = stdin.write_all(format!("{}\n", credential.to_text()).as_bytes()).await;
+    drop(stdin);
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    match operation {
+        Operation::Get => Ok(Some(Credential::parse(&String::from_utf8_lossy(
+            &output.stdout,
+        )))),
+        _ => Ok(None),
+    }
+}
+
+/// Ask the helpers for the credential, then prompt on the terminal for the missing username or password
+pub async fn fill(query: &Credential) -> Result<Credential, String> {
+    let mut credential = query.clone();
+    for helper in helpers().await {
+        let answer = match run_helper(&helper, Operation::Get, &credential).await {
+            Ok(Some(answer)) => answer,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("warning: {}", e);
+                continue;
+            }
+        };
+        if answer.quit {
+            return Err(format!("credential helper '{}' told us to quit", helper));
+        }
+        credential.username = answer.username.or(credential.username);
+        credential.password = answer.password.or(credential.password);
+        if credential.is_complete() {
+            return Ok(credential);
+        }
+    }
+
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "could not read the credential for '{}': terminal prompts disabled",
+            credential.url()
+        ));
+    }
+    if credential.username.is_none() {
+        print!("Username for '{}': ", credential.url());
+        // Normally your OS will buffer output by line when it's connected to a terminal,
+        // which is why it usually flushes when a newline is written to stdout.
+        io::stdout().flush().unwrap(); // ensure the prompt is shown
+        let mut username = String::new();
+        io::stdin().read_line(&mut username).unwrap();
+        credential.username = Some(username.trim().to_string());
+    }
+    if credential.password.is_none() {
+        print!(
+            "Password for '{}://{}@{}': ",
+            credential.protocol.as_deref().unwrap_or_default(),
+            credential.username.as_deref().unwrap_or_default(),
+            credential.host.as_deref().unwrap_or_default()
+        );
+        io::stdout().flush().unwrap();
+        credential.password = Some(read_password().map_err(|e| e.to_string())?);
+        // hide password
+    }
+    Ok(credential)
+}
+
+/// Tell the helpers to store the credential accepted by the remote
+pub async fn approve(credential: &Credential) {
+    for helper in helpers().await {
+        if let Err(e) = run_helper(&helper, Operation::Store, credential).await {
+            eprintln!("warning: {}", e);
+        }
+    }
+}
+
+/// Tell the helpers to erase the credential rejected by the remote
+pub async fn reject(credential: &Credential) {
+    for helper in helpers().await {
+        if let Err(e) = run_helper(&helper, Operation::Erase, credential).await {
+            eprintln!("warning: {}", e);
+        }
+    }
+}
+
+/// Send the request without authentication first, then with the credential if the remote asks for it.
+/// Return the response & the credential to use for the following requests.
+pub async fn with_credential<T, F, Fut>(
+    url: &str,
+    request: F,
+) -> Result<(T, Option<BasicAuth>), GitError>
+where
+    F: Fn(Option<BasicAuth>) -> Fut,
+    Fut: Future<Output = Result<T, GitError>>,
+{
+    match request(None).await {
+        Err(GitError::UnAuthorized(_)) => {}
+        response => return response.map(|response| (response, None)),
+    }
+    let query = Credential::from_url(url);
+    // the credential from the environment is not stored
+    let (credential, from_env) = match from_env(&query) {
+        Some(credential) => (credential, true),
+        None => (fill(&query).await.map_err(GitError::UnAuthorized)?, false),
+    };
+    let auth = credential.to_basic_auth();
+    match request(Some(auth.clone())).await {
+        Ok(response) => {
+            if !from_env {
+                approve(&credential).await;
+            }
+            Ok((response, Some(auth)))
+        }
+        Err(GitError::UnAuthorized(_)) => {
+            if !from_env {
+                reject(&credential).await;
+            }
+            Err(GitError::UnAuthorized(format!(
+                "Authentication failed for '{}'",
+                url
+            )))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_credential_protocol() {
+        let query = Credential::from_url("https://alice@example.com:8443/repo.git");
+        assert_eq!(query.protocol.as_deref(), Some("https"));
+        assert_eq!(query.host.as_deref(), Some("example.com:8443"));
+        assert_eq!(query.username.as_deref(), Some("alice"));
+        assert_eq!(query.path, None);
+        assert_eq!(
+            query.to_text(),
+            "protocol=https\nhost=example.com:8443\nusername=alice\n"
+        );
+
+        let answer =
+            Credential::parse("username=bob\npassword=secret\nunknown=1\n\nhost=ignored\n");
+        assert_eq!(answer.username.as_deref(), Some("bob"));
+        assert_eq!(answer.password.as_deref(), Some("secret"));
+        assert_eq!(answer.host, None);
+        assert!(!answer.quit);
+        assert!(Credential::parse("quit=1\n").quit);
+        let parsed = Credential::parse("url=https://example.com:8443\nusername=alice\n");
+        assert_eq!(
+            parsed,
+            Credential {
+                username: Some("alice".to_string()),
+                ..Credential::from_url("https://example.com:8443")
+            }
+        );
+
+        let stored = Credential {
+            password: Some("secret".to_string()),
+            ..query.clone()
+        };
+        assert!(stored.matches(&query));
+        assert!(stored.matches(&Credential::from_url("https://example.com:8443")));
+        assert!(!stored.matches(&Credential::from_url("https://bob@example.com:8443")));
+        assert!(!stored.matches(&Credential::from_url("http://example.com:8443")));
+    }
+}
@@ -0,0 +1,228 @@
+//! The built-in `store` helper: the credentials are kept in an encrypted file.
+//!
+//! The file is `credentials` next to the global config (or `--file <path>`), sealed with ChaCha20-Poly1305:
+//! a 12-byte nonce followed by the credentials in the helper protocol, separated by blank lines.
+//! The key is a random 32-byte file `<file>.key`, readable only by the owner.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::{Credential, Operation};
+use crate::utils::path;
+
+const KEY_LEN: usize = 32;
+
+/// Answer the operation with the store, `args` are the options of the helper like `--file <path>`
+pub fn run(
+    args: &[&str],
+    operation: Operation,
+    query: &Credential,
+) -> Result<Option<Credential>, String> {
+    let file = match args {
+        [] => path::global_database()
+            .and_then(|db| db.parent().map(|dir| dir.join("credentials")))
+            .ok_or("cannot find the home directory for the credential store")?,
+        ["--file", file] => PathBuf::from(file),
+        _ => {
+            return Err(format!(
+                "unknown options of credential store: {}",
+                args.join(" ")
+            ))
+        }
+    };
+    let store = Store::new(file);
+    let error = |e: io::Error| format!("credential store {}: {}", store.file.display(), e);
+    match operation {
+        Operation::Get => Ok(store.get(query).map_err(error)?),
+        Operation::Store => store.store(query).map(|_| None).map_err(error),
+        Operation::Erase => store.erase(query).map(|_| None).map_err(error),
+    }
+}
+
+/// An encrypted file of credentials
+pub struct Store {
+    file: PathBuf,
+}
+
+impl Store {
+    pub fn new(file: PathBuf) -> Self {
+        Store { file }
+    }
+
+    /// The first credential matching the query
+    pub fn get(&self, query: &Credential) -> io::Result<Option<Credential>> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|stored| stored.matches(query)))
+    }
+
+    /// Add the credential, replacing the ones for the same protocol, host, path & username
+    pub fn store(&self, credential: &Credential) -> io::Result<()> {
+        if credential.username.is_none() || credential.password.is_none() {
+            return Ok(());
+        }
+        let mut credentials = self.load()?;
+        credentials.retain(|stored| {
+            !(stored.matches(credential)
+                && stored.path == credential.path
+                && stored.username == credential.username)
+        });
+        credentials.push(credential.clone());
+        self.save(&credentials)
+    }
+
+    /// Remove the credentials matching the query
+    pub fn erase(&self, query: &Credential) -> io::Result<()> {
+        let mut credentials = self.load()?;
+        let count = credentials.len();
+        credentials.retain(|stored| !stored.matches(query));
+        if credentials.len() != count {
+            self.save(&credentials)?;
+        }
+        Ok(())
+    }
+
+    fn load(&self) -> io::Result<Vec<Credential>> {
+        if !self.file.exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read(&self.file)?;
+        if data.len() < NONCE_LEN {
+            return Err(invalid_data("the file is broken"));
+        }
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).unwrap();
+        let mut sealed = sealed.to_vec();
+        let text = self
+            .key()?
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| invalid_data("cannot decrypt the file, the key may be changed"))?;
+        let text = String::from_utf8_lossy(text);
+        Ok(text
+            .split("\n\n")
+            .map(Credential::parse)
+            .filter(|credential| credential.host.is_some())
+            .collect())
+    }
+
+    fn save(&self, credentials: &[Credential]) -> io::Result<()> {
+        let mut data = credentials
+            .iter()
+            .map(Credential::to_text)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes();
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| io::Error::other("cannot generate the nonce"))?;
+        self.key()?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| io::Error::other("cannot encrypt the credentials"))?;
+        let mut content = nonce.to_vec();
+        content.extend(data);
+        write_private(&self.file, &content)
+    }
+
+    /// The key in `<file>.key`, created at the first time
+    fn key(&self) -> io::Result<LessSafeKey> {
+        let mut key_file = self.file.clone().into_os_string();
+        key_file.push(".key");
+        let key_file = PathBuf::from(key_file);
+        let key = match key_file.exists() {
+            true => fs::read(&key_file)?,
+            false => {
+                let mut key = vec![0; KEY_LEN];
+                SystemRandom::new()
+                    .fill(&mut key)
+                    .map_err(|_| io::Error::other("cannot generate the key"))?;
+                write_private(&key_file, &key)?;
+                key
+            }
+        };
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| invalid_data("the key is broken"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Write the file readable only by the owner
+fn write_private(file: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(&mut options.open(file)?, content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let dir =
+            std::env::temp_dir().join(format!("libra-credential-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = Store::new(dir.join("credentials"));
+        let query = Credential::from_url("https://example.com");
+        assert_eq!(store.get(&query).unwrap(), None);
+
+        let alice = Credential {
+            username: Some("alice".to_string()),
+            password: Some("secret".to_string()),
+            ..query.clone()
+        };
+        store.store(&alice).unwrap();
+        store
+            .store(&Credential {
+                password: Some("changed".to_string()),
+                ..alice.clone()
+            })
+            .unwrap();
+        let other = Credential {
+            username: Some("bob".to_string()),
+            password: Some("other".to_string()),
+            ..Credential::from_url("https://other.com")
+        };
+        store.store(&other).unwrap();
+        let found = store.get(&query).unwrap().unwrap();
+        assert_eq!(found.password.as_deref(), Some("changed"));
+        assert_eq!(
+            store
+                .get(&Credential::from_url("https://bob@other.com"))
+                .unwrap(),
+            Some(other.clone())
+        );
+        // encrypted on the disk
+        let content = fs::read(dir.join("credentials")).unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("changed"));
+
+        store.erase(&query).unwrap();
+        assert_eq!(store.get(&query).unwrap(), None);
+        assert!(store
+            .get(&Credential::from_url("https://other.com"))
+            .unwrap()
+            .is_some());
+
+        // a different key can't open the file
+        fs::write(dir.join("credentials.key"), [0; KEY_LEN]).unwrap();
+        assert!(store.get(&query).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,11 +8,14 @@ use std::io;
 use std::io::Error as IOError;
 use std::io::ErrorKind;
 use std::path::Path;
+use std::sync::Mutex;
 use tokio::sync::OnceCell;
 
 /// Establish a connection to the database.
 ///  - `db_path` is the path to the SQLite database file.
 /// - Returns a `DatabaseConnection` if successful, or an `IOError` if the database file does not exist.
+/// - The schema script is idempotent and run on each connection, so the tables added later
+///   (e.g. `reflog`, `shallow`) are created in the repositories initialized before them.
 #[allow(dead_code)]
 pub async fn establish_connection(db_path: &str) -> Result<DatabaseConnection, IOError> {
     if !Path::new(db_path).exists() {
@@ -24,20 +27,59 @@ pub async fn establish_connection(db_path: &str) -> Result<DatabaseConnection, I
 
     let mut option = ConnectOptions::new(format!("sqlite://{}", db_path));
     option.sqlx_logging(false); // TODO use better option
-    Database::connect(option).await.map_err(|err| {
+    let conn = Database::connect(option).await.map_err(|err| {
         IOError::new(
             ErrorKind::Other,
             format!("Database connection error: {:?}", err),
         )
-    })
+    })?;
+    setup_database_sql(&conn).await.map_err(|err| {
+        IOError::new(
+            ErrorKind::Other,
+            format!("Failed to setup database: {:?}", err),
+        )
+    })?;
+    Ok(conn)
 }
 
-static DB_CONN: OnceCell<DbConn> = OnceCell::const_new();
+static DB_CONN: Mutex<Option<DbConn>> = Mutex::new(None);
 /// Get global database connection instance (singleton)
-pub async fn get_db_conn_instance() -> &'static DbConn {
-    DB_CONN
-        .get_or_init(|| async { get_db_conn().await.unwrap() })
-        .await
+/// - the connection is a cheap handle to the pool, cloned for each caller
+pub async fn get_db_conn_instance() -> DbConn {
+    if let Some(conn) = DB_CONN.lock().unwrap().as_ref() {
+        return conn.clone();
+    }
+    let conn = get_db_conn().await.unwrap();
+    // another task may connect at the same time, keep the first one
+    DB_CONN.lock().unwrap().get_or_insert(conn).clone()
+}
+
+/// Drop the connection of [`get_db_conn_instance`], the next call connects to `.libra/libra.db` again,
+/// e.g. after the repo (and its database) is removed and re-created
+pub fn reset_connection() {
+    DB_CONN.lock().unwrap().take();
+}
+
+static GLOBAL_DB_CONN: OnceCell<DbConn> = OnceCell::const_new();
+/// Get the connection to the per-user database of `--global` config (singleton)
+/// - if the database doesn't exist, it's created when `create`, or `None` is returned
+pub async fn get_global_db_conn_instance(create: bool) -> Option<&'static DbConn> {
+    if let Some(conn) = GLOBAL_DB_CONN.get() {
+        return Some(conn);
+    }
+    let db_path = path::global_database()?;
+    if !db_path.exists() {
+        if !create {
+            return None;
+        }
+        std::fs::create_dir_all(db_path.parent().unwrap()).ok()?;
+        // another task may create it at the same time
+        let _ = create_database(db_path.to_str().unwrap()).await;
+    }
+    let conn = GLOBAL_DB_CONN
+        .get_or_try_init(|| async { establish_connection(db_path.to_str().unwrap()).await })
+        .await;
+    conn.ok()
 }
 
 /// Create a connection to the database of current repo: `.libra/libra.db`
@@ -108,21 +150,8 @@ pub async fn create_database(db_path: &str) -> io::Result<DatabaseConnection> {
         )
     })?;
 
-    // Connect to the new database and set up the schema.
-    if let Ok(conn) = establish_connection(db_path).await {
-        setup_database_sql(&conn).await.map_err(|err| {
-            IOError::new(
-                ErrorKind::Other,
-                format!("Failed to setup database: {:?}", err),
-            )
-        })?;
-        Ok(conn)
-    } else {
-        Err(IOError::new(
-            ErrorKind::Other,
-            "Failed to connect to new database.",
-        ))
-    }
+    // Connect to the new database, the schema is set up by the connection.
+    establish_connection(db_path).await
 }
 
 #[cfg(test)]
@@ -176,6 +205,33 @@ mod tests {
         // fs::remove_file(db_path).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_schema_upgrade_on_connect() {
+        // a database of an old repository, without the tables added later
+        let db_path = "/tmp/test_schema_upgrade_on_connect.db";
+        if Path::new(db_path).exists() {
+            fs::remove_file(db_path).unwrap();
+        }
+        fs::File::create(db_path).unwrap();
+        let conn = Database::connect(format!("sqlite://{}", db_path)).await.unwrap();
+        conn.execute(Statement::from_string(
+            conn.get_database_backend(),
+            "CREATE TABLE `config` (`id` INTEGER PRIMARY KEY AUTOINCREMENT, `configuration` TEXT NOT NULL, \
+             `name` TEXT, `key` TEXT NOT NULL, `value` TEXT NOT NULL);",
+        ))
+        .await
+        .unwrap();
+        drop(conn);
+
+        let conn = establish_connection(db_path).await.unwrap();
+        assert!(reflog::Entity::find().all(&conn).await.unwrap().is_empty());
+        assert!(shallow::Entity::find().all(&conn).await.unwrap().is_empty());
+        assert!(stash::Entity::find().all(&conn).await.unwrap().is_empty());
+        // connecting again is fine
+        establish_connection(db_path).await.unwrap();
+        fs::remove_file(db_path).unwrap();
+    }
+
     #[tokio::test]
     async fn test_insert_config() {
         // insert into config_entry & config_section, check foreign key constraint
@@ -17,7 +17,7 @@ pub enum Head {
 
 impl Head {
     async fn query_local_head() -> reference::Model {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
         reference::Entity::find()
             .filter(reference::Column::Kind.eq(reference::ConfigKind::Head))
             .filter(reference::Column::Remote.is_null())
@@ -28,7 +28,7 @@ impl Head {
     }
 
     async fn query_remote_head(remote: &str) -> Option<reference::Model> {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
         reference::Entity::find()
             .filter(reference::Column::Kind.eq(reference::ConfigKind::Head))
             .filter(reference::Column::Remote.eq(remote))
@@ -77,7 +77,7 @@ impl Head {
 
     // HEAD is unique, update if exists, insert if not
     pub async fn update(new_head: Self, remote: Option<&str>) {
-        let db_conn = get_db_conn_instance().await;
+        let db_conn = &get_db_conn_instance().await;
 
         let head = match remote {
             Some(remote) => Self::query_remote_head(remote).await,
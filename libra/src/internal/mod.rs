@@ -1,6 +1,13 @@
 pub mod branch;
 pub mod config;
+pub mod credential;
 pub mod db;
 pub mod head;
 pub mod model;
 pub mod protocol;
+pub mod reachable;
+pub mod reflog;
+pub mod shallow;
+pub mod signing;
+pub mod stash;
+pub mod tag;
@@ -1,2 +1,5 @@
 pub mod config;
-pub mod reference;
\ No newline at end of file
+pub mod reference;
+pub mod reflog;
+pub mod shallow;
+pub mod stash;
@@ -1,13 +1,13 @@
 use super::ProtocolClient;
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
 use ceres::protocol::ServiceType;
 use ceres::protocol::ServiceType::UploadPack;
-use futures_util::{StreamExt, TryStreamExt};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use mercury::errors::GitError;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::{Body, Response};
-use std::io::Error as IoError;
+use std::io::{Error as IoError, IsTerminal, Write};
 use tokio_util::bytes::BytesMut;
 use url::Url;
 use mercury::hash::SHA1;
@@ -41,6 +41,32 @@ pub struct BasicAuth {
     pub(crate) password: String,
 }
 
+/// How to deepen the history of a shallow fetch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deepen {
+    /// `deepen <depth>`: the commits within the depth from the wanted refs
+    Depth(usize),
+    /// `deepen-since <timestamp>`: the commits newer than the time
+    Since(i64),
+    /// `deepen <depth>` with `deepen-relative`: deepen the current shallow boundary
+    Relative(usize),
+}
+
+/// The shallow part of the upload-pack request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShallowRequest {
+    /// the shallow commits of the client, whose parents are missing
+    pub shallow: Vec<String>,
+    pub deepen: Option<Deepen>,
+}
+
+impl ShallowRequest {
+    /// The server sends the shallow update (`shallow` & `unshallow` lines) before `NAK`
+    pub fn is_shallow(&self) -> bool {
+        !self.shallow.is_empty() || self.deepen.is_some()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct DiscoveredReference {
     pub(crate) _hash: String,
@@ -110,49 +136,7 @@ impl HttpsClient {
             )));
         }
 
-        let mut ref_list = vec![];
-        let mut read_first_line = false;
-        loop {
-            let (bytes_take, pkt_line) = read_pkt_line(&mut response_content);
-            if bytes_take == 0 {
-                if response_content.is_empty() {
-                    break;
-                } else {
-                    continue;
-                }
-            }
-            let pkt_line = String::from_utf8(pkt_line.to_vec()).unwrap();
-            let (hash, mut refs) = pkt_line.split_at(40); // hex SHA1 string is 40 bytes
-            refs = refs.trim();
-            if !read_first_line {
-                if hash == SHA1::default().to_plain_str() {
-                    break; // empty repo, return empty list
-                }
-                let (head, caps) = refs.split_once('\0').unwrap();
-                if service == UploadPack.to_string() {
-                    // for git-upload-pack, the first line is HEAD
-                    assert_eq!(head, "HEAD");
-                }
-                // default ref named HEAD as the first ref. The stream MUST include capability declarations behind a NUL on the first ref.
-                ref_list.push(DiscoveredReference {
-                    _hash: hash.to_string(),
-                    _ref: head.to_string(),
-                });
-                let caps = caps.split(' ').collect::<Vec<&str>>();
-                tracing::debug!("capability declarations: {:?}", caps);
-                // tracing::warn!(
-                //     "temporary ignore capability declarations:[ {:?} ]",
-                //     refs[4..].to_string()
-                // );
-                read_first_line = true;
-            } else {
-                ref_list.push(DiscoveredReference {
-                    _hash: hash.to_string(),
-                    _ref: refs.to_string(),
-                });
-            }
-        }
-        Ok(ref_list)
+        Ok(parse_references(&mut response_content, service))
     }
 
     /// POST $GIT_URL/git-upload-pack HTTP/1.0
@@ -161,15 +145,18 @@ impl HttpsClient {
     /// Obtain the `want` references from the `discovery_reference` method.
     /// If the returned stream is empty, it may be due to incorrect refs or an incorrect format.
     // TODO support some necessary options
+    /// For a shallow fetch, the response starts with the shallow update, see [`ShallowRequest`].
+    /// The progress of the server (side-band) is printed, only the pkt-lines before the pack & the pack are returned.
     pub async fn fetch_objects(
         &self,
         have: &Vec<String>,
         want: &Vec<String>,
+        shallow: &ShallowRequest,
         auth: Option<BasicAuth>,
     ) -> Result<impl StreamExt<Item = Result<Bytes, IoError>>, IoError> {
         // POST $GIT_URL/git-upload-pack HTTP/1.0
         let url = self.url.join("git-upload-pack").unwrap();
-        let body = generate_upload_pack_content(have, want).await;
+        let body = generate_upload_pack_content(have, want, shallow).await;
         tracing::debug!("fetch_objects with body: {:?}", body);
 
         let mut req = self
@@ -194,7 +181,7 @@ impl HttpsClient {
             .bytes_stream()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
 
-        Ok(result)
+        Ok(demultiplex(result))
     }
 
     pub async fn send_pack<T: Into<Body>>(
@@ -216,21 +203,98 @@ impl HttpsClient {
     }
 }
 
-async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) -> Bytes {
+/// Parse the references advertised by the server (after `# service=` line of HTTP), until the end of `content`
+/// - the first line has the capability declarations behind a NUL
+/// - an empty repository advertises no refs, but a zero id with the capabilities
+pub(crate) fn parse_references(content: &mut Bytes, service: &str) -> Vec<DiscRef> {
+    let mut ref_list = vec![];
+    let mut read_first_line = false;
+    loop {
+        let (bytes_take, pkt_line) = read_pkt_line(content);
+        if bytes_take == 0 {
+            if content.is_empty() {
+                break;
+            } else {
+                continue;
+            }
+        }
+        let pkt_line = String::from_utf8(pkt_line.to_vec()).unwrap();
+        let (hash, mut refs) = pkt_line.split_at(40); // hex SHA1 string is 40 bytes
+        refs = refs.trim();
+        if !read_first_line {
+            if hash == SHA1::default().to_plain_str() {
+                break; // empty repo, return empty list
+            }
+            let (head, caps) = refs.split_once('\0').unwrap();
+            if service == UploadPack.to_string() {
+                // for git-upload-pack, the first line is HEAD
+                assert_eq!(head, "HEAD");
+            }
+            // default ref named HEAD as the first ref. The stream MUST include capability declarations behind a NUL on the first ref.
+            ref_list.push(DiscoveredReference {
+                _hash: hash.to_string(),
+                _ref: head.to_string(),
+            });
+            let caps = caps.split(' ').collect::<Vec<&str>>();
+            tracing::debug!("capability declarations: {:?}", caps);
+            // tracing::warn!(
+            //     "temporary ignore capability declarations:[ {:?} ]",
+            //     refs[4..].to_string()
+            // );
+            read_first_line = true;
+        } else {
+            ref_list.push(DiscoveredReference {
+                _hash: hash.to_string(),
+                _ref: refs.to_string(),
+            });
+        }
+    }
+    ref_list
+}
+
+pub(crate) async fn generate_upload_pack_content(
+    have: &Vec<String>,
+    want: &Vec<String>,
+    shallow: &ShallowRequest,
+) -> Bytes {
     let mut buf = BytesMut::new();
     let mut write_first_line = false;
 
+    // the pack & progress are multiplexed, see [`SideBand`]; the progress is useless if not shown
+    let mut capabilities = String::from("side-band-64k ");
+    if !std::io::stderr().is_terminal() {
+        capabilities.push_str("no-progress ");
+    }
+    if shallow.is_shallow() {
+        capabilities.push_str("shallow ");
+    }
+    match shallow.deepen {
+        Some(Deepen::Since(_)) => capabilities.push_str("deepen-since "),
+        Some(Deepen::Relative(_)) => capabilities.push_str("deepen-relative "),
+        _ => {}
+    }
+    // unlike the advertisement, the capabilities follow the first `want` after a space
     for w in want {
         if !write_first_line {
             add_pkt_line_string(
                 &mut buf,
-                format!("want {}\0agent=libra/0.1.0\n", w).to_string(),
+                format!("want {} {}agent=libra/0.1.0\n", w, capabilities).to_string(),
             );
             write_first_line = true;
         } else {
             add_pkt_line_string(&mut buf, format!("want {}\n", w).to_string());
         }
     }
+    for s in &shallow.shallow {
+        add_pkt_line_string(&mut buf, format!("shallow {}\n", s));
+    }
+    match shallow.deepen {
+        Some(Deepen::Depth(depth)) | Some(Deepen::Relative(depth)) => {
+            add_pkt_line_string(&mut buf, format!("deepen {}\n", depth))
+        }
+        Some(Deepen::Since(timestamp)) => add_pkt_line_string(&mut buf, format!("deepen-since {}\n", timestamp)),
+        None => {}
+    }
     buf.extend(b"0000"); // split pkt-lines with a flush-pkt
     for h in have {
         add_pkt_line_string(&mut buf, format!("have {}\n", h).to_string());
@@ -241,6 +305,140 @@ async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) ->
     buf.freeze()
 }
 
+/// The channels of `side-band-64k`: 1 for the data, 2 for the progress & 3 for the fatal error.
+/// Demultiplex the response, the progress is printed to stderr with the prefix `remote: `.
+/// The response without side-band (e.g. the server doesn't support it) is passed through.
+pub struct SideBand {
+    buf: BytesMut,
+    state: SideBandState,
+    /// the incomplete line of the progress, a line ends with `\r` or `\n`
+    progress: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SideBandState {
+    /// pkt-lines before the pack of `git-upload-pack`: the shallow update, `NAK` or `ACK`
+    Preamble,
+    /// check if the response is multiplexed by the first pkt-line
+    Detect,
+    Band,
+    /// not multiplexed, pass through
+    Raw,
+    /// the flush-pkt after the data
+    Done,
+}
+
+impl SideBand {
+    /// `preamble`: the response of `git-upload-pack` starts with the pkt-lines before the pack, which are kept as they are
+    pub fn new(preamble: bool) -> Self {
+        SideBand {
+            buf: BytesMut::new(),
+            state: if preamble { SideBandState::Preamble } else { SideBandState::Detect },
+            progress: Vec::new(),
+        }
+    }
+
+    /// Feed a chunk of the response, return the data of channel 1 that is complete in it
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Bytes, IoError> {
+        self.buf.extend_from_slice(chunk);
+        let mut data = BytesMut::new();
+        loop {
+            match self.state {
+                SideBandState::Raw => {
+                    data.extend_from_slice(&self.buf.split());
+                    break;
+                }
+                SideBandState::Done => {
+                    self.buf.clear();
+                    break;
+                }
+                _ => {}
+            }
+            if self.state == SideBandState::Detect && self.buf.starts_with(b"PACK") {
+                self.state = SideBandState::Raw;
+                continue;
+            }
+            if self.buf.len() < 4 {
+                break;
+            }
+            let len = match std::str::from_utf8(&self.buf[..4]).ok().and_then(|len| usize::from_str_radix(len, 16).ok()) {
+                Some(len) if len == 0 || len >= 4 => len,
+                _ if self.state == SideBandState::Detect => {
+                    self.state = SideBandState::Raw;
+                    continue;
+                }
+                _ => return Err(IoError::new(std::io::ErrorKind::InvalidData, "protocol error: bad pkt-line length")),
+            };
+            if len == 0 {
+                let flush = self.buf.split_to(4);
+                match self.state {
+                    SideBandState::Preamble => data.extend_from_slice(&flush), // after the shallow update
+                    _ => self.state = SideBandState::Done,
+                }
+                continue;
+            }
+            if self.buf.len() < len {
+                break;
+            }
+            let payload = &self.buf[4..len];
+            match self.state {
+                SideBandState::Preamble => {
+                    if payload.starts_with(b"ERR ") {
+                        return Err(remote_error(&payload[4..]));
+                    }
+                    if payload.starts_with(b"NAK") || payload.starts_with(b"ACK ") {
+                        self.state = SideBandState::Detect;
+                    }
+                    data.extend_from_slice(&self.buf.split_to(len));
+                }
+                _ => match payload.first() {
+                    Some(1) => {
+                        data.extend_from_slice(&payload[1..]);
+                        self.state = SideBandState::Band;
+                        self.buf.advance(len);
+                    }
+                    Some(2) => {
+                        let message = payload[1..].to_vec();
+                        self.print_progress(&message);
+                        self.state = SideBandState::Band;
+                        self.buf.advance(len);
+                    }
+                    Some(3) => return Err(remote_error(&payload[1..])),
+                    _ if self.state == SideBandState::Detect => self.state = SideBandState::Raw,
+                    _ => return Err(IoError::new(std::io::ErrorKind::InvalidData, "protocol error: bad band")),
+                },
+            }
+        }
+        Ok(data.freeze())
+    }
+
+    fn print_progress(&mut self, message: &[u8]) {
+        let mut stderr = std::io::stderr().lock();
+        for &byte in message {
+            self.progress.push(byte);
+            if byte == b'\r' || byte == b'\n' {
+                let _ = stderr.write_all(b"remote: ");
+                let _ = stderr.write_all(&self.progress);
+                self.progress.clear();
+            }
+        }
+        let _ = stderr.flush();
+    }
+}
+
+fn remote_error(message: &[u8]) -> IoError {
+    IoError::other(format!("remote error: {}", String::from_utf8_lossy(message).trim_end()))
+}
+
+/// Demultiplex the response stream of `git-upload-pack`, see [`SideBand`]
+pub(crate) fn demultiplex<S>(stream: S) -> impl Stream<Item = Result<Bytes, IoError>>
+where
+    S: Stream<Item = Result<Bytes, IoError>>,
+{
+    let mut side_band = SideBand::new(true);
+    stream.map(move |chunk| chunk.and_then(|chunk| side_band.feed(&chunk)))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -251,6 +449,31 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_side_band() {
+        let mut response = b"0035shallow aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n00000008NAK\n".to_vec();
+        response.extend_from_slice(b"0016\x02Counting objects\r");
+        response.extend_from_slice(b"0009\x01PACK0008\x01abc0000");
+        let mut side_band = SideBand::new(true);
+        // fed byte by byte, the pkt-lines are split in any position
+        let data = response.iter().map(|byte| side_band.feed(&[*byte]).unwrap()).collect::<Vec<_>>().concat();
+        assert_eq!(
+            data,
+            b"0035shallow aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n00000008NAK\nPACKabc"
+        );
+
+        let mut side_band = SideBand::new(true);
+        assert!(side_band.feed(b"0008NAK\n0010\x03fatal error").is_err());
+
+        // not multiplexed
+        let mut side_band = SideBand::new(true);
+        assert_eq!(side_band.feed(b"0008NAK\nPACK1234").unwrap(), "0008NAK\nPACK1234");
+        let mut side_band = SideBand::new(false);
+        assert_eq!(side_band.feed(b"000eunpack ok\n0000").unwrap(), "000eunpack ok\n0000");
+        let mut side_band = SideBand::new(false);
+        assert_eq!(side_band.feed(b"0013\x01000eunpack ok\n0009\x0100000000").unwrap(), "000eunpack ok\n0000");
+    }
+
     #[tokio::test]
     async fn test_discover_reference_upload() {
         init_debug_logger();
@@ -286,7 +509,7 @@ mod tests {
         let want = refs.iter().map(|r| r._hash.clone()).collect();
 
         let have = vec!["81a162e7b725bbad2adfe01879fd57e0119406b9".to_string()];
-        let mut result_stream = client.fetch_objects(&have, &want, None).await.unwrap();
+        let mut result_stream = client.fetch_objects(&have, &want, &ShallowRequest::default(), None).await.unwrap();
 
         let mut buffer = vec![];
         while let Some(item) = result_stream.next().await {
@@ -322,7 +545,7 @@ mod tests {
         let have = vec!["1c05d7f7dd70e38150bfd2d5fb8fb969e2eb9851".to_string()];
         // **want MUST change to one of the refs in the remote repo, such as `refs/heads/main` before running the test**
         let want = vec!["7ef152d43162e28b3177f6df380112f6412f5b42".to_string()];
-        let body = generate_upload_pack_content(&have, &want).await;
+        let body = generate_upload_pack_content(&have, &want, &ShallowRequest::default()).await;
         tracing::info!("upload-pack content: {:?}", body);
         let mut cmd = tokio::process::Command::new("/usr/bin/git-upload-pack");
         cmd.arg("..");
@@ -0,0 +1,705 @@
+//! Local transport: the remote repository is a directory on disk, `file:///path/to/repo` or a plain path.
+//! Both libra repositories and Git repositories (bare or not) are supported, their objects & packs
+//! are read and written directly, no `git-upload-pack` / `git-receive-pack` is needed.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bytes::{Bytes, BytesMut};
+use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
+use ceres::protocol::ServiceType;
+use futures_util::Stream;
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+use mercury::internal::pack::encode::PackEncoder;
+use mercury::internal::pack::entry::Entry;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DbConn, EntityTrait, QueryFilter};
+use tokio::sync::mpsc;
+use url::Url;
+
+use super::https_client::{Deepen, DiscoveredReference, ShallowRequest};
+use super::ProtocolClient;
+use crate::internal::db;
+use crate::internal::model::reference::{self, ConfigKind};
+use crate::internal::model::shallow;
+use crate::internal::reachable::walk_objects;
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::progress::Progress;
+use crate::utils::util;
+
+/// A client of the repository on the local file system
+pub struct LocalClient {
+    /// path of the repository, the working directory or the git directory
+    path: PathBuf,
+}
+
+impl ProtocolClient for LocalClient {
+    /// `file:///path/to/repo`, use [`super::parse_url`] to convert the plain path
+    fn from_url(url: &Url) -> Self {
+        let path = url.to_file_path().unwrap_or_else(|_| PathBuf::from(url.path()));
+        Self { path }
+    }
+}
+
+impl LocalClient {
+    pub async fn discovery_reference(&self, _service: ServiceType) -> Result<Vec<DiscoveredReference>, GitError> {
+        LocalRepo::open(&self.path)?.references().await
+    }
+
+    /// Pack the objects reachable from `want` but not from `have`, the response is the same as
+    /// `git-upload-pack` without multi_ack: `NAK` & the pack
+    pub async fn fetch_objects(
+        &self,
+        have: &[String],
+        want: &[String],
+        shallow: &ShallowRequest,
+    ) -> Result<impl Stream<Item = Result<Bytes, IoError>>, IoError> {
+        let repo = LocalRepo::open(&self.path).map_err(IoError::other)?;
+        let storage = ClientStorage::init(repo.objects());
+        let parse = |ids: &[String]| -> Vec<SHA1> { ids.iter().filter_map(|id| SHA1::from_str(id).ok()).collect() };
+        let client_shallow = parse(&shallow.shallow).into_iter().collect();
+        let server_shallow = repo.shallow().await;
+        let plan = collect_objects(
+            &storage,
+            &parse(want),
+            &parse(have),
+            &client_shallow,
+            &server_shallow,
+            shallow.deepen,
+        )
+        .map_err(IoError::other)?;
+        let pack = encode_pack(plan.objs, DEFAULT_WINDOW).await.map_err(IoError::other)?;
+
+        let mut response = BytesMut::new();
+        if shallow.is_shallow() {
+            for id in plan.shallow.iter().filter(|id| !client_shallow.contains(id)) {
+                add_pkt_line_string(&mut response, format!("shallow {}\n", id.to_plain_str()));
+            }
+            for id in plan.unshallow {
+                add_pkt_line_string(&mut response, format!("unshallow {}\n", id.to_plain_str()));
+            }
+            response.extend_from_slice(b"0000");
+        }
+        add_pkt_line_string(&mut response, "NAK\n".to_string());
+        Ok(futures_util::stream::iter(vec![Ok(response.freeze()), Ok(Bytes::from(pack))]))
+    }
+
+    /// Receive the ref update commands & pack like `git-receive-pack`, return the report-status
+    pub async fn send_pack(&self, mut data: Bytes) -> Result<Bytes, GitError> {
+        let repo = LocalRepo::open(&self.path)?;
+
+        let mut commands = Vec::new();
+        loop {
+            let (len, line) = read_pkt_line(&mut data);
+            if len == 0 {
+                break;
+            }
+            // the capabilities follow the first command after `\0`
+            let line = String::from_utf8_lossy(&line);
+            let line = line.split('\0').next().unwrap().trim_end();
+            let mut parts = line.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(old), Some(new), Some(name)) => {
+                    commands.push((old.to_string(), new.to_string(), name.to_string()))
+                }
+                _ => return Err(GitError::CustomError(format!("protocol error: expected old/new/ref, got '{}'", line))),
+            }
+        }
+
+        let mut report = BytesMut::new();
+        let unpacked = match data.is_empty() {
+            true => Ok(()), // deleting refs only
+            false => ClientStorage::init(repo.objects()).save_pack(&data).map(|_| ()),
+        };
+        match &unpacked {
+            Ok(_) => add_pkt_line_string(&mut report, "unpack ok\n".to_string()),
+            Err(e) => add_pkt_line_string(&mut report, format!("unpack {}\n", e)),
+        }
+
+        let checked_out = repo.checked_out_branch().await;
+        for (old, new, name) in commands {
+            let result = if unpacked.is_err() {
+                Err("unpacker error".to_string())
+            } else if checked_out.as_deref() == Some(name.as_str()) {
+                Err("branch is currently checked out".to_string())
+            } else {
+                repo.update_reference(&name, &old, &new).await
+            };
+            match result {
+                Ok(_) => add_pkt_line_string(&mut report, format!("ok {}\n", name)),
+                Err(reason) => add_pkt_line_string(&mut report, format!("ng {} {}\n", name, reason)),
+            }
+        }
+        report.extend_from_slice(b"0000");
+        Ok(report.freeze())
+    }
+}
+
+/// The repository on disk, found by its layout
+enum LocalRepo {
+    /// the `.libra` directory, references are stored in the database
+    Libra(PathBuf),
+    /// the git directory, `.git` or a bare repository, references are stored in files
+    Git { git_dir: PathBuf, bare: bool },
+}
+
+impl LocalRepo {
+    fn open(path: &Path) -> Result<Self, GitError> {
+        if path.join(util::ROOT_DIR).join(util::DATABASE).is_file() {
+            Ok(LocalRepo::Libra(path.join(util::ROOT_DIR)))
+        } else if path.join(util::DATABASE).is_file() {
+            Ok(LocalRepo::Libra(path.to_path_buf()))
+        } else if path.join(".git").join("HEAD").is_file() {
+            Ok(LocalRepo::Git {
+                git_dir: path.join(".git"),
+                bare: false,
+            })
+        } else if path.join("HEAD").is_file() && path.join("objects").is_dir() {
+            Ok(LocalRepo::Git {
+                git_dir: path.to_path_buf(),
+                bare: true,
+            })
+        } else {
+            Err(GitError::CustomError(format!(
+                "'{}' does not appear to be a git repository",
+                path.display()
+            )))
+        }
+    }
+
+    fn objects(&self) -> PathBuf {
+        match self {
+            LocalRepo::Libra(storage) => storage.join("objects"),
+            LocalRepo::Git { git_dir, .. } => git_dir.join("objects"),
+        }
+    }
+
+    /// The shallow commits of the repository itself
+    async fn shallow(&self) -> HashSet<SHA1> {
+        let commits = match self {
+            LocalRepo::Libra(storage) => match Self::db_conn(storage).await {
+                Ok(conn) => shallow::Entity::find()
+                    .all(&conn)
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|model| model.commit)
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+            LocalRepo::Git { git_dir, .. } => fs::read_to_string(git_dir.join("shallow"))
+                .unwrap_or_default()
+                .lines()
+                .map(|line| line.to_string())
+                .collect(),
+        };
+        commits.iter().filter_map(|id| SHA1::from_str(id.trim()).ok()).collect()
+    }
+
+    async fn db_conn(storage: &Path) -> Result<DbConn, GitError> {
+        let db_path = storage.join(util::DATABASE);
+        db::establish_connection(db_path.to_str().unwrap())
+            .await
+            .map_err(GitError::IOError)
+    }
+
+    /// All references, `HEAD` is the first one if it points to a commit
+    async fn references(&self) -> Result<Vec<DiscoveredReference>, GitError> {
+        let mut refs = Vec::new();
+        let mut head = None;
+        match self {
+            LocalRepo::Libra(storage) => {
+                let conn = Self::db_conn(storage).await?;
+                let models = reference::Entity::find()
+                    .filter(reference::Column::Remote.is_null())
+                    .all(&conn)
+                    .await
+                    .map_err(|e| GitError::CustomError(e.to_string()))?;
+                for model in models.iter() {
+                    let name = model.name.clone().unwrap_or_default();
+                    match model.kind {
+                        ConfigKind::Branch => refs.push((format!("refs/heads/{}", name), model.commit.clone())),
+                        ConfigKind::Tag => refs.push((format!("refs/tags/{}", name), model.commit.clone())),
+                        ConfigKind::Head => head = Some(model),
+                    }
+                }
+                head = head.filter(|head| head.commit.is_some() || head.name.is_some());
+                let head = head.and_then(|head| match &head.name {
+                    Some(branch) => refs
+                        .iter()
+                        .find(|(name, _)| *name == format!("refs/heads/{}", branch))
+                        .and_then(|(_, commit)| commit.clone()),
+                    None => head.commit.clone(), // detached
+                });
+                if let Some(head) = head {
+                    refs.insert(0, ("HEAD".to_string(), Some(head)));
+                }
+            }
+            LocalRepo::Git { git_dir, .. } => {
+                let mut git_refs = read_packed_refs(git_dir);
+                read_loose_refs(git_dir, &git_dir.join("refs"), &mut git_refs)?;
+                if let Some(head) = resolve_git_head(git_dir, &git_refs) {
+                    refs.push(("HEAD".to_string(), Some(head)));
+                }
+                refs.extend(git_refs.into_iter().map(|(name, hash)| (name, Some(hash))));
+            }
+        }
+        Ok(refs
+            .into_iter()
+            .filter_map(|(_ref, hash)| hash.map(|_hash| DiscoveredReference { _hash, _ref }))
+            .collect())
+    }
+
+    /// The branch that the worktree is on, which can't be updated by push,
+    /// same as `receive.denyCurrentBranch = refuse` of Git
+    async fn checked_out_branch(&self) -> Option<String> {
+        match self {
+            LocalRepo::Libra(storage) => {
+                let conn = Self::db_conn(storage).await.ok()?;
+                let head = reference::Entity::find()
+                    .filter(reference::Column::Kind.eq(ConfigKind::Head))
+                    .filter(reference::Column::Remote.is_null())
+                    .one(&conn)
+                    .await
+                    .ok()??;
+                head.name.map(|name| format!("refs/heads/{}", name))
+            }
+            LocalRepo::Git { bare: true, .. } => None,
+            LocalRepo::Git { git_dir, .. } => fs::read_to_string(git_dir.join("HEAD"))
+                .ok()?
+                .trim()
+                .strip_prefix("ref: ")
+                .map(|name| name.to_string()),
+        }
+    }
+
+    /// Update the reference `name` from `old` to `new`, all zeros `new` to delete it.
+    /// Fail if its current value is not `old`, it's changed by others.
+    async fn update_reference(&self, name: &str, old: &str, new: &str) -> Result<(), String> {
+        let zero = SHA1::default().to_plain_str();
+        if !name.starts_with("refs/") || name.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err("funny refname".to_string());
+        }
+        if new != zero {
+            let new = SHA1::from_str(new).map_err(|_| "invalid new value".to_string())?;
+            // the pack may not have brought the object, e.g. the client thought the remote had it
+            if !ClientStorage::init(self.objects()).exist(&new) {
+                return Err("missing necessary objects".to_string());
+            }
+        }
+        let current = self
+            .references()
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|r| r._ref == name)
+            .map(|r| r._hash)
+            .unwrap_or_else(|| zero.clone());
+        if current != old {
+            return Err("failed to lock".to_string());
+        }
+
+        match self {
+            LocalRepo::Libra(storage) => {
+                let (kind, short_name) = if let Some(branch) = name.strip_prefix("refs/heads/") {
+                    (ConfigKind::Branch, branch)
+                } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                    (ConfigKind::Tag, tag)
+                } else {
+                    return Err("unsupported reference".to_string());
+                };
+                let conn = Self::db_conn(storage).await.map_err(|e| e.to_string())?;
+                let model = reference::Entity::find()
+                    .filter(reference::Column::Kind.eq(kind.clone()))
+                    .filter(reference::Column::Name.eq(short_name))
+                    .filter(reference::Column::Remote.is_null())
+                    .one(&conn)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let result = match model {
+                    Some(model) if new == zero => reference::ActiveModel::from(model).delete(&conn).await.map(|_| ()),
+                    Some(model) => {
+                        let mut model: reference::ActiveModel = model.into();
+                        model.commit = Set(Some(new.to_string()));
+                        model.update(&conn).await.map(|_| ())
+                    }
+                    None if new == zero => Ok(()),
+                    None => reference::ActiveModel {
+                        name: Set(Some(short_name.to_string())),
+                        kind: Set(kind),
+                        commit: Set(Some(new.to_string())),
+                        remote: Set(None),
+                        ..Default::default()
+                    }
+                    .insert(&conn)
+                    .await
+                    .map(|_| ()),
+                };
+                result.map_err(|e| e.to_string())
+            }
+            LocalRepo::Git { git_dir, .. } => {
+                let path = git_dir.join(name);
+                if new == zero {
+                    if path.exists() {
+                        fs::remove_file(&path).map_err(|e| e.to_string())?;
+                    }
+                    remove_packed_ref(git_dir, name).map_err(|e| e.to_string())
+                } else {
+                    fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+                    fs::write(&path, format!("{}\n", new)).map_err(|e| e.to_string())
+                }
+            }
+        }
+    }
+}
+
+/// `packed-refs` of Git, `<hash> <ref>` per line, the peeled tags (`^<hash>`) & comments are skipped
+fn read_packed_refs(git_dir: &Path) -> Vec<(String, String)> {
+    let content = fs::read_to_string(git_dir.join("packed-refs")).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, name)| (name.trim().to_string(), hash.to_string()))
+        .collect()
+}
+
+/// Read the loose refs under `dir`, they override the packed ones
+fn read_loose_refs(git_dir: &Path, dir: &Path, refs: &mut Vec<(String, String)>) -> Result<(), GitError> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)?.filter_map(|entry| entry.ok()).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            read_loose_refs(git_dir, &path, refs)?;
+            continue;
+        }
+        let hash = fs::read_to_string(&path)?.trim().to_string();
+        if SHA1::from_str(&hash).is_err() {
+            continue; // symbolic ref, e.g. `refs/remotes/origin/HEAD`
+        }
+        let name = path
+            .strip_prefix(git_dir)
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        refs.retain(|(existing, _)| *existing != name);
+        refs.push((name, hash));
+    }
+    Ok(())
+}
+
+/// The commit of `HEAD`, `None` if it points to a branch without commits
+fn resolve_git_head(git_dir: &Path, refs: &[(String, String)]) -> Option<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    match head.trim().strip_prefix("ref: ") {
+        Some(name) => refs.iter().find(|(r, _)| r == name).map(|(_, hash)| hash.clone()),
+        None => Some(head.trim().to_string()),
+    }
+}
+
+fn remove_packed_ref(git_dir: &Path, name: &str) -> std::io::Result<()> {
+    let path = git_dir.join("packed-refs");
+    if !path.exists() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(&path)?;
+    let mut lines = Vec::new();
+    let mut removed = false;
+    for line in content.lines() {
+        if line.starts_with('^') && removed {
+            continue; // the peeled line of the removed tag
+        }
+        removed = line.split_once(' ').map(|(_, r)| r.trim() == name).unwrap_or(false);
+        if !removed {
+            lines.push(line);
+        }
+    }
+    fs::write(&path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>())
+}
+
+/// The objects to send, and the update of the client's shallow boundary
+#[derive(Default)]
+struct PackPlan {
+    objs: Vec<Entry>,
+    shallow: Vec<SHA1>,
+    unshallow: Vec<SHA1>,
+}
+
+/// Collect the objects reachable from `want` but not from `have` (the ones exist in `storage`),
+/// the history is cut by `deepen` and the shallow commits of both sides (`client_shallow` & `server_shallow`)
+fn collect_objects(
+    storage: &ClientStorage,
+    want: &[SHA1],
+    have: &[SHA1],
+    client_shallow: &HashSet<SHA1>,
+    server_shallow: &HashSet<SHA1>,
+    deepen: Option<Deepen>,
+) -> Result<PackPlan, GitError> {
+    // the objects the client has, its history stops at its shallow commits
+    let mut seen = HashSet::new();
+    let cut: HashSet<SHA1> = client_shallow.union(server_shallow).copied().collect();
+    for have in have.iter().filter(|have| storage.exist(have)) {
+        walk_objects(storage, *have, &mut seen, &cut, None)?;
+    }
+    let client_has = |id: &SHA1, seen: &HashSet<SHA1>| seen.contains(id) || client_shallow.contains(id);
+
+    let mut plan = PackPlan::default();
+    // (commit, how many commits can be sent from it on this path, `None` if unlimited)
+    let mut queue = VecDeque::new();
+    let limit = match deepen {
+        Some(Deepen::Depth(depth)) => Some(depth.max(1)),
+        _ => None,
+    };
+    for want in want {
+        // peel the tags, the tag objects are sent too
+        let mut id = *want;
+        while storage.get_object_type(&id)? == ObjectType::Tag {
+            let tag = Tag::from_bytes(&storage.get(&id)?, id)?;
+            let target = tag.object_hash;
+            if seen.insert(id) {
+                plan.objs.push(tag.into());
+            }
+            id = target;
+        }
+        match storage.get_object_type(&id)? {
+            ObjectType::Commit => queue.push_back((id, limit)),
+            _ => walk_objects(storage, id, &mut seen, &cut, Some(&mut plan.objs))?,
+        }
+    }
+
+    let mut visited = HashSet::new();
+    while let Some((id, remaining)) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let commit = Commit::from_bytes(&storage.get(&id)?, id)?;
+        let parents = match server_shallow.contains(&id) {
+            true => Vec::new(),
+            false => commit.parent_commit_ids.clone(),
+        };
+        if client_has(&id, &seen) {
+            if client_shallow.is_empty() || parents.is_empty() {
+                continue; // the client has the whole history
+            }
+            // walk down to the shallow commits of the client, and deepen the history below them
+            let remaining = match (deepen, remaining) {
+                (_, Some(remaining)) if remaining > 1 => Some(remaining - 1),
+                (_, Some(_)) | (None, _) => continue,
+                (Some(Deepen::Relative(depth)), None) if client_shallow.contains(&id) => Some(depth),
+                (_, None) => None,
+            };
+            if client_shallow.contains(&id) {
+                if let Some(Deepen::Since(since)) = deepen {
+                    let mut newer = false;
+                    for parent in parents.iter() {
+                        newer |= commit_time(storage, parent)? >= since;
+                    }
+                    if !newer {
+                        continue;
+                    }
+                }
+                plan.unshallow.push(id);
+            }
+            queue.extend(parents.into_iter().map(|parent| (parent, remaining)));
+            continue;
+        }
+
+        walk_objects(storage, commit.tree_id, &mut seen, &cut, Some(&mut plan.objs))?;
+        seen.insert(id);
+        plan.objs.push(commit.into());
+        if parents.is_empty() {
+            if server_shallow.contains(&id) {
+                plan.shallow.push(id);
+            }
+            continue;
+        }
+        let cut_here = match (deepen, remaining) {
+            (_, Some(1)) => true,
+            (Some(Deepen::Since(since)), _) => {
+                let mut older = true;
+                for parent in parents.iter() {
+                    older &= commit_time(storage, parent)? < since;
+                }
+                older
+            }
+            _ => false,
+        };
+        if cut_here {
+            plan.shallow.push(id);
+        } else {
+            queue.extend(parents.into_iter().map(|parent| (parent, remaining.map(|r| r - 1))));
+        }
+    }
+    Ok(plan)
+}
+
+fn commit_time(storage: &ClientStorage, id: &SHA1) -> Result<i64, GitError> {
+    Ok(Commit::from_bytes(&storage.get(id)?, *id)?.committer.timestamp as i64)
+}
+
+/// Objects to try as the delta base when encoding a pack to send
+pub(crate) const DEFAULT_WINDOW: usize = 5;
+
+/// Encode the objects into a pack, which may be empty; `window` objects are tried as the delta base of each
+pub(crate) async fn encode_pack(objs: Vec<Entry>, window: usize) -> Result<Vec<u8>, GitError> {
+    if objs.is_empty() {
+        // the encoder requires objects, an empty pack is only the header & checksum
+        let mut pack = b"PACK\0\0\0\x02\0\0\0\0".to_vec();
+        pack.extend_from_slice(&SHA1::new(&pack).0);
+        return Ok(pack);
+    }
+    // the entries are sent one by one as the encoder goes, to show the progress
+    let (entry_tx, entry_rx) = mpsc::channel(1);
+    let (stream_tx, mut stream_rx) = mpsc::channel(1_000_000);
+    let encoder = PackEncoder::new(objs.len(), window, stream_tx);
+    encoder.encode_async(entry_rx).await?;
+    let writer = tokio::spawn(async move {
+        let mut pack = Vec::new();
+        while let Some(chunk) = stream_rx.recv().await {
+            pack.extend(chunk);
+        }
+        pack
+    });
+    let progress = Progress::new("Compressing objects", Some(objs.len()));
+    for entry in objs {
+        entry_tx
+            .send(entry)
+            .await
+            .map_err(|e| GitError::PackEncodeError(e.to_string()))?;
+        progress.inc(1);
+    }
+    drop(entry_tx);
+    progress.finish();
+
+    writer.await.map_err(|e| GitError::PackEncodeError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::command::tag::{self, TagArgs};
+    use crate::utils::test;
+
+    fn collect_all(storage: &ClientStorage, want: SHA1) -> Vec<Entry> {
+        let none = HashSet::new();
+        collect_objects(storage, &[want], &[], &none, &none, None).unwrap().objs
+    }
+
+    fn pkt_lines(mut data: Bytes) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let (len, line) = read_pkt_line(&mut data);
+            if len == 0 {
+                return lines;
+            }
+            lines.push(String::from_utf8_lossy(&line).to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_libra() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "1\n", "commit 1").await;
+        tag::execute(TagArgs::parse_from(["tag", "v1"])).await;
+        let second = test::commit_file("a.txt", "2\n", "commit 2").await;
+
+        let client = LocalClient::from_url(&Url::from_file_path(util::cur_dir()).unwrap());
+        let refs = client.discovery_reference(ServiceType::UploadPack).await.unwrap();
+        assert_eq!(refs[0]._ref, "HEAD");
+        assert_eq!(refs[0]._hash, second.to_plain_str());
+        assert!(refs.iter().any(|r| r._ref == "refs/heads/master" && r._hash == second.to_plain_str()));
+        assert!(refs.iter().any(|r| r._ref == "refs/tags/v1" && r._hash == first.to_plain_str()));
+
+        let mut stream = client
+            .fetch_objects(&[first.to_plain_str()], &[second.to_plain_str()], &ShallowRequest::default())
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            response.extend(chunk.unwrap());
+        }
+        assert!(response.starts_with(b"0008NAK\nPACK"));
+        // the new commit, tree & blob
+        let count = u32::from_be_bytes(response[16..20].try_into().unwrap());
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_push_to_git_and_libra() {
+        test::setup_with_new_libra().await;
+        let head = test::commit_file("a.txt", "1\n", "commit 1").await;
+        let zero = SHA1::default().to_plain_str();
+        let objs = collect_all(&util::objects_storage(), head);
+        let pack = encode_pack(objs, DEFAULT_WINDOW).await.unwrap();
+        let request = |old: &str, new: &str, name: &str, pack: &[u8]| {
+            let mut data = BytesMut::new();
+            add_pkt_line_string(&mut data, format!("{} {} {}\0report-status\n", old, new, name));
+            data.extend_from_slice(b"0000");
+            data.extend_from_slice(pack);
+            data.freeze()
+        };
+
+        // a bare git repository
+        let remote = std::env::temp_dir().join("libra_test_local_remote.git");
+        let _ = fs::remove_dir_all(&remote);
+        fs::create_dir_all(remote.join("objects")).unwrap();
+        fs::create_dir_all(remote.join("refs/heads")).unwrap();
+        fs::write(remote.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        let client = LocalClient::from_url(&Url::from_file_path(&remote).unwrap());
+        assert!(client.discovery_reference(ServiceType::ReceivePack).await.unwrap().is_empty());
+
+        let report = client
+            .send_pack(request(&zero, &head.to_plain_str(), "refs/heads/master", &pack))
+            .await
+            .unwrap();
+        assert_eq!(pkt_lines(report), ["unpack ok\n", "ok refs/heads/master\n"]);
+        let refs = client.discovery_reference(ServiceType::UploadPack).await.unwrap();
+        assert_eq!(refs[0]._ref, "HEAD");
+        assert_eq!(refs[0]._hash, head.to_plain_str());
+        let remote_storage = ClientStorage::init(remote.join("objects"));
+        assert_eq!(collect_all(&remote_storage, head).len(), 3);
+
+        // the old value is out of date
+        let report = client.send_pack(request(&zero, &zero, "refs/heads/master", &[])).await.unwrap();
+        assert_eq!(pkt_lines(report)[1], "ng refs/heads/master failed to lock\n");
+        let report = client
+            .send_pack(request(&head.to_plain_str(), &zero, "refs/heads/master", &[]))
+            .await
+            .unwrap();
+        assert_eq!(pkt_lines(report)[1], "ok refs/heads/master\n");
+        assert!(client.discovery_reference(ServiceType::UploadPack).await.unwrap().is_empty());
+
+        // the new value is not in the repository
+        let missing = SHA1::new(&b"missing".to_vec()).to_plain_str();
+        let report = client.send_pack(request(&zero, &missing, "refs/heads/master", &[])).await.unwrap();
+        assert_eq!(pkt_lines(report)[1], "ng refs/heads/master missing necessary objects
+");
+        assert!(client.discovery_reference(ServiceType::UploadPack).await.unwrap().is_empty());
+
+        // the libra repository itself, the checked-out branch can't be updated
+        let client = LocalClient::from_url(&Url::from_file_path(util::cur_dir()).unwrap());
+        let report = client.send_pack(request(&head.to_plain_str(), &zero, "refs/heads/master", &[])).await.unwrap();
+        assert_eq!(pkt_lines(report)[1], "ng refs/heads/master branch is currently checked out\n");
+        let report = client.send_pack(request(&zero, &head.to_plain_str(), "refs/heads/feature", &[])).await.unwrap();
+        assert_eq!(pkt_lines(report)[1], "ok refs/heads/feature\n");
+        let refs = client.discovery_reference(ServiceType::UploadPack).await.unwrap();
+        assert!(refs.iter().any(|r| r._ref == "refs/heads/feature" && r._hash == head.to_plain_str()));
+    }
+}
@@ -1,12 +1,155 @@
+use std::io::Error as IoError;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use ceres::protocol::ServiceType;
+use futures_util::{Stream, StreamExt};
+use mercury::errors::GitError;
 use url::Url;
 
+use https_client::{BasicAuth, DiscoveredReference, HttpsClient, ShallowRequest, SideBand};
+use local_client::LocalClient;
+use ssh_client::SshClient;
+
+use crate::utils::util;
+
 pub mod https_client;
-#[allow(dead_code)] // todo: unimplemented
+pub mod local_client;
+pub mod ssh_client;
+
 pub trait ProtocolClient {
     /// create client from url
     fn from_url(url: &Url) -> Self;
 }
 
+/// Parse the url of the remote repository, the scp-like syntax of SSH & local paths are converted:
+/// - `[user@]host:path/to/repo.git` -> `ssh://[user@]host/path/to/repo.git`
+/// - `/path/to/repo`, `../repo` -> `file:///path/to/repo`, relative to the current directory
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    if !url.contains("://") {
+        if let Some((host, path)) = url.split_once(':') {
+            // `C:\repo` is a path on Windows
+            let is_drive = host.len() == 1 && host.chars().all(|c| c.is_ascii_alphabetic());
+            if !host.is_empty() && !host.contains('/') && !is_drive {
+                let path = path.strip_prefix('/').unwrap_or(path);
+                return Url::parse(&format!("ssh://{}/{}", host, path))
+                    .map_err(|e| format!("fatal: invalid URL '{}': {}", url, e));
+            }
+        }
+        let path = util::cur_dir().join(url);
+        return Url::from_file_path(&path)
+            .map_err(|_| format!("fatal: invalid path '{}'", path.display()));
+    }
+    Url::parse(url).map_err(|e| format!("fatal: invalid URL '{}': {}", url, e))
+}
+
+/// The client of the remote repository, chosen by the scheme of the url
+pub enum RemoteClient {
+    Https(HttpsClient),
+    Ssh(SshClient),
+    Local(LocalClient),
+}
+
+impl RemoteClient {
+    pub fn from_url(url: &Url) -> Result<Self, String> {
+        match url.scheme() {
+            "http" | "https" => Ok(RemoteClient::Https(HttpsClient::from_url(url))),
+            "ssh" | "git+ssh" | "ssh+git" => Ok(RemoteClient::Ssh(SshClient::from_url(url))),
+            "file" => Ok(RemoteClient::Local(LocalClient::from_url(url))),
+            scheme => Err(format!("fatal: Unable to find remote helper for '{}'", scheme)),
+        }
+    }
+
+    /// Discover the references of the remote repository, `auth` is only used by HTTP
+    pub async fn discovery_reference(
+        &self,
+        service: ServiceType,
+        auth: Option<BasicAuth>,
+    ) -> Result<Vec<DiscoveredReference>, GitError> {
+        match self {
+            RemoteClient::Https(client) => client.discovery_reference(service, auth).await,
+            RemoteClient::Ssh(client) => client.discovery_reference(service).await,
+            RemoteClient::Local(client) => client.discovery_reference(service).await,
+        }
+    }
+
+    /// Fetch the objects, see [`HttpsClient::fetch_objects`]
+    pub async fn fetch_objects(
+        &self,
+        have: &Vec<String>,
+        want: &Vec<String>,
+        shallow: &ShallowRequest,
+        auth: Option<BasicAuth>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, IoError>> + Send>>, IoError> {
+        Ok(match self {
+            RemoteClient::Https(client) => client.fetch_objects(have, want, shallow, auth).await?.boxed(),
+            RemoteClient::Ssh(client) => client.fetch_objects(have, want, shallow).await?.boxed(),
+            RemoteClient::Local(client) => client.fetch_objects(have, want, shallow).await?.boxed(),
+        })
+    }
+
+    /// End the session if no request follows the discovery, only SSH is stateful
+    pub async fn disconnect(&self) {
+        if let RemoteClient::Ssh(client) = self {
+            client.disconnect().await;
+        }
+    }
+
+    /// Send the ref update commands & pack to `git-receive-pack`, return the report of the server
+    /// - the report is demultiplexed if `side-band-64k` is requested, the progress of the server is printed
+    pub async fn send_pack(&self, data: Bytes, auth: Option<BasicAuth>) -> Result<Bytes, GitError> {
+        let report = match self {
+            RemoteClient::Https(client) => {
+                let res = client
+                    .send_pack(data, auth)
+                    .await
+                    .map_err(|e| GitError::NetworkError(e.to_string()))?;
+                if res.status() != 200 {
+                    return Err(GitError::NetworkError(format!("status code: {}", res.status())));
+                }
+                res.bytes().await.map_err(|e| GitError::NetworkError(e.to_string()))
+            }
+            RemoteClient::Ssh(client) => client
+                .send_pack(data)
+                .await
+                .map_err(|e| GitError::NetworkError(e.to_string())),
+            RemoteClient::Local(client) => return client.send_pack(data).await,
+        }?;
+        SideBand::new(false)
+            .feed(&report)
+            .map_err(|e| GitError::NetworkError(e.to_string()))
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = parse_url("git@github.com:web3infra-foundation/mega.git").unwrap();
+        assert_eq!(url.as_str(), "ssh://git@github.com/web3infra-foundation/mega.git");
+        let url = parse_url("example.com:/srv/repo.git").unwrap();
+        assert_eq!(url.as_str(), "ssh://example.com/srv/repo.git");
+        let url = parse_url("ssh://git@example.com:2222/repo.git").unwrap();
+        assert_eq!(url.port(), Some(2222));
+        let url = parse_url("https://github.com/web3infra-foundation/mega.git").unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(parse_url("C:\\repo").unwrap().scheme(), "file");
+        let url = parse_url("/srv/repo").unwrap();
+        assert_eq!(url.as_str(), "file:///srv/repo");
+        let url = parse_url("repo").unwrap();
+        assert_eq!(url.to_file_path().unwrap(), util::cur_dir().join("repo"));
+    }
+
+    #[test]
+    fn test_remote_client_scheme() {
+        let client = RemoteClient::from_url(&parse_url("git@github.com:a/b.git").unwrap());
+        assert!(matches!(client, Ok(RemoteClient::Ssh(_))));
+        let client = RemoteClient::from_url(&parse_url("https://github.com/a/b.git").unwrap());
+        assert!(matches!(client, Ok(RemoteClient::Https(_))));
+        let client = RemoteClient::from_url(&parse_url("/srv/repo").unwrap());
+        assert!(matches!(client, Ok(RemoteClient::Local(_))));
+        assert!(RemoteClient::from_url(&parse_url("ftp://example.com/a.git").unwrap()).is_err());
+    }
 }
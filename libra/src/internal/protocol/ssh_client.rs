@@ -0,0 +1,287 @@
+//! SSH transport, the same as Git: `git-upload-pack` / `git-receive-pack` is run on the remote host
+//! through the `ssh` command, then the pkt-lines are exchanged over its stdin & stdout.
+//! So the keys in `~/.ssh`, `known_hosts` checking and `ssh-agent` are all handled by OpenSSH.
+//!
+//! The command is `$GIT_SSH_COMMAND`, `core.sshCommand` of config, `$GIT_SSH`, or `ssh`.
+
+use std::env;
+use std::io::Error as IoError;
+use std::process::Stdio;
+
+use bytes::{Bytes, BytesMut};
+use ceres::protocol::ServiceType;
+use futures_util::Stream;
+use mercury::errors::GitError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
+use url::Url;
+
+use super::https_client::{demultiplex, generate_upload_pack_content, parse_references, DiscoveredReference, ShallowRequest};
+use super::ProtocolClient;
+use crate::internal::config::Config;
+
+/// A Git protocol client that communicates with a Git server over SSH.
+/// Unlike HTTP, the connection is stateful: the refs are advertised once the service starts,
+/// then the request is sent in the same session.
+pub struct SshClient {
+    /// `[user@]host`
+    host: String,
+    port: Option<u16>,
+    /// path of the repository on the remote host
+    path: String,
+    /// the `ssh` process of the current service
+    session: Mutex<Option<Child>>,
+}
+
+impl ProtocolClient for SshClient {
+    /// `ssh://[user@]host[:port]/path`, use [`super::parse_url`] to convert the scp-like syntax
+    fn from_url(url: &Url) -> Self {
+        let host = url.host_str().unwrap_or_default();
+        // IPv6 address is bracketed in the url, but not in the `ssh` args
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let host = match url.username() {
+            "" => host.to_string(),
+            user => format!("{}@{}", user, host),
+        };
+        let path = percent_decode(url.path());
+        let path = path.trim_end_matches('/');
+        // `ssh://host/~user/repo` is relative to the home of user
+        let path = match path.strip_prefix("/~") {
+            Some(rest) => format!("~{}", rest),
+            None => path.to_string(),
+        };
+        Self {
+            host,
+            port: url.port(),
+            path,
+            session: Mutex::new(None),
+        }
+    }
+}
+
+impl SshClient {
+    /// The args of `ssh` to run the service on the remote host.
+    /// A host or path like an option is refused, e.g. `-oProxyCommand=...:repo` runs the command locally
+    fn args(&self, service: ServiceType) -> Result<Vec<String>, GitError> {
+        if self.host.starts_with('-') {
+            return Err(GitError::NetworkError(format!("strange hostname '{}' blocked", self.host)));
+        }
+        // the scp-like `host:-path` becomes `/-path`
+        if self.path.starts_with('-') || self.path.starts_with("/-") {
+            return Err(GitError::NetworkError(format!("strange pathname '{}' blocked", self.path)));
+        }
+        let mut args = Vec::new();
+        if let Some(port) = self.port {
+            args.push("-p".to_string());
+            args.push(port.to_string());
+        }
+        // the end of options, in case the host is taken as one anyway
+        args.push("--".to_string());
+        args.push(self.host.clone());
+        args.push(format!("{} {}", service, sq_quote(&self.path)));
+        Ok(args)
+    }
+
+    /// The `ssh` command to run the service on the remote host
+    async fn command(&self, service: ServiceType) -> Result<Command, GitError> {
+        let args = self.args(service)?;
+
+        let ssh_command = match env::var("GIT_SSH_COMMAND") {
+            Ok(command) if !command.is_empty() => Some(command),
+            _ => Config::get("core", None, "sshcommand").await,
+        };
+        let mut command = match ssh_command {
+            // may contain options, run it by shell, the args are appended
+            Some(ssh_command) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(format!("{} \"$@\"", ssh_command)).arg("ssh");
+                command
+            }
+            None => Command::new(env::var("GIT_SSH").unwrap_or_else(|_| "ssh".to_string())),
+        };
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // the errors & prompts of `ssh` (e.g. host key verification) go to the user
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        Ok(command)
+    }
+
+    /// Start the service on the remote host, and read the references it advertises.
+    /// The session is kept for [`Self::fetch_objects`] or [`Self::send_pack`].
+    pub async fn discovery_reference(&self, service: ServiceType) -> Result<Vec<DiscoveredReference>, GitError> {
+        let mut child = self.command(service).await?.spawn().map_err(|e| {
+            GitError::NetworkError(format!("failed to run ssh: {}", e))
+        })?;
+        let stdout = child.stdout.as_mut().unwrap();
+        let mut advertisement = BytesMut::new();
+        loop {
+            match read_pkt_line(stdout).await {
+                Ok(Some(line)) => advertisement.extend_from_slice(&line),
+                Ok(None) => break, // flush-pkt, end of the advertisement
+                Err(_) => {
+                    let status = child.wait().await.ok();
+                    return Err(GitError::NetworkError(format!(
+                        "Could not read from remote repository ({}).\n\
+                         Please make sure you have the correct access rights and the repository exists.",
+                        status.map(|s| s.to_string()).unwrap_or_default()
+                    )));
+                }
+            }
+        }
+        advertisement.extend_from_slice(b"0000");
+        let refs = parse_references(&mut advertisement.freeze(), &service.to_string());
+        *self.session.lock().await = Some(child);
+        Ok(refs)
+    }
+
+    /// Send the `want` & `have` in the session of `git-upload-pack`, return the response stream
+    pub async fn fetch_objects(
+        &self,
+        have: &Vec<String>,
+        want: &Vec<String>,
+        shallow: &ShallowRequest,
+    ) -> Result<impl Stream<Item = Result<Bytes, IoError>>, IoError> {
+        let mut child = self.take_session().await?;
+        let body = generate_upload_pack_content(have, want, shallow).await;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&body).await?;
+        stdin.flush().await?;
+        // the server exits after sending the pack, then the stream ends;
+        // stdin is kept open until then, some servers treat EOF as hang-up
+        let stdout = child.stdout.take().unwrap();
+        tokio::spawn(async move {
+            let _ = child.wait().await;
+            drop(stdin);
+        });
+        Ok(demultiplex(ReaderStream::new(stdout)))
+    }
+
+    /// Send the commands & pack in the session of `git-receive-pack`, return the report
+    pub async fn send_pack(&self, data: Bytes) -> Result<Bytes, IoError> {
+        let mut child = self.take_session().await?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&data).await?;
+        drop(stdin); // EOF, the server starts to process
+        let output = child.wait_with_output().await?;
+        Ok(Bytes::from(output.stdout))
+    }
+
+    /// End the session without a request, the flush-pkt tells the server there is nothing to do,
+    /// otherwise it complains that the remote end hung up
+    pub async fn disconnect(&self) {
+        if let Ok(mut child) = self.take_session().await {
+            let mut stdin = child.stdin.take().unwrap();
+            let _ = stdin.write_all(b"0000").await;
+            drop(stdin);
+            let _ = child.wait().await;
+        }
+    }
+
+    async fn take_session(&self) -> Result<Child, IoError> {
+        self.session
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| IoError::new(std::io::ErrorKind::NotConnected, "no ssh session, discover references first"))
+    }
+}
+
+/// Read a pkt-line from the stream, `None` for the flush-pkt
+async fn read_pkt_line<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Bytes>, IoError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).await?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| IoError::new(std::io::ErrorKind::InvalidData, "invalid pkt-line length"))?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len < 4 {
+        return Err(IoError::new(std::io::ErrorKind::InvalidData, "invalid pkt-line length"));
+    }
+    let mut line = vec![0u8; len];
+    line[..4].copy_from_slice(format!("{:04x}", len).as_bytes());
+    reader.read_exact(&mut line[4..]).await?;
+    Ok(Some(Bytes::from(line)))
+}
+
+/// Quote the argument for the remote shell, e.g. `it's` -> `'it'\''s'`
+fn sq_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Decode `%XX` of the url path, e.g. `%20` -> ` `
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::protocol::parse_url;
+
+    #[test]
+    fn test_from_url() {
+        let client = SshClient::from_url(&Url::parse("ssh://git@example.com:2222/group/repo.git/").unwrap());
+        assert_eq!(client.host, "git@example.com");
+        assert_eq!(client.port, Some(2222));
+        assert_eq!(client.path, "/group/repo.git");
+
+        let client = SshClient::from_url(&Url::parse("ssh://[::1]/~alice/my%20repo").unwrap());
+        assert_eq!(client.host, "::1");
+        assert_eq!(client.port, None);
+        assert_eq!(client.path, "~alice/my repo");
+    }
+
+    #[test]
+    fn test_args() {
+        let client = SshClient::from_url(&Url::parse("ssh://git@example.com:2222/repo.git").unwrap());
+        assert_eq!(
+            client.args(ServiceType::UploadPack).unwrap(),
+            ["-p", "2222", "--", "git@example.com", "git-upload-pack '/repo.git'"]
+        );
+
+        // hosts & paths like options are blocked
+        let client = SshClient::from_url(&parse_url("-oProxyCommand=touch${IFS}pwned:repo").unwrap());
+        assert!(client.args(ServiceType::UploadPack).is_err());
+        let client = SshClient::from_url(&parse_url("example.com:-repo").unwrap());
+        assert!(client.args(ServiceType::UploadPack).is_err());
+        let client = SshClient::from_url(&parse_url("example.com:repo-1").unwrap());
+        assert!(client.args(ServiceType::UploadPack).is_ok());
+    }
+
+    #[test]
+    fn test_sq_quote() {
+        assert_eq!(sq_quote("/repo.git"), "'/repo.git'");
+        assert_eq!(sq_quote("it's"), "'it'\\''s'");
+    }
+
+    #[tokio::test]
+    async fn test_read_pkt_line() {
+        let mut data: &[u8] = b"000ahello\n0000";
+        assert_eq!(read_pkt_line(&mut data).await.unwrap().unwrap(), Bytes::from("000ahello\n"));
+        assert_eq!(read_pkt_line(&mut data).await.unwrap(), None);
+        assert!(read_pkt_line(&mut data).await.is_err());
+    }
+}
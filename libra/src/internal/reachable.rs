@@ -0,0 +1,145 @@
+//! Reachability of the objects: what is kept by `gc` & `repack`, and what is sent to the other side.
+
+use std::collections::HashSet;
+use std::fs;
+use std::str::FromStr;
+
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+use mercury::internal::pack::entry::Entry;
+
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::internal::stash::Stash;
+use crate::internal::tag::Tag;
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::path;
+
+/// The mode of a submodule entry in the index (gitlink), the commit is not in this repository
+const GITLINK_MODE: u32 = 0o160000;
+
+/// The objects that keep the others alive:
+/// - local & remote branches, tags and a detached `HEAD`
+/// - the old & new values in the reflogs, and the stashes
+/// - the blobs in the index, including the conflicted stages
+/// - the commits recorded by the operations in progress (merge, rebase, cherry-pick & revert)
+///
+/// The roots not in `storage` are skipped, e.g. a reflog entry of a commit that was never fetched.
+pub async fn roots(storage: &ClientStorage) -> Result<Vec<SHA1>, GitError> {
+    let mut roots = Vec::new();
+    if let Head::Detached(commit) = Head::current().await {
+        roots.push(commit);
+    }
+    roots.extend(Branch::list_branches(None).await.into_iter().map(|b| b.commit));
+    for remote in Config::all_remote_configs().await {
+        roots.extend(Branch::list_branches(Some(&remote.name)).await.into_iter().map(|b| b.commit));
+    }
+    roots.extend(Tag::list_tags().await.into_iter().map(|t| t.object));
+    // a reflog that can't be read must not let its commits be pruned
+    let reflog = Reflog::list_all()
+        .await
+        .map_err(|e| GitError::CustomError(format!("unable to read the reflog: {}", e)))?;
+    for entry in reflog {
+        roots.push(entry.old_oid);
+        roots.push(entry.new_oid);
+    }
+    let stashes = Stash::list()
+        .await
+        .map_err(|e| GitError::CustomError(format!("unable to read the stash list: {}", e)))?;
+    roots.extend(stashes.into_iter().map(|s| s.commit));
+
+    let index = Index::load(path::index())?;
+    for stage in 0..=3 {
+        let entries = index.tracked_entries(stage);
+        roots.extend(entries.into_iter().filter(|e| e.mode != GITLINK_MODE).map(|e| e.hash));
+    }
+
+    // the state files only contain hashes, refs & messages, so any word like a hash is taken
+    let mut state_files = vec![path::merge_head(), path::cherry_pick_head(), path::revert_head()];
+    for dir in [path::rebase_merge(), path::sequencer()] {
+        if let Ok(entries) = fs::read_dir(dir) {
+            state_files.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+        }
+    }
+    for file in state_files.iter().filter(|file| file.is_file()) {
+        let content = fs::read_to_string(file).unwrap_or_default();
+        roots.extend(
+            content
+                .split_whitespace()
+                .filter(|word| word.len() == 40)
+                .filter_map(|word| SHA1::from_str(word).ok()),
+        );
+    }
+
+    let zero = SHA1::default();
+    let mut seen = HashSet::new();
+    roots.retain(|id| *id != zero && seen.insert(*id) && storage.exist(id));
+    Ok(roots)
+}
+
+/// All objects reachable from the `roots`, the history stops at the `shallow` commits
+pub fn reachable_objects(
+    storage: &ClientStorage,
+    roots: &[SHA1],
+    shallow: &HashSet<SHA1>,
+) -> Result<HashSet<SHA1>, GitError> {
+    let mut seen = HashSet::new();
+    for root in roots {
+        walk_objects(storage, *root, &mut seen, shallow, None)?;
+    }
+    Ok(seen)
+}
+
+/// Walk the objects reachable from `id`, skip the `seen` ones and the parents of the `shallow` commits;
+/// add them to `objs` if provided
+pub fn walk_objects(
+    storage: &ClientStorage,
+    id: SHA1,
+    seen: &mut HashSet<SHA1>,
+    shallow: &HashSet<SHA1>,
+    mut objs: Option<&mut Vec<Entry>>,
+) -> Result<(), GitError> {
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let obj_type = storage.get_object_type(&id)?;
+        if obj_type == ObjectType::Blob && objs.is_none() {
+            continue; // no need to read the content
+        }
+        let data = storage.get(&id)?;
+        match obj_type {
+            ObjectType::Commit => {
+                let commit = Commit::from_bytes(&data, id)?;
+                stack.push(commit.tree_id);
+                if !shallow.contains(&id) {
+                    stack.extend(commit.parent_commit_ids);
+                }
+            }
+            ObjectType::Tree => {
+                let tree = Tree::from_bytes(&data, id)?;
+                for item in tree.tree_items {
+                    match item.mode {
+                        TreeItemMode::Commit => {} // submodule, not in this repository
+                        _ => stack.push(item.id),
+                    }
+                }
+            }
+            ObjectType::Tag => stack.push(TagObject::from_bytes(&data, id)?.object_hash),
+            _ => {}
+        }
+        if let Some(objs) = objs.as_mut() {
+            objs.push(Entry { obj_type, data, hash: id });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use sea_orm::ActiveModelTrait;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder};
+
+use mercury::hash::SHA1;
+
+use crate::command::commit;
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::head::Head;
+use crate::internal::model::reflog;
+
+pub const HEAD: &str = "HEAD";
+
+/// An entry of the reflog, which records a movement of the ref
+/// - `old_oid`: zero hash if the ref is created
+#[derive(Debug)]
+pub struct Reflog {
+    pub old_oid: SHA1,
+    pub new_oid: SHA1,
+    pub message: String,
+}
+
+impl TryFrom<reflog::Model> for Reflog {
+    type Error = String;
+
+    fn try_from(model: reflog::Model) -> Result<Self, Self::Error> {
+        let parse = |oid: &str| {
+            SHA1::from_str(oid).map_err(|_| format!("bad object id '{}' in the reflog of '{}'", oid, model.ref_name))
+        };
+        Ok(Reflog {
+            old_oid: parse(&model.old_oid)?,
+            new_oid: parse(&model.new_oid)?,
+            message: model.message,
+        })
+    }
+}
+
+/// Convert the rows, a corrupt one is skipped with a warning
+fn from_models(models: Vec<reflog::Model>) -> Vec<Reflog> {
+    models
+        .into_iter()
+        .filter_map(|model| match Reflog::try_from(model) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("warning: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Full ref name of the local branch
+pub fn branch_ref(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+/// Full ref name of `HEAD`, a branch or a full ref, `""` is `HEAD` (as in `@{1}`)
+pub fn full_ref_name(name: &str) -> String {
+    match name {
+        "" | HEAD => HEAD.to_string(),
+        full if full.starts_with("refs/") => full.to_string(),
+        branch => branch_ref(branch),
+    }
+}
+
+impl Reflog {
+    /// Record the movement of the ref, `old` is `None` if the ref is created
+    /// - the ref is already updated, so a failure is only warned
+    pub async fn insert(ref_name: &str, old: Option<SHA1>, new: SHA1, message: &str) {
+        let db_conn = &get_db_conn_instance().await;
+        let committer = commit::committer().await;
+        let result = reflog::ActiveModel {
+            ref_name: Set(ref_name.to_owned()),
+            old_oid: Set(old.unwrap_or_default().to_plain_str()),
+            new_oid: Set(new.to_plain_str()),
+            committer_name: Set(committer.name),
+            committer_email: Set(committer.email),
+            timestamp: Set(committer.timestamp as i64),
+            message: Set(message.to_owned()),
+            ..Default::default()
+        }
+        .insert(db_conn)
+        .await;
+        if let Err(e) = result {
+            eprintln!("warning: unable to update the reflog of '{}': {}", ref_name, e);
+        }
+    }
+
+    /// All entries of the ref, the latest first (`<ref>@{0}`)
+    pub async fn list(ref_name: &str) -> Result<Vec<Self>, DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        let models = reflog::Entity::find()
+            .filter(reflog::Column::RefName.eq(ref_name))
+            .order_by_desc(reflog::Column::Id)
+            .all(db_conn)
+            .await?;
+        Ok(from_models(models))
+    }
+
+    /// All entries of all refs
+    pub async fn list_all() -> Result<Vec<Self>, DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        Ok(from_models(reflog::Entity::find().all(db_conn).await?))
+    }
+
+    /// Remove the entries older than `before` (unix timestamp), all refs if `ref_name` is `None`
+    /// - return the number of removed entries
+    pub async fn expire(ref_name: Option<&str>, before: i64) -> Result<u64, DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        let mut delete = reflog::Entity::delete_many().filter(reflog::Column::Timestamp.lt(before));
+        if let Some(ref_name) = ref_name {
+            delete = delete.filter(reflog::Column::RefName.eq(ref_name));
+        }
+        Ok(delete.exec(db_conn).await?.rows_affected)
+    }
+
+    /// Remove all entries of the ref, e.g. the branch is deleted
+    pub async fn delete(ref_name: &str) -> Result<(), DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        reflog::Entity::delete_many()
+            .filter(reflog::Column::RefName.eq(ref_name))
+            .exec(db_conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the movement of `HEAD`, and the branch if `HEAD` points to it
+    /// - call it after `HEAD` is updated
+    pub async fn log_head_update(old: Option<SHA1>, new: SHA1, message: &str) {
+        Self::insert(HEAD, old, new, message).await;
+        if let Head::Branch(name) = Head::current().await {
+            Self::insert(&branch_ref(&name), old, new, message).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reflog_insert_and_expire() {
+        test::setup_with_new_libra().await;
+
+        let first = SHA1::new(&"first".as_bytes().to_vec());
+        let second = SHA1::new(&"second".as_bytes().to_vec());
+        Reflog::log_head_update(None, first, "commit (initial): first").await;
+        Reflog::log_head_update(Some(first), second, "commit: second").await;
+
+        let head_log = Reflog::list(HEAD).await.unwrap();
+        assert_eq!(head_log.len(), 2);
+        assert_eq!(head_log[0].new_oid, second);
+        assert_eq!(head_log[0].old_oid, first);
+        assert_eq!(head_log[1].old_oid, SHA1::default());
+        assert_eq!(Reflog::list(&branch_ref("master")).await.unwrap().len(), 2);
+
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(Reflog::expire(Some(HEAD), now - 3600).await.unwrap(), 0);
+        assert_eq!(Reflog::expire(Some(HEAD), now + 3600).await.unwrap(), 2);
+        assert!(Reflog::list(HEAD).await.unwrap().is_empty());
+        assert_eq!(Reflog::list(&branch_ref("master")).await.unwrap().len(), 2);
+
+        // a corrupt row is skipped, not a panic
+        reflog::ActiveModel {
+            ref_name: Set(HEAD.to_owned()),
+            old_oid: Set("bad".to_owned()),
+            new_oid: Set(second.to_plain_str()),
+            committer_name: Set(String::new()),
+            committer_email: Set(String::new()),
+            timestamp: Set(now),
+            message: Set("corrupt".to_owned()),
+            ..Default::default()
+        }
+        .insert(&get_db_conn_instance().await)
+        .await
+        .unwrap();
+        assert!(Reflog::list(HEAD).await.unwrap().is_empty());
+        assert_eq!(Reflog::list_all().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use sea_orm::ActiveModelTrait;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::model::shallow;
+
+/// The shallow boundary of a shallow clone: the commits whose parents are not fetched.
+/// They are treated as root commits when walking the history, like the grafts of Git.
+pub struct Shallow;
+
+impl Shallow {
+    /// All shallow commits, empty if the repository is complete (or the boundary can't be read)
+    pub async fn list() -> HashSet<SHA1> {
+        let db_conn = &get_db_conn_instance().await;
+        match shallow::Entity::find().all(db_conn).await {
+            Ok(models) => models
+                .into_iter()
+                .filter_map(|model| SHA1::from_str(&model.commit).ok())
+                .collect(),
+            Err(e) => {
+                eprintln!("warning: unable to read the shallow commits: {}", e);
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Apply the `shallow` & `unshallow` lines sent by the server
+    pub async fn update(shallow: &[SHA1], unshallow: &[SHA1]) {
+        let db_conn = &get_db_conn_instance().await;
+        let existing = Self::list().await;
+        for commit in shallow.iter().filter(|commit| !existing.contains(commit)) {
+            shallow::ActiveModel {
+                commit: Set(commit.to_plain_str()),
+                ..Default::default()
+            }
+            .insert(db_conn)
+            .await
+            .unwrap();
+        }
+        if !unshallow.is_empty() {
+            shallow::Entity::delete_many()
+                .filter(shallow::Column::Commit.is_in(unshallow.iter().map(|c| c.to_plain_str())))
+                .exec(db_conn)
+                .await
+                .unwrap();
+        }
+    }
+}
+
+/// Parents of the commit, none for a shallow commit (its parents are missing)
+pub fn parents(commit: &Commit, shallow: &HashSet<SHA1>) -> Vec<SHA1> {
+    if shallow.contains(&commit.id) {
+        Vec::new()
+    } else {
+        commit.parent_commit_ids.clone()
+    }
+}
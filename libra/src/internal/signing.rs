@@ -0,0 +1,378 @@
+//! Signatures of the commits & tags, made & verified by the external programs like Git does.
+//!
+//! - OpenPGP (`gpg.format=openpgp`, the default): by `gpg.program`, `gpg` by default.
+//!   The key is `user.signingkey`, or the committer identity `Name <email>` if not set.
+//! - SSH (`gpg.format=ssh`): by `gpg.ssh.program`, `ssh-keygen` by default.
+//!   The key is `user.signingkey`: the path of a key, or `key::<public key>` for a key in the agent.
+//!   The trusted keys to verify are in `gpg.ssh.allowedSignersFile`, see `ssh-keygen(1)`.
+//!
+//! The signature of a commit is in its `gpgsig` header, the signature of a tag is appended to its message.
+//! The signed payload is the object without the signature.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::ObjectTrait;
+use tempfile::TempDir;
+
+use crate::command::{commit, format_commit_msg, parse_commit_msg};
+use crate::internal::config::Config;
+
+const PGP_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+/// The namespace of the SSH signatures, same as Git so that they can verify each other's
+const SSH_NAMESPACE: &str = "git";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureFormat {
+    OpenPgp,
+    Ssh,
+}
+
+impl SignatureFormat {
+    /// The format of `gpg.format`
+    async fn configured() -> Result<Self, String> {
+        match Config::get("gpg", None, "format").await.as_deref() {
+            None | Some("openpgp") => Ok(SignatureFormat::OpenPgp),
+            Some("ssh") => Ok(SignatureFormat::Ssh),
+            Some(format) => Err(format!("unsupported value for gpg.format: {}", format)),
+        }
+    }
+
+    /// The format of an armored signature
+    fn detect(signature: &str) -> Option<Self> {
+        if signature.starts_with(PGP_BEGIN) {
+            Some(SignatureFormat::OpenPgp)
+        } else if signature.starts_with(SSH_BEGIN) {
+            Some(SignatureFormat::Ssh)
+        } else {
+            None
+        }
+    }
+}
+
+/// The result of verifying a signature
+#[derive(Debug, Clone)]
+pub struct Verification {
+    /// the signature is valid & made by a trusted key
+    pub good: bool,
+    /// the report of the program for humans, like `gpg: Good signature from ...`
+    pub output: String,
+}
+
+/// Sign the payload, return the armored signature; `key` overrides `user.signingkey`
+pub async fn sign(payload: &[u8], key: Option<&str>) -> Result<String, String> {
+    let format = SignatureFormat::configured().await?;
+    let key = match key {
+        Some(key) => Some(key.to_string()),
+        None => Config::get("user", None, "signingkey").await,
+    };
+    match format {
+        SignatureFormat::OpenPgp => {
+            let key = match key {
+                Some(key) => key,
+                None => {
+                    let committer = commit::committer().await;
+                    format!("{} <{}>", committer.name, committer.email)
+                }
+            };
+            sign_pgp(payload, &key).await
+        }
+        SignatureFormat::Ssh => {
+            let key = key.ok_or("user.signingkey needs to be set for ssh signing")?;
+            sign_ssh(payload, &key).await
+        }
+    }
+}
+
+/// Verify the signature of the payload, the format is detected from the signature
+pub async fn verify(payload: &[u8], signature: &str) -> Result<Verification, String> {
+    match SignatureFormat::detect(signature) {
+        Some(SignatureFormat::OpenPgp) => verify_pgp(payload, signature).await,
+        Some(SignatureFormat::Ssh) => verify_ssh(payload, signature).await,
+        None => Err("unknown signature format".to_string()),
+    }
+}
+
+/// Sign the commit, the signature is put in the `gpgsig` header
+pub async fn sign_commit(commit: &Commit, key: Option<&str>) -> Result<Commit, String> {
+    let (message, _) = parse_commit_msg(&commit.message);
+    let unsigned = Commit {
+        message: format_commit_msg(&message, None),
+        ..commit.clone()
+    };
+    let signature = sign(&unsigned.to_data().map_err(|e| e.to_string())?, key).await?;
+    // the continuation lines of a header start with a space
+    let header = format!("gpgsig {}", signature.trim_end().replace('\n', "\n "));
+    Ok(Commit::new(
+        unsigned.author,
+        unsigned.committer,
+        unsigned.tree_id,
+        unsigned.parent_commit_ids,
+        &format_commit_msg(&message, Some(&header)),
+    ))
+}
+
+/// The signed payload & the signature of a commit, `None` if not signed
+pub fn commit_signature(commit: &Commit) -> Option<(Vec<u8>, String)> {
+    let (message, header) = parse_commit_msg(&commit.message);
+    let signature = header?.strip_prefix("gpgsig ")?.replace("\n ", "\n") + "\n";
+    let unsigned = Commit {
+        message: format_commit_msg(&message, None),
+        ..commit.clone()
+    };
+    Some((unsigned.to_data().ok()?, signature))
+}
+
+/// Sign the annotated tag, the signature is appended to the message
+pub async fn sign_tag(tag: &TagObject, key: Option<&str>) -> Result<TagObject, String> {
+    let signature = sign(&tag.to_data().map_err(|e| e.to_string())?, key).await?;
+    let mut signed = TagObject {
+        message: format!("{}{}", tag.message, signature),
+        ..tag.clone()
+    };
+    signed.id = SHA1::from_type_and_data(
+        signed.get_type(),
+        &signed.to_data().map_err(|e| e.to_string())?,
+    );
+    Ok(signed)
+}
+
+/// The signed payload & the signature of an annotated tag, `None` if not signed
+pub fn tag_signature(tag: &TagObject) -> Option<(Vec<u8>, String)> {
+    let start = [PGP_BEGIN, SSH_BEGIN]
+        .iter()
+        .filter_map(|begin| tag.message.find(&format!("\n{}", begin)))
+        .min()?
+        + 1;
+    let unsigned = TagObject {
+        message: tag.message[..start].to_string(),
+        ..tag.clone()
+    };
+    Some((unsigned.to_data().ok()?, tag.message[start..].to_string()))
+}
+
+/// Run the program with the input on stdin
+fn run(command: &mut Command, input: &[u8]) -> Result<Output, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run {:?}: {}", command.get_program(), e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    // the program may exit without reading the input
+    let _ = stdin.write_all(input);
+    drop(stdin);
+    child.wait_with_output().map_err(|e| e.to_string())
+}
+
+fn temp_dir() -> Result<TempDir, String> {
+    tempfile::Builder::new()
+        .prefix("libra-signing")
+        .tempdir()
+        .map_err(|e| e.to_string())
+}
+
+async fn gpg_program() -> String {
+    Config::get("gpg", None, "program")
+        .await
+        .unwrap_or_else(|| "gpg".to_string())
+}
+
+async fn ssh_program() -> String {
+    Config::get("gpg", Some("ssh"), "program")
+        .await
+        .unwrap_or_else(|| "ssh-keygen".to_string())
+}
+
+async fn sign_pgp(payload: &[u8], key: &str) -> Result<String, String> {
+    let output = run(
+        Command::new(gpg_program().await).args(["--status-fd=2", "-bsau", key]),
+        payload,
+    )?;
+    // the status lines are mixed into stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.contains("[GNUPG:] SIG_CREATED ") {
+        eprint!("{}", stderr);
+        return Err("gpg failed to sign the data".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn verify_pgp(payload: &[u8], signature: &str) -> Result<Verification, String> {
+    let dir = temp_dir()?;
+    let signature_file = dir.path().join("signature");
+    std::fs::write(&signature_file, signature).map_err(|e| e.to_string())?;
+    let output = run(
+        Command::new(gpg_program().await)
+            .args(["--keyid-format=long", "--status-fd=1", "--verify"])
+            .arg(&signature_file)
+            .arg("-"),
+        payload,
+    )?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let has = |keyword: &str| {
+        status
+            .lines()
+            .any(|line| line.starts_with(&format!("[GNUPG:] {} ", keyword)))
+    };
+    Ok(Verification {
+        good: has("GOODSIG") && !has("BADSIG") && !has("ERRSIG"),
+        output: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+/// The key file of `user.signingkey`, a literal key is written to a temporary file
+fn ssh_key_file(key: &str, dir: &Path) -> Result<(String, bool), String> {
+    let literal = key
+        .strip_prefix("key::")
+        .or_else(|| key.starts_with("ssh-").then_some(key));
+    match literal {
+        Some(literal) => {
+            let file = dir.join("key.pub");
+            std::fs::write(&file, literal).map_err(|e| e.to_string())?;
+            Ok((file.to_string_lossy().into_owned(), true))
+        }
+        None => Ok((key.to_string(), false)),
+    }
+}
+
+async fn sign_ssh(payload: &[u8], key: &str) -> Result<String, String> {
+    let dir = temp_dir()?;
+    let (key_file, literal) = ssh_key_file(key, dir.path())?;
+    let payload_file = dir.path().join("payload");
+    std::fs::write(&payload_file, payload).map_err(|e| e.to_string())?;
+    let mut command = Command::new(ssh_program().await);
+    command.args(["-Y", "sign", "-n", SSH_NAMESPACE, "-f", &key_file]);
+    if literal {
+        // the private key is in the agent
+        command.arg("-U");
+    }
+    let output = run(command.arg(&payload_file), &[])?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err("ssh-keygen failed to sign the data".to_string());
+    }
+    std::fs::read_to_string(dir.path().join("payload.sig")).map_err(|e| e.to_string())
+}
+
+async fn verify_ssh(payload: &[u8], signature: &str) -> Result<Verification, String> {
+    let allowed = Config::get("gpg", Some("ssh"), "allowedsignersfile")
+        .await
+        .filter(|file| Path::new(file).exists())
+        .ok_or("gpg.ssh.allowedSignersFile needs to be configured and exist for ssh signature verification")?;
+    let dir = temp_dir()?;
+    let signature_file = dir.path().join("signature");
+    std::fs::write(&signature_file, signature).map_err(|e| e.to_string())?;
+    let program = ssh_program().await;
+
+    let found = run(
+        Command::new(&program)
+            .args(["-Y", "find-principals", "-f", &allowed, "-s"])
+            .arg(&signature_file),
+        &[],
+    )?;
+    let found = String::from_utf8_lossy(&found.stdout);
+    let principals: Vec<&str> = found.lines().filter(|line| !line.is_empty()).collect();
+    if principals.is_empty() {
+        // still check the signature to tell the key
+        let output = run(
+            Command::new(&program)
+                .args(["-Y", "check-novalidate", "-n", SSH_NAMESPACE, "-s"])
+                .arg(&signature_file),
+            payload,
+        )?;
+        let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+        report.push_str("No principal matched.\n");
+        return Ok(Verification {
+            good: false,
+            output: report,
+        });
+    }
+
+    let mut report = String::new();
+    for principal in principals {
+        let output = run(
+            Command::new(&program)
+                .args([
+                    "-Y",
+                    "verify",
+                    "-n",
+                    SSH_NAMESPACE,
+                    "-f",
+                    &allowed,
+                    "-I",
+                    principal,
+                    "-s",
+                ])
+                .arg(&signature_file),
+            payload,
+        )?;
+        if output.status.success() {
+            return Ok(Verification {
+                good: true,
+                output: String::from_utf8_lossy(&output.stdout).into_owned(),
+            });
+        }
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(Verification {
+        good: false,
+        output: report,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::internal::object::signature::Signature;
+    use mercury::internal::object::types::ObjectType;
+
+    use super::*;
+
+    #[test]
+    fn test_signature_payloads() {
+        let signature =
+            "-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n\nAAAA\n-----END SSH SIGNATURE-----\n";
+        let unsigned = Commit::from_tree_id(
+            SHA1::default(),
+            vec![],
+            &format_commit_msg("subject\n\nbody", None),
+        );
+        assert!(commit_signature(&unsigned).is_none());
+        let header = format!("gpgsig {}", signature.trim_end().replace('\n', "\n "));
+        let signed = Commit {
+            message: format_commit_msg("subject\n\nbody", Some(&header)),
+            ..unsigned.clone()
+        };
+        assert!(String::from_utf8(signed.to_data().unwrap())
+            .unwrap()
+            .contains("\ngpgsig -----BEGIN SSH SIGNATURE-----\n U1NIU0lH\n \n AAAA\n -----END SSH SIGNATURE-----\n\nsubject"));
+        assert_eq!(signed.format_message(), "subject");
+        let (payload, found) = commit_signature(&signed).unwrap();
+        assert_eq!(payload, unsigned.to_data().unwrap());
+        assert_eq!(found, signature);
+
+        let tag = TagObject {
+            id: SHA1::default(),
+            object_hash: unsigned.id,
+            object_type: ObjectType::Commit,
+            tag_name: "v1".to_string(),
+            tagger: Signature::from_data(b"tagger a <a@b.c> 1700000000 +0800".to_vec()).unwrap(),
+            message: "\nrelease\n".to_string(),
+        };
+        assert!(tag_signature(&tag).is_none());
+        let signed = TagObject {
+            message: format!("{}{}", tag.message, signature),
+            ..tag.clone()
+        };
+        let (payload, found) = tag_signature(&signed).unwrap();
+        assert_eq!(payload, tag.to_data().unwrap());
+        assert_eq!(found, signature);
+    }
+}
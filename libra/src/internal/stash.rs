@@ -0,0 +1,105 @@
+use std::str::FromStr;
+
+use sea_orm::ActiveModelTrait;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DbErr, EntityTrait, QueryOrder};
+
+use mercury::hash::SHA1;
+
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::model::stash;
+
+/// Stash entry, the stash list is a stack, `stash@{0}` is the latest one
+/// - `commit`: the `WIP` commit, its parents are `HEAD` & the index commit (same as Git's `refs/stash`)
+#[derive(Debug)]
+pub struct Stash {
+    id: i64,
+    pub commit: SHA1,
+    pub message: String,
+}
+
+impl TryFrom<stash::Model> for Stash {
+    type Error = String;
+
+    fn try_from(model: stash::Model) -> Result<Self, Self::Error> {
+        Ok(Stash {
+            id: model.id,
+            commit: SHA1::from_str(&model.commit)
+                .map_err(|_| format!("bad commit id '{}' in the stash list", model.commit))?,
+            message: model.message,
+        })
+    }
+}
+
+impl Stash {
+    /// list all stash entries, the latest first; a corrupt entry is skipped with a warning
+    pub async fn list() -> Result<Vec<Self>, DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        Ok(stash::Entity::find()
+            .order_by_desc(stash::Column::Id)
+            .all(db_conn)
+            .await?
+            .into_iter()
+            .filter_map(|model| match Stash::try_from(model) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    eprintln!("warning: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// get `stash@{n}`
+    pub async fn get(n: usize) -> Result<Option<Self>, DbErr> {
+        Ok(Self::list().await?.into_iter().nth(n))
+    }
+
+    /// push a new entry on the top, it becomes `stash@{0}`
+    pub async fn push(commit: &SHA1, message: &str) -> Result<(), DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        stash::ActiveModel {
+            commit: Set(commit.to_plain_str()),
+            message: Set(message.to_owned()),
+            ..Default::default()
+        }
+        .insert(db_conn)
+        .await?;
+        Ok(())
+    }
+
+    /// remove the entry from the stash list, the entries above it are renumbered
+    pub async fn remove(&self) -> Result<(), DbErr> {
+        let db_conn = &get_db_conn_instance().await;
+        stash::Entity::delete_by_id(self.id).exec(db_conn).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stash_list_order() {
+        test::setup_with_new_libra().await;
+
+        let first = SHA1::new(&"first".as_bytes().to_vec());
+        let second = SHA1::new(&"second".as_bytes().to_vec());
+        Stash::push(&first, "WIP on master: first").await.unwrap();
+        Stash::push(&second, "WIP on master: second").await.unwrap();
+
+        let list = Stash::list().await.unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].commit, second);
+        assert_eq!(list[1].commit, first);
+
+        Stash::get(0).await.unwrap().unwrap().remove().await.unwrap();
+        let top = Stash::get(0).await.unwrap().unwrap();
+        assert_eq!(top.commit, first);
+        assert_eq!(top.message, "WIP on master: first");
+        assert!(Stash::get(1).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+use sea_orm::ActiveModelTrait;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use mercury::hash::SHA1;
+
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::model::reference;
+
+/// Tag reference (`refs/tags/<name>`), tags are always local (remote is NULL)
+/// - `object`: the commit (lightweight tag) or tag object (annotated tag) it points to
+#[derive(Debug)]
+pub struct Tag {
+    pub name: String,
+    pub object: SHA1,
+}
+
+async fn query_reference(tag_name: &str) -> Option<reference::Model> {
+    let db_conn = &get_db_conn_instance().await;
+    reference::Entity::find()
+        .filter(reference::Column::Name.eq(tag_name))
+        .filter(reference::Column::Kind.eq(reference::ConfigKind::Tag))
+        .one(db_conn)
+        .await
+        .unwrap()
+}
+
+impl From<reference::Model> for Tag {
+    fn from(model: reference::Model) -> Self {
+        Tag {
+            name: model.name.unwrap(),
+            object: SHA1::from_str(model.commit.as_ref().unwrap()).unwrap(),
+        }
+    }
+}
+
+impl Tag {
+    /// list all tags, sorted by name
+    pub async fn list_tags() -> Vec<Self> {
+        let db_conn = &get_db_conn_instance().await;
+        reference::Entity::find()
+            .filter(reference::Column::Kind.eq(reference::ConfigKind::Tag))
+            .order_by_asc(reference::Column::Name)
+            .all(db_conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(Tag::from)
+            .collect()
+    }
+
+    pub async fn exists(tag_name: &str) -> bool {
+        Self::find_tag(tag_name).await.is_some()
+    }
+
+    /// get the tag by name
+    pub async fn find_tag(tag_name: &str) -> Option<Self> {
+        query_reference(tag_name).await.map(Tag::from)
+    }
+
+    /// create the tag or move it to `object`
+    pub async fn update_tag(tag_name: &str, object: &str) {
+        let db_conn = &get_db_conn_instance().await;
+        match query_reference(tag_name).await {
+            Some(tag) => {
+                let mut tag: reference::ActiveModel = tag.into();
+                tag.commit = Set(Some(object.to_owned()));
+                tag.update(db_conn).await.unwrap();
+            }
+            None => {
+                reference::ActiveModel {
+                    name: Set(Some(tag_name.to_owned())),
+                    kind: Set(reference::ConfigKind::Tag),
+                    commit: Set(Some(object.to_owned())),
+                    remote: Set(None),
+                    ..Default::default()
+                }
+                .insert(db_conn)
+                .await
+                .unwrap();
+            }
+        }
+    }
+
+    pub async fn delete_tag(tag_name: &str) {
+        let db_conn = &get_db_conn_instance().await;
+        let tag: reference::ActiveModel = query_reference(tag_name).await.unwrap().into();
+        tag.delete(db_conn).await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_update_and_list_tags() {
+        test::setup_with_new_libra().await;
+
+        let hash = SHA1::default().to_plain_str();
+        Tag::update_tag("v2", &hash).await;
+        Tag::update_tag("v1", &hash).await;
+        Tag::update_tag("v1", &hash).await; // update, not insert
+
+        let tags: Vec<String> = Tag::list_tags().await.into_iter().map(|t| t.name).collect();
+        assert_eq!(tags, vec!["v1", "v2"]);
+
+        Tag::delete_tag("v1").await;
+        assert!(!Tag::exists("v1").await);
+        assert!(Tag::exists("v2").await);
+    }
+}
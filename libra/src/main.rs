@@ -37,8 +37,16 @@ enum Commands {
     Rm(command::remove::RemoveArgs),
     #[command(about = "Restore working tree files")]
     Restore(command::restore::RestoreArgs),
+    #[command(about = "Reset current HEAD to the specified state")]
+    Reset(command::reset::ResetArgs),
     #[command(about = "Show the working tree status")]
-    Status,
+    Status(command::status::StatusArgs),
+    #[command(about = "Show changes between commits, commit and working tree, etc")]
+    Diff(command::diff::DiffArgs),
+    #[command(about = "Show various types of objects")]
+    Show(command::show::ShowArgs),
+    #[command(about = "Show what revision and author last modified each line of a file")]
+    Blame(command::blame::BlameArgs),
     #[command(about = "Show commit logs")]
     Log(command::log::LogArgs),
     #[command(about = "List, create, or delete branches")]
@@ -47,8 +55,20 @@ enum Commands {
     Commit(command::commit::CommitArgs),
     #[command(about = "Switch branches")]
     Switch(command::switch::SwitchArgs),
+    #[command(about = "Manage reflog information")]
+    Reflog(command::reflog::ReflogArgs),
+    #[command(about = "Create, list, or delete tags")]
+    Tag(command::tag::TagArgs),
+    #[command(about = "Stash the changes in a dirty working directory away")]
+    Stash(command::stash::StashArgs),
     #[command(about = "Merge changes")]
     Merge(command::merge::MergeArgs),
+    #[command(about = "Reapply commits on top of another base tip")]
+    Rebase(command::rebase::RebaseArgs),
+    #[command(about = "Apply the changes introduced by some existing commits")]
+    CherryPick(command::cherry_pick::CherryPickArgs),
+    #[command(about = "Revert some existing commits")]
+    Revert(command::revert::RevertArgs),
     #[command(about = "Update remote refs along with associated objects")]
     Push(command::push::PushArgs),
     #[command(about = "Download objects and refs from another repository")]
@@ -56,8 +76,24 @@ enum Commands {
     #[command(about = "Fetch from and integrate with another repository or a local branch")]
     Pull(command::pull::PullArgs),
 
+    #[command(about = "Get and set repository or global options")]
+    Config(command::config::ConfigArgs),
     #[command(subcommand, about = "Manage set of tracked repositories")]
     Remote(command::remote::RemoteCmds),
+    #[command(subcommand, about = "Retrieve and store user credentials")]
+    Credential(command::credential::CredentialCmds),
+    #[command(about = "Cleanup unnecessary files and optimize the local repository")]
+    Gc(command::gc::GcArgs),
+    #[command(about = "Pack unpacked objects in a repository")]
+    Repack(command::repack::RepackArgs),
+    #[command(about = "Verifies the connectivity and validity of the objects in the database")]
+    Fsck(command::fsck::FsckArgs),
+    #[command(about = "Provide contents or details of repository objects")]
+    CatFile(command::cat_file::CatFileArgs),
+    #[command(about = "Check the signature of commits")]
+    VerifyCommit(command::verify_commit::VerifyCommitArgs),
+    #[command(about = "Check the signature of tags")]
+    VerifyTag(command::verify_tag::VerifyTagArgs),
 
     // other hidden commands
     #[command(
@@ -75,6 +111,10 @@ async fn main() {
     // TODO: try check repo before parsing
     if let Commands::Init = args.command {
     } else if let Commands::Clone(_) = args.command {
+    } else if let Commands::Config(_) = args.command {
+        // `--global` works outside of a repository
+    } else if let Commands::Credential(_) = args.command {
+        // the credentials are global
     } else if !utils::util::check_repo_exist() {
         return;
     }
@@ -95,17 +135,35 @@ async fn main() {
         Commands::Add(args) => command::add::execute(args).await,
         Commands::Rm(args) => command::remove::execute(args).unwrap(),
         Commands::Restore(args) => command::restore::execute(args).await,
-        Commands::Status => command::status::execute().await,
+        Commands::Reset(args) => command::reset::execute(args).await,
+        Commands::Status(args) => command::status::execute(args).await,
+        Commands::Diff(args) => command::diff::execute(args).await,
         Commands::Log(args) => command::log::execute(args).await,
+        Commands::Blame(args) => command::blame::execute(args).await,
+        Commands::Show(args) => command::show::execute(args).await,
         Commands::Branch(args) => command::branch::execute(args).await,
         Commands::Commit(args) => command::commit::execute(args).await,
         Commands::Switch(args) => command::switch::execute(args).await,
+        Commands::Tag(args) => command::tag::execute(args).await,
+        Commands::Reflog(args) => command::reflog::execute(args).await,
+        Commands::Stash(args) => command::stash::execute(args).await,
         Commands::Merge(args) => command::merge::execute(args).await,
+        Commands::Rebase(args) => command::rebase::execute(args).await,
+        Commands::CherryPick(args) => command::cherry_pick::execute(args).await,
+        Commands::Revert(args) => command::revert::execute(args).await,
         Commands::Push(args) => command::push::execute(args).await,
         Commands::IndexPack(args) => command::index_pack::execute(args),
         Commands::Fetch(args) => command::fetch::execute(args).await,
+        Commands::Config(args) => command::config::execute(args).await,
         Commands::Remote(cmd) => command::remote::execute(cmd).await,
+        Commands::Credential(cmd) => command::credential::execute(cmd).await,
         Commands::Pull(args) => command::pull::execute(args).await,
+        Commands::Gc(args) => command::gc::execute(args).await,
+        Commands::Repack(args) => command::repack::execute(args).await,
+        Commands::Fsck(args) => command::fsck::execute(args).await,
+        Commands::CatFile(args) => command::cat_file::execute(args).await,
+        Commands::VerifyCommit(args) => command::verify_commit::execute(args).await,
+        Commands::VerifyTag(args) => command::verify_tag::execute(args).await,
     }
 }
 
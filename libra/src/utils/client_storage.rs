@@ -4,20 +4,18 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use byteorder::{BigEndian, ReadBytesExt};
 use flate2::Compression;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 
-use mercury::internal::pack::cache_object::CacheObject;
-use mercury::internal::pack::Pack;
 use mercury::errors::GitError;
 use mercury::hash::SHA1;
 use mercury::internal::object::types::ObjectType;
-use mercury::utils::read_sha1;
 
 use crate::command;
+use crate::utils::pack_reader::{PackIndex, PackReader};
 
 #[derive(Default)]
 pub struct ClientStorage {
@@ -54,8 +52,7 @@ impl ClientStorage {
             let (obj_type, _, _) = Self::parse_header(&data);
             ObjectType::from_string(&obj_type)
         } else {
-            self.get_from_pack(obj_id).unwrap()
-                .map(|x| x.1)
+            self.find_in_packs(|packs| packs.object_type(obj_id))?
                 .ok_or(GitError::ObjectNotFound(obj_id.to_plain_str()))
         }
     }
@@ -70,16 +67,13 @@ impl ClientStorage {
 
     /// Search objects that start with `obj_id`, loose & pack
     pub fn search(&self, obj_id: &str) -> Vec<SHA1> {
-        let mut objs = self.list_objects_pack();
-        objs.extend(self.list_objects_loose());
-
-        objs.into_iter()
-            .filter(|x| x.to_plain_str().starts_with(obj_id))
-            .collect()
+        let mut objs: HashSet<SHA1> = self.packs().map(|packs| packs.search(obj_id)).unwrap_or_default().into_iter().collect();
+        objs.extend(self.list_objects_loose().into_iter().filter(|x| x.to_plain_str().starts_with(obj_id)));
+        objs.into_iter().collect()
     }
 
     /// list all objects' hash in `objects`
-    fn list_objects_loose(&self) -> Vec<SHA1> {
+    pub fn list_objects_loose(&self) -> Vec<SHA1> {
         let mut objects = Vec::new();
         let paths = fs::read_dir(&self.base_path).unwrap();
         for path in paths {
@@ -101,16 +95,8 @@ impl ClientStorage {
     }
 
     /// List all objects' hash in PACKs
-    fn list_objects_pack(&self) -> HashSet<SHA1> {
-        let idxes = self.list_all_idx();
-        let mut objs = HashSet::new();
-        for idx in idxes {
-            let res = Self::list_idx_objects(&idx).unwrap();
-            for obj in res {
-                objs.insert(obj);
-            }
-        }
-        objs
+    pub fn list_objects_pack(&self) -> HashSet<SHA1> {
+        self.packs().map(|packs| packs.list_objects()).unwrap_or_default()
     }
 }
 
@@ -153,6 +139,12 @@ impl ClientStorage {
         Ok(buffer)
     }
 
+    /// Read the loose object as it's stored (decompressed): the header `<type> <size>\0` & the content,
+    /// without any check
+    pub fn read_loose_raw(&self, obj_id: &SHA1) -> io::Result<Vec<u8>> {
+        Self::decompress_zlib(&self.read_raw_data(obj_id)?)
+    }
+
     pub fn get(&self, object_id: &SHA1) -> Result<Vec<u8>, GitError> {
         if self.exist_loosely(object_id) {
             let raw_data = self.read_raw_data(object_id)?;
@@ -162,8 +154,7 @@ impl ClientStorage {
             let (_, _, end_of_header) = Self::parse_header(&data);
             Ok(data[end_of_header + 1..].to_vec())
         } else {
-            // Ok(self.get_from_pack(object_id)?.unwrap().0)
-            self.get_from_pack(object_id).unwrap()
+            self.get_from_pack(object_id)?
                 .map(|x| x.0)
                 .ok_or(GitError::ObjectNotFound(object_id.to_plain_str()))
         }
@@ -186,154 +177,113 @@ impl ClientStorage {
     /// Check if the object with `obj_id` exists in `objects` or PACKs
     pub fn exist(&self, obj_id: &SHA1) -> bool {
         let path = self.get_obj_path(obj_id);
-        Path::exists(&path) || self.get_from_pack(obj_id).unwrap().is_some()
+        Path::exists(&path)
+            || self
+                .find_in_packs(|packs| Ok(packs.contains(obj_id).then_some(())))
+                .is_ok_and(|found| found.is_some())
     }
 
     /// Check if the object with `obj_id` exists in `objects`
-    fn exist_loosely(&self, obj_id: &SHA1) -> bool {
+    pub fn exist_loosely(&self, obj_id: &SHA1) -> bool {
         let path = self.get_obj_path(obj_id);
         Path::exists(&path)
     }
-}
 
-// TODO refactor to `PackReader`
-impl ClientStorage {
-    /// List all .pack files in `pack` directory
-    fn list_all_packs(&self) -> Vec<PathBuf> {
-        let pack_dir = self.base_path.join("pack");
-        let mut packs = Vec::new();
-        for entry in fs::read_dir(pack_dir).unwrap() {
-            let path = entry.unwrap().path();
-            if path.is_file() && path.extension().unwrap() == "pack" {
-                packs.push(path);
-            }
-        }
-        packs
-    }
-
-    /// List all .idx files in `pack` directory
-    /// - If .idx file not exists, build it
-    fn list_all_idx(&self) -> Vec<PathBuf> {
-        let packs = self.list_all_packs();
-        let mut idxs = Vec::new();
-        for pack in packs {
-            let idx = pack.with_extension("idx");
-            if !idx.exists() {
-                command::index_pack::build_index_v1(pack.to_str().unwrap(), idx.to_str().unwrap()).unwrap();
-            }
-            idxs.push(idx);
-        }
-        idxs
+    /// Last modification time of the loose object, the grace period of pruning starts from it
+    pub fn loose_mtime(&self, obj_id: &SHA1) -> io::Result<SystemTime> {
+        fs::metadata(self.get_obj_path(obj_id))?.modified()
     }
 
-    /// Get object from PACKs by hash, if not found, return None
-    fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
-        let idxes = self.list_all_idx(); // list or build
-        for idx in idxes {
-            let res = Self::read_pack_by_idx(&idx, obj_id)?;
-            if let Some(data) = res {
-                return Ok(Some((data.data_decompress.clone(), data.obj_type)));
-            }
-        }
-
-        Ok(None)
+    /// Set the modification time of the loose object, e.g. to the time of the pack it's taken from
+    pub fn set_loose_mtime(&self, obj_id: &SHA1, time: SystemTime) -> io::Result<()> {
+        fs::File::options().write(true).open(self.get_obj_path(obj_id))?.set_modified(time)
     }
 
-    fn read_idx_fanout(idx_file: &Path) -> Result<[u32; 256], io::Error> {
-        let mut idx_file = fs::File::open(idx_file)?;
-        // const FANOUT: usize = 256 * 4;
-        let mut fanout: [u32; 256] = [0; 256]; // 256 * 4 bytes
-        let mut buf = [0; 4];
-        fanout.iter_mut().for_each(|x| {
-            idx_file.read_exact(&mut buf).unwrap();
-            *x = u32::from_be_bytes(buf);
-        });
-        Ok(fanout)
+    /// Remove the loose object, and its directory if empty
+    pub fn remove_loose(&self, obj_id: &SHA1) -> io::Result<()> {
+        let path = self.get_obj_path(obj_id);
+        fs::remove_file(&path)?;
+        let dir = path.parent().unwrap();
+        if fs::read_dir(dir)?.next().is_none() {
+            fs::remove_dir(dir)?;
+        }
+        Ok(())
     }
+}
 
-    /// List all objects hash in .idx file
-    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
-
-        let mut objs = Vec::new();
-        for _ in 0..fanout[255] {
-            let _offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
+impl ClientStorage {
+    /// The reader of all packs in `pack` directory, shared by all `ClientStorage`s of the repository
+    pub fn packs(&self) -> Result<Arc<PackReader>, GitError> {
+        PackReader::shared(&self.base_path.join("pack"))
+    }
 
-            objs.push(hash);
+    /// Look up in the shared reader of the packs, rescan `pack` directory only on a miss
+    fn find_in_packs<T>(
+        &self,
+        find: impl Fn(&PackReader) -> Result<Option<T>, GitError>,
+    ) -> Result<Option<T>, GitError> {
+        if let Some(found) = find(&*self.packs()?)? {
+            return Ok(Some(found));
+        }
+        match PackReader::rescan(&self.base_path.join("pack"))? {
+            Some(packs) => find(&packs),
+            None => Ok(None),
         }
-        Ok(objs)
     }
 
-    /// Read object `offset` from .idx file by `hash`
-    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
+    /// List all .pack files in `pack` directory
+    pub fn list_all_packs(&self) -> Vec<PathBuf> {
+        self.packs().map(|packs| packs.packs()).unwrap_or_default()
+    }
 
-        let first_byte = obj_id.0[0];
-        let start = if first_byte == 0 {
-            0
-        } else {
-            fanout[first_byte as usize - 1] as usize
-        };
-        let end = fanout[first_byte as usize] as usize;
-
-        const FANOUT: usize = 256 * 4;
-        idx_file.seek(io::SeekFrom::Start((FANOUT + 24 * start) as u64))?;
-        for _ in start..end {
-            let offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            if &hash == obj_id {
-                return Ok(Some(offset as u64));
-            }
-        }
+    /// Remove the pack & its index, the shared reader is dropped first to unmap them
+    pub fn remove_pack(&self, pack: &Path) -> io::Result<()> {
+        PackReader::forget(&self.base_path.join("pack"));
+        fs::remove_file(pack.with_extension("idx"))?;
+        fs::remove_file(pack)
+    }
 
-        Ok(None)
+    /// List all objects hash in .idx file
+    pub fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, GitError> {
+        Ok(PackIndex::open(idx_file)?.hashes().collect())
     }
 
-    /// Get object from pack by .idx file
-    fn read_pack_by_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<CacheObject>, GitError> {
-        let pack_file = idx_file.with_extension("pack");
-        let res = Self::read_idx(idx_file, obj_id)?;
-        match res {
-            None => Ok(None),
-            Some(offset) => {
-                let res = Self::read_pack_obj(&pack_file, offset)?;
-                Ok(Some(res))
-            }
+    /// Save the pack to `pack` directory as `pack-<checksum>.pack` & build its index, after checking the checksum
+    /// - return `None` if the pack is empty, nothing is saved
+    pub fn save_pack(&self, pack: &[u8]) -> Result<Option<PathBuf>, GitError> {
+        if pack.len() < 32 || &pack[..4] != b"PACK" {
+            return Err(GitError::InvalidPackFile("bad pack header".to_string()));
         }
+        let checksum = SHA1::from_bytes(&pack[pack.len() - 20..]);
+        if SHA1::new(&pack[..pack.len() - 20].to_vec()) != checksum {
+            return Err(GitError::InvalidPackFile("pack checksum mismatch".to_string()));
+        }
+        if pack[8..12] == [0; 4] {
+            return Ok(None);
+        }
+        let pack_dir = self.base_path.join("pack");
+        fs::create_dir_all(&pack_dir)?;
+        let pack_file = pack_dir.join(format!("pack-{}.pack", checksum.to_plain_str()));
+        let index_file = pack_file.with_extension("idx");
+        // written under a temporary name then renamed, an interrupted save leaves no broken pack;
+        // the same name is the same content, which may be mapped by the readers, don't rewrite it
+        if !pack_file.exists() {
+            let tmp_pack = pack_dir.join(format!("tmp_pack_{}", checksum.to_plain_str()));
+            fs::write(&tmp_pack, pack)?;
+            fs::rename(&tmp_pack, &pack_file)?;
+        }
+        if !index_file.exists() {
+            let tmp_index = pack_dir.join(format!("tmp_idx_{}", checksum.to_plain_str()));
+            command::index_pack::build_index_v2(pack_file.to_str().unwrap(), tmp_index.to_str().unwrap())?;
+            fs::rename(&tmp_index, &index_file)?;
+        }
+        PackReader::forget(&pack_dir);
+        Ok(Some(pack_file))
     }
 
-    /// Read object from pack file, with offset
-    fn read_pack_obj(pack_file: &Path, offset: u64) -> Result<CacheObject, GitError> {
-        let file = fs::File::open(pack_file)?;
-        let mut pack_reader = io::BufReader::new(&file);
-        pack_reader.seek(io::SeekFrom::Start(offset))?;
-        let mut pack = Pack::new(None, None, None, false);
-        let mut offset = offset as usize;
-        let obj = pack.decode_pack_object(&mut pack_reader, &mut offset)?;
-        match obj.obj_type {
-            ObjectType::OffsetDelta => {
-                let base_offset = obj.base_offset;
-                let base_obj = Self::read_pack_obj(pack_file, base_offset as u64)?;
-                let base_obj = Arc::new(base_obj);
-                let new_obj = Pack::rebuild_delta(obj, base_obj);
-                Ok(new_obj)
-            }
-            ObjectType::HashDelta => {
-                let base_hash = obj.base_ref;
-                let idx_file = pack_file.with_extension("idx");
-                let base_offset = Self::read_idx(&idx_file, &base_hash)?.unwrap();
-
-                let base_obj = Self::read_pack_obj(pack_file, base_offset)?;
-                let base_obj = Arc::new(base_obj);
-                let new_obj = Pack::rebuild_delta(obj, base_obj);
-                Ok(new_obj)
-            },
-            _ => Ok(obj),
-        }
+    /// Get object from PACKs by hash, if not found, return None
+    fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
+        self.find_in_packs(|packs| packs.get(obj_id))
     }
 }
 
@@ -345,7 +295,9 @@ mod tests {
     use mercury::internal::object::blob::Blob;
     use mercury::internal::object::ObjectTrait;
     use mercury::internal::object::types::ObjectType;
+    use mercury::internal::pack::entry::Entry;
 
+    use crate::internal::protocol::local_client::encode_pack;
     use crate::utils::{test, util};
 
     use super::ClientStorage;
@@ -425,8 +377,23 @@ mod tests {
         println!("{:?}", String::from_utf8(decompressed_data).unwrap());
     }
 
-    #[test]
-    fn test_get_from_pack() {
-        unimplemented!();
+    #[tokio::test]
+    async fn test_get_from_pack() {
+        let source = env::temp_dir().join("libra_test_get_from_pack");
+        let _ = fs::remove_dir_all(&source);
+        let client_storage = ClientStorage::init(source.clone());
+        let blobs = [Blob::from_content("Hello, world!"), Blob::from_content("Hello, libra!")];
+        let entries = blobs.iter().map(|blob| Entry::from(blob.clone())).collect();
+        let pack = encode_pack(entries, 10).await.unwrap();
+        client_storage.save_pack(&pack).unwrap();
+
+        for blob in &blobs {
+            assert!(client_storage.exist(&blob.id));
+            assert_eq!(client_storage.get(&blob.id).unwrap(), blob.data);
+            assert_eq!(client_storage.get_object_type(&blob.id).unwrap(), ObjectType::Blob);
+        }
+        assert_eq!(client_storage.search(&blobs[1].id.to_plain_str()[..6]), vec![blobs[1].id]);
+        assert_eq!(client_storage.list_objects_pack().len(), 2);
+        fs::remove_dir_all(&source).unwrap();
     }
 }
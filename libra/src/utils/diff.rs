@@ -0,0 +1,180 @@
+//! Line based diff helpers, built on the `myers` algorithm of the `diffs` crate.
+
+use diffs::{myers, Diff};
+
+/// Split content into lines, each line keeps its trailing `\n` (if any)
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Collect the equal parts reported by the diff algorithm
+#[derive(Default)]
+struct MatchingBlocks {
+    blocks: Vec<(usize, usize, usize)>,
+}
+
+impl Diff for MatchingBlocks {
+    type Error = ();
+
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), Self::Error> {
+        // join adjacent blocks, the algorithm may report one equal part in pieces
+        if let Some(last) = self.blocks.last_mut() {
+            if last.0 + last.2 == old && last.1 + last.2 == new {
+                last.2 += len;
+                return Ok(());
+            }
+        }
+        self.blocks.push((old, new, len));
+        Ok(())
+    }
+}
+
+/// Get the matching blocks between `old` and `new` lines
+/// - each block is `(old_start, new_start, len)`, sorted by position
+pub fn matching_blocks(old: &[&[u8]], new: &[&[u8]]) -> Vec<(usize, usize, usize)> {
+    let mut collector = MatchingBlocks::default();
+    myers::diff(&mut collector, old, 0, old.len(), new, 0, new.len()).unwrap();
+    collector.blocks
+}
+
+/// One line of a hunk, the content keeps its trailing `\n` (if any)
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine<'a> {
+    Context(&'a [u8]),
+    Delete(&'a [u8]),
+    Insert(&'a [u8]),
+}
+
+/// A group of changes with surrounding context lines, like `@@ -1,3 +1,4 @@` in unified diff
+/// - `old_start` & `new_start` are 0-based line numbers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk<'a> {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLine<'a>>,
+}
+
+impl Hunk<'_> {
+    /// the hunk header of unified diff, e.g. `@@ -1,3 +1,4 @@`
+    pub fn header(&self) -> String {
+        // one line range omits the length, empty range uses the line before it
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, len),
+        };
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+/// Get the changed regions between matching blocks: `(old_start, old_end, new_start, new_end)`
+fn changed_regions(old: &[&[u8]], new: &[&[u8]]) -> Vec<(usize, usize, usize, usize)> {
+    let mut blocks = matching_blocks(old, new);
+    blocks.push((old.len(), new.len(), 0)); // sentinel
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (old_start, new_start, len) in blocks {
+        if old_start > i || new_start > j {
+            changes.push((i, old_start, j, new_start));
+        }
+        (i, j) = (old_start + len, new_start + len);
+    }
+    changes
+}
+
+/// Group changes into hunks with `context` lines around them, like `diff -U<context>`
+/// - changes closer than `2 * context` lines are in the same hunk
+pub fn hunks<'a>(old: &[&'a [u8]], new: &[&'a [u8]], context: usize) -> Vec<Hunk<'a>> {
+    let changes = changed_regions(old, new);
+    let mut groups: Vec<Vec<(usize, usize, usize, usize)>> = Vec::new();
+    for change in changes {
+        match groups.last_mut() {
+            Some(group) if change.0 - group.last().unwrap().1 <= 2 * context => group.push(change),
+            _ => groups.push(vec![change]),
+        }
+    }
+
+    let mut hunks = Vec::new();
+    for group in groups {
+        let first = group[0];
+        let last = group[group.len() - 1];
+        let before = context.min(first.0); // equal lines before the first change
+        let after = context.min(old.len() - last.1);
+        let mut lines = Vec::new();
+        lines.extend(old[first.0 - before..first.0].iter().map(|l| DiffLine::Context(l)));
+        for (k, &(old_start, old_end, new_start, new_end)) in group.iter().enumerate() {
+            lines.extend(old[old_start..old_end].iter().map(|l| DiffLine::Delete(l)));
+            lines.extend(new[new_start..new_end].iter().map(|l| DiffLine::Insert(l)));
+            let equal_end = match group.get(k + 1) {
+                Some(next) => next.0,
+                None => old_end + after,
+            };
+            lines.extend(old[old_end..equal_end].iter().map(|l| DiffLine::Context(l)));
+        }
+        let old_len = lines.iter().filter(|l| !matches!(l, DiffLine::Insert(_))).count();
+        let new_len = lines.iter().filter(|l| !matches!(l, DiffLine::Delete(_))).count();
+        hunks.push(Hunk {
+            old_start: first.0 - before,
+            old_len,
+            new_start: first.2 - before,
+            new_len,
+            lines,
+        });
+    }
+    hunks
+}
+
+/// Count the inserted & deleted lines: `(insertions, deletions)`
+pub fn line_stats(old: &[&[u8]], new: &[&[u8]]) -> (usize, usize) {
+    changed_regions(old, new)
+        .iter()
+        .fold((0, 0), |(ins, del), (o_s, o_e, n_s, n_e)| (ins + n_e - n_s, del + o_e - o_s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(split_lines(b"a\nb\nc"), vec![&b"a\n"[..], b"b\n", b"c"]);
+        assert!(split_lines(b"").is_empty());
+    }
+
+    #[test]
+    fn test_matching_blocks() {
+        let old = split_lines(b"a\nb\nc\nd\n");
+        let new = split_lines(b"a\nx\nc\nd\ne\n");
+        let blocks = matching_blocks(&old, &new);
+        assert_eq!(blocks, vec![(0, 0, 1), (2, 2, 2)]);
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old = split_lines(b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+        let new = split_lines(b"1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n");
+        let hunks = hunks(&old, &new, 1);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -2,3 +2,3 @@");
+        assert_eq!(
+            hunks[0].lines,
+            vec![
+                DiffLine::Context(b"2\n"),
+                DiffLine::Delete(b"3\n"),
+                DiffLine::Insert(b"three\n"),
+                DiffLine::Context(b"4\n"),
+            ]
+        );
+        assert_eq!(hunks[1].header(), "@@ -10 +10,2 @@");
+        assert_eq!(line_stats(&old, &new), (2, 1));
+
+        let hunks = super::hunks(&[], &new[..1], 3);
+        assert_eq!(hunks[0].header(), "@@ -0,0 +1 @@");
+    }
+}
@@ -0,0 +1,294 @@
+//! `.gitignore` matcher, see [gitignore](https://git-scm.com/docs/gitignore)
+//!
+//! Patterns are read from (low to high priority):
+//! 1. the global excludes file, `core.excludesfile` or `$XDG_CONFIG_HOME/git/ignore`
+//! 2. `.libra/info/exclude`
+//! 3. `.gitignore` of each directory, the deeper one has higher priority
+//!
+//! The last matching pattern decides the result, a negated pattern (`!`) re-includes the path.
+//! A file can't be re-included if its parent directory is excluded.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::internal::config::Config;
+use crate::utils::util;
+
+const GITIGNORE: &str = ".gitignore";
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    /// pattern contains `/` (except the trailing one), matches the path relative to `base`;
+    /// otherwise matches the file name at any depth
+    anchored: bool,
+    /// directory of the `.gitignore` (to workdir), empty for root & global patterns
+    base: PathBuf,
+}
+
+impl Pattern {
+    fn parse(line: &str, base: &Path) -> Option<Pattern> {
+        let mut line = line.trim_end_matches(['\n', '\r']).to_string();
+        // trailing spaces are ignored unless escaped with `\`
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line.pop();
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut glob = line.as_str();
+        let negated = glob.starts_with('!');
+        // `\!` & `\#` escape the leading char
+        if negated || glob.starts_with("\\!") || glob.starts_with("\\#") {
+            glob = &glob[1..];
+        }
+        let dir_only = glob.ends_with('/');
+        let glob = glob.trim_end_matches('/');
+        if glob.is_empty() {
+            return None;
+        }
+        let anchored = glob.contains('/');
+        Some(Pattern {
+            glob: glob.trim_start_matches('/').to_string(),
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_path_buf(),
+        })
+    }
+
+    /// `path`: to workdir
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = match path.strip_prefix(&self.base) {
+            Ok(rel) => rel,
+            Err(_) => return false,
+        };
+        let text = if self.anchored {
+            util::path_to_string(rel).replace('\\', "/")
+        } else {
+            match rel.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => return false,
+            }
+        };
+        wildmatch(self.glob.as_bytes(), text.as_bytes())
+    }
+}
+
+/// Match `text` with glob `pattern`, `*` & `?` don't match `/`, `**` matches across directories
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            if let Some(rest) = rest.strip_prefix(b"/") {
+                // `**/` matches zero or more directories
+                wildmatch(rest, text)
+                    || (0..text.len()).any(|i| text[i] == b'/' && wildmatch(rest, &text[i + 1..]))
+            } else {
+                (0..=text.len()).any(|i| wildmatch(rest, &text[i..]))
+            }
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if wildmatch(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && wildmatch(&pattern[1..], &text[1..]),
+        Some(b'[') => match (match_class(&pattern[1..], text.first()), text.first()) {
+            (Some((true, len)), Some(_)) => wildmatch(&pattern[1 + len..], &text[1..]),
+            (Some(_), _) => false,
+            // no closing `]`, match `[` literally
+            (None, Some(b'[')) => wildmatch(&pattern[1..], &text[1..]),
+            (None, _) => false,
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && wildmatch(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Match a char class like `[a-z]` or `[!0-9]` (`pattern` starts after `[`)
+/// - return `(matched, class length include ']')`, `None` if the class isn't closed
+fn match_class(pattern: &[u8], c: Option<&u8>) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let ch = pattern[i];
+        if ch == b']' && !first {
+            let matched = c.is_some_and(|&c| c != b'/' && matched != negated);
+            return Some((matched, i + 1));
+        }
+        first = false;
+        if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            if let Some(&c) = c {
+                matched |= ch <= c && c <= pattern[i + 2];
+            }
+            i += 3;
+        } else {
+            matched |= c == Some(&ch);
+            i += 1;
+        }
+    }
+    None
+}
+
+fn parse_file(file: &Path, base: &Path) -> Vec<Pattern> {
+    match fs::read_to_string(file) {
+        Ok(content) => content.lines().filter_map(|line| Pattern::parse(line, base)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Default global excludes file: `$XDG_CONFIG_HOME/git/ignore` or `$HOME/.config/git/ignore`
+fn default_excludes_file() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) if !config.is_empty() => Some(PathBuf::from(config).join("git/ignore")),
+        _ => util::home_dir().map(|home| home.join(".config/git/ignore")),
+    }
+}
+
+pub struct IgnoreMatcher {
+    /// patterns of the global excludes file & `info/exclude`
+    base_patterns: Vec<Pattern>,
+    /// loaded `.gitignore` of each directory (to workdir)
+    dir_patterns: HashMap<PathBuf, Vec<Pattern>>,
+}
+
+impl IgnoreMatcher {
+    /// - `excludes_file`: the global excludes file, may not exist
+    pub fn new(excludes_file: Option<PathBuf>) -> Self {
+        let mut base_patterns = Vec::new();
+        if let Some(file) = excludes_file {
+            base_patterns.extend(parse_file(&file, Path::new("")));
+        }
+        base_patterns.extend(parse_file(&util::storage_path().join("info/exclude"), Path::new("")));
+        IgnoreMatcher {
+            base_patterns,
+            dir_patterns: HashMap::new(),
+        }
+    }
+
+    /// Create the matcher with the global excludes file of config (`core.excludesfile`)
+    pub async fn load() -> Self {
+        let excludes_file = match Config::get("core", None, "excludesfile").await {
+            Some(file) => match file.strip_prefix("~/") {
+                Some(rest) => util::home_dir().map(|home| home.join(rest)),
+                None => Some(PathBuf::from(file)),
+            },
+            None => default_excludes_file(),
+        };
+        Self::new(excludes_file)
+    }
+
+    fn load_dir(&mut self, dir: &Path) {
+        if !self.dir_patterns.contains_key(dir) {
+            let file = util::workdir_to_absolute(dir).join(GITIGNORE);
+            self.dir_patterns.insert(dir.to_path_buf(), parse_file(&file, dir));
+        }
+    }
+
+    /// Match the path itself, without checking its parent directories
+    /// - used while walking the worktree, the ignored directories are skipped
+    /// - `path`: to workdir
+    pub fn is_excluded(&mut self, path: &Path, is_dir: bool) -> bool {
+        // `.gitignore` of all parent directories, from the root
+        let dirs: Vec<PathBuf> = {
+            let mut dirs: Vec<PathBuf> = path.ancestors().skip(1).map(Path::to_path_buf).collect();
+            dirs.reverse();
+            dirs
+        };
+        for dir in dirs.iter() {
+            self.load_dir(dir);
+        }
+
+        let patterns = self
+            .base_patterns
+            .iter()
+            .chain(dirs.iter().flat_map(|dir| self.dir_patterns[dir].iter()));
+        let mut excluded = false;
+        for pattern in patterns {
+            if pattern.matches(path, is_dir) {
+                excluded = !pattern.negated;
+            }
+        }
+        excluded
+    }
+
+    /// Check if the path is ignored, the path is also ignored if any of its parent directories is ignored
+    /// - `path`: to workdir
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let parents: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .collect();
+        parents.iter().rev().any(|dir| self.is_excluded(dir, true)) || self.is_excluded(path, is_dir)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch(b"*.o", b"main.o"));
+        assert!(!wildmatch(b"*.o", b"src/main.o"));
+        assert!(wildmatch(b"src/*.rs", b"src/main.rs"));
+        assert!(!wildmatch(b"src/*.rs", b"src/a/main.rs"));
+        assert!(wildmatch(b"**/foo", b"foo"));
+        assert!(wildmatch(b"**/foo", b"a/b/foo"));
+        assert!(wildmatch(b"a/**/b", b"a/b"));
+        assert!(wildmatch(b"a/**/b", b"a/x/y/b"));
+        assert!(wildmatch(b"abc/**", b"abc/x/y"));
+        assert!(!wildmatch(b"abc/**", b"abc"));
+        assert!(wildmatch(b"file?.txt", b"file1.txt"));
+        assert!(wildmatch(b"[a-c]at", b"bat"));
+        assert!(!wildmatch(b"[!a-c]at", b"bat"));
+        assert!(wildmatch(b"\\*", b"*"));
+    }
+
+    #[tokio::test]
+    async fn test_ignore_matcher() {
+        test::setup_with_new_libra().await;
+        test::ensure_file(".gitignore", Some("target/\n*.log\n!keep.log\n/root.txt\n# comment\n"));
+        test::ensure_file("sub/.gitignore", Some("*.tmp\n!important.log\n"));
+        fs::write(util::storage_path().join("info/exclude"), "secret\n").unwrap();
+
+        let mut matcher = IgnoreMatcher::new(None);
+        assert!(matcher.is_ignored(Path::new("target"), true));
+        assert!(matcher.is_ignored(Path::new("target/debug/libra"), false));
+        assert!(!matcher.is_ignored(Path::new("target"), false)); // `target/` only matches dir
+        assert!(matcher.is_ignored(Path::new("a/b.log"), false));
+        assert!(!matcher.is_ignored(Path::new("a/keep.log"), false));
+        assert!(matcher.is_ignored(Path::new("root.txt"), false));
+        assert!(!matcher.is_ignored(Path::new("sub/root.txt"), false));
+        assert!(matcher.is_ignored(Path::new("sub/a.tmp"), false));
+        assert!(!matcher.is_ignored(Path::new("a.tmp"), false));
+        assert!(!matcher.is_ignored(Path::new("sub/important.log"), false));
+        assert!(matcher.is_ignored(Path::new("sub/secret"), false));
+    }
+}
@@ -0,0 +1,329 @@
+//! Three-way merge of files and trees, used by `merge` and other commands which need to combine histories.
+//!
+//! File contents are merged line by line (diff3): the regions where `base`, `ours` and `theirs` are all
+//! equal are used as synchronization points, the regions between them are taken from the side that changed,
+//! or written with conflict markers if both sides changed them differently.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use mercury::hash::SHA1;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::utils::diff;
+use crate::utils::object_ext::BlobExt;
+
+const CONFLICT_MARKER_SIZE: usize = 7;
+
+/// Labels shown after the conflict markers, e.g. `HEAD` and the merged branch name
+#[derive(Debug, Clone)]
+pub struct MergeLabels {
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// Result of a file content merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMergeResult {
+    pub content: Vec<u8>,
+    pub conflict: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictKind {
+    /// both sides modified the file
+    Content,
+    /// both sides added the file with different content
+    AddAdd,
+    /// one side modified the file, the other side deleted it
+    ModifyDelete { deleted_by_us: bool },
+}
+
+/// Conflict information of a path, the hashes are used as stage 1/2/3 entries of the index
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub base: Option<SHA1>,
+    pub ours: Option<SHA1>,
+    pub theirs: Option<SHA1>,
+}
+
+/// Merge result of one path (to workdir)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedEntry {
+    pub path: PathBuf,
+    /// blob should be in the worktree after merge, `None` means deleted
+    /// - for a conflict, it's the content with conflict markers (or the version left in tree)
+    pub hash: Option<SHA1>,
+    /// the content is merged from both sides (`Auto-merging`)
+    pub auto_merged: bool,
+    pub conflict: Option<Conflict>,
+}
+
+/// Region of the merged content, indexes are line numbers of each side
+enum Region {
+    Unchanged(usize, usize),      // base[start..end]
+    Same(usize, usize),           // ours[start..end], both sides made the same change
+    Ours(usize, usize),           // ours[start..end]
+    Theirs(usize, usize),         // theirs[start..end]
+    Conflict(usize, usize, usize, usize), // ours[start..end], theirs[start..end]
+}
+
+/// Find regions where `base`, `ours` and `theirs` all match: `(base_start, base_end, ours_start, ours_end, theirs_start, theirs_end)`
+/// - the last one is an empty sentinel at the end of each side
+fn sync_regions(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+    let ours_blocks = diff::matching_blocks(base, ours);
+    let theirs_blocks = diff::matching_blocks(base, theirs);
+
+    let mut regions = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < ours_blocks.len() && j < theirs_blocks.len() {
+        let (o_base, o_match, o_len) = ours_blocks[i];
+        let (t_base, t_match, t_len) = theirs_blocks[j];
+        // intersection of two base ranges
+        let start = o_base.max(t_base);
+        let end = (o_base + o_len).min(t_base + t_len);
+        if start < end {
+            let ours_start = o_match + (start - o_base);
+            let theirs_start = t_match + (start - t_base);
+            let len = end - start;
+            regions.push((start, end, ours_start, ours_start + len, theirs_start, theirs_start + len));
+        }
+        if o_base + o_len < t_base + t_len {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    regions.push((base.len(), base.len(), ours.len(), ours.len(), theirs.len(), theirs.len()));
+    regions
+}
+
+fn merge_regions(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let (mut iz, mut ia, mut ib) = (0, 0, 0);
+    for (z_start, z_end, a_start, a_end, b_start, b_end) in sync_regions(base, ours, theirs) {
+        if a_start > ia || b_start > ib {
+            let ours_changed = base[iz..z_start] != ours[ia..a_start];
+            let theirs_changed = base[iz..z_start] != theirs[ib..b_start];
+            if ours[ia..a_start] == theirs[ib..b_start] {
+                regions.push(Region::Same(ia, a_start));
+            } else if ours_changed && theirs_changed {
+                regions.push(Region::Conflict(ia, a_start, ib, b_start));
+            } else if ours_changed {
+                regions.push(Region::Ours(ia, a_start));
+            } else {
+                regions.push(Region::Theirs(ib, b_start));
+            }
+        }
+        if z_end > z_start {
+            regions.push(Region::Unchanged(z_start, z_end));
+        }
+        (iz, ia, ib) = (z_end, a_end, b_end);
+    }
+    regions
+}
+
+fn extend_lines(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        content.extend_from_slice(line);
+    }
+}
+
+/// write lines for conflict, make sure the marker starts at a new line
+fn extend_conflict_lines(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    extend_lines(content, lines);
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+}
+
+pub fn is_binary(data: &[u8]) -> bool {
+    // same as Git: check NUL in the first 8000 bytes
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Three-way merge file contents
+/// - conflict regions are surrounded by conflict markers with `labels`
+/// - binary files can't be merged, `ours` is kept
+pub fn merge_file(base: &[u8], ours: &[u8], theirs: &[u8], labels: &MergeLabels) -> FileMergeResult {
+    if ours == theirs || base == theirs {
+        return FileMergeResult { content: ours.to_vec(), conflict: false };
+    }
+    if base == ours {
+        return FileMergeResult { content: theirs.to_vec(), conflict: false };
+    }
+    if is_binary(base) || is_binary(ours) || is_binary(theirs) {
+        return FileMergeResult { content: ours.to_vec(), conflict: true };
+    }
+
+    let base_lines = diff::split_lines(base);
+    let ours_lines = diff::split_lines(ours);
+    let theirs_lines = diff::split_lines(theirs);
+
+    let mut content = Vec::new();
+    let mut conflict = false;
+    for region in merge_regions(&base_lines, &ours_lines, &theirs_lines) {
+        match region {
+            Region::Unchanged(start, end) => extend_lines(&mut content, &base_lines[start..end]),
+            Region::Same(start, end) | Region::Ours(start, end) => {
+                extend_lines(&mut content, &ours_lines[start..end])
+            }
+            Region::Theirs(start, end) => extend_lines(&mut content, &theirs_lines[start..end]),
+            Region::Conflict(a_start, a_end, b_start, b_end) => {
+                let a = &ours_lines[a_start..a_end];
+                let b = &theirs_lines[b_start..b_end];
+                // move the common lines at both ends out of the conflict (like Git's `zealous` level)
+                let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+                let suffix = a[prefix..].iter().rev()
+                    .zip(b[prefix..].iter().rev())
+                    .take_while(|(x, y)| x == y)
+                    .count();
+                extend_lines(&mut content, &a[..prefix]);
+                if !content.is_empty() && !content.ends_with(b"\n") {
+                    content.push(b'\n');
+                }
+                content.extend(format!("{} {}\n", "<".repeat(CONFLICT_MARKER_SIZE), labels.ours).as_bytes());
+                extend_conflict_lines(&mut content, &a[prefix..a.len() - suffix]);
+                content.extend(format!("{}\n", "=".repeat(CONFLICT_MARKER_SIZE)).as_bytes());
+                extend_conflict_lines(&mut content, &b[prefix..b.len() - suffix]);
+                content.extend(format!("{} {}\n", ">".repeat(CONFLICT_MARKER_SIZE), labels.theirs).as_bytes());
+                extend_lines(&mut content, &a[a.len() - suffix..]);
+                conflict = true;
+            }
+        }
+    }
+    FileMergeResult { content, conflict }
+}
+
+/// load blob data, `None` is empty
+fn blob_data(hash: Option<SHA1>) -> Vec<u8> {
+    hash.map(|h| Blob::load(&h).data).unwrap_or_default()
+}
+
+/// save content as a blob object, return its hash
+fn save_blob(content: Vec<u8>) -> SHA1 {
+    let id = SHA1::from_type_and_data(ObjectType::Blob, &content);
+    Blob::from_bytes(&content, id).unwrap().save()
+}
+
+/// merge blob contents, save the merged blob
+fn merge_blobs(base: Option<SHA1>, ours: SHA1, theirs: SHA1, labels: &MergeLabels) -> (SHA1, bool) {
+    let result = merge_file(&blob_data(base), &blob_data(Some(ours)), &blob_data(Some(theirs)), labels);
+    (save_blob(result.content), result.conflict)
+}
+
+/// Three-way merge trees file by file
+/// - `base`, `ours`, `theirs`: all items (blobs) of the trees, see `TreeExt::get_plain_items`
+/// - return the result of every path in any of the trees, sorted by path
+pub fn merge_trees(
+    base: &[(PathBuf, SHA1)],
+    ours: &[(PathBuf, SHA1)],
+    theirs: &[(PathBuf, SHA1)],
+    labels: &MergeLabels,
+) -> Vec<MergedEntry> {
+    let base: HashMap<_, _> = base.iter().cloned().collect();
+    let ours: HashMap<_, _> = ours.iter().cloned().collect();
+    let theirs: HashMap<_, _> = theirs.iter().cloned().collect();
+    let paths: BTreeSet<&PathBuf> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let (b, o, t) = (base.get(path).copied(), ours.get(path).copied(), theirs.get(path).copied());
+        let mut entry = MergedEntry {
+            path: path.clone(),
+            hash: None,
+            auto_merged: false,
+            conflict: None,
+        };
+        if o == t || b == t {
+            entry.hash = o; // same change or only `ours` changed
+        } else if b == o {
+            entry.hash = t; // only `theirs` changed
+        } else {
+            match (o, t) {
+                (Some(o), Some(t)) => {
+                    let (hash, conflict) = merge_blobs(b, o, t, labels);
+                    entry.hash = Some(hash);
+                    entry.auto_merged = true;
+                    if conflict {
+                        entry.conflict = Some(Conflict {
+                            kind: if b.is_some() { ConflictKind::Content } else { ConflictKind::AddAdd },
+                            base: b,
+                            ours: Some(o),
+                            theirs: Some(t),
+                        });
+                    }
+                }
+                (o, t) => {
+                    // one side deleted and the other side modified, keep the modified version in tree
+                    entry.hash = o.or(t);
+                    entry.conflict = Some(Conflict {
+                        kind: ConflictKind::ModifyDelete { deleted_by_us: o.is_none() },
+                        base: b,
+                        ours: o,
+                        theirs: t,
+                    });
+                }
+            }
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+/// Convert merge result to tree items, conflicts are kept with markers
+/// - used to create a virtual merge base from multiple merge bases
+pub fn merged_items(entries: &[MergedEntry]) -> Vec<(PathBuf, SHA1)> {
+    entries
+        .iter()
+        .filter_map(|entry| entry.hash.map(|hash| (entry.path.clone(), hash)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labels() -> MergeLabels {
+        MergeLabels {
+            ours: "HEAD".to_string(),
+            theirs: "feature".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_file_clean() {
+        let base = b"a\nb\nc\nd\ne\n";
+        let ours = b"a\nB\nc\nd\ne\n";
+        let theirs = b"a\nb\nc\nD\ne\nf\n";
+        let result = merge_file(base, ours, theirs, &labels());
+        assert!(!result.conflict);
+        assert_eq!(result.content, b"a\nB\nc\nD\ne\nf\n");
+    }
+
+    #[test]
+    fn test_merge_file_conflict() {
+        let base = b"a\nb\nc\n";
+        let ours = b"a\nours\nc\n";
+        let theirs = b"a\ntheirs\nc\n";
+        let result = merge_file(base, ours, theirs, &labels());
+        assert!(result.conflict);
+        assert_eq!(
+            String::from_utf8(result.content).unwrap(),
+            "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_file_without_newline_at_end() {
+        let result = merge_file(b"a", b"b", b"c", &labels());
+        assert!(result.conflict);
+        assert_eq!(
+            String::from_utf8(result.content).unwrap(),
+            "<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> feature\n"
+        );
+    }
+}
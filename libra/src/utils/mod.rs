@@ -3,4 +3,10 @@ pub(crate) mod test;
 pub(crate) mod path;
 pub(crate) mod object_ext;
 pub(crate) mod path_ext;
-pub(crate) mod client_storage;
\ No newline at end of file
+pub(crate) mod client_storage;
+pub(crate) mod pack_reader;
+pub(crate) mod diff;
+pub(crate) mod ignore;
+pub(crate) mod merge;
+pub(crate) mod progress;
+pub(crate) mod revision;
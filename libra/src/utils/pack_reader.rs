@@ -0,0 +1,596 @@
+//! Read objects from the packs by their `.idx` files, both are memory-mapped.
+//!
+//! The `.idx` (version 1 written by old Libra, or version 2 by Git & Libra) maps the hash to the offset in the pack:
+//! the fanout table narrows the sorted hashes down to those with the same first byte, then a binary search finds it.
+//! Delta objects are resolved by their base, recently used bases are cached since they're shared by many deltas.
+//! See [pack-format](https://git-scm.com/docs/pack-format).
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use byteorder::{BigEndian, ByteOrder};
+use flate2::read::ZlibDecoder;
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::pack::utils;
+
+use crate::command::index_pack;
+
+/// Size of the fanout table of .idx file
+const FANOUT: usize = 256 * 4;
+/// .idx v2 starts with the magic number & version number
+const IDX_V2_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+const IDX_V2_HEADER: usize = 8;
+/// pack header: `PACK`, version & number of objects
+const PACK_HEADER: usize = 12;
+/// the memory limit of the cached delta bases, same as `core.deltaBaseCacheLimit` of Git
+const DELTA_BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+/// longer delta chains are treated as broken, e.g. a loop of ref-deltas in a corrupt pack
+const MAX_DELTA_DEPTH: usize = 10_000;
+
+/// A memory-mapped `.idx` file
+pub struct PackIndex {
+    path: PathBuf,
+    map: Mmap,
+    v2: bool,
+    count: usize,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> Result<Self, GitError> {
+        let file = fs::File::open(path)?;
+        // SAFETY: the packs & indexes are never modified once written, only removed
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = |reason: &str| GitError::InvalidIdxFile(format!("{}: {}", path.display(), reason));
+
+        let v2 = map.len() >= IDX_V2_HEADER && map[..4] == IDX_V2_MAGIC;
+        if v2 && BigEndian::read_u32(&map[4..8]) != 2 {
+            return Err(invalid("unsupported version"));
+        }
+        let fanout_start = if v2 { IDX_V2_HEADER } else { 0 };
+        if map.len() < fanout_start + FANOUT {
+            return Err(invalid("too short"));
+        }
+        let count = BigEndian::read_u32(&map[fanout_start + FANOUT - 4..]) as usize;
+        // v1: (offset, hash) entries; v2: hashes, CRC32s, offsets, and then the large offsets
+        let min_len = match v2 {
+            true => IDX_V2_HEADER + FANOUT + count * (20 + 4 + 4) + 40,
+            false => FANOUT + count * 24 + 40,
+        };
+        if map.len() < min_len {
+            return Err(invalid("truncated"));
+        }
+        Ok(PackIndex { path: path.to_path_buf(), map, v2, count })
+    }
+
+    /// Number of objects whose first byte of hash is less than or equal to `byte`
+    fn fanout(&self, byte: u8) -> usize {
+        let start = if self.v2 { IDX_V2_HEADER } else { 0 };
+        BigEndian::read_u32(&self.map[start + 4 * byte as usize..]) as usize
+    }
+
+    /// The `i`-th hash in the sorted order
+    fn hash(&self, i: usize) -> &[u8] {
+        let start = match self.v2 {
+            true => IDX_V2_HEADER + FANOUT + 20 * i,
+            false => FANOUT + 24 * i + 4,
+        };
+        &self.map[start..start + 20]
+    }
+
+    /// The offset in the pack of the `i`-th object
+    fn offset(&self, i: usize) -> Result<u64, GitError> {
+        if !self.v2 {
+            return Ok(BigEndian::read_u32(&self.map[FANOUT + 24 * i..]) as u64);
+        }
+        let offsets = IDX_V2_HEADER + FANOUT + self.count * 24;
+        let offset = BigEndian::read_u32(&self.map[offsets + 4 * i..]);
+        if offset & 0x8000_0000 == 0 {
+            return Ok(offset as u64);
+        }
+        // the MSB is set: the index into the 8-byte offsets, for packs larger than 2GB
+        let large_offsets = offsets + self.count * 4;
+        let start = large_offsets + 8 * (offset & 0x7fff_ffff) as usize;
+        // the table ends before the checksums of the pack & the index
+        match self.map[..self.map.len() - 40].get(start..start + 8) {
+            Some(large_offset) => Ok(BigEndian::read_u64(large_offset)),
+            None => Err(GitError::InvalidIdxFile(format!(
+                "{}: large offset of object {} out of bounds",
+                self.path.display(),
+                i
+            ))),
+        }
+    }
+
+    /// The range of the hashes whose first byte is `byte`
+    fn range(&self, byte: u8) -> (usize, usize) {
+        let start = if byte == 0 { 0 } else { self.fanout(byte - 1) };
+        (start, self.fanout(byte).min(self.count))
+    }
+
+    /// Find the offset of the object in the pack
+    pub fn find(&self, id: &SHA1) -> Result<Option<u64>, GitError> {
+        let (mut low, mut high) = self.range(id.0[0]);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.hash(mid).cmp(&id.0[..]) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return self.offset(mid).map(Some),
+            }
+        }
+        Ok(None)
+    }
+
+    /// All hashes in the sorted order
+    pub fn hashes(&self) -> impl Iterator<Item = SHA1> + '_ {
+        (0..self.count).map(|i| SHA1::from_bytes(self.hash(i)))
+    }
+
+    /// All (hash, offset) in the sorted order
+    pub fn entries(&self) -> impl Iterator<Item = Result<(SHA1, u64), GitError>> + '_ {
+        (0..self.count).map(|i| Ok((SHA1::from_bytes(self.hash(i)), self.offset(i)?)))
+    }
+
+    /// The hashes starting with the hex `prefix`
+    pub fn search(&self, prefix: &str) -> Vec<SHA1> {
+        let prefix = prefix.to_lowercase();
+        let range = match prefix.get(..2).and_then(|byte| u8::from_str_radix(byte, 16).ok()) {
+            Some(byte) => self.range(byte),
+            None => (0, self.count),
+        };
+        (range.0..range.1)
+            .map(|i| SHA1::from_bytes(self.hash(i)))
+            .filter(|id| id.to_plain_str().starts_with(&prefix))
+            .collect()
+    }
+}
+
+/// A memory-mapped `.pack` file & its index
+struct PackFile {
+    path: PathBuf,
+    index: PackIndex,
+    data: Mmap,
+}
+
+/// Where the data of an object starts, and how to get the whole object
+enum PackEntry {
+    Base(ObjectType),
+    /// the delta against the object at the offset in the same pack
+    OffsetDelta(u64),
+    /// the delta against the object with the hash, maybe in another pack
+    HashDelta(SHA1),
+}
+
+impl PackFile {
+    fn open(path: &Path) -> Result<Self, GitError> {
+        let idx = path.with_extension("idx");
+        if !idx.exists() {
+            index_pack::build_index_v2(path.to_str().unwrap(), idx.to_str().unwrap())?;
+        }
+        let index = PackIndex::open(&idx)?;
+        let file = fs::File::open(path)?;
+        // SAFETY: same as the index
+        let data = unsafe { Mmap::map(&file)? };
+        if data.len() < PACK_HEADER + 20 || &data[..4] != b"PACK" {
+            return Err(GitError::InvalidPackFile(format!("{}: bad pack header", path.display())));
+        }
+        Ok(PackFile { path: path.to_path_buf(), index, data })
+    }
+
+    /// Parse the header of the object at `offset`, return the entry, its size & where the compressed data starts
+    fn entry(&self, offset: u64) -> Result<(PackEntry, usize, usize), GitError> {
+        let offset = offset as usize;
+        if offset < PACK_HEADER || offset >= self.data.len() - 20 {
+            return Err(GitError::InvalidPackFile(format!("{}: bad offset {}", self.path.display(), offset)));
+        }
+        let mut reader = Cursor::new(&self.data[offset..self.data.len() - 20]);
+        let mut header_len = 0;
+        let (type_bits, size) = utils::read_type_and_varint_size(&mut reader, &mut header_len)?;
+        let entry = match ObjectType::from_u8(type_bits)? {
+            ObjectType::OffsetDelta => {
+                let (distance, _) = utils::read_offset_encoding(&mut reader)?;
+                let base = (offset as u64).checked_sub(distance).ok_or_else(|| {
+                    GitError::InvalidPackFile(format!("{}: bad delta offset at {}", self.path.display(), offset))
+                })?;
+                PackEntry::OffsetDelta(base)
+            }
+            ObjectType::HashDelta => {
+                let mut hash = [0u8; 20];
+                reader.read_exact(&mut hash)?;
+                PackEntry::HashDelta(SHA1::from_bytes(&hash))
+            }
+            obj_type => PackEntry::Base(obj_type),
+        };
+        Ok((entry, size, offset + reader.position() as usize))
+    }
+
+    /// Decompress the data of the entry, which is the object or the delta
+    fn inflate(&self, start: usize, size: usize) -> Result<Vec<u8>, GitError> {
+        let mut data = Vec::with_capacity(size);
+        ZlibDecoder::new(&self.data[start..self.data.len() - 20]).read_to_end(&mut data)?;
+        if data.len() != size {
+            return Err(GitError::InvalidPackFile(format!(
+                "{}: inflated size mismatch at {}",
+                self.path.display(),
+                start
+            )));
+        }
+        Ok(data)
+    }
+}
+
+/// Where the object is: (index of the pack, offset in the pack)
+pub type Location = (usize, u64);
+
+struct CachedBase {
+    obj_type: ObjectType,
+    data: Arc<Vec<u8>>,
+    /// the tick of the last access
+    used: u64,
+}
+
+/// LRU cache of the delta bases
+#[derive(Default)]
+struct DeltaBaseCache {
+    entries: HashMap<Location, CachedBase>,
+    size: usize,
+    /// increased by every access, the entry with the smallest one is the least recently used
+    tick: u64,
+}
+
+impl DeltaBaseCache {
+    fn get(&mut self, location: Location) -> Option<(ObjectType, Arc<Vec<u8>>)> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(&location)?;
+        entry.used = self.tick;
+        Some((entry.obj_type, entry.data.clone()))
+    }
+
+    fn put(&mut self, location: Location, obj_type: ObjectType, data: Arc<Vec<u8>>) {
+        if data.len() > DELTA_BASE_CACHE_LIMIT / 4 || self.entries.contains_key(&location) {
+            return;
+        }
+        self.tick += 1;
+        self.size += data.len();
+        self.entries.insert(location, CachedBase { obj_type, data, used: self.tick });
+        while self.size > DELTA_BASE_CACHE_LIMIT {
+            let oldest = *self.entries.iter().min_by_key(|(_, entry)| entry.used).unwrap().0;
+            let removed = self.entries.remove(&oldest).unwrap();
+            self.size -= removed.data.len();
+        }
+    }
+}
+
+/// The shared readers by `pack` directory
+type Readers = Mutex<HashMap<PathBuf, Arc<PackReader>>>;
+static READERS: OnceLock<Readers> = OnceLock::new();
+
+/// All packs in a `pack` directory
+pub struct PackReader {
+    packs: Vec<PackFile>,
+    cache: Mutex<DeltaBaseCache>,
+}
+
+impl PackReader {
+    /// Open all packs in `pack_dir`, build the missing `.idx` files
+    pub fn open(pack_dir: &Path) -> Result<Self, GitError> {
+        let mut packs = Vec::new();
+        for path in list_packs(pack_dir) {
+            packs.push(PackFile::open(&path)?);
+        }
+        Ok(PackReader {
+            packs,
+            cache: Mutex::new(DeltaBaseCache::default()),
+        })
+    }
+
+    /// The reader of `pack_dir` shared by all callers, opened at the first use;
+    /// it's dropped by [`forget`](Self::forget) when a pack is saved or removed
+    pub fn shared(pack_dir: &Path) -> Result<Arc<Self>, GitError> {
+        let mut readers = READERS.get_or_init(Default::default).lock().unwrap();
+        if let Some(reader) = readers.get(pack_dir) {
+            return Ok(reader.clone());
+        }
+        let reader = Arc::new(Self::open(pack_dir)?);
+        readers.insert(pack_dir.to_path_buf(), reader.clone());
+        Ok(reader)
+    }
+
+    /// Reopen the shared reader of `pack_dir` if its packs have changed, e.g. by another process;
+    /// `None` if they haven't. Called when an object is missing from the shared reader.
+    pub fn rescan(pack_dir: &Path) -> Result<Option<Arc<Self>>, GitError> {
+        let mut readers = READERS.get_or_init(Default::default).lock().unwrap();
+        let packs = list_packs(pack_dir);
+        if let Some(reader) = readers.get(pack_dir) {
+            if reader.packs.iter().map(|p| &p.path).eq(packs.iter()) {
+                return Ok(None);
+            }
+        }
+        let reader = Arc::new(Self::open(pack_dir)?);
+        readers.insert(pack_dir.to_path_buf(), reader.clone());
+        Ok(Some(reader))
+    }
+
+    /// Drop the shared reader of `pack_dir`, unmapping its packs once the other users are done,
+    /// the mapped files can't be removed on Windows
+    pub fn forget(pack_dir: &Path) {
+        if let Some(readers) = READERS.get() {
+            readers.lock().unwrap().remove(pack_dir);
+        }
+    }
+
+    /// Paths of the `.pack` files
+    pub fn packs(&self) -> Vec<PathBuf> {
+        self.packs.iter().map(|pack| pack.path.clone()).collect()
+    }
+
+    fn locate(&self, id: &SHA1) -> Result<Option<Location>, GitError> {
+        for (i, pack) in self.packs.iter().enumerate() {
+            if let Some(offset) = pack.index.find(id)? {
+                return Ok(Some((i, offset)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn contains(&self, id: &SHA1) -> bool {
+        matches!(self.locate(id), Ok(Some(_)))
+    }
+
+    /// All objects in the packs
+    pub fn list_objects(&self) -> HashSet<SHA1> {
+        self.packs.iter().flat_map(|pack| pack.index.hashes()).collect()
+    }
+
+    /// The objects whose hash starts with the hex `prefix`
+    pub fn search(&self, prefix: &str) -> Vec<SHA1> {
+        let found: HashSet<_> = self.packs.iter().flat_map(|pack| pack.index.search(prefix)).collect();
+        found.into_iter().collect()
+    }
+
+    /// Every object in every pack, with where it is; an object may be in multiple packs
+    pub fn objects(&self) -> Result<Vec<(Location, SHA1)>, GitError> {
+        let mut objects = Vec::new();
+        for (i, pack) in self.packs.iter().enumerate() {
+            for entry in pack.index.entries() {
+                let (id, offset) = entry?;
+                objects.push(((i, offset), id));
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Check the checksums of the packs & their indexes, return the problems
+    pub fn verify_checksums(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for pack in &self.packs {
+            let (data, idx) = (&pack.data[..], &pack.index.map[..]);
+            let trailer = &data[data.len() - 20..];
+            if Sha1::digest(&data[..data.len() - 20])[..] != *trailer {
+                problems.push(format!("{}: pack checksum mismatch", pack.path.display()));
+            }
+            if idx[idx.len() - 40..idx.len() - 20] != *trailer {
+                problems.push(format!("{}: index does not match the pack", pack.path.display()));
+            }
+            if Sha1::digest(&idx[..idx.len() - 20])[..] != idx[idx.len() - 20..] {
+                problems.push(format!("{}: index checksum mismatch", pack.path.with_extension("idx").display()));
+            }
+        }
+        problems
+    }
+
+    /// Type of the object, without decompressing it (deltas have the type of the base)
+    pub fn object_type(&self, id: &SHA1) -> Result<Option<ObjectType>, GitError> {
+        match self.locate(id)? {
+            Some(location) => Ok(Some(self.type_at(location)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Type of the object at the location
+    pub fn type_at(&self, mut location: Location) -> Result<ObjectType, GitError> {
+        for _ in 0..MAX_DELTA_DEPTH {
+            let (entry, _, _) = self.packs[location.0].entry(location.1)?;
+            location = match entry {
+                PackEntry::Base(obj_type) => return Ok(obj_type),
+                PackEntry::OffsetDelta(base) => (location.0, base),
+                PackEntry::HashDelta(base) => self.locate(&base)?.ok_or(GitError::ObjectNotFound(base.to_plain_str()))?,
+            };
+        }
+        Err(GitError::DeltaObjectError("delta chain too long".to_string()))
+    }
+
+    /// Read the object, `None` if it's not in the packs
+    pub fn get(&self, id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
+        match self.locate(id)? {
+            Some(location) => Ok(Some(self.read_at(location)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the object at the location
+    pub fn read_at(&self, location: Location) -> Result<(Vec<u8>, ObjectType), GitError> {
+        let (obj_type, data) = self.read(location)?;
+        Ok((Arc::try_unwrap(data).unwrap_or_else(|data| (*data).clone()), obj_type))
+    }
+
+    /// Read the object at the location, resolve the delta chain down to the base or a cached one
+    fn read(&self, location: Location) -> Result<(ObjectType, Arc<Vec<u8>>), GitError> {
+        let mut deltas = Vec::new();
+        let mut current = location;
+        let (obj_type, mut data) = loop {
+            if let Some(cached) = self.cache.lock().unwrap().get(current) {
+                break cached;
+            }
+            let pack = &self.packs[current.0];
+            let (entry, size, start) = pack.entry(current.1)?;
+            let next = match entry {
+                PackEntry::Base(obj_type) => break (obj_type, Arc::new(pack.inflate(start, size)?)),
+                PackEntry::OffsetDelta(base) => (current.0, base),
+                PackEntry::HashDelta(base) => self.locate(&base)?.ok_or(GitError::ObjectNotFound(base.to_plain_str()))?,
+            };
+            deltas.push((current, pack.inflate(start, size)?));
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(GitError::DeltaObjectError("delta chain too long".to_string()));
+            }
+            current = next;
+        };
+        // every object in the chain except the requested one is a delta base
+        let mut base_location = current;
+        for (location, delta) in deltas.into_iter().rev() {
+            self.cache.lock().unwrap().put(base_location, obj_type, data.clone());
+            data = Arc::new(apply_delta(&data, &delta)?);
+            base_location = location;
+        }
+        Ok((obj_type, data))
+    }
+}
+
+/// List the `.pack` files in the directory, sorted
+fn list_packs(pack_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(pack_dir) else {
+        return Vec::new();
+    };
+    let mut packs: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "pack"))
+        .collect();
+    packs.sort();
+    packs
+}
+
+/// Rebuild the object from the base & the delta: the sizes, then the `copy` & `insert` instructions
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, GitError> {
+    let invalid = |reason: &str| GitError::DeltaObjectError(reason.to_string());
+    let mut reader = Cursor::new(delta);
+    let (base_size, _) = utils::read_varint_le(&mut reader)?;
+    let (result_size, _) = utils::read_varint_le(&mut reader)?;
+    if base_size as usize != base.len() {
+        return Err(invalid("base size mismatch"));
+    }
+    let mut result = Vec::with_capacity(result_size as usize);
+    let mut pos = reader.position() as usize;
+    while pos < delta.len() {
+        let instruction = delta[pos];
+        pos += 1;
+        if instruction & 0x80 == 0 {
+            // insert the following bytes
+            let len = instruction as usize;
+            if len == 0 || pos + len > delta.len() {
+                return Err(invalid("bad insert instruction"));
+            }
+            result.extend_from_slice(&delta[pos..pos + len]);
+            pos += len;
+            continue;
+        }
+        // copy from the base: the bits tell which bytes of the offset (4) & size (3) are present
+        let mut fields = [0usize; 7];
+        for (bit, field) in fields.iter_mut().enumerate() {
+            if instruction & (1 << bit) != 0 {
+                *field = *delta.get(pos).ok_or_else(|| invalid("truncated copy instruction"))? as usize;
+                pos += 1;
+            }
+        }
+        let offset = fields[0] | fields[1] << 8 | fields[2] << 16 | fields[3] << 24;
+        let size = match fields[4] | fields[5] << 8 | fields[6] << 16 {
+            0 => 0x10000,
+            size => size,
+        };
+        let copy = base.get(offset..offset + size).ok_or_else(|| invalid("copy out of the base"))?;
+        result.extend_from_slice(copy);
+    }
+    if result.len() as u64 != result_size {
+        return Err(invalid("result size mismatch"));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::pack::entry::Entry;
+
+    use super::*;
+    use crate::internal::protocol::local_client::encode_pack;
+    use crate::utils::client_storage::ClientStorage;
+
+    #[tokio::test]
+    async fn test_pack_reader() {
+        let objects = std::env::temp_dir().join("libra_test_pack_reader");
+        let _ = fs::remove_dir_all(&objects);
+        let storage = ClientStorage::init(objects.clone());
+
+        // similar blobs, so that most of them are deltas
+        let base: String = (0..20).map(|i| format!("line {}\n", i)).collect();
+        let blobs: Vec<_> = (0..8).map(|i| Blob::from_content(&format!("{}{}\n", base, i))).collect();
+        let entries = blobs.iter().map(|blob| Entry::from(blob.clone())).collect();
+        let pack = encode_pack(entries, 3).await.unwrap();
+        let pack_file = storage.save_pack(&pack).unwrap().unwrap();
+
+        let reader = PackReader::shared(&objects.join("pack")).unwrap();
+        assert_eq!(reader.list_objects().len(), blobs.len());
+        let pack = &reader.packs[0];
+        let deltas = pack.index.hashes().filter(|id| {
+            let offset = pack.index.find(id).unwrap().unwrap();
+            matches!(pack.entry(offset).unwrap().0, PackEntry::OffsetDelta(_))
+        });
+        assert!(deltas.count() > 0);
+        for blob in &blobs {
+            assert_eq!(reader.get(&blob.id).unwrap(), Some((blob.data.clone(), ObjectType::Blob)));
+            assert_eq!(reader.object_type(&blob.id).unwrap(), Some(ObjectType::Blob));
+            assert_eq!(reader.search(&blob.id.to_plain_str()[..7]), vec![blob.id]);
+        }
+        assert_eq!(reader.get(&Blob::from_content("missing").id).unwrap(), None);
+
+        // the index of version 1 finds the same offsets
+        let v1 = objects.join("v1.idx");
+        index_pack::build_index_v1(pack_file.to_str().unwrap(), v1.to_str().unwrap()).unwrap();
+        let v1 = PackIndex::open(&v1).unwrap();
+        assert!(!v1.v2 && pack.index.v2);
+        for blob in &blobs {
+            assert_eq!(v1.find(&blob.id).unwrap(), pack.index.find(&blob.id).unwrap());
+        }
+
+        // a large offset beyond the table is an error, not a panic
+        let mut idx = pack.index.map.to_vec();
+        let offsets = IDX_V2_HEADER + FANOUT + blobs.len() * 24;
+        idx[offsets..offsets + 4].copy_from_slice(&0x8000_0005u32.to_be_bytes());
+        let corrupt = objects.join("corrupt.idx");
+        fs::write(&corrupt, idx).unwrap();
+        let corrupt = PackIndex::open(&corrupt).unwrap();
+        let first = corrupt.hashes().next().unwrap();
+        assert!(matches!(corrupt.find(&first), Err(GitError::InvalidIdxFile(_))));
+
+        // a pack added by another process is found by rescanning on the miss
+        let blob = Blob::from_content("from another process");
+        let other = encode_pack(vec![Entry::from(blob.clone())], 0).await.unwrap();
+        let other_file = objects.join("pack").join("pack-other.pack");
+        fs::write(&other_file, other).unwrap();
+        let other_index = other_file.with_extension("idx");
+        index_pack::build_index_v2(other_file.to_str().unwrap(), other_index.to_str().unwrap()).unwrap();
+        assert_eq!(PackReader::shared(&objects.join("pack")).unwrap().packs().len(), 1);
+        assert!(storage.exist(&blob.id));
+        assert_eq!(PackReader::shared(&objects.join("pack")).unwrap().packs().len(), 2);
+        assert!(PackReader::rescan(&objects.join("pack")).unwrap().is_none());
+        PackReader::forget(&objects.join("pack"));
+        fs::remove_dir_all(&objects).unwrap();
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello, world";
+        // sizes 12 & 12, copy 7 bytes at 0, insert "libra"
+        let delta = [12, 12, 0x80 | 0x01 | 0x10, 0, 7, 5, b'l', b'i', b'b', b'r', b'a'];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello, libra");
+        assert!(apply_delta(b"short", &delta).is_err());
+        assert!(apply_delta(base, &[12, 12, 0x80 | 0x01 | 0x10, 8, 7]).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::env;
 use std::path::PathBuf;
 use crate::utils::util;
 
@@ -10,4 +11,47 @@ pub fn objects() -> PathBuf {
 
 pub fn database() -> PathBuf {
     util::storage_path().join(util::DATABASE)
-}
\ No newline at end of file
+}
+
+/// The per-user database of `--global` config: `$LIBRA_CONFIG_GLOBAL_DB`,
+/// or `$XDG_CONFIG_HOME/libra/config.db` (`$HOME/.config/libra/config.db`)
+pub fn global_database() -> Option<PathBuf> {
+    if let Some(db) = env::var_os("LIBRA_CONFIG_GLOBAL_DB") {
+        return Some(PathBuf::from(db));
+    }
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) if !config.is_empty() => PathBuf::from(config),
+        _ => util::home_dir()?.join(".config"),
+    };
+    Some(config_home.join("libra").join("config.db"))
+}
+
+/// `MERGE_HEAD`: the commit being merged, exists while a merge is in progress
+pub fn merge_head() -> PathBuf {
+    util::storage_path().join("MERGE_HEAD")
+}
+
+/// `MERGE_MSG`: the message of the merge commit
+pub fn merge_msg() -> PathBuf {
+    util::storage_path().join("MERGE_MSG")
+}
+
+/// `rebase-merge`: the state of the rebase in progress, the dir exists until the rebase is finished or aborted
+pub fn rebase_merge() -> PathBuf {
+    util::storage_path().join("rebase-merge")
+}
+
+/// `CHERRY_PICK_HEAD`: the commit being cherry-picked, exists while stopped by conflicts
+pub fn cherry_pick_head() -> PathBuf {
+    util::storage_path().join("CHERRY_PICK_HEAD")
+}
+
+/// `REVERT_HEAD`: the commit being reverted, exists while stopped by conflicts
+pub fn revert_head() -> PathBuf {
+    util::storage_path().join("REVERT_HEAD")
+}
+
+/// `sequencer`: the state of the cherry-pick or revert in progress
+pub fn sequencer() -> PathBuf {
+    util::storage_path().join("sequencer")
+}
@@ -0,0 +1,141 @@
+//! Progress of long operations on stderr like Git, e.g. `Receiving objects:  45% (450/1000), 1.20 MiB | 2.00 MiB/s`.
+//! The line is redrawn in place, so it's only shown if stderr is a terminal.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// the minimal interval between two redraws
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A progress line, can be updated from multiple threads (e.g. the callback of `Pack::decode`)
+pub struct Progress {
+    title: String,
+    /// the percentage is shown if the total is known
+    total: Option<usize>,
+    count: AtomicUsize,
+    /// the throughput is shown if any bytes are counted
+    bytes: AtomicUsize,
+    start: Instant,
+    last_draw: Mutex<Option<Instant>>,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(title: &str, total: Option<usize>) -> Self {
+        Progress {
+            title: title.to_string(),
+            total,
+            count: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            start: Instant::now(),
+            last_draw: Mutex::new(None),
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Count `n` more items
+    pub fn inc(&self, n: usize) {
+        self.count.fetch_add(n, Ordering::Relaxed);
+        self.draw(false);
+    }
+
+    /// Count `n` more bytes transferred
+    pub fn add_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n, Ordering::Relaxed);
+        self.draw(false);
+    }
+
+    /// Draw the final line with `, done.`
+    pub fn finish(&self) {
+        self.draw(true);
+    }
+
+    fn draw(&self, done: bool) {
+        if !self.enabled {
+            return;
+        }
+        {
+            let mut last_draw = self.last_draw.lock().unwrap();
+            let now = Instant::now();
+            if !done && last_draw.is_some_and(|last| now - last < REDRAW_INTERVAL) {
+                return;
+            }
+            *last_draw = Some(now);
+        }
+        let line = self.line();
+        let mut stderr = io::stderr().lock();
+        let _ = match done {
+            true => writeln!(stderr, "{}, done.", line),
+            false => write!(stderr, "{}\r", line),
+        };
+        let _ = stderr.flush();
+    }
+
+    fn line(&self) -> String {
+        let count = self.count.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let mut parts = Vec::new();
+        match self.total {
+            Some(total) => {
+                let percent = (count * 100).checked_div(total).unwrap_or(100);
+                parts.push(format!("{:3}% ({}/{})", percent, count, total));
+            }
+            None if count > 0 || bytes == 0 => parts.push(count.to_string()),
+            None => {}
+        }
+        if bytes > 0 {
+            let seconds = self.start.elapsed().as_secs_f64().max(0.001);
+            parts.push(format!(
+                "{} | {}/s",
+                human_size(bytes as f64),
+                human_size(bytes as f64 / seconds)
+            ));
+        }
+        format!("{}: {}", self.title, parts.join(", "))
+    }
+}
+
+/// `1234567` -> `1.18 MiB`
+fn human_size(bytes: f64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024.0 {
+        return format!("{} bytes", bytes as usize);
+    }
+    let mut size = bytes / 1024.0;
+    for unit in &UNITS[..UNITS.len() - 1] {
+        if size < 1024.0 {
+            return format!("{:.2} {}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.2} {}", size, UNITS[UNITS.len() - 1])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress_line() {
+        let progress = Progress::new("Compressing objects", Some(8));
+        progress.inc(2);
+        assert_eq!(progress.line(), "Compressing objects:  25% (2/8)");
+
+        let progress = Progress::new("Counting objects", None);
+        progress.inc(3);
+        assert_eq!(progress.line(), "Counting objects: 3");
+
+        let progress = Progress::new("Receiving objects", None);
+        progress.add_bytes(2048);
+        assert!(progress.line().starts_with("Receiving objects: 2.00 KiB | "));
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(100.0), "100 bytes");
+        assert_eq!(human_size(1536.0), "1.50 KiB");
+        assert_eq!(human_size(3.0 * 1024.0 * 1024.0 * 1024.0), "3.00 GiB");
+    }
+}
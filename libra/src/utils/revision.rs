@@ -0,0 +1,307 @@
+//! Revision parsing like `git rev-parse`: names of objects and the suffixes to navigate from them.
+//!
+//! - `HEAD` (or `@`), tags, local & remote branches, full or abbreviated hashes, reflog selectors `<ref>@{n}`
+//! - `<rev>~<n>`: the n-th generation ancestor, following the first parents
+//! - `<rev>^<n>`: the n-th parent, `^0` is the commit itself
+//! - `<rev>^{}`, `<rev>^{<type>}`: peel the tags (and the commit to its tree)
+//! - `<rev>:<path>`: the object at the path in the tree of the revision, `:<path>` in the index
+
+use std::path::{Component, Path};
+
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::internal::branch::Branch;
+use crate::internal::head::Head;
+use crate::internal::tag::Tag;
+use crate::utils::{path, util};
+
+/// Resolve the revision to an object id, of any type
+pub async fn resolve(rev: &str) -> Result<SHA1, String> {
+    let unknown = || {
+        format!(
+            "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
+            rev
+        )
+    };
+    if let Some((base, file)) = split_path(rev) {
+        let file = file.trim_start_matches("./");
+        if base.is_empty() {
+            let index = Index::load(path::index()).map_err(|e| format!("fatal: {}", e))?;
+            return index
+                .get_hash(file, 0)
+                .ok_or_else(|| format!("fatal: path '{}' does not exist in the index", file));
+        }
+        let tree = peel(Box::pin(resolve(base)).await?, ObjectType::Tree)?;
+        if file.is_empty() {
+            return Ok(tree);
+        }
+        return lookup_path(&tree, Path::new(file))
+            .map(|(_, id)| id)
+            .ok_or_else(|| format!("fatal: path '{}' does not exist in '{}'", file, base));
+    }
+
+    let end = suffix_start(rev);
+    let mut id = resolve_name(&rev[..end]).await?.ok_or_else(unknown)?;
+    let mut suffixes = &rev[end..];
+    while !suffixes.is_empty() {
+        let navigation = match suffixes.as_bytes()[0] {
+            operator @ (b'~' | b'^') => operator,
+            _ => return Err(unknown()), // e.g. `HEAD~1x`
+        };
+        suffixes = &suffixes[1..];
+        if navigation == b'^' && suffixes.starts_with('{') {
+            let close = suffixes.find('}').ok_or_else(unknown)?;
+            id = match &suffixes[1..close] {
+                "" => peel_tags(id)?,
+                obj_type => peel(
+                    id,
+                    ObjectType::from_string(obj_type).map_err(|_| unknown())?,
+                )?,
+            };
+            suffixes = &suffixes[close + 1..];
+            continue;
+        }
+        let digits = suffixes.bytes().take_while(u8::is_ascii_digit).count();
+        let n = match digits {
+            0 => 1,
+            _ => suffixes[..digits].parse::<usize>().map_err(|_| unknown())?,
+        };
+        suffixes = &suffixes[digits..];
+        let commit = peel(id, ObjectType::Commit).map_err(|_| unknown())?;
+        id = match navigation {
+            b'~' => {
+                let mut commit = commit;
+                for _ in 0..n {
+                    commit = *load_commit(&commit)?
+                        .parent_commit_ids
+                        .first()
+                        .ok_or_else(unknown)?;
+                }
+                commit
+            }
+            _ if n == 0 => commit,
+            _ => *load_commit(&commit)?
+                .parent_commit_ids
+                .get(n - 1)
+                .ok_or_else(unknown)?,
+        };
+    }
+    Ok(id)
+}
+
+/// Resolve the revision to a commit, peeling the tags
+pub async fn resolve_commit(rev: &str) -> Result<SHA1, String> {
+    let id = resolve(rev).await?;
+    peel(id, ObjectType::Commit).map_err(|_| format!("fatal: '{}' is not a commit", rev))
+}
+
+/// Split `<rev>:<path>`; the `:` in a reflog selector like `@{2.days.ago}` doesn't count
+fn split_path(rev: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some((&rev[..i], &rev[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where the `~` & `^` suffixes start
+fn suffix_start(rev: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '~' | '^' if depth == 0 => return i,
+            _ => {}
+        }
+    }
+    rev.len()
+}
+
+/// Resolve a name without suffixes, `None` if not found.
+/// The order is like Git: `HEAD`, a full hash, tags, branches, then abbreviated hashes
+async fn resolve_name(name: &str) -> Result<Option<SHA1>, String> {
+    if name.contains("@{") {
+        return util::get_commit_base(name).await.map(Some);
+    }
+    if name == "HEAD" || name == "@" {
+        return Head::current_commit()
+            .await
+            .map(Some)
+            .ok_or_else(|| "fatal: HEAD does not point to a commit".to_string());
+    }
+    let storage = util::objects_storage();
+    let is_hex = !name.is_empty() && name.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && name.len() == 40 {
+        return Ok(Some(
+            name.parse()
+                .map_err(|_| format!("fatal: invalid object name {}", name))?,
+        ));
+    }
+    let name = name.strip_prefix("refs/").unwrap_or(name);
+    let tag_name = name.strip_prefix("tags/").unwrap_or(name);
+    if let Some(tag) = Tag::find_tag(tag_name).await {
+        return Ok(Some(tag.object));
+    }
+    let branch_name = name
+        .strip_prefix("heads/")
+        .or_else(|| name.strip_prefix("remotes/"))
+        .unwrap_or(name);
+    let branches = Branch::search_branch(branch_name).await;
+    match branches.len() {
+        0 => {}
+        1 => return Ok(Some(branches[0].commit)),
+        _ => return Err(format!("fatal: ambiguous branch name '{}'", name)),
+    }
+    if is_hex && name.len() >= 4 {
+        let found = storage.search(name);
+        match found.len() {
+            0 => {}
+            1 => return Ok(Some(found[0])),
+            _ => return Err(format!("error: short object ID {} is ambiguous", name)),
+        }
+    }
+    Ok(None)
+}
+
+fn load_commit(id: &SHA1) -> Result<Commit, String> {
+    let data = util::objects_storage()
+        .get(id)
+        .map_err(|e| format!("fatal: {}", e))?;
+    Commit::from_bytes(&data, *id).map_err(|e| format!("fatal: {}", e))
+}
+
+/// Follow the tags to the object that is not a tag
+fn peel_tags(mut id: SHA1) -> Result<SHA1, String> {
+    let storage = util::objects_storage();
+    while storage
+        .get_object_type(&id)
+        .map_err(|e| format!("fatal: {}", e))?
+        == ObjectType::Tag
+    {
+        let data = storage.get(&id).map_err(|e| format!("fatal: {}", e))?;
+        id = TagObject::from_bytes(&data, id)
+            .map_err(|e| format!("fatal: {}", e))?
+            .object_hash;
+    }
+    Ok(id)
+}
+
+/// Peel the object to the type: the tags to their objects & a commit to its tree
+pub fn peel(id: SHA1, target: ObjectType) -> Result<SHA1, String> {
+    let id = peel_tags(id)?;
+    let obj_type = util::objects_storage()
+        .get_object_type(&id)
+        .map_err(|e| format!("fatal: {}", e))?;
+    match (obj_type, target) {
+        (obj_type, target) if obj_type == target => Ok(id),
+        (ObjectType::Commit, ObjectType::Tree) => Ok(load_commit(&id)?.tree_id),
+        _ => Err(format!(
+            "error: {} is a {}, not a {}",
+            id.to_plain_str(),
+            obj_type,
+            target
+        )),
+    }
+}
+
+/// Find the entry of `path` in the tree by walking the path components
+pub fn lookup_path(tree: &SHA1, path: &Path) -> Option<(TreeItemMode, SHA1)> {
+    let storage = util::objects_storage();
+    let mut found = (TreeItemMode::Tree, *tree);
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            return None;
+        };
+        if found.0 != TreeItemMode::Tree {
+            return None;
+        }
+        let tree = Tree::from_bytes(&storage.get(&found.1).ok()?, found.1).ok()?;
+        let item = tree
+            .tree_items
+            .into_iter()
+            .find(|item| *item.name == *name.to_string_lossy())?;
+        found = (item.mode, item.id);
+    }
+    Some(found)
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use mercury::internal::object::blob::Blob;
+
+    use super::*;
+    use crate::command::tag::{self, TagArgs};
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_resolve() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "1\n", "1").await;
+        let second = test::commit_file("a.txt", "2\n", "2").await;
+        let third = test::commit_file("a.txt", "3\n", "3").await;
+        tag::execute(TagArgs::parse_from([
+            "tag", "-m", "release", "v1", "HEAD~1",
+        ]))
+        .await;
+
+        for (rev, expected) in [
+            ("HEAD", third),
+            ("@", third),
+            ("master", third),
+            ("refs/heads/master", third),
+            (&third.to_plain_str()[..8], third),
+            ("HEAD~2", first),
+            ("HEAD~", second),
+            ("HEAD^", second),
+            ("HEAD^0", third),
+            ("master~1^1", first),
+            ("v1^{}", second),
+            ("tags/v1^{commit}", second),
+            ("v1~1", first),
+        ] {
+            assert_eq!(resolve(rev).await.unwrap(), expected, "{}", rev);
+        }
+        let tag_object = resolve("v1").await.unwrap();
+        assert_ne!(tag_object, second);
+        assert_eq!(resolve_commit("v1").await.unwrap(), second);
+
+        let first_tree = load_commit(&first).unwrap().tree_id;
+        assert_eq!(resolve("HEAD~2^{tree}").await.unwrap(), first_tree);
+        assert_eq!(resolve("HEAD~2:").await.unwrap(), first_tree);
+        assert_eq!(
+            resolve("HEAD~2:a.txt").await.unwrap(),
+            Blob::from_content("1\n").id
+        );
+        assert_eq!(
+            resolve(":a.txt").await.unwrap(),
+            Blob::from_content("3\n").id
+        );
+
+        for rev in [
+            "HEAD~3",
+            "HEAD^2",
+            "nothing",
+            "HEAD:b.txt",
+            ":b.txt",
+            "HEAD^{blob}",
+            "HEAD~1x",
+            "HEAD~é",
+        ] {
+            assert!(resolve(rev).await.is_err(), "{}", rev);
+        }
+        assert!(resolve_commit("HEAD:a.txt").await.is_err());
+    }
+}
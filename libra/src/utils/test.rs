@@ -8,6 +8,14 @@ use std::io::Write;
 use std::path::Path;
 use std::{env, fs, path::PathBuf};
 
+use clap::Parser;
+use mercury::hash::SHA1;
+
+use crate::command::add::AddArgs;
+use crate::command::commit::CommitArgs;
+use crate::internal::config::Config;
+use crate::internal::db;
+use crate::internal::head::Head;
 use crate::utils::util;
 use crate::command;
 
@@ -49,6 +57,7 @@ fn find_cargo_dir() -> PathBuf {
 /// 3. Appends the test directory to the Cargo directory.
 /// 4. If the test directory does not exist, it creates it.
 /// 5. Sets the current directory to the test directory.
+/// 6. Points the global config database to a temporary file.
 fn setup_env() {
     // Install the color_backtrace crate to provide colored backtraces
     color_backtrace::install();
@@ -66,33 +75,39 @@ fn setup_env() {
 
     // Set the current directory to the test directory
     env::set_current_dir(&path).unwrap();
+
+    // Point the global config to a temporary file, keep the one of the user untouched
+    env::set_var("LIBRA_CONFIG_GLOBAL_DB", env::temp_dir().join("libra_test_global_config.db"));
 }
 
 /// Sets up a clean environment for testing.
 ///
 /// This function first calls `setup_env()` to switch the current directory to the test directory.
-/// Then, it checks if the Libra root directory (`.libra`) exists in the current directory.
-/// If it does, the function removes the entire `.libra` directory.
+/// Then, it removes everything in the test directory: the Libra root directory (`.libra`)
+/// and the files left in the work tree by the previous tests, e.g. `.gitignore`.
 pub fn setup_clean_testing_env() {
     // Switch the current directory to the test directory
     setup_env();
 
-    // Get the current directory
-    let mut path = util::cur_dir();
-
-    // Append the Libra root directory to the current directory
-    path.push(util::ROOT_DIR);
-
-    // If the Libra root directory exists, remove it
-    if path.exists() {
-        fs::remove_dir_all(&path).unwrap();
+    // Remove the repo & the work tree of the previous test
+    for entry in fs::read_dir(util::cur_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path).unwrap();
+        } else {
+            fs::remove_file(&path).unwrap();
+        }
     }
+    // the connection to the removed database can't be used anymore
+    db::reset_connection();
 }
 
-/// switch to test dir and create a new .libra
+/// switch to test dir and create a new .libra, with the user identity in the local config
 pub async fn setup_with_new_libra() {
     setup_clean_testing_env();
     command::init::init().await.unwrap();
+    Config::insert("user", None, "name", "Libra Tester").await;
+    Config::insert("user", None, "email", "tester@libra.dev").await;
 }
 
 pub fn init_debug_logger() {
@@ -127,3 +142,31 @@ pub fn ensure_file(path: impl AsRef<Path>, content: Option<&str>) {
             .unwrap();
     }
 }
+
+/// Write the file, add & commit it, return the new commit
+pub async fn commit_file(path: &str, content: &str, message: &str) -> SHA1 {
+    ensure_file(path, Some(content));
+    command::add::execute(AddArgs::parse_from(["add", path])).await;
+    command::commit::execute(CommitArgs {
+        message: message.to_string(),
+        allow_empty: false,
+        gpg_sign: None,
+        no_gpg_sign: false,
+    })
+    .await;
+    Head::current_commit().await.unwrap()
+}
+
+/// copy the directory recursively, e.g. copy the repository as a remote
+pub fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            fs::copy(&path, &target).unwrap();
+        }
+    }
+}
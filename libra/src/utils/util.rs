@@ -7,7 +7,9 @@ use std::{env, fs, io};
 use mercury::hash::SHA1;
 use mercury::internal::object::types::ObjectType;
 
+use crate::internal::reflog::{self, Reflog};
 use crate::utils::client_storage::ClientStorage;
+use crate::utils::ignore::IgnoreMatcher;
 use crate::utils::path;
 use crate::utils::path_ext::PathExt;
 
@@ -28,6 +30,13 @@ pub fn cur_dir() -> PathBuf {
     env::current_dir().unwrap()
 }
 
+/// Home directory of the current user, `$HOME` (or `%USERPROFILE%` on Windows)
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
 /// Try to get the storage path of the repository, which is the path of the `.libra` directory
 /// - if the current directory is not a repository, return an error
 pub fn try_get_storage_path() -> Result<PathBuf, io::Error> {
@@ -223,10 +232,36 @@ pub fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-/// list all files in the working dir(include sub_dir)
+/// list all files in the working dir(include sub_dir), except the ignored files (`.gitignore`)
 /// - output: to workdir path
-pub fn list_workdir_files() -> io::Result<Vec<PathBuf>> {
-    list_files(&working_dir())
+pub async fn list_workdir_files() -> io::Result<Vec<PathBuf>> {
+    let mut matcher = IgnoreMatcher::load().await;
+    list_files_not_ignored(&working_dir(), &mut matcher)
+}
+
+/// Same as [list_files], but skip the ignored files & dirs
+fn list_files_not_ignored(path: &Path, matcher: &mut IgnoreMatcher) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        if path.file_name().unwrap_or_default() == ROOT_DIR {
+            // ignore `.libra`
+            return Ok(files);
+        }
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let path_wd = to_workdir_path(&path);
+            let is_dir = path.is_dir();
+            if matcher.is_excluded(&path_wd, is_dir) {
+                continue; // the files in ignored dir are also ignored
+            }
+            if is_dir {
+                files.extend(list_files_not_ignored(&path, matcher)?);
+            } else {
+                files.push(path_wd);
+            }
+        }
+    }
+    Ok(files)
 }
 
 /// Integrate the input paths (relative, absolute, file, dir) to workdir paths
@@ -292,8 +327,27 @@ pub fn path_to_string(path: &Path) -> String {
     path.to_string_lossy().to_string()
 }
 
+/// Parse the reflog selector `<ref>@{n}`, return `(ref, n)`, `ref` may be empty (`HEAD`)
+fn parse_reflog_selector(rev: &str) -> Option<(&str, usize)> {
+    let (name, n) = rev.strip_suffix('}')?.rsplit_once("@{")?;
+    Some((name, n.parse().ok()?))
+}
+
 /// extend hash, panic if not valid or ambiguous
-pub fn get_commit_base(commit_base: &str) -> Result<SHA1, String> {
+/// - `<ref>@{n}`: the n-th prior value of the ref (`HEAD` or branch) in reflog
+pub async fn get_commit_base(commit_base: &str) -> Result<SHA1, String> {
+    if let Some((name, n)) = parse_reflog_selector(commit_base) {
+        let ref_name = reflog::full_ref_name(name);
+        let entries = Reflog::list(&ref_name)
+            .await
+            .map_err(|e| format!("fatal: unable to read the reflog: {}", e))?;
+        return match entries.get(n) {
+            Some(entry) => Ok(entry.new_oid),
+            None if entries.is_empty() => Err(format!("fatal: log for '{}' is empty", ref_name)),
+            None => Err(format!("fatal: log for '{}' only has {} entries", ref_name, entries.len())),
+        };
+    }
+
     let storage = objects_storage();
 
     let commits = storage.search(commit_base);
@@ -313,16 +367,43 @@ pub fn get_commit_base(commit_base: &str) -> Result<SHA1, String> {
     }
 }
 
+/// Parse a time zone like `+0800` into seconds east of UTC
+pub fn parse_timezone(timezone: &str) -> Option<i32> {
+    let (sign, digits) = match timezone.as_bytes().first()? {
+        b'+' => (1, &timezone[1..]),
+        b'-' => (-1, &timezone[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = (digits[..2].parse().ok()?, digits[2..].parse().ok()?);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Format the time of a signature in its own time zone, `format` is of `chrono`, e.g. `%Y-%m-%d %H:%M:%S %z`
+pub fn format_time(timestamp: usize, timezone: &str, format: &str) -> String {
+    let offset = chrono::FixedOffset::east_opt(parse_timezone(timezone).unwrap_or(0))
+        .unwrap_or(chrono::FixedOffset::east_opt(0).unwrap());
+    match chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(time) => time.with_timezone(&offset).format(format).to_string(),
+        None => format!("{} {}", timestamp, timezone),
+    }
+}
+
 /// Get the repository name from the url
 /// - e.g. https://github.com/web3infra-foundation/mega.git/ -> mega
 /// - e.g. https://github.com/web3infra-foundation/mega.git -> mega
-pub fn get_repo_name_from_url(mut url: &str) -> Option<&str> {
-    if url.ends_with('/') {
-        url = &url[..url.len() - 1];
+/// - e.g. git@github.com:mega.git -> mega
+pub fn get_repo_name_from_url(url: &str) -> Option<&str> {
+    let url = url.trim_end_matches('/');
+    let name = url.rsplit(['/', ':']).next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
     }
-    let repo_start = url.rfind('/')? + 1;
-    let repo_end = url.rfind('.')?;
-    Some(&url[repo_start..repo_end])
 }
 
 #[cfg(test)]
@@ -332,6 +413,29 @@ mod test {
     use std::env;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_format_time() {
+        assert_eq!(parse_timezone("+0800"), Some(8 * 3600));
+        assert_eq!(parse_timezone("-0130"), Some(-5400));
+        assert_eq!(parse_timezone("0800"), None);
+        let format = "%Y-%m-%d %H:%M:%S %z";
+        assert_eq!(format_time(1700000000, "+0800", format), "2023-11-15 06:13:20 +0800");
+        assert_eq!(format_time(1700000000, "-0500", format), "2023-11-14 17:13:20 -0500");
+    }
+
+    #[test]
+    fn test_get_repo_name_from_url() {
+        let urls = [
+            "https://github.com/web3infra-foundation/mega.git/",
+            "https://github.com/web3infra-foundation/mega",
+            "git@github.com:web3infra-foundation/mega.git",
+            "git@github.com:mega.git",
+        ];
+        for url in urls {
+            assert_eq!(get_repo_name_from_url(url), Some("mega"));
+        }
+    }
+
     #[test]
     fn cur_dir_returns_current_directory() {
         let expected = env::current_dir().unwrap();
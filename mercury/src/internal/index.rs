@@ -400,6 +400,18 @@ impl Index {
         self.tracked_entries(0).iter().map(|entry| PathBuf::from(&entry.name)).collect()
     }
 
+    /// Get all files with conflicts (stage 1~3), each file only once
+    pub fn unmerged_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|(_, stage)| *stage != 0)
+            .map(|(name, _)| PathBuf::from(name))
+            .collect();
+        files.dedup(); // entries are sorted by (name, stage)
+        files
+    }
+
     /// Judge if the file(s) of `dir` is in the index
     /// - false if `dir` is a file
     pub fn contains_dir_file(&self, dir: &str) -> bool {
@@ -110,7 +110,9 @@ impl Commit {
             if has_signature && !line.trim().is_empty() {
                 return line.to_owned();
             }
-            if line.contains("-----END PGP SIGNATURE-----") {
+            if line.contains("-----END PGP SIGNATURE-----")
+                || line.contains("-----END SSH SIGNATURE-----")
+            {
                 has_signature = true;
             }
         }